@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use ntex_bytes::{Bytes, BytesMut};
+use ntex_codec::{Decoder, Encoder};
+use ntex_h2::{frame, Codec};
+use ntex_http::{HeaderMap, HeaderName, HeaderValue, Method};
+
+fn data_frames(size: usize, count: usize) -> BytesMut {
+    let codec = Codec::default();
+    let payload = Bytes::from(vec![b'x'; size]);
+    let mut buf = BytesMut::new();
+    for _ in 0..count {
+        let frame = frame::Data::new(1.into(), payload.clone());
+        codec.encode(frame.into(), &mut buf).unwrap();
+    }
+    buf
+}
+
+fn headers_frames(count: usize) -> BytesMut {
+    let codec = Codec::default();
+    let mut buf = BytesMut::new();
+    for idx in 0..count {
+        let mut hdrs = HeaderMap::new();
+        hdrs.insert(
+            HeaderName::from_static("content-type"),
+            HeaderValue::from_static("text/plain"),
+        );
+        hdrs.insert(
+            HeaderName::from_static("x-request-id"),
+            HeaderValue::from_static("0123456789"),
+        );
+        let pseudo = frame::PseudoHeaders::request(
+            Method::GET,
+            "https://example.com/index.html".parse().unwrap(),
+            None,
+        );
+        let frame = frame::Headers::new(((idx * 2 + 1) as u32).into(), pseudo, hdrs, false);
+        codec.encode(frame.into(), &mut buf).unwrap();
+    }
+    buf
+}
+
+fn decode_all(codec: &Codec, mut buf: BytesMut) {
+    while let Some(frame) = codec.decode(&mut buf).unwrap() {
+        black_box(frame);
+    }
+}
+
+fn bench_decode(c: &mut Criterion) {
+    for size in [16, 1024, 16_384] {
+        let buf = data_frames(size, 64);
+        c.bench_function(&format!("decode data {}b", size), |b| {
+            b.iter_batched(
+                || (Codec::default(), buf.clone()),
+                |(codec, buf)| decode_all(&codec, buf),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    let buf = headers_frames(64);
+    c.bench_function("decode headers", |b| {
+        b.iter_batched(
+            || (Codec::default(), buf.clone()),
+            |(codec, buf)| decode_all(&codec, buf),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
@@ -0,0 +1,182 @@
+use std::{error::Error, fmt, future::poll_fn, rc::Rc, task::Context, task::Poll};
+
+use ntex_bytes::Bytes;
+use ntex_http::body::{BodySize, MessageBody};
+use ntex_http::{header::CONTENT_LENGTH, HeaderMap, HeaderValue, StatusCode};
+
+use crate::message::{Message, PayloadItem};
+use crate::{client::RecvStream, error::OperationError, frame::Reason};
+use crate::{detached::DetachedStream, stream::StreamRef};
+
+impl StreamRef {
+    /// Send response with message body.
+    ///
+    /// `content-length` header is set if body size is known. Trailers
+    /// are sent after last chunk of the body.
+    pub async fn send_response_body<B: MessageBody>(
+        &self,
+        status: StatusCode,
+        mut headers: HeaderMap,
+        body: B,
+        trailers: Option<HeaderMap>,
+    ) -> Result<(), OperationError> {
+        let size = body.size();
+        if let BodySize::Sized(len) = size {
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+        }
+
+        if size.is_eof() && trailers.is_none() {
+            self.send_response(status, headers, true)
+        } else {
+            self.send_response(status, headers, false)?;
+            self.send_body(body, trailers).await
+        }
+    }
+
+    /// Send message body as stream payload.
+    ///
+    /// Body chunks are sent as DATA frames respecting flow control,
+    /// stream is closed with last chunk or with trailers. Stream is
+    /// reset with `INTERNAL_ERROR` if body fails.
+    pub async fn send_body<B: MessageBody>(
+        &self,
+        mut body: B,
+        trailers: Option<HeaderMap>,
+    ) -> Result<(), OperationError> {
+        // keep one chunk, so last chunk could carry END_STREAM flag
+        let mut pending: Option<Bytes> = None;
+        loop {
+            match poll_fn(|cx| body.poll_next_chunk(cx)).await {
+                Some(Ok(chunk)) => {
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    if let Some(prev) = pending.replace(chunk) {
+                        self.send_payload(prev, false).await?;
+                    }
+                }
+                Some(Err(err)) => {
+                    log::debug!("{}: Body of {:?} failed: {}", self.tag(), self.id(), err);
+                    self.reset(Reason::INTERNAL_ERROR);
+                    return Err(OperationError::LocalReset(Reason::INTERNAL_ERROR));
+                }
+                None => break,
+            }
+        }
+
+        if let Some(trailers) = trailers {
+            if let Some(prev) = pending {
+                self.send_payload(prev, false).await?;
+            }
+            self.send_trailers(trailers);
+            Ok(())
+        } else {
+            self.send_payload(pending.unwrap_or_default(), true).await
+        }
+    }
+}
+
+/// Payload of received stream as message body.
+///
+/// Body size is taken from `content-length` header of the message,
+/// trailers are available after last chunk is received.
+pub struct StreamBody {
+    source: Source,
+    trailers: Option<HeaderMap>,
+}
+
+enum Source {
+    Client(RecvStream),
+    Detached(DetachedStream),
+}
+
+impl StreamBody {
+    #[inline]
+    /// Get stream reference
+    pub fn stream(&self) -> &StreamRef {
+        match self.source {
+            Source::Client(ref rcv) => rcv.stream(),
+            Source::Detached(ref rcv) => rcv.stream(),
+        }
+    }
+
+    /// Received trailers
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Get received trailers
+    pub fn take_trailers(&mut self) -> Option<HeaderMap> {
+        self.trailers.take()
+    }
+
+    pub(crate) fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        match self.source {
+            Source::Client(ref rcv) => rcv.poll_recv(cx),
+            Source::Detached(ref rcv) => rcv.poll_recv(cx),
+        }
+    }
+}
+
+impl From<RecvStream> for StreamBody {
+    fn from(rcv: RecvStream) -> Self {
+        StreamBody {
+            source: Source::Client(rcv),
+            trailers: None,
+        }
+    }
+}
+
+impl From<DetachedStream> for StreamBody {
+    fn from(rcv: DetachedStream) -> Self {
+        StreamBody {
+            source: Source::Detached(rcv),
+            trailers: None,
+        }
+    }
+}
+
+impl MessageBody for StreamBody {
+    fn size(&self) -> BodySize {
+        match self.stream().content_length() {
+            Some(len) => BodySize::Sized(len),
+            None => BodySize::Stream,
+        }
+    }
+
+    fn poll_next_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Rc<dyn Error>>>> {
+        loop {
+            let msg = match self.poll_recv(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            return match msg.kind.into_payload() {
+                PayloadItem::Chunk(data) => Poll::Ready(Some(Ok(data))),
+                PayloadItem::Trailers(hdrs) => {
+                    self.trailers = Some(hdrs);
+                    Poll::Ready(None)
+                }
+                PayloadItem::Error(err) => {
+                    let err: Rc<dyn Error> = Rc::new(err);
+                    Poll::Ready(Some(Err(err)))
+                }
+                PayloadItem::Skip => continue,
+                PayloadItem::Eof => Poll::Ready(None),
+            };
+        }
+    }
+}
+
+impl fmt::Debug for StreamBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamBody")
+            .field("stream", self.stream())
+            .field("trailers", &self.trailers)
+            .finish()
+    }
+}
@@ -0,0 +1,251 @@
+//! Binary wire capture.
+//!
+//! Capture contains raw http/2 frames exactly as they were sent or received.
+//! Each record is stored in following format:
+//!
+//! ```text
+//! +---------------+-------------------------------+---------------+---------+
+//! | Direction (8) | Timestamp, micros (64)        | Length (32)   | Data    |
+//! +---------------+-------------------------------+---------------+---------+
+//! ```
+//!
+//! All numbers are big-endian. Direction is `0` for inbound and `1` for outbound
+//! frames, timestamp is number of microseconds since unix epoch. Outbound record
+//! could contain several frames, for example HEADERS frame with CONTINUATION frames.
+use std::{cell::RefCell, fmt, io, rc::Rc, time::Duration, time::SystemTime};
+
+use ntex_bytes::Bytes;
+
+use crate::{codec::Direction, frame};
+
+/// Factory for per-connection capture writers
+pub(crate) type CaptureFactory = Rc<dyn Fn(&'static str) -> Option<Box<dyn io::Write>>>;
+
+const RECORD_HEADER_LEN: usize = 13;
+
+/// Largest record, single frame of maximum allowed size
+const MAX_RECORD_LEN: usize = frame::MAX_MAX_FRAME_SIZE as usize + frame::HEADER_LEN;
+
+/// Writes captured frames to a sink
+pub(crate) struct CaptureWriter(RefCell<Box<dyn io::Write>>);
+
+impl CaptureWriter {
+    pub(crate) fn new(writer: Box<dyn io::Write>) -> Self {
+        CaptureWriter(RefCell::new(writer))
+    }
+
+    pub(crate) fn write(&self, dir: Direction, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let ts = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let mut hdr = [0u8; RECORD_HEADER_LEN];
+        hdr[0] = match dir {
+            Direction::Inbound => 0,
+            Direction::Outbound => 1,
+        };
+        hdr[1..9].copy_from_slice(&ts.to_be_bytes());
+        hdr[9..].copy_from_slice(&(data.len() as u32).to_be_bytes());
+
+        let mut writer = self.0.borrow_mut();
+        if let Err(e) = writer.write_all(&hdr).and_then(|_| writer.write_all(data)) {
+            log::warn!("Cannot write capture record: {:?}", e);
+        }
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        let _ = self.0.borrow_mut().flush();
+    }
+}
+
+impl fmt::Debug for CaptureWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureWriter").finish()
+    }
+}
+
+/// Captured record
+#[derive(Clone, Debug)]
+pub struct Record {
+    direction: Direction,
+    timestamp: SystemTime,
+    data: Bytes,
+}
+
+impl Record {
+    /// Frame direction
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Time when frame has been sent or received
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Raw frames data, including frame headers
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+}
+
+/// Reads capture records
+#[derive(Debug)]
+pub struct CaptureReader<R> {
+    reader: R,
+}
+
+impl<R: io::Read> CaptureReader<R> {
+    /// Create new capture reader
+    pub fn new(reader: R) -> Self {
+        CaptureReader { reader }
+    }
+
+    /// Read next record.
+    ///
+    /// Returns `None` at the end of the capture.
+    pub fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut hdr = [0u8; RECORD_HEADER_LEN];
+
+        // check for clean end of capture
+        let n = self.reader.read(&mut hdr)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut hdr[n..])?;
+
+        let direction = match hdr[0] {
+            0 => Direction::Inbound,
+            1 => Direction::Outbound,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown frame direction",
+                ))
+            }
+        };
+        let ts = u64::from_be_bytes(hdr[1..9].try_into().unwrap());
+        let len = u32::from_be_bytes(hdr[9..].try_into().unwrap()) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Record length exceeds max frame size",
+            ));
+        }
+
+        let mut data = vec![0; len];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(Record {
+            direction,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_micros(ts),
+            data: Bytes::from(data),
+        }))
+    }
+
+    /// Unwrap underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: io::Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ntex_bytes::BytesMut;
+    use ntex_codec::{Decoder, Encoder};
+
+    use super::*;
+    use crate::Codec;
+
+    #[derive(Clone, Default)]
+    struct Buf(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for Buf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_read() {
+        let buf = Buf::default();
+        let writer = CaptureWriter::new(Box::new(buf.clone()));
+        writer.write(Direction::Outbound, b"frame1");
+        writer.write(Direction::Inbound, b"");
+        writer.write(Direction::Inbound, b"frame2");
+        drop(writer);
+
+        let data = buf.0.borrow().clone();
+        let records: Vec<_> = CaptureReader::new(&data[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction(), Direction::Outbound);
+        assert_eq!(records[0].data(), &b"frame1"[..]);
+        assert_eq!(records[1].direction(), Direction::Inbound);
+        assert_eq!(records[1].data(), &b"frame2"[..]);
+        assert!(records[0].timestamp() <= records[1].timestamp());
+
+        let res = CaptureReader::new(&data[..data.len() - 1]).nth(1).unwrap();
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_read_oversized() {
+        let mut data = vec![0u8; RECORD_HEADER_LEN];
+        data[9..].copy_from_slice(&u32::MAX.to_be_bytes());
+        let res = CaptureReader::new(&data[..]).read_record();
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_codec_capture() {
+        let capture = Buf::default();
+        let codec = Codec::default();
+        codec.set_capture(capture.clone());
+
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&[0, 0, 5, 0, 1, 0, 0, 0, 1]);
+        buf.extend_from_slice(b"hello");
+        let inbound = buf.clone();
+        codec.decode(&mut buf).unwrap().unwrap();
+
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                frame::Reset::new(1.into(), frame::Reason::CANCEL).into(),
+                &mut buf,
+            )
+            .unwrap();
+
+        let data = capture.0.borrow().clone();
+        let records: Vec<_> = CaptureReader::new(&data[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction(), Direction::Inbound);
+        assert_eq!(records[0].data(), &inbound[..]);
+        assert_eq!(records[1].direction(), Direction::Outbound);
+        assert_eq!(records[1].data(), &buf[..]);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{fmt, pin::Pin, task::Context, task::Poll};
+
+use futures_sink::Sink;
+use ntex_bytes::Bytes;
+use ntex_util::Stream as FutStream;
+
+use super::stream::{RecvStream, SendStream};
+use crate::error::OperationError;
+
+impl RecvStream {
+    /// Convert to payload stream.
+    ///
+    /// Response headers must be received with `recv()` first.
+    pub fn into_body(self) -> RecvBody {
+        RecvBody(self)
+    }
+}
+
+impl SendStream {
+    /// Convert to payload sink.
+    ///
+    /// Closing sink closes send side of the stream.
+    pub fn into_sink(self) -> SendSink {
+        SendSink {
+            stream: self,
+            buf: Bytes::new(),
+        }
+    }
+}
+
+/// Payload of the client stream, as `futures` stream
+#[derive(Debug)]
+pub struct RecvBody(RecvStream);
+
+impl RecvBody {
+    /// Get inner stream
+    pub fn into_inner(self) -> RecvStream {
+        self.0
+    }
+}
+
+impl FutStream for RecvBody {
+    type Item = Result<Bytes, OperationError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_next_chunk(cx)
+    }
+}
+
+/// Payload sender of the client stream, as `futures` sink.
+///
+/// Sink accepts next chunk only after previous one is sent,
+/// sending respects stream flow control.
+pub struct SendSink {
+    stream: SendStream,
+    buf: Bytes,
+}
+
+impl SendSink {
+    /// Get inner stream
+    pub fn into_inner(self) -> SendStream {
+        self.stream
+    }
+}
+
+impl Sink<Bytes> for SendSink {
+    type Error = OperationError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        debug_assert!(self.buf.is_empty());
+        self.buf = item;
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        this.stream.stream().poll_send_payload(cx, &mut this.buf)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        match this.stream.stream().poll_send_payload(cx, &mut this.buf) {
+            Poll::Ready(Ok(())) => Poll::Ready(this.stream.end_stream()),
+            res => res,
+        }
+    }
+}
+
+impl fmt::Debug for SendSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink")
+            .field("stream", &self.stream)
+            .field("buffered", &self.buf.len())
+            .finish()
+    }
+}
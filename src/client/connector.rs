@@ -2,12 +2,14 @@ use std::{marker::PhantomData, ops};
 
 use ntex_bytes::ByteString;
 use ntex_http::uri::Scheme;
-use ntex_io::IoBoxed;
+use ntex_io::{types::HttpProtocol, IoBoxed};
 use ntex_net::connect::{self as connect, Address, Connect, Connector as DefaultConnector};
 use ntex_service::{IntoService, Pipeline, Service};
-use ntex_util::time::timeout_checked;
+use ntex_util::time::Seconds;
 
-use crate::{client::ClientError, client::SimpleClient, config::Config};
+use crate::config::{Config, ConfigValues};
+use crate::{client::ClientError, client::SimpleClient, clock::timeout_checked};
+use crate::{error::ConfigError, OperationError};
 
 #[derive(Debug)]
 /// Http2 client connector
@@ -54,6 +56,168 @@ where
     }
 }
 
+impl<A> Connector<A, DefaultConnector<A>>
+where
+    A: Address,
+{
+    /// Create new http2 connector with provided configuration
+    ///
+    /// Configuration must be created with `Config::client()` or
+    /// `Config::from_env(false)`. Connector uses http scheme, use
+    /// [`Connector::scheme`] to change it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if configuration is server configuration.
+    pub fn from_config(config: Config) -> Self {
+        assert!(
+            !config.is_server(),
+            "Server configuration cannot be used for connector"
+        );
+        Connector {
+            config,
+            connector: DefaultConnector::default().into(),
+            scheme: Scheme::HTTP,
+            _t: PhantomData,
+        }
+    }
+
+    /// Create by-value connector builder
+    ///
+    /// Builder does not hold shared configuration until `finish()` is called,
+    /// so it could be constructed and moved between threads.
+    pub fn builder() -> ConnectorBuilder<A> {
+        ConnectorBuilder {
+            values: ConfigValues::new(&Config::client()),
+            scheme: Scheme::HTTP,
+            _t: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// By-value builder for http2 client connector
+///
+/// Values are validated by `.finish()` method.
+pub struct ConnectorBuilder<A> {
+    values: ConfigValues,
+    scheme: Scheme,
+
+    _t: PhantomData<fn() -> A>,
+}
+
+impl<A: Address> ConnectorBuilder<A> {
+    /// Set scheme.
+    ///
+    /// By default scheme is http.
+    pub fn scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Set initial window size for new streams.
+    ///
+    /// See [`Config::initial_window_size`].
+    pub fn initial_window_size(mut self, size: u32) -> Self {
+        self.values.initial_window_size = size;
+        self
+    }
+
+    /// Set initial window size for connection.
+    ///
+    /// See [`Config::initial_connection_window_size`].
+    pub fn initial_connection_window_size(mut self, size: u32) -> Self {
+        self.values.initial_connection_window_size = size;
+        self
+    }
+
+    /// Set max frame size.
+    ///
+    /// See [`Config::max_frame_size`].
+    pub fn max_frame_size(mut self, max: u32) -> Self {
+        self.values.max_frame_size = max;
+        self
+    }
+
+    /// Set max size of the header list.
+    ///
+    /// See [`Config::max_header_list_size`].
+    pub fn max_header_list_size(mut self, max: u32) -> Self {
+        self.values.max_header_list_size = max;
+        self
+    }
+
+    /// Set max number of concurrent streams.
+    ///
+    /// See [`Config::max_concurrent_streams`].
+    pub fn max_concurrent_streams(mut self, max: u32) -> Self {
+        self.values.max_concurrent_streams = max;
+        self
+    }
+
+    /// Set max number of CONTINUATION frames per header block.
+    ///
+    /// See [`Config::max_header_continuation_frames`].
+    pub fn max_header_continuation_frames(mut self, max: usize) -> Self {
+        self.values.max_header_continuation_frames = max;
+        self
+    }
+
+    /// Set max number of concurrent locally reset streams.
+    ///
+    /// See [`Config::max_concurrent_reset_streams`].
+    pub fn max_concurrent_reset_streams(mut self, max: usize) -> Self {
+        self.values.max_concurrent_reset_streams = max;
+        self
+    }
+
+    /// Set duration to remember locally reset streams.
+    ///
+    /// See [`Config::reset_stream_duration`].
+    pub fn reset_stream_duration(mut self, dur: Seconds) -> Self {
+        self.values.reset_stream_duration = dur.0;
+        self
+    }
+
+    /// Set handshake timeout.
+    ///
+    /// See [`Config::handshake_timeout`].
+    pub fn handshake_timeout(mut self, timeout: Seconds) -> Self {
+        self.values.handshake_timeout = timeout.0;
+        self
+    }
+
+    /// Set ping timeout.
+    ///
+    /// See [`Config::ping_timeout`].
+    pub fn ping_timeout(mut self, timeout: Seconds) -> Self {
+        self.values.ping_timeout = timeout.0;
+        self
+    }
+
+    /// Set disconnect timeout.
+    ///
+    /// See [`Config::disconnect_timeout`].
+    pub fn disconnect_timeout(mut self, timeout: Seconds) -> Self {
+        self.values.disconnect_timeout = timeout.0;
+        self
+    }
+
+    /// Validate values and create connector
+    pub fn finish(self) -> Result<Connector<A, DefaultConnector<A>>, ConfigError> {
+        let config = Config::client();
+        self.values.apply(&config)?;
+        config.validate()?;
+
+        Ok(Connector {
+            connector: DefaultConnector::default().into(),
+            config,
+            scheme: self.scheme,
+            _t: PhantomData,
+        })
+    }
+}
+
 impl<A: Address, T> ops::Deref for Connector<A, T> {
     type Target = Config;
 
@@ -102,22 +266,73 @@ where
     IoBoxed: From<T::Response>,
 {
     /// Connect to http2 server
+    ///
+    /// Connect completes after server's SETTINGS frame is received.
+    /// Failure to write connection preface is reported as
+    /// `ClientError::Operation(OperationError::Disconnected)`.
     pub async fn connect(&self, address: A) -> Result<SimpleClient, ClientError> {
+        self.config.validate()?;
+
         let scheme = self.scheme.clone();
         let authority = ByteString::from(address.host());
 
         let fut = async {
-            Ok::<_, ClientError>(SimpleClient::new(
-                self.connector.call(Connect::new(address)).await?,
-                self.config.clone(),
-                scheme,
-                authority,
-            ))
+            let io: IoBoxed = self.connector.call(Connect::new(address)).await?.into();
+            start(io, self.config.clone(), scheme, authority).await
         };
 
-        timeout_checked(self.config.0.handshake_timeout.get(), fut)
+        let timeout = self.config.0.handshake_timeout.get().into();
+        timeout_checked(&*self.config.0.clock(), timeout, fut)
             .await
             .map_err(|_| ClientError::HandshakeTimeout)
             .and_then(|item| item)
     }
 }
+
+/// Perform http2 handshake over established io object
+///
+/// Could be used with connections established by custom means, like
+/// proxies or tunnels. Handshake completes after server's SETTINGS
+/// frame is received.
+pub async fn handshake<T>(
+    io: T,
+    config: Config,
+    scheme: Scheme,
+    authority: ByteString,
+) -> Result<SimpleClient, ClientError>
+where
+    IoBoxed: From<T>,
+{
+    config.validate()?;
+
+    let clock = config.0.clock();
+    let timeout = config.0.handshake_timeout.get().into();
+    timeout_checked(
+        &*clock,
+        timeout,
+        start(io.into(), config, scheme, authority),
+    )
+    .await
+    .map_err(|_| ClientError::HandshakeTimeout)
+    .and_then(|item| item)
+}
+
+async fn start(
+    io: IoBoxed,
+    config: Config,
+    scheme: Scheme,
+    authority: ByteString,
+) -> Result<SimpleClient, ClientError> {
+    if scheme == Scheme::HTTPS {
+        if let Some(proto @ HttpProtocol::Http1) = io.query::<HttpProtocol>().get() {
+            return Err(ClientError::Alpn(proto));
+        }
+    }
+
+    let client = SimpleClient::new(io, config, scheme, authority);
+    match client.connection().remote_settings_received().await {
+        Ok(()) => Ok(client),
+        Err(OperationError::Connection(err)) => Err(ClientError::Settings(Box::new(err))),
+        Err(err) => Err(err.into()),
+    }
+}
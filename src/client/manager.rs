@@ -0,0 +1,419 @@
+//! Multi-origin client.
+//!
+//! Connections are keyed by authority. Request to an authority could be sent
+//! over connection established for another authority (connection coalescing,
+//! RFC 9113 §9.1.1), if origins resolver reports that connection is
+//! authoritative for requested authority. For tls connections resolver
+//! usually checks names of the server certificate.
+use std::{cell::Cell, cell::RefCell, collections::VecDeque, fmt, rc::Rc};
+
+use ntex_bytes::ByteString;
+use ntex_http::{uri::Scheme, HeaderMap, Method};
+use ntex_io::IoBoxed;
+use ntex_net::connect::{self as connect, Connect, Connector as DefaultConnector};
+use ntex_service::{IntoService, Pipeline, Service};
+use ntex_util::time::Millis;
+use ntex_util::{channel::oneshot, future::BoxFuture, HashSet};
+
+use super::pool::notify;
+use super::stream::{InflightStorage, RecvStream, SendStream};
+use super::{simple::SimpleClient, ClientError};
+use crate::stats::{MetricsSnapshot, Stats};
+use crate::{clock::timeout_checked, config::Config, OperationError};
+
+type Connector =
+    Box<dyn Fn(ByteString) -> BoxFuture<'static, Result<IoBoxed, connect::ConnectError>>>;
+type Origins = Box<dyn Fn(&SimpleClient) -> Vec<ByteString>>;
+
+#[derive(Clone)]
+/// Manages http/2 connections to multiple authorities.
+pub struct MultiClient {
+    inner: Rc<Inner>,
+}
+
+/// Multi-origin client builder
+///
+/// The `MultiClientBuilder` type uses a builder-like combinator pattern for
+/// client construction that finishes by calling the `.finish()` method.
+pub struct MultiClientBuilder(Inner);
+
+struct Inner {
+    maxconn: usize,
+    conn_timeout: Millis,
+    max_streams: u32,
+    scheme: Scheme,
+    config: Config,
+    connector: Connector,
+    origins: Option<Origins>,
+    connecting: RefCell<HashSet<ByteString>>,
+    connections: RefCell<Vec<Conn>>,
+    waiters: Rc<RefCell<VecDeque<oneshot::Sender<()>>>>,
+    total_connections: Cell<usize>,
+    coalesced_requests: Cell<usize>,
+    connect_errors: Cell<usize>,
+}
+
+struct Conn {
+    client: SimpleClient,
+    origins: Vec<ByteString>,
+}
+
+impl Conn {
+    fn serves(&self, authority: &ByteString) -> bool {
+        self.client.authority() == authority || self.origins.contains(authority)
+    }
+}
+
+impl MultiClient {
+    #[inline]
+    /// Configure and build client
+    pub fn build<T, F>(connector: F) -> MultiClientBuilder
+    where
+        F: IntoService<T, Connect<ByteString>>,
+        T: Service<Connect<ByteString>, Error = connect::ConnectError> + 'static,
+        IoBoxed: From<T::Response>,
+    {
+        MultiClientBuilder::new(connector)
+    }
+
+    #[inline]
+    /// Configure and build client with default connector
+    pub fn with_default() -> MultiClientBuilder {
+        MultiClientBuilder::new(DefaultConnector::default())
+    }
+
+    /// Send request to the authority
+    ///
+    /// Request is sent over existing connection of the authority or over
+    /// connection of other authority if it could be coalesced. New
+    /// connection is opened if all connections are busy and number of
+    /// authority's connections is less than limit.
+    pub async fn send(
+        &self,
+        authority: ByteString,
+        method: Method,
+        path: ByteString,
+        headers: HeaderMap,
+        eof: bool,
+    ) -> Result<(SendStream, RecvStream), ClientError> {
+        loop {
+            let (client, num) = {
+                let mut connections = self.inner.connections.borrow_mut();
+
+                // cleanup closed connections and connections
+                // that do not accept new streams
+                connections.retain(|item| !(item.client.is_closed() || item.client.is_closing()));
+
+                // least loaded connection which is allowed to serve authority
+                let client = connections
+                    .iter()
+                    .filter(|item| item.serves(&authority))
+                    .filter(|item| {
+                        item.client.is_ready()
+                            && (self.inner.max_streams == 0
+                                || item.client.active_streams() < self.inner.max_streams)
+                    })
+                    .min_by_key(|item| item.client.active_streams())
+                    .map(|item| item.client.clone());
+                let num = connections
+                    .iter()
+                    .filter(|item| *item.client.authority() == authority)
+                    .count();
+                (client, num)
+            };
+
+            if let Some(client) = client {
+                if *client.authority() != authority {
+                    log::trace!(
+                        "Coalesce request for {} to connection of {}",
+                        authority,
+                        client.authority()
+                    );
+                    self.inner
+                        .coalesced_requests
+                        .set(self.inner.coalesced_requests.get() + 1);
+                }
+                return client
+                    .send_to(authority, method, path, headers, eof)
+                    .await
+                    .map_err(|err| match err {
+                        OperationError::Connection(_) | OperationError::Disconnected => {
+                            ClientError::ConnectionFailed(Box::new(client.connection().context()))
+                        }
+                        err => err.into(),
+                    });
+            }
+
+            // can create new connection
+            let connecting = self.inner.connecting.borrow().contains(&authority);
+            if !connecting && num < self.inner.maxconn {
+                self.inner.connecting.borrow_mut().insert(authority.clone());
+                let rx = self.connect(authority.clone());
+                return rx
+                    .await??
+                    .send(method, path, headers, eof)
+                    .await
+                    .map_err(From::from);
+            } else {
+                log::debug!(
+                    "New connection to {} is being established {:?} or number of existing cons {} greater than allowed {}",
+                    authority, connecting, num, self.inner.maxconn);
+
+                // wait for available connection
+                let (tx, rx) = oneshot::channel();
+                self.inner.waiters.borrow_mut().push_back(tx);
+                let _ = rx.await;
+            }
+        }
+    }
+
+    /// Open new connection to authority on spawned task
+    fn connect(
+        &self,
+        authority: ByteString,
+    ) -> oneshot::Receiver<Result<SimpleClient, ClientError>> {
+        let (tx, rx) = oneshot::channel();
+        let inner = self.inner.clone();
+        let _ = ntex_util::spawn(async move {
+            let clock = inner.config.0.clock();
+            let res = match timeout_checked(&*clock, inner.conn_timeout.into(), async {
+                inner.config.validate()?;
+                (*inner.connector)(authority.clone())
+                    .await
+                    .map_err(ClientError::from)
+            })
+            .await
+            {
+                Ok(Ok(io)) => {
+                    // callbacks for end of stream
+                    let waiters2 = inner.waiters.clone();
+                    let storage = InflightStorage::new(move |_| {
+                        notify(&mut waiters2.borrow_mut());
+                    });
+                    // construct client
+                    let client = SimpleClient::with_params(
+                        io,
+                        inner.config.clone(),
+                        inner.scheme.clone(),
+                        authority.clone(),
+                        storage,
+                    );
+                    let origins = inner
+                        .origins
+                        .as_ref()
+                        .map(|f| f(&client))
+                        .unwrap_or_default();
+                    log::debug!(
+                        "Connection to {} is established, origins {:?}",
+                        authority,
+                        origins
+                    );
+                    inner.connections.borrow_mut().push(Conn {
+                        client: client.clone(),
+                        origins,
+                    });
+                    inner
+                        .total_connections
+                        .set(inner.total_connections.get() + 1);
+                    Ok(client)
+                }
+                Ok(Err(err)) => Err(err),
+                Err(_) => Err(ClientError::HandshakeTimeout),
+            };
+            inner.connecting.borrow_mut().remove(&authority);
+            for waiter in inner.waiters.borrow_mut().drain(..) {
+                let _ = waiter.send(());
+            }
+
+            if res.is_err() {
+                inner.connect_errors.set(inner.connect_errors.get() + 1);
+            }
+            let _ = tx.send(res);
+        });
+        rx
+    }
+
+    /// Authorities with established connections
+    pub fn authorities(&self) -> Vec<ByteString> {
+        let mut authorities: Vec<_> = self
+            .inner
+            .connections
+            .borrow()
+            .iter()
+            .map(|item| item.client.authority().clone())
+            .collect();
+        authorities.sort();
+        authorities.dedup();
+        authorities
+    }
+
+    /// Get aggregated statistics of active connections
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        for item in &*self.inner.connections.borrow() {
+            stats += item.client.stats();
+        }
+        stats
+    }
+
+    /// Get aggregated metrics of active connections
+    pub fn metrics(&self) -> MetricsSnapshot {
+        let mut metrics = MetricsSnapshot::default();
+        for item in &*self.inner.connections.borrow() {
+            metrics += item.client.metrics();
+        }
+        metrics
+    }
+}
+
+#[doc(hidden)]
+impl MultiClient {
+    pub fn stat_active_connections(&self) -> usize {
+        self.inner.connections.borrow().len()
+    }
+
+    pub fn stat_total_connections(&self) -> usize {
+        self.inner.total_connections.get()
+    }
+
+    pub fn stat_coalesced_requests(&self) -> usize {
+        self.inner.coalesced_requests.get()
+    }
+
+    pub fn stat_connect_errors(&self) -> usize {
+        self.inner.connect_errors.get()
+    }
+}
+
+impl MultiClientBuilder {
+    fn new<T, F>(connector: F) -> Self
+    where
+        F: IntoService<T, Connect<ByteString>>,
+        T: Service<Connect<ByteString>, Error = connect::ConnectError> + 'static,
+        IoBoxed: From<T::Response>,
+    {
+        let connector = Pipeline::new(connector.into_service());
+        let connector = Box::new(move |authority: ByteString| {
+            log::trace!("Opening http/2 connection to {}", authority);
+            let svc = connector.clone();
+            let f: BoxFuture<'static, _> =
+                Box::pin(async move { svc.call(Connect::new(authority)).await.map(IoBoxed::from) });
+            f
+        });
+
+        MultiClientBuilder(Inner {
+            connector,
+            conn_timeout: Millis(1_000),
+            max_streams: 100,
+            maxconn: 4,
+            scheme: Scheme::HTTP,
+            config: Config::client(),
+            origins: None,
+            connecting: Default::default(),
+            connections: Default::default(),
+            waiters: Default::default(),
+            total_connections: Cell::new(0),
+            coalesced_requests: Cell::new(0),
+            connect_errors: Cell::new(0),
+        })
+    }
+
+    #[inline]
+    /// Set client's connection scheme
+    pub fn scheme(mut self, scheme: Scheme) -> Self {
+        self.0.scheme = scheme;
+        self
+    }
+
+    /// Connection timeout.
+    ///
+    /// i.e. max time to connect to remote host including dns name resolution.
+    /// Set to 1 second by default.
+    pub fn timeout<T: Into<Millis>>(mut self, timeout: T) -> Self {
+        self.0.conn_timeout = timeout.into();
+        self
+    }
+
+    /// Set total number of simultaneous streams per connection.
+    ///
+    /// If limit is 0, only "MAX_CONCURRENT_STREAMS" config from connection
+    /// settings applies.
+    /// The default limit size is 100.
+    pub fn max_streams(mut self, limit: u32) -> Self {
+        self.0.max_streams = limit;
+        self
+    }
+
+    /// Sets the maximum concurrent connections per authority.
+    ///
+    /// Coalesced connections are not counted for authority.
+    ///
+    /// By default max connections is set to a 4.
+    pub fn maxconn(mut self, num: usize) -> Self {
+        self.0.maxconn = num;
+        self
+    }
+
+    /// Set origins resolver.
+    ///
+    /// Resolver is called for every new connection and returns authorities,
+    /// in addition to connection's own authority, that connection could serve.
+    /// Resolver is responsible for checking that connection is authoritative
+    /// for returned authorities, for example by server certificate names.
+    ///
+    /// By default requests are not coalesced.
+    pub fn origins<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&SimpleClient) -> Vec<ByteString> + 'static,
+    {
+        self.0.origins = Some(Box::new(f));
+        self
+    }
+
+    /// Configure http2 connection settings
+    pub fn configure<O, R>(self, f: O) -> Self
+    where
+        O: FnOnce(&Config) -> R,
+    {
+        let _ = f(&self.0.config);
+        self
+    }
+
+    /// Http/2 connection settings
+    pub fn config(&self) -> &Config {
+        &self.0.config
+    }
+
+    /// Finish configuration process and create client.
+    pub fn finish(self) -> MultiClient {
+        MultiClient {
+            inner: Rc::new(self.0),
+        }
+    }
+}
+
+impl fmt::Debug for MultiClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiClient")
+            .field("scheme", &self.inner.scheme)
+            .field("conn_timeout", &self.inner.conn_timeout)
+            .field("maxconn", &self.inner.maxconn)
+            .field("max-streams", &self.inner.max_streams)
+            .field("origins", &self.inner.origins.is_some())
+            .field("config", &self.inner.config)
+            .finish()
+    }
+}
+
+impl fmt::Debug for MultiClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiClientBuilder")
+            .field("scheme", &self.0.scheme)
+            .field("conn_timeout", &self.0.conn_timeout)
+            .field("maxconn", &self.0.maxconn)
+            .field("max-streams", &self.0.max_streams)
+            .field("origins", &self.0.origins.is_some())
+            .field("config", &self.0.config)
+            .finish()
+    }
+}
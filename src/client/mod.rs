@@ -1,16 +1,28 @@
 //! Http2 client
 
+#[cfg(feature = "futures")]
+mod adapters;
 mod connector;
+mod manager;
 mod pool;
 mod simple;
 mod stream;
+mod tunnel;
 
-use crate::{error::ConnectionError, error::OperationError, frame};
+use ntex_io::types::HttpProtocol;
 
-pub use self::connector::Connector;
+use crate::error::{ConfigError, ConnectionContext, ConnectionError, OperationError};
+use crate::frame;
+
+pub use self::connector::{handshake, Connector, ConnectorBuilder};
+pub use self::manager::{MultiClient, MultiClientBuilder};
 pub use self::pool::{Client, ClientBuilder};
 pub use self::simple::SimpleClient;
 pub use self::stream::{RecvStream, SendStream};
+pub use self::tunnel::Tunnel;
+
+#[cfg(feature = "futures")]
+pub use self::adapters::{RecvBody, SendSink};
 
 /// Errors which can occur when attempting to handle http2 client connection.
 #[derive(thiserror::Error, Debug)]
@@ -21,6 +33,9 @@ pub enum ClientError {
     /// Operation error
     #[error("Operation error: {0}")]
     Operation(#[from] OperationError),
+    /// Invalid configuration
+    #[error("Configuration error: {0}")]
+    Config(#[from] ConfigError),
     /// Http/2 frame codec error
     #[error("Http/2 codec error: {0}")]
     Frame(#[from] frame::FrameError),
@@ -30,9 +45,35 @@ pub enum ClientError {
     /// Connect error
     #[error("Connect error: {0}")]
     Connect(Box<ntex_net::connect::ConnectError>),
+    /// Tls connection negotiated protocol other than http/2
+    #[error("Unexpected negotiated protocol: {0:?}")]
+    Alpn(HttpProtocol),
+    /// Server did not send valid SETTINGS frame
+    #[error("Server settings error: {0}")]
+    Settings(Box<ConnectionError>),
     /// Peer disconnected
     #[error("Peer disconnected err: {0}")]
     Disconnected(#[from] std::io::Error),
+    /// Connection failed
+    #[error("Connection failed: {0}")]
+    ConnectionFailed(Box<ConnectionContext>),
+}
+
+impl ClientError {
+    /// Check if request could be retried.
+    ///
+    /// Connect errors are always retryable, operation errors
+    /// are checked with `OperationError::is_retryable()`.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Operation(err) => err.is_retryable(),
+            Self::HandshakeTimeout
+            | Self::Connect(_)
+            | Self::Disconnected(_)
+            | Self::ConnectionFailed(_) => true,
+            _ => false,
+        }
+    }
 }
 
 impl From<ConnectionError> for ClientError {
@@ -58,12 +99,16 @@ impl Clone for ClientError {
         match self {
             Self::Protocol(err) => Self::Protocol(err.clone()),
             Self::Operation(err) => Self::Operation(err.clone()),
+            Self::Config(err) => Self::Config(*err),
             Self::Frame(err) => Self::Frame(*err),
             Self::HandshakeTimeout => Self::HandshakeTimeout,
             Self::Connect(err) => Self::Connect(err.clone()),
+            Self::Alpn(proto) => Self::Alpn(*proto),
+            Self::Settings(err) => Self::Settings(err.clone()),
             Self::Disconnected(err) => {
                 Self::Disconnected(std::io::Error::new(err.kind(), format!("{}", err)))
             }
+            Self::ConnectionFailed(ctx) => Self::ConnectionFailed(ctx.clone()),
         }
     }
 }
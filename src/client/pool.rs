@@ -6,11 +6,13 @@ use ntex_http::{uri::Scheme, HeaderMap, Method};
 use ntex_io::IoBoxed;
 use ntex_net::connect::{self as connect, Address, Connect, Connector as DefaultConnector};
 use ntex_service::{IntoService, Pipeline, Service};
-use ntex_util::time::{timeout_checked, Millis, Seconds};
+use ntex_util::time::{Millis, Seconds};
 use ntex_util::{channel::oneshot, future::BoxFuture};
 
 use super::stream::{InflightStorage, RecvStream, SendStream};
 use super::{simple::SimpleClient, ClientError};
+use crate::stats::{MetricsSnapshot, Stats};
+use crate::{clock::timeout_checked, OperationError};
 
 type Fut = BoxFuture<'static, Result<IoBoxed, connect::ConnectError>>;
 type Connector = Box<dyn Fn() -> BoxFuture<'static, Result<IoBoxed, connect::ConnectError>>>;
@@ -23,7 +25,7 @@ pub struct Client {
 }
 
 /// Notify one active waiter
-fn notify(waiters: &mut VecDeque<oneshot::Sender<()>>) {
+pub(super) fn notify(waiters: &mut VecDeque<oneshot::Sender<()>>) {
     log::debug!("Notify waiter, total {:?}", waiters.len());
     while let Some(waiter) = waiters.pop_front() {
         if waiter.send(()).is_ok() {
@@ -69,10 +71,11 @@ impl Client {
             let (client, num) = {
                 let mut connections = self.inner.connections.borrow_mut();
 
-                // cleanup connections
+                // cleanup closed connections and connections
+                // that do not accept new streams
                 let mut idx = 0;
                 while idx < connections.len() {
-                    if connections[idx].is_closed() {
+                    if connections[idx].is_closed() || connections[idx].is_closing() {
                         connections.remove(idx);
                     } else {
                         idx += 1;
@@ -113,7 +116,12 @@ impl Client {
                 return client
                     .send(method, path, headers, eof)
                     .await
-                    .map_err(From::from);
+                    .map_err(|err| match err {
+                        OperationError::Connection(_) | OperationError::Disconnected => {
+                            ClientError::ConnectionFailed(Box::new(client.connection().context()))
+                        }
+                        err => err.into(),
+                    });
             }
 
             // can create new connection
@@ -127,7 +135,12 @@ impl Client {
                 let inner = self.inner.clone();
                 let waiters = self.waiters.clone();
                 let _ = ntex_util::spawn(async move {
-                    let res = match timeout_checked(inner.conn_timeout, (*inner.connector)()).await
+                    let clock = inner.config.0.clock();
+                    let res = match timeout_checked(&*clock, inner.conn_timeout.into(), async {
+                        inner.config.validate()?;
+                        (*inner.connector)().await.map_err(ClientError::from)
+                    })
+                    .await
                     {
                         Ok(Ok(io)) => {
                             // callbacks for end of stream
@@ -149,7 +162,7 @@ impl Client {
                                 .set(inner.total_connections.get() + 1);
                             Ok(client)
                         }
-                        Ok(Err(err)) => Err(ClientError::from(err)),
+                        Ok(Err(err)) => Err(err),
                         Err(_) => Err(ClientError::HandshakeTimeout),
                     };
                     inner.connecting.set(false);
@@ -218,6 +231,26 @@ impl Client {
     }
 }
 
+impl Client {
+    /// Get aggregated statistics of active connections
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        for client in &*self.inner.connections.borrow() {
+            stats += client.stats();
+        }
+        stats
+    }
+
+    /// Get aggregated metrics of active connections
+    pub fn metrics(&self) -> MetricsSnapshot {
+        let mut metrics = MetricsSnapshot::default();
+        for client in &*self.inner.connections.borrow() {
+            metrics += client.metrics();
+        }
+        metrics
+    }
+}
+
 #[doc(hidden)]
 impl Client {
     pub fn stat_active_connections(&self) -> usize {
@@ -1,4 +1,4 @@
-use std::{fmt, rc::Rc};
+use std::{fmt, rc::Rc, time::Duration};
 
 use ntex_bytes::ByteString;
 use ntex_http::{uri::Scheme, HeaderMap, Method};
@@ -7,7 +7,9 @@ use ntex_io::{Dispatcher as IoDispatcher, IoBoxed, IoRef, OnDisconnect};
 use crate::connection::Connection;
 use crate::default::DefaultControlService;
 use crate::dispatcher::Dispatcher;
-use crate::{codec::Codec, config::Config, OperationError};
+use crate::OperationError;
+use crate::{codec::Codec, config::Config, frame::Protocol};
+use crate::{dump::NegotiatedSettings, stats::MetricsSnapshot, stats::Stats};
 
 use super::stream::{HandleService, InflightStorage, RecvStream, SendStream};
 
@@ -89,7 +91,65 @@ impl SimpleClient {
         let stream = self
             .0
             .con
-            .send_request(self.0.authority.clone(), method, path, headers, eof)
+            .send_request(self.0.authority.clone(), method, path, None, headers, eof)
+            .await?;
+
+        Ok(self.0.storage.inflight(stream))
+    }
+
+    /// Send request for other authority, for coalesced connections
+    pub(super) async fn send_to(
+        &self,
+        authority: ByteString,
+        method: Method,
+        path: ByteString,
+        headers: HeaderMap,
+        eof: bool,
+    ) -> Result<(SendStream, RecvStream), OperationError> {
+        let stream = self
+            .0
+            .con
+            .send_request(authority, method, path, None, headers, eof)
+            .await?;
+
+        Ok(self.0.storage.inflight(stream))
+    }
+
+    /// Send extended CONNECT request to the peer
+    ///
+    /// Peer must advertise support of the [extended CONNECT protocol]
+    /// with `SETTINGS_ENABLE_CONNECT_PROTOCOL` setting. Response headers
+    /// must be received before payload is exchanged.
+    ///
+    /// [extended CONNECT protocol]: https://datatracker.ietf.org/doc/html/rfc8441#section-4
+    pub async fn send_connect(
+        &self,
+        protocol: Protocol,
+        path: ByteString,
+        headers: HeaderMap,
+    ) -> Result<(SendStream, RecvStream), OperationError> {
+        self.0.con.remote_settings_received().await?;
+        if self
+            .0
+            .con
+            .remote_settings()
+            .is_extended_connect_protocol_enabled()
+            != Some(true)
+        {
+            return Err(OperationError::ExtendedConnectNotSupported);
+        }
+
+        let stream = self
+            .0
+            .con
+            .send_request(
+                self.0.authority.clone(),
+                Method::CONNECT,
+                path,
+                Some(protocol),
+                headers,
+                false,
+            )
             .await?;
 
         Ok(self.0.storage.inflight(stream))
@@ -130,6 +190,14 @@ impl SimpleClient {
         self.0.con.is_closed()
     }
 
+    #[inline]
+    /// Check if connection is gracefully closing
+    ///
+    /// Closing connection does not accept new requests.
+    pub fn is_closing(&self) -> bool {
+        self.0.con.is_closing()
+    }
+
     #[inline]
     /// Notify when connection get closed
     pub fn on_disconnect(&self) -> OnDisconnect {
@@ -152,6 +220,26 @@ impl SimpleClient {
         self.0.con.active_streams()
     }
 
+    /// Get connection statistics
+    pub fn stats(&self) -> Stats {
+        self.0.con.stats()
+    }
+
+    /// Send ping and wait for acknowledgement, returns round-trip time
+    pub async fn ping(&self) -> Result<Duration, OperationError> {
+        self.0.con.ping().await
+    }
+
+    /// Get connection metrics snapshot
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.0.con.metrics()
+    }
+
+    /// Get local, remote and effective settings of the connection
+    pub fn negotiated_settings(&self) -> NegotiatedSettings {
+        self.0.con.negotiated_settings()
+    }
+
     #[doc(hidden)]
     /// Get access to underlining io object
     pub fn io_ref(&self) -> &IoRef {
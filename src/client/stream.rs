@@ -1,14 +1,14 @@
 use std::task::{Context, Poll};
 use std::{cell::RefCell, collections::VecDeque, fmt, future::poll_fn, pin::Pin, rc::Rc};
 
-use ntex_bytes::Bytes;
+use ntex_bytes::{Bytes, BytesMut};
 use ntex_http::HeaderMap;
 use ntex_service::{Service, ServiceCtx};
 use ntex_util::{future::Either, task::LocalWaker, HashMap, Stream as FutStream};
 
 use crate::error::OperationError;
 use crate::frame::{Reason, StreamId, WindowSize};
-use crate::message::{Message, MessageKind};
+use crate::message::{Message, MessageKind, PayloadItem};
 use crate::{Stream, StreamRef};
 
 #[derive(Clone, Default)]
@@ -67,7 +67,7 @@ pub struct SendStream(StreamRef, ());
 impl Drop for SendStream {
     fn drop(&mut self) {
         if !self.0.send_state().is_closed() {
-            self.0.reset(Reason::CANCEL);
+            self.0.drop_reset();
         }
     }
 }
@@ -114,6 +114,12 @@ impl SendStream {
         self.0.send_trailers(map)
     }
 
+    #[inline]
+    /// Finish sending payload
+    pub fn end_stream(&self) -> Result<(), OperationError> {
+        self.0.end_stream()
+    }
+
     #[inline]
     /// Reset stream
     pub fn reset(&self, reason: Reason) {
@@ -155,6 +161,12 @@ impl RecvStream {
         &self.0
     }
 
+    #[inline]
+    /// Reset stream, for example to cancel response download
+    pub fn reset(&self, reason: Reason) {
+        self.0.reset(reason)
+    }
+
     /// Attempt to pull out the next value of http/2 stream
     pub async fn recv(&self) -> Option<Message> {
         poll_fn(|cx| self.poll_recv(cx)).await
@@ -178,12 +190,59 @@ impl RecvStream {
             Poll::Ready(None)
         }
     }
+
+    /// Read next chunk of the payload.
+    ///
+    /// Response headers must be received with `recv()` first. Flow control
+    /// capacity is released as soon as chunk is returned. Returns `None` at
+    /// the end of the payload, trailers are skipped.
+    pub async fn next_chunk(&self) -> Option<Result<Bytes, OperationError>> {
+        poll_fn(|cx| self.poll_next_chunk(cx)).await
+    }
+
+    /// Read whole payload.
+    ///
+    /// Fails with `OperationError::PayloadOverflow` and resets stream if payload
+    /// is larger than `limit`. Response headers must be received with `recv()` first.
+    pub async fn collect_body(&self, limit: usize) -> Result<Bytes, OperationError> {
+        let mut body = BytesMut::new();
+        while let Some(chunk) = self.next_chunk().await {
+            let chunk = chunk?;
+            if body.len() + chunk.len() > limit {
+                self.0.reset(Reason::CANCEL);
+                return Err(OperationError::PayloadOverflow(limit));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body.freeze())
+    }
+
+    /// Poll for next chunk of the payload
+    pub fn poll_next_chunk(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, OperationError>>> {
+        loop {
+            let msg = match self.poll_recv(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            return match msg.kind.into_payload() {
+                PayloadItem::Chunk(data) => Poll::Ready(Some(Ok(data))),
+                PayloadItem::Error(err) => Poll::Ready(Some(Err(err))),
+                PayloadItem::Skip => continue,
+                PayloadItem::Trailers(_) | PayloadItem::Eof => Poll::Ready(None),
+            };
+        }
+    }
 }
 
 impl Drop for RecvStream {
     fn drop(&mut self) {
         if !self.0.recv_state().is_closed() {
-            self.0.reset(Reason::CANCEL);
+            self.0.drop_reset();
         }
         self.1 .0.inflight.borrow_mut().remove(&self.0.id());
     }
@@ -224,7 +283,7 @@ impl Service<Message> for HandleService {
                 log::debug!("Stream {:?} is closed, notify", id);
             }
         } else {
-            log::error!("Received message for unknown stream, {:?}", msg);
+            log::debug!("Received message for unknown stream, {:?}", msg);
         }
         Ok(())
     }
@@ -0,0 +1,92 @@
+use std::{fmt, future::poll_fn, pin::Pin, task::Context, task::Poll};
+
+use ntex_bytes::Bytes;
+use ntex_util::Stream as FutStream;
+
+use super::stream::{RecvStream, SendStream};
+use crate::{error::OperationError, frame::Reason, frame::WindowSize, StreamRef};
+
+/// Byte stream over DATA frames of a single http/2 stream.
+///
+/// Tunnel is intended for CONNECT and extended CONNECT requests.
+/// Read side returns payload chunks, flow control capacity is released
+/// as soon as chunk is returned. Write side waits for send capacity,
+/// so slow peer applies backpressure to the writer.
+pub struct Tunnel {
+    snd: SendStream,
+    rcv: RecvStream,
+}
+
+impl Tunnel {
+    /// Create tunnel from client stream parts.
+    ///
+    /// Response headers must be received with `RecvStream::recv()` first.
+    pub fn new(snd: SendStream, rcv: RecvStream) -> Self {
+        Tunnel { snd, rcv }
+    }
+
+    #[inline]
+    pub fn stream(&self) -> &StreamRef {
+        self.snd.stream()
+    }
+
+    /// Read next chunk of data.
+    ///
+    /// Returns `None` if peer closed its side of the tunnel.
+    pub async fn read(&self) -> Option<Result<Bytes, OperationError>> {
+        self.rcv.next_chunk().await
+    }
+
+    /// Poll for next chunk of data
+    pub fn poll_read(&self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, OperationError>>> {
+        self.rcv.poll_next_chunk(cx)
+    }
+
+    /// Write data to the tunnel
+    pub async fn write(&self, data: Bytes) -> Result<(), OperationError> {
+        self.snd.send_payload(data, false).await
+    }
+
+    /// Check if tunnel is ready for write.
+    ///
+    /// Returns available send capacity.
+    pub fn poll_write_ready(&self, cx: &Context<'_>) -> Poll<Result<WindowSize, OperationError>> {
+        self.snd.poll_send_capacity(cx)
+    }
+
+    /// Wait until tunnel is ready for write
+    pub async fn write_ready(&self) -> Result<WindowSize, OperationError> {
+        poll_fn(|cx| self.poll_write_ready(cx)).await
+    }
+
+    /// Close write side of the tunnel
+    pub async fn shutdown(&self) -> Result<(), OperationError> {
+        self.snd.send_payload(Bytes::new(), true).await
+    }
+
+    /// Reset tunnel
+    pub fn reset(&self, reason: Reason) {
+        self.snd.reset(reason)
+    }
+
+    /// Split tunnel to stream parts
+    pub fn into_parts(self) -> (SendStream, RecvStream) {
+        (self.snd, self.rcv)
+    }
+}
+
+impl FutStream for Tunnel {
+    type Item = Result<Bytes, OperationError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_read(cx)
+    }
+}
+
+impl fmt::Debug for Tunnel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tunnel")
+            .field("stream", self.stream())
+            .finish()
+    }
+}
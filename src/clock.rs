@@ -0,0 +1,112 @@
+use std::{cell::Cell, cell::RefCell, fmt, future::poll_fn, future::Future, pin::Pin, rc::Rc};
+use std::{task::Poll, task::Waker, time::Duration, time::Instant};
+
+use ntex_util::future::{select, Either};
+
+/// Time source for connection timers
+///
+/// Clock drives reset streams expiration, drain and flush deadlines,
+/// client keep-alive pings and handshake timeout.
+pub trait Clock: fmt::Debug + 'static {
+    /// Current time
+    fn now(&self) -> Instant;
+
+    /// Sleep for specified duration
+    fn sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()>>>;
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+/// System clock, uses `ntex_util::time`
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        ntex_util::time::now()
+    }
+
+    fn sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ntex_util::time::sleep(dur))
+    }
+}
+
+#[derive(Clone)]
+/// Virtual clock for deterministic tests
+///
+/// Time does not pass on its own, it only moves with `advance()` call.
+pub struct ManualClock(Rc<ManualClockInner>);
+
+struct ManualClockInner {
+    now: Cell<Instant>,
+    sleepers: RefCell<Vec<Waker>>,
+}
+
+impl ManualClock {
+    /// Create virtual clock starting at current time
+    pub fn new() -> Self {
+        ManualClock(Rc::new(ManualClockInner {
+            now: Cell::new(Instant::now()),
+            sleepers: RefCell::new(Vec::new()),
+        }))
+    }
+
+    /// Advance virtual time and wake elapsed timers
+    pub fn advance(&self, dur: Duration) {
+        self.0.now.set(self.0.now.get() + dur);
+        for waker in self.0.sleepers.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.0.now.get()
+    }
+
+    fn sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        let inner = self.0.clone();
+        let deadline = inner.now.get() + dur;
+        Box::pin(poll_fn(move |cx| {
+            if inner.now.get() >= deadline {
+                Poll::Ready(())
+            } else {
+                inner.sleepers.borrow_mut().push(cx.waker().clone());
+                Poll::Pending
+            }
+        }))
+    }
+}
+
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManualClock")
+            .field("now", &self.0.now.get())
+            .field("sleepers", &self.0.sleepers.borrow().len())
+            .finish()
+    }
+}
+
+/// Run future with timeout, zero duration disables timeout
+pub(crate) async fn timeout_checked<F>(
+    clock: &dyn Clock,
+    dur: Duration,
+    fut: F,
+) -> Result<F::Output, ()>
+where
+    F: Future,
+{
+    if dur.is_zero() {
+        Ok(fut.await)
+    } else {
+        match select(fut, clock.sleep(dur)).await {
+            Either::Left(res) => Ok(res),
+            Either::Right(_) => Err(()),
+        }
+    }
+}
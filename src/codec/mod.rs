@@ -1,4 +1,4 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, fmt, io, rc::Rc};
 
 use ntex_bytes::BytesMut;
 use ntex_codec::{Decoder, Encoder};
@@ -9,7 +9,7 @@ mod length_delimited;
 pub use self::error::EncoderError;
 
 use self::length_delimited::LengthDelimitedCodec;
-use crate::{consts, frame, frame::Frame, frame::Kind, hpack};
+use crate::{capture::CaptureWriter, consts, frame, frame::Frame, frame::Kind, hpack};
 
 // Push promise frame kind
 const PUSH_PROMISE: u8 = 5;
@@ -17,6 +17,26 @@ const PUSH_PROMISE: u8 = 5;
 #[derive(Clone, Debug)]
 pub struct Codec(Rc<RefCell<CodecInner>>);
 
+/// Frame direction
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Frame is received from the peer
+    Inbound,
+    /// Frame is sent to the peer
+    Outbound,
+}
+
+/// Frame observer
+pub(crate) type FrameObserver = Rc<dyn Fn(&Frame, Direction)>;
+
+struct Observer(Option<FrameObserver>);
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Observer").field(&self.0.is_some()).finish()
+    }
+}
+
 /// Partially loaded headers frame
 #[derive(Debug)]
 struct Partial {
@@ -34,6 +54,8 @@ struct CodecInner {
     encoder_hpack: hpack::Encoder,
     encoder_last_data_frame: Option<frame::Data>,
     encoder_max_frame_size: frame::FrameSize, // Max frame size, this is specified by the peer
+    encoder_header_bytes: u64,
+    encoder_bytes: u64,
 
     // decoder state
     decoder: LengthDelimitedCodec,
@@ -41,6 +63,13 @@ struct CodecInner {
     decoder_max_header_list_size: usize,
     decoder_max_header_continuations: usize,
     partial: Option<Partial>, // Partially loaded headers frame
+    decoder_header_bytes: u64,
+    decoder_bytes: u64,
+
+    // frames observer
+    observer: Observer,
+    // raw frames capture
+    capture: Option<CaptureWriter>,
 }
 
 impl Default for Codec {
@@ -61,10 +90,16 @@ impl Default for Codec {
             decoder_max_header_list_size: consts::DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE as usize,
             decoder_max_header_continuations: consts::DEFAULT_MAX_COUNTINUATIONS,
             partial: None,
+            decoder_header_bytes: 0,
+            decoder_bytes: 0,
 
             encoder_hpack: hpack::Encoder::default(),
             encoder_last_data_frame: None,
             encoder_max_frame_size: frame::DEFAULT_MAX_FRAME_SIZE,
+            encoder_header_bytes: 0,
+            encoder_bytes: 0,
+            observer: Observer(None),
+            capture: None,
         })))
     }
 }
@@ -119,6 +154,57 @@ impl Codec {
     pub fn send_frame_size(&self) -> u32 {
         self.0.borrow_mut().encoder_max_frame_size
     }
+
+    /// Set frames observer.
+    ///
+    /// Observer is called for each frame after decoding and before encoding.
+    pub fn set_frame_observer<F>(&self, f: F)
+    where
+        F: Fn(&Frame, Direction) + 'static,
+    {
+        self.set_observer(Rc::new(f));
+    }
+
+    pub(crate) fn set_observer(&self, f: FrameObserver) {
+        self.0.borrow_mut().observer = Observer(Some(f));
+    }
+
+    /// Set raw frames capture writer.
+    ///
+    /// Each received and sent frame is written to the writer,
+    /// see [`capture`](crate::capture) module for the format description.
+    pub fn set_capture<W>(&self, writer: W)
+    where
+        W: io::Write + 'static,
+    {
+        self.set_capture_writer(Box::new(writer));
+    }
+
+    pub(crate) fn set_capture_writer(&self, writer: Box<dyn io::Write>) {
+        self.0.borrow_mut().capture = Some(CaptureWriter::new(writer));
+    }
+
+    /// Snapshot of hpack encoder dynamic table
+    pub fn encoder_table(&self) -> hpack::TableState {
+        self.0.borrow().encoder_hpack.table_state()
+    }
+
+    /// Snapshot of hpack decoder dynamic table
+    pub fn decoder_table(&self) -> hpack::TableState {
+        self.0.borrow().decoder_hpack.table_state()
+    }
+
+    /// Size of encoded and decoded header blocks, including frame headers.
+    pub(crate) fn header_bytes(&self) -> (u64, u64) {
+        let inner = self.0.borrow();
+        (inner.encoder_header_bytes, inner.decoder_header_bytes)
+    }
+
+    /// Size of encoded and decoded frames, including frame headers.
+    pub(crate) fn frame_bytes(&self) -> (u64, u64) {
+        let inner = self.0.borrow();
+        (inner.encoder_bytes, inner.decoder_bytes)
+    }
 }
 
 impl Decoder for Codec {
@@ -136,6 +222,10 @@ impl Decoder for Codec {
             } else {
                 return Ok(None);
             };
+            inner.decoder_bytes += bytes.len() as u64;
+            if let Some(ref capture) = inner.capture {
+                capture.write(Direction::Inbound, &bytes);
+            }
 
             // check push promise, we do not support push
             if bytes[3] == PUSH_PROMISE {
@@ -155,6 +245,10 @@ impl Decoder for Codec {
 
             log::trace!("decoding {:?} frame, frame buf len {}", kind, bytes.len());
 
+            if matches!(kind, Kind::Headers | Kind::Continuation) {
+                inner.decoder_header_bytes += bytes.len() as u64;
+            }
+
             let frame = match kind {
                 Kind::Settings => frame::Settings::load(head, &bytes[frame::HEADER_LEN..])
                     .map_err(|e| {
@@ -345,6 +439,12 @@ impl Decoder for Codec {
                 }
             };
 
+            // observer could use codec, release borrow first
+            let observer = inner.observer.0.clone();
+            drop(inner);
+            if let Some(f) = observer {
+                f(&frame, Direction::Inbound);
+            }
             return Ok(Some(frame));
         }
     }
@@ -358,7 +458,13 @@ impl Encoder for Codec {
         // Ensure that we have enough capacity to accept the write.
         // log::debug!(frame = ?item, "send");
 
+        let observer = self.0.borrow().observer.0.clone();
+        if let Some(f) = observer {
+            f(&item, Direction::Outbound);
+        }
+
         let mut inner = self.0.borrow_mut();
+        let start = buf.len();
 
         match item {
             Frame::Data(v) => {
@@ -374,7 +480,9 @@ impl Encoder for Codec {
             }
             Frame::Headers(v) => {
                 let max_size = inner.encoder_max_frame_size as usize;
+                let len = buf.len();
                 v.encode(&mut inner.encoder_hpack, buf, max_size);
+                inner.encoder_header_bytes += (buf.len() - len) as u64;
             }
             Frame::Settings(v) => {
                 v.encode(buf);
@@ -383,18 +491,140 @@ impl Encoder for Codec {
                 v.encode(buf);
             }
             Frame::Ping(v) => {
+                buf.reserve(frame::Ping::ENCODED_LEN);
                 v.encode(buf);
             }
             Frame::WindowUpdate(v) => {
+                buf.reserve(frame::WindowUpdate::ENCODED_LEN);
                 v.encode(buf);
             }
 
             Frame::Priority(_) => (),
             Frame::Reset(v) => {
+                buf.reserve(frame::Reset::ENCODED_LEN);
                 v.encode(buf);
             }
         }
 
+        inner.encoder_bytes += (buf.len() - start) as u64;
+        if let Some(ref capture) = inner.capture {
+            capture.write(Direction::Outbound, &buf[start..]);
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ntex_bytes::Bytes;
+    use ntex_http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+
+    use super::*;
+
+    #[test]
+    fn test_encode_exact() {
+        let codec = Codec::default();
+
+        let mut buf = BytesMut::new();
+        codec
+            .encode(frame::Ping::new([1; 8]).into(), &mut buf)
+            .unwrap();
+        assert_eq!(
+            &buf[..],
+            &[0, 0, 8, 6, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1]
+        );
+
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                frame::WindowUpdate::new(frame::StreamId::CON, 1024).into(),
+                &mut buf,
+            )
+            .unwrap();
+        assert_eq!(&buf[..], &[0, 0, 4, 8, 0, 0, 0, 0, 0, 0, 0, 4, 0]);
+
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                frame::Reset::new(3.into(), frame::Reason::CANCEL).into(),
+                &mut buf,
+            )
+            .unwrap();
+        assert_eq!(&buf[..], &[0, 0, 4, 3, 0, 0, 0, 0, 3, 0, 0, 0, 8]);
+
+        let mut buf = BytesMut::new();
+        let frm = frame::GoAway::new(frame::Reason::NO_ERROR).set_data("bye");
+        codec.encode(frm.into(), &mut buf).unwrap();
+        assert_eq!(&buf[..9], &[0, 0, 11, 7, 0, 0, 0, 0, 0]);
+        assert_eq!(&buf[17..], b"bye");
+
+        // stream id uses all four bytes
+        let mut buf = BytesMut::new();
+        let frm = frame::Data::new(0x0102_0305.into(), Bytes::from_static(b"hello"));
+        codec.encode(frm.into(), &mut buf).unwrap();
+        assert_eq!(&buf[..9], &[0, 0, 5, 0, 0, 1, 2, 3, 5]);
+        assert_eq!(&buf[9..], b"hello");
+    }
+
+    #[test]
+    fn test_frame_observer() {
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let frames2 = frames.clone();
+        let codec = Codec::default();
+        codec.set_frame_observer(move |frm, dir| {
+            frames2.borrow_mut().push((format!("{:?}", frm), dir));
+        });
+
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&[0, 0, 5, 0, 1, 0, 0, 0, 1]);
+        buf.extend_from_slice(b"hello");
+        codec.decode(&mut buf).unwrap().unwrap();
+
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                frame::Reset::new(1.into(), frame::Reason::CANCEL).into(),
+                &mut buf,
+            )
+            .unwrap();
+
+        let frames = frames.borrow();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].0.contains("Data"));
+        assert_eq!(frames[0].1, Direction::Inbound);
+        assert!(frames[1].0.contains("Reset"));
+        assert_eq!(frames[1].1, Direction::Outbound);
+    }
+
+    #[test]
+    fn test_hpack_table_state() {
+        let mut hdrs = HeaderMap::new();
+        hdrs.insert(
+            HeaderName::from_static("x-custom"),
+            HeaderValue::from_static("value"),
+        );
+        let pseudo = frame::PseudoHeaders::response(StatusCode::OK);
+
+        let codec = Codec::default();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                frame::Headers::new(1.into(), pseudo, hdrs, true).into(),
+                &mut buf,
+            )
+            .unwrap();
+
+        let table = codec.encoder_table();
+        assert_eq!(table.entries().len(), 1);
+        assert_eq!(table.size(), 45);
+        assert!(format!("{}", table).contains("[62] x-custom: value (45)"));
+
+        let codec = Codec::default();
+        codec.decode(&mut buf).unwrap().unwrap();
+        let table = codec.decoder_table();
+        assert_eq!(table.entries().len(), 1);
+        assert_eq!(table.size(), 45);
+        assert_eq!(table.max_size(), 4096);
+    }
+}
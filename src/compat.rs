@@ -0,0 +1,67 @@
+//! Conversion between `ntex_http` and `http` header maps.
+//!
+//! Header names are shared between both crates. Values of `ntex_http`
+//! headers are converted without copying, values of `http` headers are
+//! copied because `http` does not expose underlying storage.
+use ntex_http::{HeaderMap, HeaderValue};
+
+/// Convert header value to `http::HeaderValue`.
+pub fn to_http_value(value: &HeaderValue) -> http::HeaderValue {
+    let shared = bytes::Bytes::from_owner(value.as_shared().clone());
+    match http::HeaderValue::from_maybe_shared(shared) {
+        Ok(mut val) => {
+            val.set_sensitive(value.is_sensitive());
+            val
+        }
+        Err(_) => value.into(),
+    }
+}
+
+/// Convert header map to `http::HeaderMap`.
+pub fn to_http(map: &HeaderMap) -> http::HeaderMap {
+    let mut hdrs = http::HeaderMap::with_capacity(map.len());
+    for (name, value) in map {
+        hdrs.append(name.clone(), to_http_value(value));
+    }
+    hdrs
+}
+
+/// Convert `http::HeaderMap` to header map.
+pub fn from_http(map: &http::HeaderMap) -> HeaderMap {
+    let mut hdrs = HeaderMap::with_capacity(map.keys_len());
+    for (name, value) in map {
+        hdrs.append(name.clone(), value.into());
+    }
+    hdrs
+}
+
+#[cfg(test)]
+mod tests {
+    use ntex_http::header;
+
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let mut map = HeaderMap::new();
+        map.append(header::ACCEPT, HeaderValue::from_static("text/html"));
+        map.append(header::ACCEPT, HeaderValue::from_static("text/plain"));
+        let mut value = HeaderValue::from_shared(vec![b'a'; 1024]).unwrap();
+        value.set_sensitive(true);
+        map.insert(header::AUTHORIZATION, value);
+
+        let hdrs = to_http(&map);
+        assert_eq!(hdrs.get_all(header::ACCEPT).iter().count(), 2);
+        let value = hdrs.get(header::AUTHORIZATION).unwrap();
+        assert_eq!(value.len(), 1024);
+        assert!(value.is_sensitive());
+
+        let map2 = from_http(&hdrs);
+        assert_eq!(map2.get_all(header::ACCEPT).count(), 2);
+        assert_eq!(
+            map2.get(header::AUTHORIZATION).unwrap(),
+            map.get(header::AUTHORIZATION).unwrap()
+        );
+        assert!(map2.get(header::AUTHORIZATION).unwrap().is_sensitive());
+    }
+}
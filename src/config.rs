@@ -1,22 +1,92 @@
-use std::{cell::Cell, fmt, rc::Rc, time::Duration};
+use std::{cell::Cell, cell::RefCell, env, fmt, io, rc::Rc, str::FromStr, time::Duration};
 
 use ntex_io::DispatcherConfig;
 use ntex_util::{channel::pool, time::Seconds};
 
-use crate::{consts, frame, frame::Settings, frame::WindowSize};
+use crate::clock::{Clock, SystemClock};
+use crate::codec::{Direction, FrameObserver};
+use crate::error::{ConfigError, ConnectionError};
+use crate::frame::{self, Settings, StreamId, WindowSize};
+use crate::{capture::CaptureFactory, consts, hooks::Hooks, qlog::QLogFactory};
 
 bitflags::bitflags! {
     #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-    struct ConfigFlags: u8 {
+    struct ConfigFlags: u16 {
         const SERVER =    0b0000_0001;
         const HTTPS  =    0b0000_0010;
         const SHUTDOWN  = 0b0000_0100;
+        const RESET_STALLED = 0b0000_1000;
+        const KEEP_ON_DROP = 0b0001_0000;
+        const PING_IDLE_ONLY = 0b0010_0000;
+        const LENIENT_PSEUDO = 0b0100_0000;
+        const STRIP_CONN_HEADERS = 0b1000_0000;
+        const RESET_NO_FLOW_CONTROL = 0b0001_0000_0000;
+        const FRAME_NOTIFY = 0b0010_0000_0000;
+        const STREAM_NOTIFY = 0b0100_0000_0000;
     }
 }
 
 #[derive(Clone)]
 pub struct Config(pub(crate) Rc<ConfigInner>);
 
+/// Content-length enforcement policy for received messages
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ContentLengthPolicy {
+    /// Invalid, conflicting or mismatched content-length is a stream error
+    #[default]
+    Strict,
+    /// Log and strip invalid or conflicting content-length header,
+    /// log and stop checking payload size on mismatch
+    Strip,
+    /// Do not check content-length
+    Ignore,
+}
+
+/// Policy for non-empty payload of HEAD response
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HeadPayloadPolicy {
+    /// Reset stream with `PROTOCOL_ERROR`
+    #[default]
+    Reset,
+    /// Discard payload
+    Truncate,
+    /// Deliver payload as is
+    PassThrough,
+}
+
+/// Handling of violations that could be treated either
+/// as stream or as connection errors
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ViolationPolicy {
+    /// DATA or HEADERS for half-closed stream resets the stream,
+    /// WINDOW_UPDATE for unknown stream closes connection
+    #[default]
+    Recommended,
+    /// Keep connection, reset offending stream or ignore the frame
+    Stream,
+    /// Close connection with `GOAWAY`
+    Connection,
+}
+
+/// Handling of frames received for locally reset stream
+/// after grace limit is reached
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ResetStreamViolation {
+    /// Reply with `RST_STREAM(STREAM_CLOSED)`
+    #[default]
+    Stream,
+    /// Close connection with `GOAWAY(STREAM_CLOSED)`
+    Connection,
+}
+
 /// Http2 connection configuration
 pub(crate) struct ConfigInner {
     /// Initial window size of locally initiated streams
@@ -26,6 +96,10 @@ pub(crate) struct ConfigInner {
     pub(crate) reset_duration: Cell<Duration>,
     /// Maximum number of locally reset streams to keep at a time
     pub(crate) reset_max: Cell<usize>,
+    /// Maximum number of frames accepted for locally reset stream
+    pub(crate) reset_max_frames: Cell<u32>,
+    /// Handling of frames above reset stream limit
+    pub(crate) reset_violation: Cell<ResetStreamViolation>,
     pub(crate) settings: Cell<Settings>,
     /// Initial window size for new connections.
     pub(crate) connection_window_sz: Cell<WindowSize>,
@@ -34,15 +108,43 @@ pub(crate) struct ConfigInner {
     pub(crate) remote_max_concurrent_streams: Cell<Option<u32>>,
     /// Limit number of continuation frames for headers
     pub(crate) max_header_continuations: Cell<usize>,
+    /// Max number of empty DATA frames per second
+    pub(crate) max_empty_data_frames: Cell<u32>,
+    /// Max number of concurrently processed messages
+    pub(crate) max_concurrent_publish: Cell<usize>,
+    /// Max number of frames processed before yielding to other tasks
+    pub(crate) frames_budget: Cell<usize>,
+    /// Max size of received but not consumed payload
+    pub(crate) read_high_watermark: Cell<u32>,
+    /// Max number of received but not consumed payload frames
+    pub(crate) max_buffered_frames: Cell<usize>,
+    /// Max number of streams created over connection lifetime
+    pub(crate) max_lifetime_streams: Cell<u64>,
     // /// If extended connect protocol is enabled.
     // pub extended_connect_protocol_enabled: bool,
     /// Connection timeouts
     pub(crate) handshake_timeout: Cell<Seconds>,
     pub(crate) ping_timeout: Cell<Seconds>,
+    pub(crate) send_stall_timeout: Cell<Seconds>,
     pub(crate) dispatcher_config: DispatcherConfig,
+    /// Content-length enforcement policy
+    pub(crate) content_length_policy: Cell<ContentLengthPolicy>,
+    /// Non-empty HEAD response payload policy
+    pub(crate) head_payload_policy: Cell<HeadPayloadPolicy>,
+    pub(crate) violation_policy: Cell<ViolationPolicy>,
 
     /// Config flags
     flags: Cell<ConfigFlags>,
+    /// qlog writers factory
+    pub(crate) qlog: RefCell<Option<QLogFactory>>,
+    /// Frames observer
+    pub(crate) frame_observer: RefCell<Option<FrameObserver>>,
+    /// Wire capture writers factory
+    pub(crate) capture: RefCell<Option<CaptureFactory>>,
+    /// Connection event callbacks
+    pub(crate) hooks: RefCell<Hooks>,
+    /// Time source for connection timers
+    pub(crate) clock: RefCell<Rc<dyn Clock>>,
 
     pub(crate) pool: pool::Pool<()>,
 }
@@ -58,6 +160,145 @@ impl Config {
         Config::new(false)
     }
 
+    /// Create configuration from environment variables.
+    ///
+    /// Following variables are recognized, unset variables keep
+    /// default values:
+    ///
+    /// * `NTEX_H2_INITIAL_WINDOW_SIZE`
+    /// * `NTEX_H2_INITIAL_CONNECTION_WINDOW_SIZE`
+    /// * `NTEX_H2_MAX_FRAME_SIZE`
+    /// * `NTEX_H2_MAX_HEADER_LIST_SIZE`
+    /// * `NTEX_H2_MAX_CONCURRENT_STREAMS`
+    /// * `NTEX_H2_MAX_CONCURRENT_RESET_STREAMS`
+    /// * `NTEX_H2_RESET_STREAM_DURATION`, in seconds
+    /// * `NTEX_H2_HANDSHAKE_TIMEOUT`, in seconds
+    /// * `NTEX_H2_PING_TIMEOUT`, in seconds
+    /// * `NTEX_H2_DISCONNECT_TIMEOUT`, in seconds
+    ///
+    /// Returns `ConfigError::Env` if variable value cannot be parsed.
+    pub fn from_env(server: bool) -> Result<Self, ConfigError> {
+        Config::from_lookup(server, |name| {
+            env::var_os(name).map(|val| val.to_string_lossy().into_owned())
+        })
+    }
+
+    /// Create configuration from variables provided by lookup function.
+    ///
+    /// Lookup function is called with variable names recognized by
+    /// [`Config::from_env`], `None` keeps default value.
+    ///
+    /// Returns `ConfigError::Env` if variable value cannot be parsed.
+    pub fn from_lookup<F>(server: bool, lookup: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let cfg = Config::new(server);
+        let mut values = ConfigValues::new(&cfg);
+
+        lookup_var(
+            &lookup,
+            "NTEX_H2_INITIAL_WINDOW_SIZE",
+            &mut values.initial_window_size,
+        )?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_INITIAL_CONNECTION_WINDOW_SIZE",
+            &mut values.initial_connection_window_size,
+        )?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_MAX_FRAME_SIZE",
+            &mut values.max_frame_size,
+        )?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_MAX_HEADER_LIST_SIZE",
+            &mut values.max_header_list_size,
+        )?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_MAX_CONCURRENT_STREAMS",
+            &mut values.max_concurrent_streams,
+        )?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_MAX_CONCURRENT_RESET_STREAMS",
+            &mut values.max_concurrent_reset_streams,
+        )?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_RESET_STREAM_DURATION",
+            &mut values.reset_stream_duration,
+        )?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_HANDSHAKE_TIMEOUT",
+            &mut values.handshake_timeout,
+        )?;
+        lookup_var(&lookup, "NTEX_H2_PING_TIMEOUT", &mut values.ping_timeout)?;
+        lookup_var(
+            &lookup,
+            "NTEX_H2_DISCONNECT_TIMEOUT",
+            &mut values.disconnect_timeout,
+        )?;
+
+        values.apply(&cfg)?;
+        Ok(cfg)
+    }
+
+    /// Apply preset for gRPC services.
+    ///
+    /// gRPC multiplexes many long-lived streams over single connection,
+    /// so stream and connection windows are larger than defaults and
+    /// more concurrent streams are allowed. Header list size is limited
+    /// to 16Kb, which is enough for gRPC metadata.
+    ///
+    /// Preset could be applied to client and server configuration,
+    /// values could be adjusted after preset is applied.
+    pub fn grpc(&self) -> &Self {
+        self.initial_window_size(1024 * 1024)
+            .initial_connection_window_size(16 * 1024 * 1024)
+            .max_concurrent_streams(1024)
+            .max_header_list_size(16 * 1024)
+    }
+
+    /// Apply preset for memory constrained environments.
+    ///
+    /// Small windows, default frame size and low concurrency limit the
+    /// amount of data buffered per connection. Received but not consumed
+    /// payload is limited to the connection window.
+    ///
+    /// Preset could be applied to client and server configuration,
+    /// values could be adjusted after preset is applied.
+    pub fn low_memory(&self) -> &Self {
+        self.initial_window_size(16 * 1024)
+            .initial_connection_window_size(64 * 1024)
+            .max_frame_size(frame::DEFAULT_MAX_FRAME_SIZE)
+            .max_concurrent_streams(32)
+            .max_header_list_size(16 * 1024)
+            .max_concurrent_reset_streams(10)
+            .read_high_watermark(64 * 1024)
+            .max_buffered_frames(64)
+    }
+
+    /// Apply preset for bulk transfers.
+    ///
+    /// Large windows let peer send data without waiting for window
+    /// updates on high latency links, larger frames reduce per-frame
+    /// overhead and bigger budget lets busy connection process more
+    /// frames before yielding.
+    ///
+    /// Preset could be applied to client and server configuration,
+    /// values could be adjusted after preset is applied.
+    pub fn high_throughput(&self) -> &Self {
+        self.initial_window_size(8 * 1024 * 1024)
+            .initial_connection_window_size(64 * 1024 * 1024)
+            .max_frame_size(256 * 1024)
+            .max_concurrent_streams(256)
+            .frames_budget(256)
+    }
+
     fn new(server: bool) -> Self {
         let window_sz = Cell::new(frame::DEFAULT_INITIAL_WINDOW_SIZE);
         let window_sz_threshold =
@@ -93,10 +334,27 @@ impl Config {
             settings: Cell::new(settings),
             reset_max: Cell::new(consts::DEFAULT_RESET_STREAM_MAX),
             reset_duration: Cell::new(consts::DEFAULT_RESET_STREAM_SECS.into()),
+            reset_max_frames: Cell::new(0),
+            reset_violation: Cell::new(ResetStreamViolation::Stream),
             remote_max_concurrent_streams: Cell::new(None),
             max_header_continuations: Cell::new(consts::DEFAULT_MAX_COUNTINUATIONS),
+            max_empty_data_frames: Cell::new(consts::DEFAULT_MAX_EMPTY_DATA_FRAMES),
+            max_concurrent_publish: Cell::new(0),
+            frames_budget: Cell::new(consts::DEFAULT_FRAMES_BUDGET),
+            read_high_watermark: Cell::new(0),
+            max_buffered_frames: Cell::new(0),
+            max_lifetime_streams: Cell::new(0),
             handshake_timeout: Cell::new(Seconds(5)),
             ping_timeout: Cell::new(Seconds(10)),
+            send_stall_timeout: Cell::new(Seconds::ZERO),
+            content_length_policy: Cell::new(ContentLengthPolicy::Strict),
+            head_payload_policy: Cell::new(HeadPayloadPolicy::Reset),
+            violation_policy: Cell::new(ViolationPolicy::Recommended),
+            qlog: RefCell::new(None),
+            frame_observer: RefCell::new(None),
+            capture: RefCell::new(None),
+            hooks: RefCell::new(Hooks::default()),
+            clock: RefCell::new(Rc::new(SystemClock)),
             pool: pool::new(),
         }))
     }
@@ -116,10 +374,19 @@ impl Config {
 
         let mut s = self.0.settings.get();
         s.set_initial_window_size(Some(size));
-        self.0.settings.set(s);
+        self.set_settings(s);
         self
     }
 
+    /// Same as `initial_window_size()`, but fails for window size larger than 2^31-1
+    pub fn try_initial_window_size(&self, size: u32) -> Result<&Self, ConfigError> {
+        if size as usize > frame::MAX_INITIAL_WINDOW_SIZE {
+            Err(ConfigError::WindowSize(size))
+        } else {
+            Ok(self.initial_window_size(size))
+        }
+    }
+
     /// Indicates the initial window size (in octets) for connection-level flow control
     /// for received data.
     ///
@@ -138,6 +405,15 @@ impl Config {
         self
     }
 
+    /// Same as `initial_connection_window_size()`, but returns error instead of panic
+    pub fn try_initial_connection_window_size(&self, size: u32) -> Result<&Self, ConfigError> {
+        if size > consts::MAX_WINDOW_SIZE {
+            Err(ConfigError::ConnectionWindowSize(size))
+        } else {
+            Ok(self.initial_connection_window_size(size))
+        }
+    }
+
     /// Indicates the size (in octets) of the largest HTTP/2 frame payload that the
     /// configured server is able to accept.
     ///
@@ -154,10 +430,19 @@ impl Config {
     pub fn max_frame_size(&self, max: u32) -> &Self {
         let mut s = self.0.settings.get();
         s.set_max_frame_size(max);
-        self.0.settings.set(s);
+        self.set_settings(s);
         self
     }
 
+    /// Same as `max_frame_size()`, but returns error instead of panic
+    pub fn try_max_frame_size(&self, max: u32) -> Result<&Self, ConfigError> {
+        if (frame::DEFAULT_MAX_FRAME_SIZE..=frame::MAX_MAX_FRAME_SIZE).contains(&max) {
+            Ok(self.max_frame_size(max))
+        } else {
+            Err(ConfigError::MaxFrameSize(max))
+        }
+    }
+
     /// Sets the max size of received header frames.
     ///
     /// This advisory setting informs a peer of the maximum size of header list
@@ -172,7 +457,7 @@ impl Config {
     pub fn max_header_list_size(&self, max: u32) -> &Self {
         let mut s = self.0.settings.get();
         s.set_max_header_list_size(Some(max));
-        self.0.settings.set(s);
+        self.set_settings(s);
         self
     }
 
@@ -184,6 +469,81 @@ impl Config {
         self
     }
 
+    /// Sets the max number of empty DATA frames received within one second.
+    ///
+    /// DATA frames without payload and without END_STREAM flag are not
+    /// limited by flow control. If peer exceeds the limit, connection
+    /// is closed with `ENHANCE_YOUR_CALM` reason.
+    ///
+    /// To disable limit set value to 0. By default value is set to 128.
+    pub fn max_empty_data_frames(&self, max: u32) -> &Self {
+        self.0.max_empty_data_frames.set(max);
+        self
+    }
+
+    /// Sets the maximum number of concurrently processed messages.
+    ///
+    /// Limits number of simultaneously executing publish service calls per connection.
+    /// If limit is reached, connection stops reading new frames until one of
+    /// in-flight calls completes.
+    ///
+    /// To disable limit set value to 0. By default there is no limit.
+    pub fn max_concurrent_publish(&self, max: usize) -> &Self {
+        self.0.max_concurrent_publish.set(max);
+        self
+    }
+
+    /// Sets the number of frames processed in one go.
+    ///
+    /// Once connection has processed `budget` frames, it yields execution
+    /// to other tasks, so busy connection cannot starve other connections
+    /// running on the same worker.
+    ///
+    /// To disable budget set value to 0. By default budget is set to 64 frames.
+    pub fn frames_budget(&self, budget: usize) -> &Self {
+        self.0.frames_budget.set(budget);
+        self
+    }
+
+    /// Sets the max size of received but not yet consumed payload per connection.
+    ///
+    /// Payload is consumed once [`Capacity`] of the data message is consumed or dropped.
+    /// If buffered payload reaches the limit, connection stops reading new frames.
+    ///
+    /// To disable limit set value to 0. By default there is no limit.
+    ///
+    /// [`Capacity`]: crate::Capacity
+    pub fn read_high_watermark(&self, size: u32) -> &Self {
+        self.0.read_high_watermark.set(size);
+        self
+    }
+
+    /// Sets the max number of received but not yet consumed payload frames per connection.
+    ///
+    /// If number of buffered frames reaches the limit, connection stops
+    /// reading new frames.
+    ///
+    /// To disable limit set value to 0. By default there is no limit.
+    pub fn max_buffered_frames(&self, max: usize) -> &Self {
+        self.0.max_buffered_frames.set(max);
+        self
+    }
+
+    /// Sets the max number of streams created over connection lifetime.
+    ///
+    /// If number of created streams reaches the limit, connection sends
+    /// GOAWAY frame with `NO_ERROR` reason, new streams are not accepted
+    /// and connection is closed once active streams are complete.
+    ///
+    /// Server counts streams opened by the peer, client counts
+    /// locally initiated streams.
+    ///
+    /// To disable limit set value to 0. By default there is no limit.
+    pub fn max_lifetime_streams(&self, max: u64) -> &Self {
+        self.0.max_lifetime_streams.set(max);
+        self
+    }
+
     /// Sets the maximum number of concurrent streams.
     ///
     /// The maximum concurrent streams setting only controls the maximum number
@@ -211,7 +571,7 @@ impl Config {
         self.0.remote_max_concurrent_streams.set(Some(max));
         let mut s = self.0.settings.get();
         s.set_max_concurrent_streams(Some(max));
-        self.0.settings.set(s);
+        self.set_settings(s);
         self
     }
 
@@ -267,15 +627,57 @@ impl Config {
         self
     }
 
-    // /// Enables the [extended CONNECT protocol].
-    // ///
-    // /// [extended CONNECT protocol]: https://datatracker.ietf.org/doc/html/rfc8441#section-4
-    // pub fn enable_connect_protocol(&self) -> &Self {
-    //     let mut s = self.0.settings.get();
-    //     s.set_enable_connect_protocol(Some(1));
-    //     self.0.settings.set(s);
-    //     self
-    // }
+    /// Sets the maximum number of frames accepted for locally reset stream.
+    ///
+    /// Frames received for locally reset stream during `reset_stream_duration`
+    /// are ignored. Frames above this limit are handled according
+    /// to `reset_stream_violation` policy.
+    ///
+    /// To disable limit set value to 0.
+    ///
+    /// By default number of frames is not limited.
+    pub fn reset_stream_max_frames(&self, max: u32) -> &Self {
+        self.0.reset_max_frames.set(max);
+        self
+    }
+
+    /// Set handling of frames above `reset_stream_max_frames` limit.
+    ///
+    /// By default such frames are replied with `RST_STREAM(STREAM_CLOSED)`.
+    pub fn reset_stream_violation(&self, policy: ResetStreamViolation) -> &Self {
+        self.0.reset_violation.set(policy);
+        self
+    }
+
+    /// Account DATA frames of locally reset streams in connection flow control.
+    ///
+    /// Payload of such frames is dropped, but it is consumed from
+    /// connection receive window and window is replenished for the peer.
+    /// If disabled, peer's view of connection window could shrink
+    /// after each dropped frame.
+    ///
+    /// By default payload of reset streams is accounted.
+    pub fn reset_stream_flow_control(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::RESET_NO_FLOW_CONTROL, !val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Enables the [extended CONNECT protocol].
+    ///
+    /// Requests with `:protocol` pseudo header are accepted only
+    /// if extended CONNECT protocol is advertised to the peer.
+    ///
+    /// By default extended CONNECT protocol is disabled.
+    ///
+    /// [extended CONNECT protocol]: https://datatracker.ietf.org/doc/html/rfc8441#section-4
+    pub fn enable_connect_protocol(&self) -> &Self {
+        let mut s = self.0.settings.get();
+        s.set_enable_connect_protocol(Some(1));
+        self.set_settings(s);
+        self
+    }
 
     /// Set handshake timeout.
     ///
@@ -322,6 +724,273 @@ impl Config {
         self
     }
 
+    /// Set content-length enforcement policy.
+    ///
+    /// Policy defines how invalid content-length header and payload
+    /// size mismatch of received messages are handled.
+    ///
+    /// By default policy is `ContentLengthPolicy::Strict`.
+    pub fn content_length_policy(&self, policy: ContentLengthPolicy) -> &Self {
+        self.0.content_length_policy.set(policy);
+        self
+    }
+
+    /// Set policy for non-empty payload of HEAD response.
+    ///
+    /// By default policy is `HeadPayloadPolicy::Reset`.
+    pub fn head_payload_policy(&self, policy: HeadPayloadPolicy) -> &Self {
+        self.0.head_payload_policy.set(policy);
+        self
+    }
+
+    /// Set escalation policy for stream violations.
+    ///
+    /// Applies to DATA and HEADERS frames received for half-closed streams
+    /// and to WINDOW_UPDATE frames received for idle or forgotten streams.
+    /// Proxies usually prefer to isolate misbehaving streams, while strict
+    /// origins prefer to tear down connection.
+    ///
+    /// By default policy is `ViolationPolicy::Recommended`.
+    pub fn violation_policy(&self, policy: ViolationPolicy) -> &Self {
+        self.0.violation_policy.set(policy);
+        self
+    }
+
+    /// Apply ping timeout only to idle connections.
+    ///
+    /// If enabled, missing pong response does not close connection
+    /// while connection has active streams, next ping is sent instead.
+    ///
+    /// By default ping timeout applies to all connections.
+    pub fn ping_idle_only(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::PING_IDLE_ONLY, val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Accept out of order and repeated pseudo headers.
+    ///
+    /// Pseudo headers placed after regular fields or repeated
+    /// in a header block are malformed, such streams are reset
+    /// with `PROTOCOL_ERROR`. Lenient mode keeps first value of repeated
+    /// pseudo header instead, it could be useful for proxies.
+    ///
+    /// By default pseudo headers ordering is enforced.
+    pub fn lenient_pseudo_headers(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::LENIENT_PSEUDO, val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Strip connection specific header fields from received messages.
+    ///
+    /// Connection specific fields like `transfer-encoding`, `connection`
+    /// or `upgrade` are not allowed in http/2 messages, such streams are reset
+    /// with `PROTOCOL_ERROR`. If enabled, these fields are removed from the message
+    /// and stream is processed as usual.
+    ///
+    /// By default streams with connection specific fields are reset.
+    pub fn strip_connection_headers(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::STRIP_CONN_HEADERS, val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Notify control service about received SETTINGS, WINDOW_UPDATE and PING frames.
+    ///
+    /// If enabled, `Control::SettingsReceived`, `Control::SettingsAcked`,
+    /// `Control::WindowUpdate` and `Control::PingReceived` messages are sent
+    /// to control service. PING frames are acknowledged by control service.
+    ///
+    /// By default notifications are disabled.
+    pub fn frame_notifications(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::FRAME_NOTIFY, val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Notify control service about stream lifecycle.
+    ///
+    /// If enabled, `Control::StreamOpened`, `Control::StreamClosed` and
+    /// `Control::StreamResetByPeer` messages are sent to control service.
+    ///
+    /// By default notifications are disabled.
+    pub fn stream_notifications(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::STREAM_NOTIFY, val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Set send stall timeout.
+    ///
+    /// If stream cannot send data because peer does not grant send window
+    /// for longer than this timeout, `Control::StreamStalled` message
+    /// is sent to control service.
+    ///
+    /// To disable stall detection set value to 0.
+    ///
+    /// By default stall detection is disabled.
+    pub fn send_stall_timeout(&self, timeout: Seconds) -> &Self {
+        self.0.send_stall_timeout.set(timeout);
+        self
+    }
+
+    /// Reset stalled streams.
+    ///
+    /// If enabled, default ack for `Control::StreamStalled` message
+    /// resets the stream with `CANCEL` reason.
+    ///
+    /// By default stalled streams are not reset.
+    pub fn reset_stalled_streams(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::RESET_STALLED, val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Reset streams on drop.
+    ///
+    /// If enabled, dropping stream handles before stream is complete
+    /// resets the stream with `CANCEL` reason. Otherwise stream keeps
+    /// running and received payload is discarded.
+    ///
+    /// By default streams are reset on drop.
+    pub fn reset_streams_on_drop(&self, val: bool) -> &Self {
+        let mut flags = self.0.flags.get();
+        flags.set(ConfigFlags::KEEP_ON_DROP, !val);
+        self.0.flags.set(flags);
+        self
+    }
+
+    /// Set qlog writer factory.
+    ///
+    /// Factory is called for each new connection with connection's io tag.
+    /// If factory returns a writer, connection frames, flow-control updates
+    /// and stream state transitions are recorded as qlog events in JSON-SEQ format.
+    ///
+    /// By default qlog is disabled.
+    pub fn qlog<F, W>(&self, f: F) -> &Self
+    where
+        F: Fn(&'static str) -> Option<W> + 'static,
+        W: io::Write + 'static,
+    {
+        *self.0.qlog.borrow_mut() = Some(Rc::new(move |tag| {
+            f(tag).map(|w| Box::new(w) as Box<dyn io::Write>)
+        }));
+        self
+    }
+
+    /// Set time source for connection timers.
+    ///
+    /// Clock drives handshake timeout, client keep-alive pings, reset streams
+    /// expiration, stream deadlines and drain deadlines. Virtual clock, like
+    /// `ManualClock`, makes timers deterministic in tests.
+    ///
+    /// By default `ntex_util::time` is used.
+    pub fn clock<T: Clock>(&self, clock: T) -> &Self {
+        *self.0.clock.borrow_mut() = Rc::new(clock);
+        self
+    }
+
+    /// Set frames observer.
+    ///
+    /// Observer is called for each received frame after decoding
+    /// and for each sent frame before encoding.
+    ///
+    /// By default there is no observer.
+    pub fn frame_observer<F>(&self, f: F) -> &Self
+    where
+        F: Fn(&frame::Frame, Direction) + 'static,
+    {
+        *self.0.frame_observer.borrow_mut() = Some(Rc::new(f));
+        self
+    }
+
+    /// Set wire capture writer factory.
+    ///
+    /// Factory is called for each new connection with connection's io tag.
+    /// If factory returns a writer, all raw frames sent and received by
+    /// the connection are written to it, see [`capture`](crate::capture) module.
+    ///
+    /// By default capture is disabled.
+    pub fn capture<F, W>(&self, f: F) -> &Self
+    where
+        F: Fn(&'static str) -> Option<W> + 'static,
+        W: io::Write + 'static,
+    {
+        *self.0.capture.borrow_mut() = Some(Rc::new(move |tag| {
+            f(tag).map(|w| Box::new(w) as Box<dyn io::Write>)
+        }));
+        self
+    }
+
+    /// Set callback for received GOAWAY frames.
+    ///
+    /// Callbacks are lightweight alternative to control service
+    /// for observing connection events.
+    ///
+    /// By default there is no callback.
+    pub fn on_goaway<F>(&self, f: F) -> &Self
+    where
+        F: Fn(&frame::GoAway) + 'static,
+    {
+        self.0.hooks.borrow_mut().on_goaway = Some(Rc::new(f));
+        self
+    }
+
+    /// Set callback for received PING frames, including ping acknowledgements.
+    ///
+    /// By default there is no callback.
+    pub fn on_ping<F>(&self, f: F) -> &Self
+    where
+        F: Fn(&frame::Ping) + 'static,
+    {
+        self.0.hooks.borrow_mut().on_ping = Some(Rc::new(f));
+        self
+    }
+
+    /// Set callback for streams reset by the peer.
+    ///
+    /// By default there is no callback.
+    pub fn on_stream_reset<F>(&self, f: F) -> &Self
+    where
+        F: Fn(StreamId, frame::Reason) + 'static,
+    {
+        self.0.hooks.borrow_mut().on_stream_reset = Some(Rc::new(f));
+        self
+    }
+
+    /// Set callback for received SETTINGS frames, including settings acknowledgements.
+    ///
+    /// By default there is no callback.
+    pub fn on_settings<F>(&self, f: F) -> &Self
+    where
+        F: Fn(&Settings) + 'static,
+    {
+        self.0.hooks.borrow_mut().on_settings = Some(Rc::new(f));
+        self
+    }
+
+    /// Set protocol error to GOAWAY frame mapping.
+    ///
+    /// Mapping is called with protocol error and default GOAWAY frame,
+    /// returned frame is used for connection error handling, for example
+    /// to hide debug data or to change error reason.
+    ///
+    /// By default `ConnectionError::to_goaway()` is used.
+    pub fn map_goaway<F>(&self, f: F) -> &Self
+    where
+        F: Fn(&ConnectionError, frame::GoAway) -> frame::GoAway + 'static,
+    {
+        self.0.hooks.borrow_mut().map_goaway = Some(Rc::new(f));
+        self
+    }
+
     /// Check if configuration defined for server.
     pub fn is_server(&self) -> bool {
         self.0.flags.get().contains(ConfigFlags::SERVER)
@@ -332,6 +1001,37 @@ impl Config {
         self.0.flags.get().contains(ConfigFlags::SHUTDOWN)
     }
 
+    /// Validate configuration.
+    ///
+    /// Clients validate configuration before connect, servers
+    /// during service factory initialization.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let window_sz = self.0.window_sz.get();
+        if window_sz as usize > frame::MAX_INITIAL_WINDOW_SIZE {
+            return Err(ConfigError::WindowSize(window_sz));
+        }
+        let connection_window_sz = self.0.connection_window_sz.get();
+        if connection_window_sz > consts::MAX_WINDOW_SIZE {
+            return Err(ConfigError::ConnectionWindowSize(connection_window_sz));
+        }
+        if let Some(max) = self.0.settings.get().max_frame_size() {
+            if !(frame::DEFAULT_MAX_FRAME_SIZE..=frame::MAX_MAX_FRAME_SIZE).contains(&max) {
+                return Err(ConfigError::MaxFrameSize(max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Update configuration values from other configuration.
+    ///
+    /// Tunable values, like limits, timeouts and policies, are copied.
+    /// Callbacks and writer factories are not copied. New connections use
+    /// updated values, established connections keep negotiated settings.
+    pub fn update(&self, other: &Config) -> Result<&Self, ConfigError> {
+        ConfigValues::new(other).apply(self)?;
+        Ok(self)
+    }
+
     /// Set service shutdown.
     pub fn shutdown(&self) {
         let mut flags = self.0.flags.get();
@@ -342,13 +1042,65 @@ impl Config {
     pub(crate) fn inner(&self) -> &ConfigInner {
         self.0.as_ref()
     }
+
+    fn set_settings(&self, settings: Settings) {
+        self.0.settings.set(settings);
+    }
 }
 
 impl ConfigInner {
+    /// Time source for timers
+    pub(crate) fn clock(&self) -> Rc<dyn Clock> {
+        self.clock.borrow().clone()
+    }
+
     /// Check if service is shutting down.
     pub(crate) fn is_shutdown(&self) -> bool {
         self.flags.get().contains(ConfigFlags::SHUTDOWN)
     }
+
+    /// Check if stalled streams must be reset
+    pub(crate) fn reset_stalled_streams(&self) -> bool {
+        self.flags.get().contains(ConfigFlags::RESET_STALLED)
+    }
+
+    /// Check if ping timeout applies only to idle connections
+    pub(crate) fn ping_idle_only(&self) -> bool {
+        self.flags.get().contains(ConfigFlags::PING_IDLE_ONLY)
+    }
+
+    /// Check if malformed pseudo headers are accepted
+    pub(crate) fn lenient_pseudo_headers(&self) -> bool {
+        self.flags.get().contains(ConfigFlags::LENIENT_PSEUDO)
+    }
+
+    /// Check if connection specific header fields are stripped
+    pub(crate) fn strip_connection_headers(&self) -> bool {
+        self.flags.get().contains(ConfigFlags::STRIP_CONN_HEADERS)
+    }
+
+    /// Check if control service is notified about settings and window updates
+    pub(crate) fn frame_notifications(&self) -> bool {
+        self.flags.get().contains(ConfigFlags::FRAME_NOTIFY)
+    }
+
+    /// Check if control service is notified about stream lifecycle
+    pub(crate) fn stream_notifications(&self) -> bool {
+        self.flags.get().contains(ConfigFlags::STREAM_NOTIFY)
+    }
+
+    /// Check if payload of reset streams is accounted in connection flow control
+    pub(crate) fn reset_stream_flow_control(&self) -> bool {
+        !self
+            .flags
+            .get()
+            .contains(ConfigFlags::RESET_NO_FLOW_CONTROL)
+    }
+
+    /// Check if dropped streams must be reset
+    pub(crate) fn reset_streams_on_drop(&self) -> bool {
+        !self.flags.get().contains(ConfigFlags::KEEP_ON_DROP)
+    }
 }
 
 impl fmt::Debug for Config {
@@ -358,6 +1110,8 @@ impl fmt::Debug for Config {
             .field("window_sz_threshold", &self.0.window_sz_threshold.get())
             .field("reset_duration", &self.0.reset_duration.get())
             .field("reset_max", &self.0.reset_max.get())
+            .field("reset_max_frames", &self.0.reset_max_frames.get())
+            .field("reset_violation", &self.0.reset_violation.get())
             .field("connection_window_sz", &self.0.connection_window_sz.get())
             .field(
                 "connection_window_sz_threshold",
@@ -367,6 +1121,23 @@ impl fmt::Debug for Config {
                 "remote_max_concurrent_streams",
                 &self.0.remote_max_concurrent_streams.get(),
             )
+            .field(
+                "max_concurrent_publish",
+                &self.0.max_concurrent_publish.get(),
+            )
+            .field("frames_budget", &self.0.frames_budget.get())
+            .field("send_stall_timeout", &self.0.send_stall_timeout.get())
+            .field("content_length_policy", &self.0.content_length_policy.get())
+            .field("head_payload_policy", &self.0.head_payload_policy.get())
+            .field("violation_policy", &self.0.violation_policy.get())
+            .field("read_high_watermark", &self.0.read_high_watermark.get())
+            .field("max_buffered_frames", &self.0.max_buffered_frames.get())
+            .field("max_lifetime_streams", &self.0.max_lifetime_streams.get())
+            .field("qlog", &self.0.qlog.borrow().is_some())
+            .field("frame_observer", &self.0.frame_observer.borrow().is_some())
+            .field("capture", &self.0.capture.borrow().is_some())
+            .field("hooks", &*self.0.hooks.borrow())
+            .field("clock", &*self.0.clock.borrow())
             .field("settings", &self.0.settings.get())
             .finish()
     }
@@ -379,6 +1150,8 @@ impl fmt::Debug for ConfigInner {
             .field("window_sz_threshold", &self.window_sz_threshold.get())
             .field("reset_duration", &self.reset_duration.get())
             .field("reset_max", &self.reset_max.get())
+            .field("reset_max_frames", &self.reset_max_frames.get())
+            .field("reset_violation", &self.reset_violation.get())
             .field("connection_window_sz", &self.connection_window_sz.get())
             .field(
                 "connection_window_sz_threshold",
@@ -388,7 +1161,238 @@ impl fmt::Debug for ConfigInner {
                 "remote_max_concurrent_streams",
                 &self.remote_max_concurrent_streams.get(),
             )
+            .field("max_concurrent_publish", &self.max_concurrent_publish.get())
+            .field("frames_budget", &self.frames_budget.get())
+            .field("send_stall_timeout", &self.send_stall_timeout.get())
+            .field("content_length_policy", &self.content_length_policy.get())
+            .field("head_payload_policy", &self.head_payload_policy.get())
+            .field("violation_policy", &self.violation_policy.get())
+            .field("read_high_watermark", &self.read_high_watermark.get())
+            .field("max_buffered_frames", &self.max_buffered_frames.get())
+            .field("max_lifetime_streams", &self.max_lifetime_streams.get())
+            .field("qlog", &self.qlog.borrow().is_some())
+            .field("frame_observer", &self.frame_observer.borrow().is_some())
+            .field("capture", &self.capture.borrow().is_some())
+            .field("hooks", &*self.hooks.borrow())
+            .field("clock", &*self.clock.borrow())
             .field("settings", &self.settings.get())
             .finish()
     }
 }
+
+/// Tunable configuration values
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub(crate) struct ConfigValues {
+    pub(crate) server: bool,
+    pub(crate) initial_window_size: u32,
+    pub(crate) initial_connection_window_size: u32,
+    pub(crate) max_frame_size: u32,
+    pub(crate) max_header_list_size: u32,
+    pub(crate) max_concurrent_streams: u32,
+    pub(crate) max_header_continuation_frames: usize,
+    pub(crate) max_empty_data_frames: u32,
+    pub(crate) max_concurrent_publish: usize,
+    pub(crate) frames_budget: usize,
+    pub(crate) read_high_watermark: u32,
+    pub(crate) max_buffered_frames: usize,
+    pub(crate) max_lifetime_streams: u64,
+    pub(crate) max_concurrent_reset_streams: usize,
+    pub(crate) reset_stream_duration: u16,
+    pub(crate) reset_stream_max_frames: u32,
+    pub(crate) reset_stream_violation: ResetStreamViolation,
+    pub(crate) reset_stream_flow_control: bool,
+    pub(crate) enable_connect_protocol: bool,
+    pub(crate) handshake_timeout: u16,
+    pub(crate) disconnect_timeout: u16,
+    pub(crate) ping_timeout: u16,
+    pub(crate) ping_idle_only: bool,
+    pub(crate) send_stall_timeout: u16,
+    pub(crate) reset_stalled_streams: bool,
+    pub(crate) reset_streams_on_drop: bool,
+    pub(crate) lenient_pseudo_headers: bool,
+    pub(crate) strip_connection_headers: bool,
+    pub(crate) frame_notifications: bool,
+    pub(crate) stream_notifications: bool,
+    pub(crate) content_length_policy: ContentLengthPolicy,
+    pub(crate) head_payload_policy: HeadPayloadPolicy,
+    pub(crate) violation_policy: ViolationPolicy,
+}
+
+impl ConfigValues {
+    pub(crate) fn new(cfg: &Config) -> Self {
+        let inner = cfg.inner();
+        let settings = inner.settings.get();
+
+        ConfigValues {
+            server: cfg.is_server(),
+            initial_window_size: inner.window_sz.get(),
+            initial_connection_window_size: inner.connection_window_sz.get(),
+            max_frame_size: settings
+                .max_frame_size()
+                .unwrap_or(frame::DEFAULT_MAX_FRAME_SIZE),
+            max_header_list_size: settings
+                .max_header_list_size()
+                .unwrap_or(consts::DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE),
+            max_concurrent_streams: settings.max_concurrent_streams().unwrap_or(u32::MAX),
+            max_header_continuation_frames: inner.max_header_continuations.get(),
+            max_empty_data_frames: inner.max_empty_data_frames.get(),
+            max_concurrent_publish: inner.max_concurrent_publish.get(),
+            frames_budget: inner.frames_budget.get(),
+            read_high_watermark: inner.read_high_watermark.get(),
+            max_buffered_frames: inner.max_buffered_frames.get(),
+            max_lifetime_streams: inner.max_lifetime_streams.get(),
+            max_concurrent_reset_streams: inner.reset_max.get(),
+            reset_stream_duration: inner.reset_duration.get().as_secs() as u16,
+            reset_stream_max_frames: inner.reset_max_frames.get(),
+            reset_stream_violation: inner.reset_violation.get(),
+            reset_stream_flow_control: inner.reset_stream_flow_control(),
+            enable_connect_protocol: settings
+                .is_extended_connect_protocol_enabled()
+                .unwrap_or(false),
+            handshake_timeout: inner.handshake_timeout.get().0,
+            disconnect_timeout: inner.dispatcher_config.disconnect_timeout().0,
+            ping_timeout: inner.ping_timeout.get().0,
+            ping_idle_only: inner.ping_idle_only(),
+            send_stall_timeout: inner.send_stall_timeout.get().0,
+            reset_stalled_streams: inner.reset_stalled_streams(),
+            reset_streams_on_drop: inner.reset_streams_on_drop(),
+            lenient_pseudo_headers: inner.lenient_pseudo_headers(),
+            strip_connection_headers: inner.strip_connection_headers(),
+            frame_notifications: inner.frame_notifications(),
+            stream_notifications: inner.stream_notifications(),
+            content_length_policy: inner.content_length_policy.get(),
+            head_payload_policy: inner.head_payload_policy.get(),
+            violation_policy: inner.violation_policy.get(),
+        }
+    }
+
+    /// Apply changed values to configuration
+    pub(crate) fn apply(&self, cfg: &Config) -> Result<(), ConfigError> {
+        let cur = ConfigValues::new(cfg);
+
+        if self.initial_window_size != cur.initial_window_size {
+            cfg.try_initial_window_size(self.initial_window_size)?;
+        }
+        if self.initial_connection_window_size != cur.initial_connection_window_size {
+            cfg.try_initial_connection_window_size(self.initial_connection_window_size)?;
+        }
+        if self.max_frame_size != cur.max_frame_size {
+            cfg.try_max_frame_size(self.max_frame_size)?;
+        }
+        if self.max_header_list_size != cur.max_header_list_size {
+            cfg.max_header_list_size(self.max_header_list_size);
+        }
+        if self.max_concurrent_streams != cur.max_concurrent_streams {
+            cfg.max_concurrent_streams(self.max_concurrent_streams);
+        }
+        if self.enable_connect_protocol != cur.enable_connect_protocol {
+            let mut s = cfg.0.settings.get();
+            s.set_enable_connect_protocol(Some(self.enable_connect_protocol as u32));
+            cfg.set_settings(s);
+        }
+        if self.reset_stream_duration != cur.reset_stream_duration {
+            cfg.reset_stream_duration(Seconds(self.reset_stream_duration));
+        }
+        if self.disconnect_timeout != cur.disconnect_timeout {
+            cfg.disconnect_timeout(Seconds(self.disconnect_timeout));
+        }
+
+        cfg.max_header_continuation_frames(self.max_header_continuation_frames)
+            .max_empty_data_frames(self.max_empty_data_frames)
+            .max_concurrent_publish(self.max_concurrent_publish)
+            .frames_budget(self.frames_budget)
+            .read_high_watermark(self.read_high_watermark)
+            .max_buffered_frames(self.max_buffered_frames)
+            .max_lifetime_streams(self.max_lifetime_streams)
+            .max_concurrent_reset_streams(self.max_concurrent_reset_streams)
+            .reset_stream_max_frames(self.reset_stream_max_frames)
+            .reset_stream_violation(self.reset_stream_violation)
+            .reset_stream_flow_control(self.reset_stream_flow_control)
+            .handshake_timeout(Seconds(self.handshake_timeout))
+            .ping_timeout(Seconds(self.ping_timeout))
+            .ping_idle_only(self.ping_idle_only)
+            .send_stall_timeout(Seconds(self.send_stall_timeout))
+            .reset_stalled_streams(self.reset_stalled_streams)
+            .reset_streams_on_drop(self.reset_streams_on_drop)
+            .lenient_pseudo_headers(self.lenient_pseudo_headers)
+            .strip_connection_headers(self.strip_connection_headers)
+            .frame_notifications(self.frame_notifications)
+            .stream_notifications(self.stream_notifications)
+            .content_length_policy(self.content_length_policy)
+            .head_payload_policy(self.head_payload_policy)
+            .violation_policy(self.violation_policy);
+        Ok(())
+    }
+}
+
+/// Parse variable, unset variable keeps current value
+fn lookup_var<F, T>(lookup: &F, name: &'static str, val: &mut T) -> Result<(), ConfigError>
+where
+    F: Fn(&str) -> Option<String>,
+    T: FromStr,
+{
+    if let Some(s) = lookup(name) {
+        *val = s.trim().parse().map_err(|_| ConfigError::Env(name))?;
+    }
+    Ok(())
+}
+
+impl Default for ConfigValues {
+    fn default() -> Self {
+        ConfigValues::new(&Config::server())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Config {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigValues::new(self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Config {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = ConfigValues::deserialize(deserializer)?;
+        let cfg = Config::new(values.server);
+        values.apply(&cfg).map_err(serde::de::Error::custom)?;
+        Ok(cfg)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serde() {
+        let cfg: Config = serde_json::from_str(
+            r#"{"server": false, "max_frame_size": 32768, "ping_timeout": 30,
+                "violation_policy": "connection"}"#,
+        )
+        .unwrap();
+        assert!(!cfg.is_server());
+
+        let val = serde_json::to_value(&cfg).unwrap();
+        assert_eq!(val["max_frame_size"], 32768);
+        assert_eq!(val["ping_timeout"], 30);
+        assert_eq!(val["violation_policy"], "connection");
+        assert_eq!(val["handshake_timeout"], 5);
+
+        // hot reload
+        let server = Config::server();
+        server.update(&cfg).unwrap();
+        assert!(server.is_server());
+        assert_eq!(serde_json::to_value(&server).unwrap()["ping_timeout"], 30);
+
+        let res: Result<Config, _> = serde_json::from_str(r#"{"max_frame_size": 100}"#);
+        assert!(res.is_err());
+
+        let settings: frame::Settings =
+            serde_json::from_str(r#"{"max_concurrent_streams": 100}"#).unwrap();
+        assert_eq!(settings.max_concurrent_streams(), Some(100));
+        assert_eq!(settings.max_frame_size(), None);
+    }
+}
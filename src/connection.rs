@@ -1,17 +1,27 @@
-use std::{cell::Cell, cell::RefCell, fmt, mem, rc::Rc};
-use std::{collections::VecDeque, time::Instant};
+use std::{
+    cell::Cell, cell::RefCell, cmp, fmt, future::poll_fn, mem, rc::Rc, task::Context, task::Poll,
+};
+use std::{collections::VecDeque, time::Duration, time::Instant};
 
-use ntex_bytes::{ByteString, Bytes};
+use ntex_bytes::ByteString;
 use ntex_http::{HeaderMap, Method};
 use ntex_io::IoRef;
-use ntex_util::time::{self, now, sleep};
-use ntex_util::{channel::pool, future::Either, spawn, HashMap, HashSet};
-
-use crate::config::{Config, ConfigInner};
-use crate::error::{ConnectionError, OperationError, StreamError, StreamErrorInner};
+use ntex_util::channel::{oneshot, pool};
+use ntex_util::time;
+use ntex_util::{future::Either, spawn, task::LocalWaker, HashMap};
+
+use crate::clock::Clock;
+use crate::codec::Codec;
+use crate::config::{Config, ConfigInner, ResetStreamViolation, ViolationPolicy};
+use crate::control::{ControlHandle, RefuseCause, StreamEvent};
+use crate::dump::{ConnectionDump, NegotiatedSettings, StreamDump};
+use crate::error::{
+    ConnectionContext, ConnectionError, OperationError, StreamError, StreamErrorInner,
+};
 use crate::frame::{self, Headers, PseudoHeaders, StreamId, WindowSize, WindowUpdate};
+use crate::stats::{MetricsSnapshot, Rtt, Stats};
 use crate::stream::{Stream, StreamRef};
-use crate::{codec::Codec, consts, message::Message, window::Window};
+use crate::{consts, hpack, message::Message, qlog::QLog, window::Window};
 
 #[derive(Clone)]
 pub struct Connection(Rc<ConnectionState>);
@@ -20,22 +30,43 @@ pub(crate) struct RecvHalfConnection(Rc<ConnectionState>);
 
 bitflags::bitflags! {
     #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-    pub(crate) struct ConnectionFlags: u8 {
+    pub(crate) struct ConnectionFlags: u16 {
         const SETTINGS_PROCESSED      = 0b0000_0001;
         const DELAY_DROP_TASK_STARTED = 0b0000_0010;
+        const KA_EXTENDED             = 0b0000_0100;
         const DISCONNECT_WHEN_READY   = 0b0000_1000;
         const SECURE                  = 0b0001_0000;
         const STREAM_REFUSED          = 0b0010_0000;
         const KA_TIMER                = 0b0100_0000;
         const RECV_PONG               = 0b1000_0000;
+        const REMOTE_SETTINGS         = 0b0001_0000_0000;
+        const IDS_EXHAUSTED           = 0b0010_0000_0000;
+        const MONITOR_STARTED         = 0b0100_0000_0000;
+        const DEADLINE_UPDATED        = 0b1000_0000_0000;
     }
 }
 
+/// Max number of not acknowledged pings
+const MAX_PENDING_PINGS: usize = 16;
+
+struct PendingPing {
+    payload: u64,
+    sent: Instant,
+    tx: Option<oneshot::Sender<Duration>>,
+}
+
 struct ConnectionState {
     io: IoRef,
     codec: Codec,
     send_window: Cell<Window>,
     recv_window: Cell<Window>,
+    // Received bytes not yet accounted in recv window
+    recv_consumed: Cell<u32>,
+    // Received but not consumed payload
+    recv_buffered_bytes: Cell<u32>,
+    recv_buffered_frames: Cell<usize>,
+    recv_buffered_waker: LocalWaker,
+    remote_settings_waker: LocalWaker,
     next_stream_id: Cell<StreamId>,
     streams: RefCell<HashMap<StreamId, StreamRef>>,
     active_remote_streams: Cell<u32>,
@@ -43,23 +74,65 @@ struct ConnectionState {
     readiness: RefCell<VecDeque<pool::Sender<()>>>,
 
     rst_count: Cell<u32>,
+    empty_data_count: Cell<(Instant, u32)>,
     total_count: Cell<u32>,
+    local_total_count: Cell<u32>,
 
     // Local config
     local_config: Config,
     // Maximum number of locally initiated streams
     local_max_concurrent_streams: Cell<Option<u32>>,
+    // Maximum number of remote initiated streams
+    remote_max_concurrent_streams: Cell<Option<u32>>,
     // Initial window size of remote initiated streams
     remote_window_sz: Cell<WindowSize>,
+    // Initial receive window size of streams, acknowledged by peer
+    local_window_sz: Cell<WindowSize>,
+    // Local settings acknowledged by peer
+    local_settings: Cell<frame::Settings>,
+    // Sent local settings waiting for acknowledgement
+    pending_settings: RefCell<VecDeque<frame::Settings>>,
     // Max frame size
     remote_frame_size: Cell<u32>,
     // Locally reset streams
     local_reset_queue: RefCell<VecDeque<(StreamId, Instant)>>,
-    local_reset_ids: RefCell<HashSet<StreamId>>,
+    /// Locally reset streams and number of frames received after reset
+    local_reset_ids: RefCell<HashMap<StreamId, u32>>,
     // protocol level error
     error: Cell<Option<OperationError>>,
     // connection state flags
     flags: Cell<ConnectionFlags>,
+    // connection statistics
+    stats: RefCell<Stats>,
+    // qlog events emitter
+    qlog: Option<QLog>,
+    // sent pings waiting for acknowledgement
+    pings: RefCell<VecDeque<PendingPing>>,
+    ping_counter: Cell<u64>,
+    // round-trip time samples
+    rtt: RefCell<Rtt>,
+    // stream lifecycle events, delivered to control service
+    stream_events: RefCell<VecDeque<StreamEvent>>,
+    // last received frame
+    last_frame: Cell<Option<(frame::Kind, StreamId)>>,
+    // frame that triggered protocol error
+    failed_frame: Cell<Option<(frame::Kind, StreamId)>>,
+    // GOAWAY frame received from the peer
+    remote_go_away: RefCell<Option<frame::GoAway>>,
+    // settings received from the peer
+    remote_settings: Cell<frame::Settings>,
+    // connection start time
+    created: Instant,
+    // time source for timers
+    clock: Rc<dyn Clock>,
+    // starts stream monitor task, set by dispatcher
+    monitor: RefCell<Option<Box<dyn Fn()>>>,
+    // wakes stream monitor on stream deadline update
+    monitor_waker: LocalWaker,
+    // applies keep-alive timeout policy, set by dispatcher
+    keepalive: RefCell<Option<Box<dyn Fn()>>>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 impl Connection {
@@ -69,59 +142,112 @@ impl Connection {
             let _ = io.with_write_buf(|buf| buf.extend_from_slice(&consts::PREFACE));
         }
 
-        // send setting to the peer
-        let settings = config.0.settings.get();
-        log::debug!("Sending local settings {:?}", settings);
-        io.encode(settings.into(), &codec).unwrap();
-
         let mut recv_window = Window::new(frame::DEFAULT_INITIAL_WINDOW_SIZE as i32);
         let send_window = Window::new(frame::DEFAULT_INITIAL_WINDOW_SIZE as i32);
 
-        // update connection window size
-        if let Some(val) = recv_window.update(
+        // update connection window size, update frame is sent after settings
+        let window_update = recv_window.update(
             0,
             config.0.connection_window_sz.get(),
             config.0.connection_window_sz_threshold.get(),
-        ) {
-            log::debug!("Sending connection window update to {:?}", val);
-            io.encode(WindowUpdate::new(StreamId::CON, val).into(), &codec)
-                .unwrap();
-        };
+        );
 
         if let Some(max) = config.0.settings.get().max_header_list_size() {
             codec.set_recv_header_list_size(max as usize);
         }
         codec.set_max_header_continuations(config.0.max_header_continuations.get());
+        if let Some(f) = config.0.frame_observer.borrow().clone() {
+            codec.set_observer(f);
+        }
+        if let Some(w) = config.0.capture.borrow().as_ref().and_then(|f| f(io.tag())) {
+            codec.set_capture_writer(w);
+        }
 
+        let clock = config.0.clock();
+        let now = clock.now();
         let remote_frame_size = Cell::new(codec.send_frame_size());
+        let qlog = config
+            .0
+            .qlog
+            .borrow()
+            .as_ref()
+            .and_then(|f| f(io.tag()))
+            .map(|w| QLog::new(w, io.tag(), config.is_server(), clock.clone()));
 
         let state = Rc::new(ConnectionState {
+            clock,
+            monitor: RefCell::new(None),
+            monitor_waker: LocalWaker::new(),
+            keepalive: RefCell::new(None),
             codec,
             remote_frame_size,
             io: io.clone(),
             send_window: Cell::new(send_window),
             recv_window: Cell::new(recv_window),
+            recv_consumed: Cell::new(0),
+            recv_buffered_bytes: Cell::new(0),
+            recv_buffered_frames: Cell::new(0),
+            recv_buffered_waker: LocalWaker::new(),
+            remote_settings_waker: LocalWaker::new(),
             streams: RefCell::new(HashMap::default()),
             active_remote_streams: Cell::new(0),
             active_local_streams: Cell::new(0),
             rst_count: Cell::new(0),
+            empty_data_count: Cell::new((now, 0)),
             total_count: Cell::new(0),
+            local_total_count: Cell::new(0),
             readiness: RefCell::new(VecDeque::new()),
             next_stream_id: Cell::new(StreamId::new(1)),
+            remote_max_concurrent_streams: Cell::new(config.0.remote_max_concurrent_streams.get()),
+            local_settings: Cell::new(config.0.settings.get()),
+            pending_settings: RefCell::new(VecDeque::from([config.0.settings.get()])),
+            local_window_sz: Cell::new(frame::DEFAULT_INITIAL_WINDOW_SIZE),
             local_config: config,
             local_max_concurrent_streams: Cell::new(None),
-            local_reset_ids: RefCell::new(HashSet::default()),
+            local_reset_ids: RefCell::new(HashMap::default()),
             local_reset_queue: RefCell::new(VecDeque::new()),
             remote_window_sz: Cell::new(frame::DEFAULT_INITIAL_WINDOW_SIZE),
             error: Cell::new(None),
+            stats: RefCell::new(Stats::default()),
+            qlog,
+            pings: RefCell::new(VecDeque::new()),
+            ping_counter: Cell::new(0),
+            rtt: RefCell::new(Rtt::default()),
+            stream_events: RefCell::new(VecDeque::new()),
+            last_frame: Cell::new(None),
+            failed_frame: Cell::new(None),
+            remote_go_away: RefCell::new(None),
+            remote_settings: Cell::new(frame::Settings::default()),
+            created: now,
             flags: Cell::new(if secure {
                 ConnectionFlags::SECURE
             } else {
                 ConnectionFlags::empty()
             }),
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "h2::connection",
+                tag = io.tag(),
+                peer = ?io.query::<ntex_io::types::PeerAddr>().get().map(|addr| addr.0),
+                alpn = ?io.query::<ntex_io::types::HttpProtocol>().get(),
+                secure,
+            ),
         });
         let con = Connection(state);
 
+        // send settings to the peer
+        let settings = con.0.local_config.0.settings.get();
+        log::debug!("{}: Sending local settings {:?}", con.tag(), settings);
+        con.encode(settings);
+        if let Some(val) = window_update {
+            log::debug!(
+                "{}: Sending connection window update to {:?}",
+                con.tag(),
+                val
+            );
+            con.encode(WindowUpdate::new(StreamId::CON, val));
+        }
+
         // start ping/pong
         if con.0.local_config.0.ping_timeout.get().non_zero() {
             let _ = spawn(ping(
@@ -150,10 +276,16 @@ impl Connection {
         &self.0.local_config.0
     }
 
+    /// Time source for connection timers
+    pub(crate) fn clock(&self) -> &dyn Clock {
+        &*self.0.clock
+    }
+
     pub(crate) fn flags(&self) -> ConnectionFlags {
         self.0.flags.get()
     }
 
+    /// Close connection, write buffer is flushed before io stream shutdown
     pub(crate) fn close(&self) {
         self.0.io.close()
     }
@@ -182,11 +314,166 @@ impl Connection {
         self.0.flags.set(flags);
     }
 
+    #[cfg(feature = "tracing")]
+    pub(crate) fn span(&self) -> &tracing::Span {
+        &self.0.span
+    }
+
     pub(crate) fn encode<T>(&self, item: T)
     where
         frame::Frame: From<T>,
     {
-        let _ = self.0.io.encode(item.into(), &self.0.codec);
+        self.0.encode(item.into())
+    }
+
+    /// Connection statistics
+    pub fn stats(&self) -> Stats {
+        self.0.stats()
+    }
+
+    /// Send PING frame and wait for acknowledgement.
+    ///
+    /// Returns measured round-trip time, the value is also
+    /// accounted in connection's rtt statistics.
+    pub async fn ping(&self) -> Result<Duration, OperationError> {
+        self.check_error()?;
+
+        let (tx, rx) = oneshot::channel();
+        self.send_ping(Some(tx));
+        rx.await.map_err(|_| OperationError::Disconnected)
+    }
+
+    fn send_ping(&self, tx: Option<oneshot::Sender<Duration>>) {
+        let payload = self.0.ping_counter.get() + 1;
+        self.0.ping_counter.set(payload);
+
+        let mut pings = self.0.pings.borrow_mut();
+        if pings.len() == MAX_PENDING_PINGS {
+            pings.pop_front();
+        }
+        pings.push_back(PendingPing {
+            payload,
+            tx,
+            sent: self.0.clock.now(),
+        });
+        drop(pings);
+
+        self.encode(frame::Ping::new(payload.to_be_bytes()));
+    }
+
+    /// Handle for sending control frames
+    pub fn control_handle(&self) -> ControlHandle {
+        ControlHandle::new(self.clone())
+    }
+
+    /// Connection metrics snapshot
+    pub fn metrics(&self) -> MetricsSnapshot {
+        MetricsSnapshot::new(
+            self.0.stats(),
+            self.0.active_local_streams.get(),
+            self.0.active_remote_streams.get(),
+            self.0.send_window.get().window_size(),
+            self.0.recv_window.get().window_size(),
+        )
+    }
+
+    /// Report of connection and active streams state
+    pub fn dump(&self) -> ConnectionDump {
+        let error = self.0.error.take();
+        self.0.error.set(error.clone());
+
+        ConnectionDump {
+            error,
+            streams: self.streams(),
+            tag: self.tag(),
+            send_window: self.0.send_window.get().window_size(),
+            recv_window: self.0.recv_window.get().window_size(),
+            recv_buffered_bytes: self.0.recv_buffered_bytes.get(),
+            recv_buffered_frames: self.0.recv_buffered_frames.get(),
+            active_local_streams: self.0.active_local_streams.get(),
+            active_remote_streams: self.0.active_remote_streams.get(),
+            local_reset_streams: self.0.local_reset_ids.borrow().len(),
+        }
+    }
+
+    /// Snapshot of hpack encoder and decoder dynamic tables
+    pub fn hpack_tables(&self) -> (hpack::TableState, hpack::TableState) {
+        (self.0.codec.encoder_table(), self.0.codec.decoder_table())
+    }
+
+    /// Settings received from the peer.
+    ///
+    /// Contains values from all received SETTINGS frames,
+    /// values that peer did not send are `None`.
+    pub fn remote_settings(&self) -> frame::Settings {
+        self.0.remote_settings.get()
+    }
+
+    /// Local, remote and effective settings of the connection.
+    ///
+    /// Local stream window is in use only after local settings
+    /// are acknowledged by the peer.
+    pub fn negotiated_settings(&self) -> NegotiatedSettings {
+        let local = self.0.local_settings.get();
+        let remote = self.0.remote_settings.get();
+        let local_acked = self.settings_processed();
+        let recv_window_size = self.0.local_window_sz.get();
+        let table_size = frame::DEFAULT_SETTINGS_HEADER_TABLE_SIZE as u32;
+
+        NegotiatedSettings {
+            local,
+            remote,
+            local_acked,
+            recv_window_size,
+            tag: self.tag(),
+            remote_received: self.flags().contains(ConnectionFlags::REMOTE_SETTINGS),
+            send_frame_size: self.0.codec.send_frame_size(),
+            recv_frame_size: self.0.codec.recv_frame_size(),
+            send_window_size: self.0.remote_window_sz.get(),
+            send_max_streams: self.0.local_max_concurrent_streams.get(),
+            recv_max_streams: local.max_concurrent_streams(),
+            send_header_table_size: remote.header_table_size().unwrap_or(table_size),
+            recv_header_table_size: local.header_table_size().unwrap_or(table_size),
+            send_header_list_size: remote.max_header_list_size(),
+            recv_header_list_size: local.max_header_list_size(),
+        }
+    }
+
+    /// Summary of active streams, ordered by stream id
+    pub fn streams(&self) -> Vec<StreamDump> {
+        let mut streams: Vec<_> = self.0.streams.borrow().values().map(|s| s.dump()).collect();
+        streams.sort_by_key(|s| s.id());
+        streams
+    }
+
+    /// Get active stream by id, for example to reset it
+    pub fn stream(&self, id: StreamId) -> Option<StreamRef> {
+        self.0.streams.borrow().get(&id).cloned()
+    }
+
+    /// Connection failure context
+    pub fn context(&self) -> ConnectionContext {
+        self.0.context()
+    }
+
+    /// Convert protocol error to GOAWAY frame
+    pub(crate) fn to_goaway(&self, err: &ConnectionError) -> frame::GoAway {
+        let frm = err
+            .to_goaway()
+            .set_last_stream_id(self.0.last_remote_stream_id());
+        self.0.local_config.0.hooks.borrow().map_goaway(err, frm)
+    }
+
+    fn stream_event(&self, ev: StreamEvent) {
+        self.0.stream_event(ev)
+    }
+
+    pub(crate) fn qlog(&self) -> Option<&QLog> {
+        self.0.qlog.as_ref()
+    }
+
+    pub(crate) fn update_stats<F: FnOnce(&mut Stats)>(&self, f: F) {
+        f(&mut self.0.stats.borrow_mut())
     }
 
     pub(crate) fn check_error(&self) -> Result<(), OperationError> {
@@ -199,18 +486,62 @@ impl Connection {
     }
 
     /// added new capacity, update recevice window size
+    ///
+    /// Window update is deferred while read buffer contains complete frames,
+    /// all received bytes are accounted at once.
     pub(crate) fn add_capacity(&self, size: u32) {
-        let mut recv_window = self.0.recv_window.get().dec(size);
+        self.0.recv_consumed.set(self.0.recv_consumed.get() + size);
+        self.0
+            .recv_buffered_bytes
+            .set(self.0.recv_buffered_bytes.get() + size);
 
-        // update connection window size
-        if let Some(val) = recv_window.update(
-            0,
-            self.0.local_config.0.connection_window_sz.get(),
-            self.0.local_config.0.connection_window_sz_threshold.get(),
-        ) {
-            self.encode(WindowUpdate::new(StreamId::CON, val));
+        if !self.0.has_buffered_frame() {
+            self.0.flush_recv_window();
+        }
+    }
+
+    /// Account received payload frame
+    pub(crate) fn buffer_frame(&self) {
+        self.0
+            .recv_buffered_frames
+            .set(self.0.recv_buffered_frames.get() + 1);
+    }
+
+    /// Payload frame is fully consumed
+    pub(crate) fn release_frame(&self) {
+        self.0
+            .recv_buffered_frames
+            .set(self.0.recv_buffered_frames.get() - 1);
+        self.0.recv_buffered_waker.wake();
+    }
+
+    /// Payload bytes are consumed
+    pub(crate) fn release_bytes(&self, size: u32) {
+        self.0
+            .recv_buffered_bytes
+            .set(self.0.recv_buffered_bytes.get() - size);
+        self.0.recv_buffered_waker.wake();
+    }
+
+    /// Check if connection can receive more frames
+    pub(crate) fn poll_recv_buffer(&self, cx: &Context<'_>) -> Poll<()> {
+        let max_bytes = self.0.local_config.0.read_high_watermark.get();
+        let max_frames = self.0.local_config.0.max_buffered_frames.get();
+
+        if (max_bytes != 0 && self.0.recv_buffered_bytes.get() >= max_bytes)
+            || (max_frames != 0 && self.0.recv_buffered_frames.get() >= max_frames)
+        {
+            log::trace!(
+                "{}: Read buffer is full, bytes: {} frames: {}",
+                self.tag(),
+                self.0.recv_buffered_bytes.get(),
+                self.0.recv_buffered_frames.get()
+            );
+            self.0.recv_buffered_waker.register(cx.waker());
+            Poll::Pending
+        } else {
+            Poll::Ready(())
         }
-        self.0.recv_window.set(recv_window);
     }
 
     pub(crate) fn remote_window_size(&self) -> WindowSize {
@@ -221,14 +552,46 @@ impl Connection {
         self.0.remote_frame_size.get() as usize
     }
 
+    /// Wait for first SETTINGS frame from the peer
+    pub(crate) async fn remote_settings_received(&self) -> Result<(), OperationError> {
+        poll_fn(|cx| {
+            self.check_error()?;
+            if self.flags().contains(ConnectionFlags::REMOTE_SETTINGS) {
+                Poll::Ready(Ok(()))
+            } else {
+                self.0.remote_settings_waker.register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
     pub(crate) fn settings_processed(&self) -> bool {
         self.flags().contains(ConnectionFlags::SETTINGS_PROCESSED)
     }
 
+    /// Initial receive window size of new streams
+    pub(crate) fn local_window_size(&self) -> WindowSize {
+        self.0.local_window_sz.get()
+    }
+
+    /// Send local SETTINGS frame, settings are applied after acknowledgement
+    pub(crate) fn send_settings(&self, settings: frame::Settings) {
+        self.0.pending_settings.borrow_mut().push_back(settings);
+        self.encode(settings);
+    }
+
     pub(crate) fn max_streams(&self) -> Option<u32> {
         self.0.local_max_concurrent_streams.get()
     }
 
+    /// Active streams, ordered by stream id
+    pub(crate) fn stream_refs(&self) -> Vec<StreamRef> {
+        let mut streams: Vec<_> = self.0.streams.borrow().values().cloned().collect();
+        streams.sort_by_key(|s| s.id());
+        streams
+    }
+
     pub(crate) fn active_streams(&self) -> u32 {
         if self.0.local_max_concurrent_streams.get().is_some() {
             self.0.active_local_streams.get()
@@ -238,7 +601,12 @@ impl Connection {
     }
 
     pub(crate) fn can_create_new_stream(&self) -> bool {
-        if let Some(max) = self.0.local_max_concurrent_streams.get() {
+        if self
+            .flags()
+            .contains(ConnectionFlags::DISCONNECT_WHEN_READY)
+        {
+            false
+        } else if let Some(max) = self.0.local_max_concurrent_streams.get() {
             self.0.active_local_streams.get() < max
         } else {
             true
@@ -248,6 +616,12 @@ impl Connection {
     pub(crate) async fn ready(&self) -> Result<(), OperationError> {
         loop {
             self.check_error()?;
+            if self
+                .flags()
+                .contains(ConnectionFlags::DISCONNECT_WHEN_READY)
+            {
+                return Err(OperationError::Disconnected);
+            }
             return if let Some(max) = self.0.local_max_concurrent_streams.get() {
                 if self.0.active_local_streams.get() < max {
                     Ok(())
@@ -265,6 +639,35 @@ impl Connection {
         }
     }
 
+    pub(crate) fn is_keepalive_extended(&self) -> bool {
+        self.flags().contains(ConnectionFlags::KA_EXTENDED)
+    }
+
+    /// Set keep-alive timeout handler
+    pub(crate) fn set_keepalive_handler<F: Fn() + 'static>(&self, f: F) {
+        *self.0.keepalive.borrow_mut() = Some(Box::new(f));
+    }
+
+    /// Notify io about keep-alive timeout
+    pub(crate) fn notify_timeout(&self) {
+        self.0.io.notify_timeout();
+    }
+
+    /// Restart keep-alive ping/pong task, deadline could be extended only once
+    pub(crate) fn extend_keepalive(&self) {
+        let timeout = self.0.local_config.0.ping_timeout.get();
+        if timeout.non_zero() && !self.is_keepalive_extended() {
+            self.set_flags(ConnectionFlags::KA_EXTENDED);
+            let _ = spawn(ping(self.clone(), timeout, self.0.io.clone()));
+        }
+    }
+
+    /// Check if connection is closing, new streams are not allowed
+    pub(crate) fn is_closing(&self) -> bool {
+        self.flags()
+            .contains(ConnectionFlags::DISCONNECT_WHEN_READY)
+    }
+
     pub(crate) fn disconnect_when_ready(&self) {
         if self.0.streams.borrow().is_empty() {
             log::trace!("{}: All streams are closed, disconnecting", self.tag());
@@ -283,10 +686,14 @@ impl Connection {
         authority: ByteString,
         method: Method,
         path: ByteString,
+        protocol: Option<frame::Protocol>,
         headers: HeaderMap,
         eof: bool,
     ) -> Result<Stream, OperationError> {
         self.check_error()?;
+        if self.flags().contains(ConnectionFlags::IDS_EXHAUSTED) {
+            return Err(OperationError::OverflowedStreamId);
+        }
 
         if !self.can_create_new_stream() {
             log::warn!(
@@ -296,18 +703,25 @@ impl Connection {
             self.ready().await?
         }
 
-        let stream = {
+        let (stream, exhausted) = {
             let id = self.0.next_stream_id.get();
             let stream = StreamRef::new(id, false, self.clone());
             self.0.streams.borrow_mut().insert(id, stream.clone());
+            self.stream_event(StreamEvent::Opened(stream.clone()));
             self.0
                 .active_local_streams
                 .set(self.0.active_local_streams.get() + 1);
-            self.0.next_stream_id.set(
-                id.next_id()
-                    .map_err(|_| OperationError::OverflowedStreamId)?,
-            );
-            stream
+            self.0
+                .local_total_count
+                .set(self.0.local_total_count.get() + 1);
+            if let Ok(next_id) = id.next_id() {
+                self.0.next_stream_id.set(next_id);
+                (stream, false)
+            } else {
+                self.set_flags(ConnectionFlags::IDS_EXHAUSTED);
+                self.stream_event(StreamEvent::IdsExhausted(id));
+                (stream, true)
+            }
         };
 
         let pseudo = PseudoHeaders {
@@ -319,12 +733,158 @@ impl Connection {
             method: Some(method),
             authority: Some(authority),
             path: Some(path),
+            protocol,
             ..Default::default()
         };
         stream.send_headers(Headers::new(stream.id(), pseudo, headers, eof));
+
+        // GOAWAY must follow HEADERS of the last stream
+        if exhausted {
+            log::debug!(
+                "{}: Stream id space is exhausted, sending GOAWAY",
+                self.tag()
+            );
+            self.go_away_when_ready();
+        }
+        self.check_lifetime_streams();
         Ok(stream.into_stream())
     }
 
+    /// Start graceful shutdown if lifetime streams limit is reached
+    fn check_lifetime_streams(&self) {
+        let max = self.0.local_config.0.max_lifetime_streams.get();
+        // server limits streams opened by the peer, client limits own requests
+        let opened = if self.0.local_config.is_server() {
+            self.0.total_count.get()
+        } else {
+            self.0.local_total_count.get()
+        };
+        if max != 0
+            && !self
+                .flags()
+                .contains(ConnectionFlags::DISCONNECT_WHEN_READY)
+            && u64::from(opened) >= max
+        {
+            log::debug!(
+                "{}: Lifetime streams limit {} is reached, sending GOAWAY",
+                self.tag(),
+                max
+            );
+            self.go_away_when_ready();
+        }
+    }
+
+    /// Send GOAWAY frame and close connection when all streams are complete
+    fn go_away_when_ready(&self) {
+        if !self
+            .flags()
+            .contains(ConnectionFlags::DISCONNECT_WHEN_READY)
+        {
+            self.go_away(frame::GoAway::new(frame::Reason::NO_ERROR));
+        }
+    }
+
+    /// Send GOAWAY frame with last processed stream id.
+    ///
+    /// New streams are refused, connection is closed when all streams are complete.
+    pub(crate) fn go_away(&self, frm: frame::GoAway) {
+        self.encode(frm.set_last_stream_id(self.0.last_remote_stream_id()));
+        if !self
+            .flags()
+            .contains(ConnectionFlags::DISCONNECT_WHEN_READY)
+        {
+            self.disconnect_when_ready();
+        }
+    }
+
+    /// Streams blocked by flow control for longer than timeout
+    pub(crate) fn stalled_streams(&self, timeout: Duration) -> Vec<(StreamRef, Duration)> {
+        self.0
+            .streams
+            .borrow()
+            .values()
+            .filter_map(|stream| stream.check_stalled(timeout).map(|d| (stream.clone(), d)))
+            .collect()
+    }
+
+    /// Set stream monitor starter
+    pub(crate) fn set_monitor<F: Fn() + 'static>(&self, f: F) {
+        *self.0.monitor.borrow_mut() = Some(Box::new(f));
+    }
+
+    /// Start stream monitor if it is not running
+    pub(crate) fn start_monitor(&self) {
+        if !self
+            .0
+            .flags
+            .get()
+            .contains(ConnectionFlags::MONITOR_STARTED)
+        {
+            if let Some(ref start) = *self.0.monitor.borrow() {
+                self.set_flags(ConnectionFlags::MONITOR_STARTED);
+                start();
+            }
+        }
+    }
+
+    /// Stream monitor is stopped
+    pub(crate) fn monitor_stopped(&self) {
+        self.unset_flags(ConnectionFlags::MONITOR_STARTED | ConnectionFlags::DEADLINE_UPDATED);
+    }
+
+    /// Stream deadline is updated, monitor must re-schedule its timer
+    pub(crate) fn deadline_updated(&self) {
+        if self
+            .0
+            .flags
+            .get()
+            .contains(ConnectionFlags::MONITOR_STARTED)
+        {
+            self.set_flags(ConnectionFlags::DEADLINE_UPDATED);
+            self.0.monitor_waker.wake();
+        } else {
+            self.start_monitor();
+        }
+    }
+
+    /// Check if stream deadline is updated
+    pub(crate) fn poll_deadline_updated(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self
+            .0
+            .flags
+            .get()
+            .contains(ConnectionFlags::DEADLINE_UPDATED)
+        {
+            self.unset_flags(ConnectionFlags::DEADLINE_UPDATED);
+            Poll::Ready(())
+        } else {
+            self.0.monitor_waker.register(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /// Earliest stream deadline
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.0
+            .streams
+            .borrow()
+            .values()
+            .filter_map(|stream| stream.deadline())
+            .min()
+    }
+
+    /// Streams with elapsed deadline
+    pub(crate) fn expired_streams(&self) -> Vec<StreamRef> {
+        let now = self.0.clock.now();
+        self.0
+            .streams
+            .borrow()
+            .values()
+            .filter(|stream| stream.check_deadline(now))
+            .cloned()
+            .collect()
+    }
+
     pub(crate) fn rst_stream(&self, id: StreamId, reason: frame::Reason) {
         let stream = self.0.streams.borrow_mut().get(&id).cloned();
         if let Some(stream) = stream {
@@ -342,6 +902,7 @@ impl Connection {
                     id,
                     stream.is_remote()
                 );
+                self.stream_event(StreamEvent::Closed(id, stream.reset_reason()));
                 if stream.is_remote() {
                     self.0
                         .active_remote_streams
@@ -381,8 +942,11 @@ impl Connection {
                 ids.remove(&id);
             }
         }
-        ids.insert(id);
-        queue.push_back((id, now() + self.0.local_config.0.reset_duration.get()));
+        ids.insert(id, 0);
+        queue.push_back((
+            id,
+            self.0.clock.now() + self.0.local_config.0.reset_duration.get(),
+        ));
         if !flags.contains(ConnectionFlags::DELAY_DROP_TASK_STARTED) {
             let _ = spawn(delay_drop_task(self.clone()));
         }
@@ -393,11 +957,123 @@ impl Connection {
     }
 }
 
+impl ConnectionState {
+    /// Queue event for control service
+    fn stream_event(&self, ev: StreamEvent) {
+        // lifecycle events are delivered only if enabled
+        if matches!(
+            ev,
+            StreamEvent::Opened(_) | StreamEvent::Closed(..) | StreamEvent::ResetByPeer(..)
+        ) && !self.local_config.0.stream_notifications()
+        {
+            return;
+        }
+        self.stream_events.borrow_mut().push_back(ev);
+    }
+
+    /// Last processed stream initiated by the peer
+    fn last_remote_stream_id(&self) -> StreamId {
+        if self.local_config.is_server() {
+            self.next_stream_id.get()
+        } else {
+            StreamId::CON
+        }
+    }
+
+    /// Check if read buffer contains complete frame
+    fn has_buffered_frame(&self) -> bool {
+        self.io.with_read_buf(|buf| {
+            if buf.len() >= frame::HEADER_LEN {
+                let len = ((buf[0] as usize) << 16) | ((buf[1] as usize) << 8) | (buf[2] as usize);
+                buf.len() >= frame::HEADER_LEN + len
+            } else {
+                false
+            }
+        })
+    }
+
+    fn encode(&self, item: frame::Frame) {
+        self.stats.borrow_mut().frame_sent(&item);
+        if let Some(ref qlog) = self.qlog {
+            qlog.frame_sent(&item);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &self.span, frame = ?item, "send frame");
+
+        let _ = self.io.encode(item, &self.codec);
+    }
+
+    fn context(&self) -> ConnectionContext {
+        let error = if let Some(err) = self.error.take() {
+            let result = match err {
+                OperationError::Connection(ref err) => Some(*err),
+                _ => None,
+            };
+            self.error.set(Some(err));
+            result
+        } else {
+            None
+        };
+
+        let (bytes_sent, bytes_received) = self.codec.frame_bytes();
+
+        ConnectionContext {
+            error,
+            bytes_sent,
+            bytes_received,
+            frame: self.failed_frame.get(),
+            go_away: self.remote_go_away.borrow().clone(),
+            stats: self.stats(),
+            duration: self.clock.now().saturating_duration_since(self.created),
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let mut stats = *self.stats.borrow();
+        (stats.header_bytes_sent, stats.header_bytes_received) = self.codec.header_bytes();
+        stats.rtt = self.rtt.borrow().stats();
+        stats
+    }
+
+    /// Update connection receive window for all accounted bytes
+    fn flush_recv_window(&self) {
+        let size = self.recv_consumed.take();
+        if size > 0 {
+            let mut recv_window = self.recv_window.get().dec(size);
+
+            // update connection window size
+            if let Some(val) = recv_window.update(
+                0,
+                self.local_config.0.connection_window_sz.get(),
+                self.local_config.0.connection_window_sz_threshold.get(),
+            ) {
+                self.encode(WindowUpdate::new(StreamId::CON, val).into());
+            }
+            if let Some(ref qlog) = self.qlog {
+                qlog.window_updated(StreamId::CON, "local", recv_window.window_size);
+            }
+            self.recv_window.set(recv_window);
+        }
+    }
+}
+
 impl RecvHalfConnection {
     pub(crate) fn tag(&self) -> &'static str {
         self.0.io.tag()
     }
 
+    #[cfg(feature = "tracing")]
+    pub(crate) fn span(&self) -> &tracing::Span {
+        &self.0.span
+    }
+
+    /// Flush deferred window updates if there is no buffered frames
+    pub(crate) fn flush_recv_window(&self) {
+        if !self.0.has_buffered_frame() {
+            self.0.flush_recv_window();
+        }
+    }
+
     fn query(&self, id: StreamId) -> Option<StreamRef> {
         self.0.streams.borrow_mut().get(&id).cloned()
     }
@@ -420,7 +1096,35 @@ impl RecvHalfConnection {
     where
         frame::Frame: From<T>,
     {
-        let _ = self.0.io.encode(item.into(), &self.0.codec);
+        self.0.encode(item.into())
+    }
+
+    /// Take pending stream lifecycle events
+    pub(crate) fn take_stream_events(&self) -> VecDeque<StreamEvent> {
+        mem::take(&mut *self.0.stream_events.borrow_mut())
+    }
+
+    /// Account stream level protocol violation
+    pub(crate) fn stream_error(&self, err: &StreamError) {
+        self.0.stats.borrow_mut().violations.stream_error(err);
+    }
+
+    /// Account received frame
+    pub(crate) fn frame_received(&self, frame: &frame::Frame) {
+        self.0.last_frame.set(Some(frame_info(frame)));
+        self.0.stats.borrow_mut().frame_received(frame);
+        if let Some(ref qlog) = self.0.qlog {
+            qlog.frame_received(frame);
+        }
+
+        // callbacks could change config, release borrow first
+        let hooks = {
+            let hooks = self.0.local_config.0.hooks.borrow();
+            (!hooks.is_empty()).then(|| hooks.clone())
+        };
+        if let Some(hooks) = hooks {
+            hooks.frame_received(frame);
+        }
     }
 
     pub(crate) fn recv_headers(
@@ -435,12 +1139,22 @@ impl RecvHalfConnection {
             )));
         }
 
+        let malformed = frm
+            .malformed_pseudo()
+            .filter(|_| !self.0.local_config.0.lenient_pseudo_headers());
+
         if let Some(stream) = self.query(id) {
+            if let Some(cause) = malformed {
+                return Err(Either::Right(StreamErrorInner::new(
+                    stream,
+                    StreamError::MalformedPseudo(cause),
+                )));
+            }
             match stream.recv_headers(frm) {
                 Ok(item) => Ok(item.map(move |msg| (stream, msg))),
-                Err(kind) => Err(Either::Right(StreamErrorInner::new(stream, kind))),
+                Err(kind) => Err(self.escalate(stream, kind, "HEADERS frame received")),
             }
-        } else if self.0.local_reset_ids.borrow().contains(&id) {
+        } else if self.recv_reset_stream_frame(id)?.is_some() {
             self.encode(frame::Reset::new(id, frame::Reason::STREAM_CLOSED));
             Ok(None)
         } else if id < self.0.next_stream_id.get() {
@@ -457,10 +1171,12 @@ impl RecvHalfConnection {
             {
                 self.encode(frame::Reset::new(id, frame::Reason::REFUSED_STREAM));
                 self.set_flags(ConnectionFlags::STREAM_REFUSED);
+                self.0
+                    .stream_event(StreamEvent::Refused(id, RefuseCause::Disconnecting));
                 return Ok(None);
             }
 
-            if let Some(max) = self.0.local_config.0.remote_max_concurrent_streams.get() {
+            if let Some(max) = self.0.remote_max_concurrent_streams.get() {
                 if self.0.active_remote_streams.get() >= max {
                     // check if client opened more streams than allowed
                     // in that case close connection
@@ -469,13 +1185,17 @@ impl RecvHalfConnection {
                     } else {
                         self.encode(frame::Reset::new(id, frame::Reason::REFUSED_STREAM));
                         self.set_flags(ConnectionFlags::STREAM_REFUSED);
+                        let ev = StreamEvent::Refused(id, RefuseCause::MaxConcurrentStreams);
+                        self.0.stream_event(ev);
                         Ok(None)
                     };
                 }
             }
 
             let pseudo = frm.pseudo();
-            if pseudo
+            if let Some(cause) = malformed {
+                Err(Either::Left(ConnectionError::MalformedPseudo(cause)))
+            } else if pseudo
                 .path
                 .as_ref()
                 .map(|s| s.as_str())
@@ -495,14 +1215,33 @@ impl RecvHalfConnection {
                 Err(Either::Left(ConnectionError::MissingPseudo("scheme")))
             } else if frm.pseudo().status.is_some() {
                 Err(Either::Left(ConnectionError::UnexpectedPseudo("scheme")))
+            } else if pseudo.protocol.is_some() && pseudo.method != Some(Method::CONNECT) {
+                Err(Either::Left(ConnectionError::ExtendedConnect(
+                    ":protocol pseudo header in non-CONNECT request",
+                )))
+            } else if pseudo.protocol.is_some()
+                && self
+                    .0
+                    .local_config
+                    .0
+                    .settings
+                    .get()
+                    .is_extended_connect_protocol_enabled()
+                    != Some(true)
+            {
+                Err(Either::Left(ConnectionError::ExtendedConnect(
+                    "SETTINGS_ENABLE_CONNECT_PROTOCOL is not advertised",
+                )))
             } else {
                 let stream = StreamRef::new(id, true, Connection(self.0.clone()));
                 self.0.next_stream_id.set(id);
                 self.0.total_count.set(self.0.total_count.get() + 1);
                 self.0.streams.borrow_mut().insert(id, stream.clone());
+                self.0.stream_event(StreamEvent::Opened(stream.clone()));
                 self.0
                     .active_remote_streams
                     .set(self.0.active_remote_streams.get() + 1);
+                Connection(self.0.clone()).check_lifetime_streams();
                 match stream.recv_headers(frm) {
                     Ok(item) => Ok(item.map(move |msg| (stream, msg))),
                     Err(kind) => Err(Either::Right(StreamErrorInner::new(stream, kind))),
@@ -515,12 +1254,24 @@ impl RecvHalfConnection {
         &self,
         frm: frame::Data,
     ) -> Result<Option<(StreamRef, Message)>, Either<ConnectionError, StreamErrorInner>> {
+        if frm.payload().is_empty() && !frm.is_end_stream() {
+            self.update_empty_data_count()?;
+        }
+
         if let Some(stream) = self.query(frm.stream_id()) {
             match stream.recv_data(frm) {
                 Ok(item) => Ok(item.map(move |msg| (stream, msg))),
-                Err(kind) => Err(Either::Right(StreamErrorInner::new(stream, kind))),
+                Err(kind) => Err(self.escalate(stream, kind, "DATA frame received")),
+            }
+        } else if self.recv_reset_stream_frame(frm.stream_id())?.is_some() {
+            // payload of reset stream is dropped, replenish connection window
+            if self.0.local_config.0.reset_stream_flow_control() {
+                let size = frm.payload().len() as u32;
+                self.0.recv_consumed.set(self.0.recv_consumed.get() + size);
+                if !self.0.has_buffered_frame() {
+                    self.0.flush_recv_window();
+                }
             }
-        } else if self.0.local_reset_ids.borrow().contains(&frm.stream_id()) {
             self.encode(frame::Reset::new(
                 frm.stream_id(),
                 frame::Reason::STREAM_CLOSED,
@@ -540,14 +1291,33 @@ impl RecvHalfConnection {
         log::trace!("processing incoming settings: {:#?}", settings);
 
         if settings.is_ack() {
-            if !self.flags().contains(ConnectionFlags::SETTINGS_PROCESSED) {
-                self.set_flags(ConnectionFlags::SETTINGS_PROCESSED);
-                if let Some(max) = self.0.local_config.0.settings.get().max_frame_size() {
+            // every sent SETTINGS frame is acknowledged separately
+            let local = self.0.pending_settings.borrow_mut().pop_front();
+            if let Some(local) = local {
+                let window_sz = if self.flags().contains(ConnectionFlags::SETTINGS_PROCESSED) {
+                    if let Some(max) = local.max_concurrent_streams() {
+                        self.0.remote_max_concurrent_streams.set(Some(max));
+                    }
+                    local.initial_window_size()
+                } else {
+                    self.set_flags(ConnectionFlags::SETTINGS_PROCESSED);
+                    Some(self.0.local_config.0.window_sz.get())
+                };
+
+                let mut acked = self.0.local_settings.get();
+                acked.merge(&local);
+                self.0.local_settings.set(acked);
+
+                if let Some(max) = local.max_frame_size() {
                     self.0.codec.set_recv_frame_size(max as usize);
                 }
-
-                let upd = (self.0.local_config.0.window_sz.get() as i32)
-                    - (frame::DEFAULT_INITIAL_WINDOW_SIZE as i32);
+                let upd = if let Some(window_sz) = window_sz {
+                    let upd = (window_sz as i32) - (self.0.local_window_sz.get() as i32);
+                    self.0.local_window_sz.set(window_sz);
+                    upd
+                } else {
+                    0
+                };
 
                 let mut stream_errors = Vec::new();
                 for stream in self.0.streams.borrow().values() {
@@ -574,6 +1344,23 @@ impl RecvHalfConnection {
             // Ack settings to the peer
             self.encode(frame::Settings::ack());
 
+            let mut remote = self.0.remote_settings.get();
+
+            // RFC 8441 §3, value cannot be reverted once enabled
+            if remote.is_extended_connect_protocol_enabled() == Some(true)
+                && settings.is_extended_connect_protocol_enabled() == Some(false)
+            {
+                return Err(Either::Left(ConnectionError::ExtendedConnect(
+                    "SETTINGS_ENABLE_CONNECT_PROTOCOL is disabled after being enabled",
+                )));
+            }
+            remote.merge(&settings);
+            self.0.remote_settings.set(remote);
+            if !self.flags().contains(ConnectionFlags::REMOTE_SETTINGS) {
+                self.set_flags(ConnectionFlags::REMOTE_SETTINGS);
+                self.0.remote_settings_waker.wake();
+            }
+
             if let Some(max) = settings.max_frame_size() {
                 self.0.codec.set_send_frame_size(max as usize);
                 self.0.remote_frame_size.set(max);
@@ -647,6 +1434,9 @@ impl RecvHalfConnection {
                     .get()
                     .inc(frm.size_increment())
                     .map_err(|_| Either::Left(ConnectionError::WindowValueOverflow))?;
+                if let Some(ref qlog) = self.0.qlog {
+                    qlog.window_updated(StreamId::CON, "remote", window.window_size);
+                }
                 self.0.send_window.set(window);
                 Ok(())
             }
@@ -654,7 +1444,16 @@ impl RecvHalfConnection {
             stream
                 .recv_window_update(frm)
                 .map_err(|kind| Either::Right(StreamErrorInner::new(stream, kind)))
-        } else if self.0.local_reset_ids.borrow().contains(&frm.stream_id()) {
+        } else if let Some(violation) = self.recv_reset_stream_frame(frm.stream_id())? {
+            if violation {
+                self.encode(frame::Reset::new(
+                    frm.stream_id(),
+                    frame::Reason::STREAM_CLOSED,
+                ));
+            }
+            Ok(())
+        } else if self.0.local_config.0.violation_policy.get() == ViolationPolicy::Stream {
+            log::trace!("{}: Ignore unknown WINDOW_UPDATE {:?}", self.tag(), frm);
             Ok(())
         } else {
             log::trace!("Unknown WINDOW_UPDATE {:?}", frm);
@@ -664,6 +1463,49 @@ impl RecvHalfConnection {
         }
     }
 
+    /// Escalate frame for half-closed stream to connection error
+    fn escalate(
+        &self,
+        stream: StreamRef,
+        kind: StreamError,
+        msg: &'static str,
+    ) -> Either<ConnectionError, StreamErrorInner> {
+        if matches!(kind, StreamError::Closed)
+            && self.0.local_config.0.violation_policy.get() == ViolationPolicy::Connection
+        {
+            Either::Left(ConnectionError::StreamClosed(stream.id(), msg))
+        } else {
+            Either::Right(StreamErrorInner::new(stream, kind))
+        }
+    }
+
+    /// Account frame received for locally reset stream.
+    ///
+    /// Returns `None` if stream is not locally reset, otherwise
+    /// returns `true` if frame is above reset stream frames limit.
+    fn recv_reset_stream_frame(
+        &self,
+        id: StreamId,
+    ) -> Result<Option<bool>, Either<ConnectionError, StreamErrorInner>> {
+        let mut ids = self.0.local_reset_ids.borrow_mut();
+        let Some(count) = ids.get_mut(&id) else {
+            return Ok(None);
+        };
+        *count += 1;
+
+        let max = self.0.local_config.0.reset_max_frames.get();
+        if max == 0 || *count <= max {
+            Ok(Some(false))
+        } else {
+            match self.0.local_config.0.reset_violation.get() {
+                ResetStreamViolation::Stream => Ok(Some(true)),
+                ResetStreamViolation::Connection => Err(Either::Left(
+                    ConnectionError::StreamClosed(id, "Too many frames for reset stream"),
+                )),
+            }
+        }
+    }
+
     fn update_rst_count(&self) -> Result<(), Either<ConnectionError, StreamErrorInner>> {
         let count = self.0.rst_count.get() + 1;
         let total_count = self.0.total_count.get();
@@ -675,6 +1517,27 @@ impl RecvHalfConnection {
         }
     }
 
+    fn update_empty_data_count(&self) -> Result<(), Either<ConnectionError, StreamErrorInner>> {
+        let max = self.0.local_config.0.max_empty_data_frames.get();
+        if max == 0 {
+            return Ok(());
+        }
+
+        let now = self.0.clock.now();
+        let (start, count) = self.0.empty_data_count.get();
+        let (start, count) = if now.duration_since(start) >= Duration::from_secs(1) {
+            (now, 1)
+        } else {
+            (start, count + 1)
+        };
+        if count > max {
+            Err(Either::Left(ConnectionError::EmptyDataFlood))
+        } else {
+            self.0.empty_data_count.set((start, count));
+            Ok(())
+        }
+    }
+
     pub(crate) fn recv_rst_stream(
         &self,
         frm: frame::Reset,
@@ -684,6 +1547,8 @@ impl RecvHalfConnection {
         if frm.stream_id().is_zero() {
             Err(Either::Left(ConnectionError::UnknownStream("RST_STREAM")))
         } else if let Some(stream) = self.query(frm.stream_id()) {
+            self.0
+                .stream_event(StreamEvent::ResetByPeer(frm.stream_id(), frm.reason()));
             stream.recv_rst_stream(&frm);
             self.update_rst_count()?;
 
@@ -691,7 +1556,7 @@ impl RecvHalfConnection {
                 stream,
                 StreamError::Reset(frm.reason()),
             )))
-        } else if self.0.local_reset_ids.borrow().contains(&frm.stream_id()) {
+        } else if self.recv_reset_stream_frame(frm.stream_id())?.is_some() {
             self.update_rst_count()
         } else {
             self.update_rst_count()?;
@@ -699,66 +1564,151 @@ impl RecvHalfConnection {
         }
     }
 
-    pub(crate) fn recv_pong(&self, _: frame::Ping) {
+    pub(crate) fn recv_pong(&self, frm: frame::Ping) {
         self.set_flags(ConnectionFlags::RECV_PONG);
+
+        let payload = u64::from_be_bytes(*frm.payload());
+        let mut pings = self.0.pings.borrow_mut();
+        if let Some(idx) = pings.iter().position(|p| p.payload == payload) {
+            // older pings are not going to be acknowledged
+            let ping = pings.drain(..=idx).next_back().unwrap();
+            drop(pings);
+
+            let rtt = self.0.clock.now().saturating_duration_since(ping.sent);
+            self.0.rtt.borrow_mut().add(rtt);
+            if let Some(tx) = ping.tx {
+                let _ = tx.send(rtt);
+            }
+        }
     }
 
+    /// Handle GOAWAY frame.
+    ///
+    /// Returns locally initiated streams not processed by the peer and
+    /// failed streams. Graceful GOAWAY keeps streams below last stream id active.
+    #[allow(clippy::type_complexity)]
     pub(crate) fn recv_go_away(
         &self,
-        reason: frame::Reason,
-        data: &Bytes,
-    ) -> HashMap<StreamId, StreamRef> {
+        frm: &frame::GoAway,
+    ) -> (HashMap<StreamId, StreamRef>, HashMap<StreamId, StreamRef>) {
+        let reason = frm.reason();
+
+        // last stream id must not increase in subsequent GOAWAY frames
+        let last_id = if let Some(ref prev) = *self.0.remote_go_away.borrow() {
+            cmp::min(prev.last_stream_id(), frm.last_stream_id())
+        } else {
+            frm.last_stream_id()
+        };
+        *self.0.remote_go_away.borrow_mut() = Some(frm.clone().set_last_stream_id(last_id));
+
         log::trace!(
             "{}: processing go away with reason: {:?}, data: {:?}",
             self.tag(),
             reason,
-            data.slice(..std::cmp::min(data.len(), 20))
+            frm.data_lossy()
         );
 
-        self.0
-            .error
-            .set(Some(ConnectionError::GoAway(reason).into()));
         self.0.readiness.borrow_mut().clear();
+        self.0.remote_settings_waker.wake();
 
         let streams = mem::take(&mut *self.0.streams.borrow_mut());
-        for stream in streams.values() {
-            stream.set_go_away(reason)
+        let (unprocessed, streams): (HashMap<_, _>, HashMap<_, _>) = streams
+            .into_iter()
+            .partition(|(id, stream)| !stream.is_remote() && *id > last_id);
+
+        let streams = if reason == frame::Reason::NO_ERROR {
+            // streams below last stream id could complete
+            *self.0.streams.borrow_mut() = streams;
+            Connection(self.0.clone()).disconnect_when_ready();
+            HashMap::default()
+        } else {
+            self.0
+                .error
+                .set(Some(ConnectionError::GoAway(reason).into()));
+            for stream in streams.values() {
+                stream.set_go_away(reason)
+            }
+            streams
+        };
+        for stream in unprocessed.values() {
+            stream.set_failed_stream(OperationError::Unprocessed(reason))
         }
-        streams
+        (unprocessed, streams)
     }
 
     pub(crate) fn ping_timeout(&self) -> HashMap<StreamId, StreamRef> {
         self.0
             .error
             .set(Some(ConnectionError::KeepaliveTimeout.into()));
+        self.0.remote_settings_waker.wake();
 
         let streams = mem::take(&mut *self.0.streams.borrow_mut());
         for stream in streams.values() {
             stream.set_failed_stream(ConnectionError::KeepaliveTimeout.into())
         }
-
-        self.encode(frame::GoAway::new(frame::Reason::NO_ERROR));
         self.0.io.close();
         streams
     }
 
     pub(crate) fn read_timeout(&self) -> HashMap<StreamId, StreamRef> {
         self.0.error.set(Some(ConnectionError::ReadTimeout.into()));
+        self.0.remote_settings_waker.wake();
 
         let streams = mem::take(&mut *self.0.streams.borrow_mut());
         for stream in streams.values() {
             stream.set_failed_stream(ConnectionError::ReadTimeout.into())
         }
-
-        self.encode(frame::GoAway::new(frame::Reason::NO_ERROR));
         self.0.io.close();
         streams
     }
 
+    /// Check if protocol error is related to single stream.
+    ///
+    /// Malformed request headers do not affect connection state,
+    /// such errors could be handled as stream errors.
+    pub(crate) fn recoverable_error(&self, err: &ConnectionError) -> Option<StreamId> {
+        match err {
+            ConnectionError::MissingPseudo(_)
+            | ConnectionError::UnexpectedPseudo(_)
+            | ConnectionError::MalformedPseudo(_)
+            | ConnectionError::ExtendedConnect(_) => self
+                .0
+                .last_frame
+                .get()
+                .map(|(_, id)| id)
+                .filter(|id| !id.is_zero()),
+            _ => None,
+        }
+    }
+
+    /// Handle protocol error as stream error, stream id is consumed
+    pub(crate) fn downgrade_error(&self, err: &ConnectionError, id: StreamId) {
+        self.0.stats.borrow_mut().violations.connection_error(err);
+        if id > self.0.next_stream_id.get() {
+            self.0.next_stream_id.set(id);
+        }
+    }
+
     pub(crate) fn proto_error(&self, err: &ConnectionError) -> HashMap<StreamId, StreamRef> {
+        if log::log_enabled!(log::Level::Debug) {
+            log::debug!(
+                "{}: Protocol error {:?}, connection state:\n{}",
+                self.tag(),
+                err,
+                self.connection().dump()
+            );
+        }
+        self.0.stats.borrow_mut().violations.connection_error(err);
         self.0.error.set(Some((*err).into()));
+        // frame that failed to decode is not recorded as last frame
+        if !matches!(err, ConnectionError::Decoder(_)) {
+            self.0.failed_frame.set(self.0.last_frame.get());
+        }
         self.0.readiness.borrow_mut().clear();
+        self.0.remote_settings_waker.wake();
 
+        // already queued frames are flushed by graceful io close,
+        // streams are not allowed to produce more frames after GOAWAY
         let streams = mem::take(&mut *self.0.streams.borrow_mut());
         for stream in &mut streams.values() {
             stream.set_failed_stream((*err).into())
@@ -773,6 +1723,9 @@ impl RecvHalfConnection {
             self.0.error.set(Some(OperationError::Disconnected));
         }
 
+        self.0.pings.borrow_mut().clear();
+        self.0.remote_settings_waker.wake();
+
         let streams = mem::take(&mut *self.0.streams.borrow_mut());
         for stream in streams.values() {
             stream.set_failed_stream(OperationError::Disconnected)
@@ -788,6 +1741,8 @@ impl fmt::Debug for Connection {
             .field("io", &self.0.io)
             .field("codec", &self.0.codec)
             .field("recv_window", &self.0.recv_window.get())
+            .field("recv_buffered_bytes", &self.0.recv_buffered_bytes.get())
+            .field("recv_buffered_frames", &self.0.recv_buffered_frames.get())
             .field("send_window", &self.0.send_window.get())
             .field("settings_processed", &self.settings_processed())
             .field("next_stream_id", &self.0.next_stream_id.get())
@@ -796,7 +1751,12 @@ impl fmt::Debug for Connection {
                 "local_max_concurrent_streams",
                 &self.0.local_max_concurrent_streams.get(),
             )
+            .field(
+                "remote_max_concurrent_streams",
+                &self.0.remote_max_concurrent_streams.get(),
+            )
             .field("remote_window_sz", &self.0.remote_window_sz.get())
+            .field("local_window_sz", &self.0.local_window_sz.get())
             .field("remote_frame_size", &self.0.remote_frame_size.get())
             .field("flags", &self.0.flags.get())
             .field("error", &self.check_error())
@@ -809,17 +1769,17 @@ async fn delay_drop_task(state: Connection) {
 
     loop {
         let next = if let Some(item) = state.0.local_reset_queue.borrow().front() {
-            item.1 - now()
+            item.1.saturating_duration_since(state.0.clock.now())
         } else {
             break;
         };
-        sleep(next).await;
+        state.0.clock.sleep(next).await;
 
         if state.is_closed() {
             return;
         }
 
-        let now = now();
+        let now = state.0.clock.now();
         let mut ids = state.0.local_reset_ids.borrow_mut();
         let mut queue = state.0.local_reset_queue.borrow_mut();
         loop {
@@ -843,7 +1803,6 @@ async fn delay_drop_task(state: Connection) {
 async fn ping(st: Connection, timeout: time::Seconds, io: IoRef) {
     log::debug!("start http client ping/pong task");
 
-    let mut counter: u64 = 0;
     let keepalive: time::Millis = time::Millis::from(timeout) + time::Millis(100);
 
     st.set_flags(ConnectionFlags::RECV_PONG);
@@ -855,17 +1814,39 @@ async fn ping(st: Connection, timeout: time::Seconds, io: IoRef) {
             );
             break;
         }
-        sleep(keepalive).await;
+        st.0.clock.sleep(keepalive.into()).await;
         if st.is_closed() {
             break;
         }
         if !st.0.flags.get().contains(ConnectionFlags::RECV_PONG) {
-            io.notify_timeout();
-            break;
+            if !st.0.local_config.0.ping_idle_only() || st.0.streams.borrow().is_empty() {
+                if let Some(ref handler) = *st.0.keepalive.borrow() {
+                    handler();
+                } else {
+                    io.notify_timeout();
+                }
+                break;
+            }
+            log::trace!(
+                "{}: did not receive pong response, connection has active streams",
+                st.tag()
+            );
         }
 
-        counter += 1;
         st.unset_flags(ConnectionFlags::RECV_PONG);
-        st.encode(frame::Ping::new(counter.to_be_bytes()));
+        st.send_ping(None);
+    }
+}
+
+fn frame_info(frame: &frame::Frame) -> (frame::Kind, StreamId) {
+    match frame {
+        frame::Frame::Data(frm) => (frame::Kind::Data, frm.stream_id()),
+        frame::Frame::Headers(frm) => (frame::Kind::Headers, frm.stream_id()),
+        frame::Frame::Priority(frm) => (frame::Kind::Priority, frm.stream_id()),
+        frame::Frame::Reset(frm) => (frame::Kind::Reset, frm.stream_id()),
+        frame::Frame::Settings(_) => (frame::Kind::Settings, StreamId::CON),
+        frame::Frame::Ping(_) => (frame::Kind::Ping, StreamId::CON),
+        frame::Frame::GoAway(_) => (frame::Kind::GoAway, StreamId::CON),
+        frame::Frame::WindowUpdate(frm) => (frame::Kind::WindowUpdate, frm.stream_id()),
     }
 }
@@ -10,6 +10,8 @@ pub(crate) const DEFAULT_RESET_STREAM_SECS: Seconds = Seconds(10);
 pub(crate) const DEFAULT_CONNECTION_WINDOW_SIZE: WindowSize = 1_048_576;
 pub(crate) const DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE: u32 = 48 * 1024;
 pub(crate) const DEFAULT_MAX_COUNTINUATIONS: usize = 5;
+pub(crate) const DEFAULT_FRAMES_BUDGET: usize = 64;
+pub(crate) const DEFAULT_MAX_EMPTY_DATA_FRAMES: u32 = 128;
 
 pub(crate) const PREFACE: [u8; 24] = *b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
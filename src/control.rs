@@ -1,7 +1,11 @@
-use std::io;
+use std::{io, time::Duration, time::Instant};
 
-use crate::frame::{Frame, Reason, Reset};
-use crate::{error, frame, stream::StreamRef};
+use ntex_bytes::Bytes;
+use ntex_http::{HeaderMap, StatusCode};
+
+use crate::frame::{Frame, Reason, Reset, StreamId};
+use crate::{connection::Connection, error, error::ConnectionContext, error::OperationError};
+use crate::{dump::NegotiatedSettings, dump::StreamDump, frame, stats::Stats, stream::StreamRef};
 
 #[doc(hidden)]
 pub type ControlMessage<E> = Control<E>;
@@ -9,6 +13,7 @@ pub type ControlMessage<E> = Control<E>;
 pub type ControlResult = ControlAck;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Control<E> {
     /// Application level error from publish service
     AppError(AppError<E>),
@@ -20,12 +25,88 @@ pub enum Control<E> {
     PeerGone(PeerGone),
     /// Protocol dispatcher is terminated
     Terminated(Terminated),
+    /// New stream is opened, see `Config::stream_notifications()`
+    StreamOpened(StreamOpened),
+    /// Stream is closed
+    StreamClosed(StreamClosed),
+    /// Stream is reset by peer
+    StreamResetByPeer(StreamResetByPeer),
+    /// New remote stream is refused by connection
+    StreamRefused(StreamRefused),
+    /// Stream send is blocked by flow control for too long
+    StreamStalled(StreamStalled),
+    /// Stream deadline is elapsed, stream is reset
+    StreamTimeout(StreamTimeout),
+    /// Local stream id space is exhausted
+    StreamIdsExhausted(StreamIdsExhausted),
+    /// Peer settings are received and applied
+    SettingsReceived(SettingsReceived),
+    /// Peer acknowledged local settings
+    SettingsAcked(SettingsAcked),
+    /// Peer granted send window
+    WindowUpdate(WindowUpdate),
+    /// PING frame is received, pong is sent by ack
+    PingReceived(PingReceived),
+    /// Keep-alive ping is not acknowledged in time
+    KeepaliveTimeout(KeepaliveTimeout),
+    /// Connection is about to send GOAWAY frame
+    LocalGoAway(LocalGoAway),
 }
 
 #[derive(Clone, Debug)]
 pub struct ControlAck {
-    pub(crate) frame: Option<Frame>,
+    pub(crate) frames: Vec<Frame>,
     pub(crate) disconnect: bool,
+    pub(crate) response: Option<ErrorResponse>,
+    pub(crate) keepalive: Option<KeepaliveAction>,
+    pub(crate) drain: bool,
+}
+
+/// Keep-alive timeout handling policy
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum KeepaliveAction {
+    Close,
+    Drain,
+    Extend,
+}
+
+/// Response for failed stream
+#[derive(Clone, Debug)]
+pub(crate) struct ErrorResponse {
+    pub(crate) stream: StreamRef,
+    pub(crate) status: StatusCode,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+    pub(crate) reason: Reason,
+}
+
+impl ControlAck {
+    fn new(disconnect: bool) -> Self {
+        ControlAck {
+            frames: Vec::new(),
+            disconnect,
+            response: None,
+            keepalive: None,
+            drain: false,
+        }
+    }
+
+    /// Send frame to the peer.
+    ///
+    /// Frames are sent in the order they were added.
+    pub fn frame<T>(mut self, frame: T) -> Self
+    where
+        Frame: From<T>,
+    {
+        self.frames.push(frame.into());
+        self
+    }
+
+    /// Disconnect after frames are sent
+    pub fn disconnect(mut self) -> Self {
+        self.disconnect = true;
+        self
+    }
 }
 
 impl<E> Control<E> {
@@ -40,17 +121,103 @@ impl<E> Control<E> {
     }
 
     /// Create a new `Control` message from GOAWAY packet.
-    pub(super) fn go_away(frm: frame::GoAway) -> Self {
-        Control::GoAway(GoAway(frm))
+    pub(super) fn go_away(frm: frame::GoAway, unprocessed: Vec<StreamId>, drain: bool) -> Self {
+        Control::GoAway(GoAway {
+            frm,
+            unprocessed,
+            drain,
+            stats: Stats::default(),
+            handle: None,
+        })
     }
 
     /// Create a new `Control` message from DISCONNECT packet.
     pub(super) fn peer_gone(err: Option<io::Error>) -> Self {
-        Control::PeerGone(PeerGone(err))
+        Control::PeerGone(PeerGone(err, ConnectionContext::default(), None))
+    }
+
+    pub(super) fn terminated(deadline: Option<Instant>, streams: Vec<StreamId>) -> Self {
+        Control::Terminated(Terminated {
+            deadline,
+            streams,
+            handle: None,
+        })
+    }
+
+    /// Create a new `Control` message from stream lifecycle event
+    pub(super) fn stream_event(ev: StreamEvent) -> Self {
+        match ev {
+            StreamEvent::Opened(stream) => Control::StreamOpened(StreamOpened(stream, None)),
+            StreamEvent::Closed(id, reason) => Control::StreamClosed(StreamClosed {
+                id,
+                reason,
+                handle: None,
+            }),
+            StreamEvent::ResetByPeer(id, reason) => Control::StreamResetByPeer(StreamResetByPeer {
+                id,
+                reason,
+                handle: None,
+            }),
+            StreamEvent::Refused(id, cause) => Control::StreamRefused(StreamRefused {
+                id,
+                cause,
+                handle: None,
+            }),
+            StreamEvent::IdsExhausted(id) => {
+                Control::StreamIdsExhausted(StreamIdsExhausted { id, handle: None })
+            }
+        }
+    }
+
+    /// Create a new `Control` message for stalled stream
+    pub(super) fn stream_stalled(stream: StreamRef, duration: Duration, reset: bool) -> Self {
+        Control::StreamStalled(StreamStalled {
+            stream,
+            duration,
+            reset,
+            handle: None,
+        })
+    }
+
+    /// Create a new `Control` message for stream with elapsed deadline
+    pub(super) fn stream_timeout(stream: StreamRef) -> Self {
+        Control::StreamTimeout(StreamTimeout {
+            stream,
+            handle: None,
+        })
+    }
+
+    /// Create a new `Control` message from SETTINGS frame
+    pub(super) fn settings(frm: frame::Settings) -> Self {
+        if frm.is_ack() {
+            Control::SettingsAcked(SettingsAcked(None))
+        } else {
+            Control::SettingsReceived(SettingsReceived(frm, None))
+        }
+    }
+
+    /// Create a new `Control` message from WINDOW_UPDATE frame
+    pub(super) fn window_update(frm: frame::WindowUpdate) -> Self {
+        Control::WindowUpdate(WindowUpdate {
+            stream_id: frm.stream_id(),
+            size: frm.size_increment(),
+            handle: None,
+        })
+    }
+
+    /// Create a new `Control` message from PING frame
+    pub(super) fn ping(frm: frame::Ping) -> Self {
+        Control::PingReceived(PingReceived(frm, None))
+    }
+
+    /// Create a new `Control` message for keep-alive timeout
+    pub(super) fn keepalive_timeout() -> Self {
+        Control::KeepaliveTimeout(KeepaliveTimeout(None))
     }
 
-    pub(super) fn terminated() -> Self {
-        Control::Terminated(Terminated)
+    /// Create a new `Control` message for GOAWAY frame generated by connection
+    pub(super) fn local_go_away(frm: frame::GoAway) -> Self {
+        Control::LocalGoAway(LocalGoAway(frm, None))
     }
 
     /// Create a new `Control` message for protocol level errors
@@ -58,6 +225,100 @@ impl<E> Control<E> {
         Control::ConnectionError(ConnectionError::new(err))
     }
 
+    /// Create a new `Control` message for protocol error related to single stream
+    pub(super) fn stream_proto_error(err: error::ConnectionError, id: StreamId) -> Self {
+        let mut item = ConnectionError::new(err);
+        item.stream_id = Some(id);
+        Control::ConnectionError(item)
+    }
+
+    /// Attach connection context
+    ///
+    /// Context is collected only for messages that carry it.
+    pub(super) fn with_context(mut self, con: &Connection) -> Self {
+        match self {
+            Control::ConnectionError(ref mut item) => item.stats = con.stats(),
+            Control::GoAway(ref mut item) => item.stats = con.stats(),
+            Control::PeerGone(ref mut item) => item.1 = con.context(),
+            Control::AppError(_)
+            | Control::Terminated(_)
+            | Control::StreamOpened(_)
+            | Control::StreamClosed(_)
+            | Control::StreamResetByPeer(_)
+            | Control::StreamRefused(_)
+            | Control::StreamStalled(_)
+            | Control::StreamTimeout(_)
+            | Control::StreamIdsExhausted(_)
+            | Control::SettingsReceived(_)
+            | Control::SettingsAcked(_)
+            | Control::WindowUpdate(_)
+            | Control::PingReceived(_)
+            | Control::KeepaliveTimeout(_)
+            | Control::LocalGoAway(_) => (),
+        }
+        self
+    }
+
+    /// Apply connection's protocol error to GOAWAY mapping
+    pub(super) fn with_goaway(mut self, con: &Connection) -> Self {
+        if let Control::ConnectionError(ref mut item) = self {
+            item.frm = con.to_goaway(&item.err);
+        }
+        self
+    }
+
+    /// Attach control handle
+    pub(super) fn with_handle(mut self, handle: ControlHandle) -> Self {
+        let h = Some(handle);
+        match self {
+            Control::AppError(ref mut item) => item.handle = h,
+            Control::ConnectionError(ref mut item) => item.handle = h,
+            Control::GoAway(ref mut item) => item.handle = h,
+            Control::PeerGone(ref mut item) => item.2 = h,
+            Control::Terminated(ref mut item) => item.handle = h,
+            Control::StreamOpened(ref mut item) => item.1 = h,
+            Control::StreamClosed(ref mut item) => item.handle = h,
+            Control::StreamResetByPeer(ref mut item) => item.handle = h,
+            Control::StreamRefused(ref mut item) => item.handle = h,
+            Control::StreamStalled(ref mut item) => item.handle = h,
+            Control::StreamTimeout(ref mut item) => item.handle = h,
+            Control::StreamIdsExhausted(ref mut item) => item.handle = h,
+            Control::SettingsReceived(ref mut item) => item.1 = h,
+            Control::SettingsAcked(ref mut item) => item.0 = h,
+            Control::WindowUpdate(ref mut item) => item.handle = h,
+            Control::PingReceived(ref mut item) => item.1 = h,
+            Control::KeepaliveTimeout(ref mut item) => item.0 = h,
+            Control::LocalGoAway(ref mut item) => item.1 = h,
+        }
+        self
+    }
+
+    /// Handle for sending control frames outside of the ack flow.
+    ///
+    /// Handle is available for messages created by the connection dispatcher.
+    pub fn handle(&self) -> Option<&ControlHandle> {
+        match self {
+            Control::AppError(item) => item.handle.as_ref(),
+            Control::ConnectionError(item) => item.handle.as_ref(),
+            Control::GoAway(item) => item.handle.as_ref(),
+            Control::PeerGone(item) => item.2.as_ref(),
+            Control::Terminated(item) => item.handle.as_ref(),
+            Control::StreamOpened(item) => item.1.as_ref(),
+            Control::StreamClosed(item) => item.handle.as_ref(),
+            Control::StreamResetByPeer(item) => item.handle.as_ref(),
+            Control::StreamRefused(item) => item.handle.as_ref(),
+            Control::StreamStalled(item) => item.handle.as_ref(),
+            Control::StreamTimeout(item) => item.handle.as_ref(),
+            Control::StreamIdsExhausted(item) => item.handle.as_ref(),
+            Control::SettingsReceived(item) => item.1.as_ref(),
+            Control::SettingsAcked(item) => item.0.as_ref(),
+            Control::WindowUpdate(item) => item.handle.as_ref(),
+            Control::PingReceived(item) => item.1.as_ref(),
+            Control::KeepaliveTimeout(item) => item.0.as_ref(),
+            Control::LocalGoAway(item) => item.1.as_ref(),
+        }
+    }
+
     /// Default ack impl
     pub fn ack(self) -> ControlAck {
         match self {
@@ -66,16 +327,113 @@ impl<E> Control<E> {
             Control::GoAway(item) => item.ack(),
             Control::PeerGone(item) => item.ack(),
             Control::Terminated(item) => item.ack(),
+            Control::StreamOpened(item) => item.ack(),
+            Control::StreamClosed(item) => item.ack(),
+            Control::StreamResetByPeer(item) => item.ack(),
+            Control::StreamRefused(item) => item.ack(),
+            Control::StreamStalled(item) => item.ack(),
+            Control::StreamTimeout(item) => item.ack(),
+            Control::StreamIdsExhausted(item) => item.ack(),
+            Control::SettingsReceived(item) => item.ack(),
+            Control::SettingsAcked(item) => item.ack(),
+            Control::WindowUpdate(item) => item.ack(),
+            Control::PingReceived(item) => item.ack(),
+            Control::KeepaliveTimeout(item) => item.ack(),
+            Control::LocalGoAway(item) => item.ack(),
         }
     }
 }
 
+/// Handle for sending control frames to the peer.
+///
+/// Handle could be stored and used at any time, for example
+/// from a background task. All operations are no-op after connection
+/// is closed.
+#[derive(Clone, Debug)]
+pub struct ControlHandle(Connection);
+
+impl ControlHandle {
+    pub(crate) fn new(con: Connection) -> Self {
+        ControlHandle(con)
+    }
+
+    /// Connection statistics
+    pub fn stats(&self) -> Stats {
+        self.0.stats()
+    }
+
+    /// Settings received from the peer
+    pub fn remote_settings(&self) -> frame::Settings {
+        self.0.remote_settings()
+    }
+
+    /// Local, remote and effective settings of the connection
+    pub fn negotiated_settings(&self) -> NegotiatedSettings {
+        self.0.negotiated_settings()
+    }
+
+    /// Summary of active streams, ordered by stream id
+    pub fn streams(&self) -> Vec<StreamDump> {
+        self.0.streams()
+    }
+
+    /// Get active stream by id
+    pub fn stream(&self, id: StreamId) -> Option<StreamRef> {
+        self.0.stream(id)
+    }
+
+    /// Send PING frame and wait for acknowledgement
+    pub async fn ping(&self) -> Result<Duration, OperationError> {
+        self.0.ping().await
+    }
+
+    /// Send GOAWAY frame.
+    ///
+    /// Last stream id is set to the last processed peer stream, new
+    /// peer streams are refused. Connection is closed after in-flight
+    /// streams are complete.
+    pub fn go_away(&self, frm: frame::GoAway) {
+        if !self.0.is_closed() {
+            self.0.go_away(frm);
+        }
+    }
+
+    /// Send SETTINGS frame.
+    ///
+    /// Initial window size, max concurrent streams and max frame size
+    /// are applied to the connection after peer acknowledges the frame.
+    pub fn settings(&self, frm: frame::Settings) {
+        if !self.0.is_closed() {
+            self.0.send_settings(frm);
+        }
+    }
+
+    /// Reset stream
+    pub fn reset(&self, id: StreamId, reason: Reason) {
+        if !self.0.is_closed() {
+            self.0.rst_stream(id, reason);
+            self.0.encode(Reset::new(id, reason));
+        }
+    }
+
+    /// Close connection
+    pub fn close(&self) {
+        self.0.close()
+    }
+
+    /// Check if connection is closed
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+}
+
 /// Service level error
 #[derive(Debug)]
 pub struct AppError<E> {
     err: E,
     reason: Reason,
     stream: Option<StreamRef>,
+    handle: Option<ControlHandle>,
 }
 
 impl<E> AppError<E> {
@@ -84,6 +442,7 @@ impl<E> AppError<E> {
             err,
             stream,
             reason: Reason::CANCEL,
+            handle: None,
         }
     }
 
@@ -100,36 +459,86 @@ impl<E> AppError<E> {
         self
     }
 
+    #[inline]
+    /// Stream, if error happened during stream processing
+    pub fn stream(&self) -> Option<&StreamRef> {
+        self.stream.as_ref()
+    }
+
+    /// Ack service error and send response to the failed stream.
+    ///
+    /// Stream is reset with configured reason if response cannot be sent,
+    /// for example if response headers have been already sent.
+    /// If error is not related to a stream, connection is closed.
+    pub fn ack_response(self, status: StatusCode, headers: HeaderMap, body: Bytes) -> ControlAck {
+        if let Some(stream) = self.stream {
+            ControlAck {
+                response: Some(ErrorResponse {
+                    stream,
+                    status,
+                    headers,
+                    body,
+                    reason: self.reason,
+                }),
+                ..ControlAck::new(false)
+            }
+        } else {
+            ControlAck::new(true)
+        }
+    }
+
     #[inline]
     /// Ack service error, return disconnect packet and close connection.
     pub fn ack(self) -> ControlAck {
         if let Some(ref stream) = self.stream {
             ControlAck {
-                frame: Some(Reset::new(stream.id(), self.reason).into()),
-                disconnect: false,
+                frames: vec![Reset::new(stream.id(), self.reason).into()],
+                ..ControlAck::new(false)
             }
         } else {
-            ControlAck {
-                frame: None,
-                disconnect: true,
-            }
+            ControlAck::new(true)
         }
     }
 }
 
 /// Dispatcher has been terminated
 #[derive(Debug)]
-pub struct Terminated;
+pub struct Terminated {
+    deadline: Option<Instant>,
+    streams: Vec<StreamId>,
+    handle: Option<ControlHandle>,
+}
 
 impl Terminated {
     #[inline]
-    /// convert packet to a result
-    pub fn ack(self) -> ControlAck {
+    /// Time when connection get closed forcibly
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    #[inline]
+    /// Ids of still active streams
+    pub fn active_streams(&self) -> &[StreamId] {
+        &self.streams
+    }
+
+    #[inline]
+    /// Wait until all active streams are closed or drain deadline
+    /// is reached, then close connection.
+    ///
+    /// Streams are not drained if disconnect timeout is disabled.
+    pub fn ack_drain(self) -> ControlAck {
         ControlAck {
-            frame: None,
-            disconnect: true,
+            drain: true,
+            ..ControlAck::new(true)
         }
     }
+
+    #[inline]
+    /// convert packet to a result
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(true)
+    }
 }
 
 /// Protocol level error
@@ -137,12 +546,18 @@ impl Terminated {
 pub struct ConnectionError {
     err: error::ConnectionError,
     frm: frame::GoAway,
+    stats: Stats,
+    stream_id: Option<StreamId>,
+    handle: Option<ControlHandle>,
 }
 
 impl ConnectionError {
     pub fn new(err: error::ConnectionError) -> Self {
         Self {
             frm: err.to_goaway(),
+            stats: Stats::default(),
+            stream_id: None,
+            handle: None,
             err,
         }
     }
@@ -153,6 +568,12 @@ impl ConnectionError {
         &self.err
     }
 
+    #[inline]
+    /// Connection statistics
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
     #[inline]
     /// Set reason code for go away packet
     pub fn reason(mut self, reason: Reason) -> Self {
@@ -160,18 +581,62 @@ impl ConnectionError {
         self
     }
 
+    #[inline]
+    /// Set debug data for go away packet
+    pub fn data<T>(mut self, data: T) -> Self
+    where
+        Bytes: From<T>,
+    {
+        self.frm = self.frm.set_data(data);
+        self
+    }
+
+    #[inline]
+    /// Id of the stream that triggered error.
+    ///
+    /// Set only if error could be handled as a stream error, see `ack_reset()`.
+    pub fn stream_id(&self) -> Option<StreamId> {
+        self.stream_id
+    }
+
+    #[inline]
+    /// Reset stream that triggered error and keep connection open.
+    ///
+    /// Works only for errors related to a single stream, for example
+    /// malformed request headers. Otherwise, same as `ack()`.
+    pub fn ack_reset(self) -> ControlAck {
+        if let Some(id) = self.stream_id {
+            ControlAck {
+                frames: vec![Reset::new(id, Reason::PROTOCOL_ERROR).into()],
+                ..ControlAck::new(false)
+            }
+        } else {
+            self.ack()
+        }
+    }
+
+    #[inline]
+    /// Ack protocol error, close connection without GOAWAY frame.
+    pub fn ack_close(self) -> ControlAck {
+        ControlAck::new(true)
+    }
+
     #[inline]
     /// Ack protocol error, return disconnect packet and close connection.
     pub fn ack(self) -> ControlAck {
         ControlAck {
-            frame: Some(self.frm.into()),
-            disconnect: true,
+            frames: vec![self.frm.into()],
+            ..ControlAck::new(true)
         }
     }
 }
 
 #[derive(Debug)]
-pub struct PeerGone(pub(super) Option<io::Error>);
+pub struct PeerGone(
+    pub(super) Option<io::Error>,
+    pub(super) ConnectionContext,
+    Option<ControlHandle>,
+);
 
 impl PeerGone {
     /// Returns error reference
@@ -179,32 +644,424 @@ impl PeerGone {
         self.0.as_ref()
     }
 
+    /// Kind of io error.
+    ///
+    /// Returns `None` if peer closed connection gracefully, for example
+    /// tcp FIN or tls close_notify, `ConnectionReset` for tcp RST.
+    pub fn error_kind(&self) -> Option<io::ErrorKind> {
+        self.0.as_ref().map(|e| e.kind())
+    }
+
+    /// Last GOAWAY frame received from the peer
+    pub fn go_away(&self) -> Option<&frame::GoAway> {
+        self.1.go_away()
+    }
+
+    /// Connection lifetime
+    pub fn duration(&self) -> Duration {
+        self.1.duration()
+    }
+
+    /// Connection statistics
+    pub fn stats(&self) -> &Stats {
+        self.1.stats()
+    }
+
+    /// Connection failure context
+    pub fn context(&self) -> &ConnectionContext {
+        &self.1
+    }
+
     /// Take error
     pub fn take(&mut self) -> Option<io::Error> {
         self.0.take()
     }
 
     pub fn ack(self) -> ControlAck {
-        ControlAck {
-            frame: None,
-            disconnect: true,
-        }
+        ControlAck::new(true)
     }
 }
 
 #[derive(Debug)]
-pub struct GoAway(frame::GoAway);
+pub struct GoAway {
+    frm: frame::GoAway,
+    unprocessed: Vec<StreamId>,
+    drain: bool,
+    stats: Stats,
+    handle: Option<ControlHandle>,
+}
 
 impl GoAway {
     /// Returns error reference
     pub fn frame(&self) -> &frame::GoAway {
+        &self.frm
+    }
+
+    /// Connection statistics
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Locally initiated streams above last stream id of the GOAWAY frame.
+    ///
+    /// Peer did not process these streams, it is safe to retry them
+    /// on a new connection.
+    pub fn unprocessed_streams(&self) -> &[StreamId] {
+        &self.unprocessed
+    }
+
+    /// Check if peer closes connection gracefully.
+    ///
+    /// Streams below last stream id are still active,
+    /// connection is closed after they complete.
+    pub fn is_draining(&self) -> bool {
+        self.drain
+    }
+
+    /// Ack GOAWAY frame.
+    ///
+    /// Gracefully closing connection is kept open until active streams complete.
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(!self.drain)
+    }
+
+    /// Ack GOAWAY frame and close connection immediately
+    pub fn ack_close(self) -> ControlAck {
+        ControlAck::new(true)
+    }
+}
+
+/// Stream lifecycle event
+#[derive(Debug)]
+pub(crate) enum StreamEvent {
+    Opened(StreamRef),
+    Closed(StreamId, Option<Reason>),
+    ResetByPeer(StreamId, Reason),
+    Refused(StreamId, RefuseCause),
+    IdsExhausted(StreamId),
+}
+
+/// New stream is opened
+#[derive(Debug)]
+pub struct StreamOpened(StreamRef, Option<ControlHandle>);
+
+impl StreamOpened {
+    /// Opened stream
+    pub fn stream(&self) -> &StreamRef {
         &self.0
     }
 
+    /// Refuse stream.
+    ///
+    /// Stream is reset with provided reason, usually `Reason::REFUSED_STREAM`,
+    /// remote stream is not passed to publish service.
+    pub fn ack_refuse(self, reason: Reason) -> ControlAck {
+        ControlAck {
+            frames: vec![Reset::new(self.0.id(), reason).into()],
+            ..ControlAck::new(false)
+        }
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Stream is closed
+#[derive(Debug)]
+pub struct StreamClosed {
+    id: StreamId,
+    reason: Option<Reason>,
+    handle: Option<ControlHandle>,
+}
+
+impl StreamClosed {
+    /// Stream id
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    /// Reset reason, if stream has been reset
+    pub fn reason(&self) -> Option<Reason> {
+        self.reason
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Stream is reset by peer
+#[derive(Debug)]
+pub struct StreamResetByPeer {
+    id: StreamId,
+    reason: Reason,
+    handle: Option<ControlHandle>,
+}
+
+impl StreamResetByPeer {
+    /// Stream id
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    /// Reset reason
+    pub fn reason(&self) -> Reason {
+        self.reason
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Cause of stream refusal
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RefuseCause {
+    /// Max number of concurrent streams is reached
+    MaxConcurrentStreams,
+    /// Connection is preparing for disconnect
+    Disconnecting,
+}
+
+/// New remote stream is refused with REFUSED_STREAM reason
+#[derive(Debug)]
+pub struct StreamRefused {
+    id: StreamId,
+    cause: RefuseCause,
+    handle: Option<ControlHandle>,
+}
+
+impl StreamRefused {
+    /// Stream id
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    /// Cause of refusal
+    pub fn cause(&self) -> RefuseCause {
+        self.cause
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Stream send is blocked by flow control
+#[derive(Debug)]
+pub struct StreamStalled {
+    stream: StreamRef,
+    duration: Duration,
+    reset: bool,
+    handle: Option<ControlHandle>,
+}
+
+impl StreamStalled {
+    /// Stalled stream
+    pub fn stream(&self) -> &StreamRef {
+        &self.stream
+    }
+
+    /// Time since stream is blocked
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Ack stalled stream event.
+    ///
+    /// Resets stream if `Config::reset_stalled_streams()` is enabled.
     pub fn ack(self) -> ControlAck {
         ControlAck {
-            frame: None,
-            disconnect: true,
+            frames: if self.reset {
+                vec![Reset::new(self.stream.id(), Reason::CANCEL).into()]
+            } else {
+                Vec::new()
+            },
+            ..ControlAck::new(false)
         }
     }
+
+    /// Reset stalled stream
+    pub fn ack_reset(self, reason: Reason) -> ControlAck {
+        ControlAck {
+            frames: vec![Reset::new(self.stream.id(), reason).into()],
+            ..ControlAck::new(false)
+        }
+    }
+}
+
+/// Stream deadline is elapsed
+#[derive(Debug)]
+pub struct StreamTimeout {
+    stream: StreamRef,
+    handle: Option<ControlHandle>,
+}
+
+impl StreamTimeout {
+    /// Stream that has been reset with `CANCEL` reason
+    pub fn stream(&self) -> &StreamRef {
+        &self.stream
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Local stream id space is exhausted.
+///
+/// Connection sends GOAWAY frame with `NO_ERROR` reason and
+/// closes after active streams are complete.
+#[derive(Debug)]
+pub struct StreamIdsExhausted {
+    id: StreamId,
+    handle: Option<ControlHandle>,
+}
+
+impl StreamIdsExhausted {
+    /// Id of the last opened stream
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Peer settings are received
+#[derive(Debug)]
+pub struct SettingsReceived(frame::Settings, Option<ControlHandle>);
+
+impl SettingsReceived {
+    /// Received settings
+    pub fn settings(&self) -> &frame::Settings {
+        &self.0
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Peer acknowledged local settings
+#[derive(Debug)]
+pub struct SettingsAcked(Option<ControlHandle>);
+
+impl SettingsAcked {
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Peer granted send window
+#[derive(Debug)]
+pub struct WindowUpdate {
+    stream_id: StreamId,
+    size: frame::WindowSize,
+    handle: Option<ControlHandle>,
+}
+
+impl WindowUpdate {
+    /// Stream id, zero for connection level window
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Window size increment
+    pub fn size(&self) -> frame::WindowSize {
+        self.size
+    }
+
+    pub fn ack(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// PING frame is received
+#[derive(Debug)]
+pub struct PingReceived(frame::Ping, Option<ControlHandle>);
+
+impl PingReceived {
+    /// Ping payload
+    pub fn payload(&self) -> &[u8; 8] {
+        self.0.payload()
+    }
+
+    /// Send pong response
+    pub fn ack(self) -> ControlAck {
+        ControlAck {
+            frames: vec![frame::Ping::pong(*self.0.payload()).into()],
+            ..ControlAck::new(false)
+        }
+    }
+
+    /// Do not respond to the ping
+    pub fn ack_ignore(self) -> ControlAck {
+        ControlAck::new(false)
+    }
+}
+
+/// Keep-alive ping is not acknowledged in time.
+///
+/// Keep-alive deadline could be extended or connection could be drained
+/// only once, on second timeout connection is closed.
+#[derive(Debug)]
+pub struct KeepaliveTimeout(Option<ControlHandle>);
+
+impl KeepaliveTimeout {
+    fn action(action: KeepaliveAction) -> ControlAck {
+        ControlAck {
+            keepalive: Some(action),
+            ..ControlAck::new(false)
+        }
+    }
+
+    /// Close connection, all active streams are failed
+    pub fn ack(self) -> ControlAck {
+        Self::action(KeepaliveAction::Close)
+    }
+
+    /// Send GOAWAY frame and close connection after all active streams are completed
+    pub fn ack_drain(self) -> ControlAck {
+        Self::action(KeepaliveAction::Drain)
+    }
+
+    /// Restart keep-alive timer
+    pub fn ack_extend(self) -> ControlAck {
+        Self::action(KeepaliveAction::Extend)
+    }
+}
+
+/// Connection is about to send GOAWAY frame.
+///
+/// Message is sent for frames generated by connection itself,
+/// for example on keep-alive or read timeouts. Frame is sent
+/// only after control service acknowledges the message.
+#[derive(Debug)]
+pub struct LocalGoAway(frame::GoAway, Option<ControlHandle>);
+
+impl LocalGoAway {
+    /// GOAWAY frame
+    pub fn frame(&self) -> &frame::GoAway {
+        &self.0
+    }
+
+    /// Send frame as is
+    pub fn ack(self) -> ControlAck {
+        let frm = self.0.clone();
+        self.ack_replace(frm)
+    }
+
+    /// Send replacement frame, for example with different debug data
+    pub fn ack_replace(self, frm: frame::GoAway) -> ControlAck {
+        ControlAck::new(false).frame(frm)
+    }
+
+    /// Do not send GOAWAY frame
+    pub fn ack_suppress(self) -> ControlAck {
+        ControlAck::new(false)
+    }
 }
@@ -0,0 +1,416 @@
+//! HTTP Datagrams and Capsule Protocol.
+//!
+//! Capsules ([RFC 9297]) are carried in DATA frames of extended CONNECT
+//! streams, for example `connect-udp` ([RFC 9298]) tunnels. Each capsule
+//! is encoded as variable-length type and length followed by payload,
+//! capsule boundaries do not depend on DATA frame boundaries.
+//!
+//! WebTransport over HTTP/2 sessions could be opened with extended CONNECT
+//! and `webtransport` protocol, and session capsules could be exchanged with
+//! [`DatagramStream`]. WebTransport specific SETTINGS and multiplexing of
+//! application streams within session are not implemented.
+//!
+//! [RFC 9297]: https://www.rfc-editor.org/rfc/rfc9297
+//! [RFC 9298]: https://www.rfc-editor.org/rfc/rfc9298
+use std::{cell::Cell, cell::RefCell, fmt, future::poll_fn, task::Context, task::Poll};
+
+use ntex_bytes::{Bytes, BytesMut};
+
+use crate::client::{RecvStream, SendStream};
+use crate::message::{MessageKind, StreamEof};
+use crate::{body::StreamBody, detached::DetachedStream, error::OperationError};
+use crate::{frame::Protocol, stream::StreamRef};
+
+/// `connect-udp` protocol
+pub const CONNECT_UDP: Protocol = Protocol::from_static("connect-udp");
+
+/// DATAGRAM capsule type
+pub const DATAGRAM: u64 = 0x00;
+
+/// Max value of variable-length integer
+pub const MAX_VARINT: u64 = (1 << 62) - 1;
+
+const DEFAULT_MAX_SIZE: usize = 64 * 1024;
+
+/// Capsule stream errors
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum DatagramError {
+    /// Capsule is larger than configured limit
+    #[error("Capsule size {0} exceeds limit")]
+    Overflow(u64),
+    /// Stream is closed in the middle of capsule
+    #[error("Stream is closed in the middle of capsule")]
+    Truncated,
+    /// Stream operation error
+    #[error("{0}")]
+    Operation(#[from] OperationError),
+}
+
+/// Encode variable-length integer
+///
+/// # Panics
+///
+/// Panics if value is larger than `MAX_VARINT`.
+pub fn encode_varint(val: u64, dst: &mut BytesMut) {
+    assert!(val <= MAX_VARINT, "Value is too large for varint");
+
+    if val < (1 << 6) {
+        dst.extend_from_slice(&[val as u8]);
+    } else if val < (1 << 14) {
+        dst.extend_from_slice(&(val as u16 | 0x4000).to_be_bytes());
+    } else if val < (1 << 30) {
+        dst.extend_from_slice(&(val as u32 | 0x8000_0000).to_be_bytes());
+    } else {
+        dst.extend_from_slice(&(val | 0xc000_0000_0000_0000).to_be_bytes());
+    }
+}
+
+/// Decode variable-length integer
+///
+/// Returns value and number of consumed bytes, or `None`
+/// if buffer does not contain complete integer.
+pub fn decode_varint(src: &[u8]) -> Option<(u64, usize)> {
+    let first = *src.first()?;
+    let len = 1 << (first >> 6);
+    if src.len() < len {
+        return None;
+    }
+
+    let val = src[1..len]
+        .iter()
+        .fold((first & 0x3f) as u64, |val, b| (val << 8) | *b as u64);
+    Some((val, len))
+}
+
+/// Capsule
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capsule {
+    kind: u64,
+    payload: Bytes,
+}
+
+impl Capsule {
+    /// Create new capsule
+    pub fn new(kind: u64, payload: Bytes) -> Self {
+        Capsule { kind, payload }
+    }
+
+    /// Create DATAGRAM capsule
+    pub fn datagram(payload: Bytes) -> Self {
+        Capsule::new(DATAGRAM, payload)
+    }
+
+    /// Create DATAGRAM capsule with UDP payload
+    ///
+    /// UDP payload is prefixed with context id 0.
+    pub fn udp(payload: &[u8]) -> Self {
+        let mut buf = BytesMut::with_capacity(payload.len() + 1);
+        encode_varint(0, &mut buf);
+        buf.extend_from_slice(payload);
+        Capsule::datagram(buf.freeze())
+    }
+
+    #[inline]
+    /// Capsule type
+    pub fn kind(&self) -> u64 {
+        self.kind
+    }
+
+    #[inline]
+    /// Check if capsule is DATAGRAM capsule
+    pub fn is_datagram(&self) -> bool {
+        self.kind == DATAGRAM
+    }
+
+    #[inline]
+    /// Capsule payload
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Get capsule payload
+    pub fn into_payload(self) -> Bytes {
+        self.payload
+    }
+
+    /// UDP payload of DATAGRAM capsule
+    ///
+    /// Returns `None` for other capsule types and for datagrams
+    /// with non-zero context id.
+    pub fn udp_payload(&self) -> Option<Bytes> {
+        if self.is_datagram() {
+            match decode_varint(&self.payload) {
+                Some((0, len)) => Some(self.payload.slice(len..)),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Encode capsule to the buffer
+    pub fn encode(&self, dst: &mut BytesMut) {
+        encode_varint(self.kind, dst);
+        encode_varint(self.payload.len() as u64, dst);
+        dst.extend_from_slice(&self.payload);
+    }
+}
+
+/// Capsule decoder
+///
+/// Decoder buffers received payload until complete capsule is available.
+#[derive(Debug)]
+pub struct CapsuleDecoder {
+    buf: BytesMut,
+    max_size: usize,
+}
+
+impl CapsuleDecoder {
+    /// Create new decoder
+    pub fn new() -> Self {
+        CapsuleDecoder {
+            buf: BytesMut::new(),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Set max capsule payload size
+    ///
+    /// By default max size is 64Kb.
+    pub fn set_max_size(&mut self, size: usize) {
+        self.max_size = size;
+    }
+
+    /// Check if decoder has buffered data
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Add received data to the decoder
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Decode next capsule
+    pub fn decode(&mut self) -> Result<Option<Capsule>, DatagramError> {
+        let Some((kind, len1)) = decode_varint(&self.buf) else {
+            return Ok(None);
+        };
+        let Some((size, len2)) = decode_varint(&self.buf[len1..]) else {
+            return Ok(None);
+        };
+        if size > self.max_size as u64 {
+            return Err(DatagramError::Overflow(size));
+        }
+        if self.buf.len() < len1 + len2 + size as usize {
+            return Ok(None);
+        }
+
+        let _ = self.buf.split_to(len1 + len2);
+        let payload = self.buf.split_to(size as usize).freeze();
+        Ok(Some(Capsule::new(kind, payload)))
+    }
+}
+
+impl Default for CapsuleDecoder {
+    fn default() -> Self {
+        CapsuleDecoder::new()
+    }
+}
+
+/// Capsule pipe over DATA frames of extended CONNECT stream
+///
+/// Response headers must be exchanged before capsules are sent.
+pub struct DatagramStream {
+    body: StreamBody,
+    decoder: RefCell<CapsuleDecoder>,
+    eof: Cell<bool>,
+    _snd: Option<SendStream>,
+}
+
+impl DatagramStream {
+    /// Create capsule pipe from client stream parts
+    pub fn new(snd: SendStream, rcv: RecvStream) -> Self {
+        DatagramStream {
+            body: rcv.into(),
+            decoder: RefCell::new(CapsuleDecoder::new()),
+            eof: Cell::new(false),
+            _snd: Some(snd),
+        }
+    }
+
+    #[inline]
+    /// Get stream reference
+    pub fn stream(&self) -> &StreamRef {
+        self.body.stream()
+    }
+
+    /// Set max capsule payload size
+    ///
+    /// By default max size is 64Kb.
+    pub fn set_max_size(&self, size: usize) {
+        self.decoder.borrow_mut().set_max_size(size);
+    }
+
+    /// Send capsule
+    pub async fn send(&self, capsule: &Capsule) -> Result<(), OperationError> {
+        let mut buf = BytesMut::with_capacity(capsule.payload().len() + 16);
+        capsule.encode(&mut buf);
+        self.stream().send_payload(buf.freeze(), false).await
+    }
+
+    /// Send DATAGRAM capsule
+    pub async fn send_datagram(&self, payload: Bytes) -> Result<(), OperationError> {
+        self.send(&Capsule::datagram(payload)).await
+    }
+
+    /// Receive next capsule
+    ///
+    /// Returns `None` if peer closed its side of the stream.
+    pub async fn recv(&self) -> Option<Result<Capsule, DatagramError>> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Receive payload of next DATAGRAM capsule
+    ///
+    /// Capsules of other types are skipped.
+    pub async fn recv_datagram(&self) -> Option<Result<Bytes, DatagramError>> {
+        loop {
+            match self.recv().await? {
+                Ok(capsule) if capsule.is_datagram() => return Some(Ok(capsule.into_payload())),
+                Ok(capsule) => {
+                    log::trace!(
+                        "{}: Skip capsule {:#x} of {:?}",
+                        self.stream().tag(),
+                        capsule.kind(),
+                        self.stream().id()
+                    );
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    /// Poll for next capsule
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<Result<Capsule, DatagramError>>> {
+        loop {
+            let mut decoder = self.decoder.borrow_mut();
+            if let Some(capsule) = decoder.decode()? {
+                return Poll::Ready(Some(Ok(capsule)));
+            }
+            if self.eof.get() {
+                return if decoder.is_empty() {
+                    Poll::Ready(None)
+                } else {
+                    decoder.buf.clear();
+                    Poll::Ready(Some(Err(DatagramError::Truncated)))
+                };
+            }
+
+            let msg = match self.body.poll_recv(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => {
+                    self.eof.set(true);
+                    continue;
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            match msg.kind {
+                MessageKind::Data(data, _cap) => decoder.push(&data),
+                MessageKind::Eof(StreamEof::Data(data)) => {
+                    decoder.push(&data);
+                    self.eof.set(true);
+                }
+                MessageKind::Eof(StreamEof::Trailers(_)) => self.eof.set(true),
+                MessageKind::Eof(StreamEof::Error(err)) => {
+                    self.eof.set(true);
+                    return Poll::Ready(Some(Err(OperationError::from(err).into())));
+                }
+                MessageKind::Disconnect(err) => {
+                    self.eof.set(true);
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                MessageKind::Headers { .. } => (),
+            }
+        }
+    }
+
+    /// Close send side of the stream
+    pub async fn close(&self) -> Result<(), OperationError> {
+        self.stream().send_payload(Bytes::new(), true).await
+    }
+}
+
+impl From<DetachedStream> for DatagramStream {
+    fn from(rcv: DetachedStream) -> Self {
+        DatagramStream {
+            body: rcv.into(),
+            decoder: RefCell::new(CapsuleDecoder::new()),
+            eof: Cell::new(false),
+            _snd: None,
+        }
+    }
+}
+
+impl fmt::Debug for DatagramStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatagramStream")
+            .field("stream", self.stream())
+            .field("eof", &self.eof.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for (val, len) in [
+            (0, 1),
+            (63, 1),
+            (64, 2),
+            (16383, 2),
+            (16384, 4),
+            (MAX_VARINT, 8),
+        ] {
+            let mut buf = BytesMut::new();
+            encode_varint(val, &mut buf);
+            assert_eq!(buf.len(), len);
+            assert_eq!(decode_varint(&buf), Some((val, len)));
+            assert_eq!(decode_varint(&buf[..len - 1]), None);
+        }
+        // RFC 9000, Appendix A.1
+        assert_eq!(decode_varint(&[0x7b, 0xbd]), Some((15293, 2)));
+    }
+
+    #[test]
+    fn test_decoder() {
+        let mut buf = BytesMut::new();
+        Capsule::udp(b"hello").encode(&mut buf);
+        Capsule::new(0x2a, Bytes::from_static(b"ext")).encode(&mut buf);
+
+        let mut decoder = CapsuleDecoder::new();
+        decoder.push(&buf[..3]);
+        assert_eq!(decoder.decode().unwrap(), None);
+        decoder.push(&buf[3..]);
+
+        let capsule = decoder.decode().unwrap().unwrap();
+        assert!(capsule.is_datagram());
+        assert_eq!(capsule.udp_payload().unwrap(), Bytes::from_static(b"hello"));
+        let capsule = decoder.decode().unwrap().unwrap();
+        assert_eq!(capsule.kind(), 0x2a);
+        assert_eq!(capsule.udp_payload(), None);
+        assert!(decoder.is_empty());
+
+        let mut buf = BytesMut::new();
+        Capsule::datagram(Bytes::from(vec![0; 100])).encode(&mut buf);
+        decoder.set_max_size(10);
+        decoder.push(&buf);
+        assert!(matches!(
+            decoder.decode(),
+            Err(DatagramError::Overflow(100))
+        ));
+    }
+}
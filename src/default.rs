@@ -3,11 +3,19 @@ use std::fmt;
 use ntex_service::{Service, ServiceCtx, ServiceFactory};
 
 use super::control::{Control, ControlAck};
+use super::frame::Reason;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 /// Default control service
 pub struct DefaultControlService;
 
+impl DefaultControlService {
+    /// Create control service with custom policy
+    pub fn with_policy(policy: ControlPolicy) -> PolicyControlService {
+        PolicyControlService(policy)
+    }
+}
+
 impl<E: fmt::Debug + 'static> ServiceFactory<Control<E>> for DefaultControlService {
     type Response = ControlAck;
     type Error = E;
@@ -32,3 +40,113 @@ impl<E: fmt::Debug + 'static> Service<Control<E>> for DefaultControlService {
         Ok(msg.ack())
     }
 }
+
+#[derive(Copy, Clone, Debug)]
+/// Control messages handling policy
+///
+/// Policy acks all control messages, behavior could be adjusted
+/// for pings, protocol and application errors.
+pub struct ControlPolicy {
+    go_away: bool,
+    ack_pings: bool,
+    log_level: log::Level,
+    app_error_reason: Reason,
+}
+
+impl ControlPolicy {
+    /// Create default policy
+    pub fn new() -> Self {
+        ControlPolicy {
+            go_away: true,
+            ack_pings: true,
+            log_level: log::Level::Trace,
+            app_error_reason: Reason::CANCEL,
+        }
+    }
+
+    /// Send GOAWAY frame on protocol error.
+    ///
+    /// If disabled, connection is closed without GOAWAY frame.
+    ///
+    /// By default GOAWAY frame is sent.
+    pub fn go_away_on_error(mut self, val: bool) -> Self {
+        self.go_away = val;
+        self
+    }
+
+    /// Respond to received pings.
+    ///
+    /// Pings are delivered to control service only if frame notifications
+    /// are enabled, see `Config::frame_notifications()`. If disabled,
+    /// received pings are not acknowledged.
+    ///
+    /// By default pong is sent for each received ping.
+    pub fn auto_ack_pings(mut self, val: bool) -> Self {
+        self.ack_pings = val;
+        self
+    }
+
+    /// Set log level for control messages.
+    ///
+    /// By default control messages are logged with `trace` level.
+    pub fn log_level(mut self, level: log::Level) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    /// Set reset reason for streams failed with application error.
+    ///
+    /// By default streams are reset with `CANCEL` reason.
+    pub fn app_error_reason(mut self, reason: Reason) -> Self {
+        self.app_error_reason = reason;
+        self
+    }
+}
+
+impl Default for ControlPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Control service with custom policy
+///
+/// See [`DefaultControlService::with_policy()`]
+pub struct PolicyControlService(ControlPolicy);
+
+impl<E: fmt::Debug + 'static> ServiceFactory<Control<E>> for PolicyControlService {
+    type Response = ControlAck;
+    type Error = E;
+    type InitError = E;
+    type Service = PolicyControlService;
+
+    async fn create(&self, _: ()) -> Result<Self::Service, Self::InitError> {
+        Ok(*self)
+    }
+}
+
+impl<E: fmt::Debug + 'static> Service<Control<E>> for PolicyControlService {
+    type Response = ControlAck;
+    type Error = E;
+
+    async fn call(
+        &self,
+        msg: Control<E>,
+        _: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let policy = &self.0;
+        log::log!(
+            policy.log_level,
+            "Policy control service is used: {:?}",
+            msg
+        );
+
+        Ok(match msg {
+            Control::AppError(item) => item.reason(policy.app_error_reason).ack(),
+            Control::ConnectionError(item) if !policy.go_away => item.ack_close(),
+            Control::PingReceived(item) if !policy.ack_pings => item.ack_ignore(),
+            msg => msg.ack(),
+        })
+    }
+}
@@ -0,0 +1,137 @@
+use std::{cell::Cell, cell::RefCell, collections::VecDeque, fmt, future::poll_fn, pin::Pin};
+use std::{rc::Rc, task::Context, task::Poll};
+
+use ntex_util::{task::LocalWaker, Stream as FutStream};
+
+use crate::message::{Message, MessageKind};
+use crate::{frame::Reason, frame::StreamId, stream::StreamRef};
+
+/// Stream detached from publish service.
+///
+/// Messages of detached stream are delivered to this receiver
+/// instead of publish service. Receiver is bounded, dispatcher waits
+/// for free space if receiver is full. Dropping receiver before stream
+/// is closed resets stream with `CANCEL` reason.
+pub struct DetachedStream {
+    stream: StreamRef,
+    inner: Rc<Channel>,
+}
+
+pub(crate) struct Channel {
+    queue: RefCell<VecDeque<Message>>,
+    capacity: usize,
+    eof: Cell<bool>,
+    dropped: Cell<bool>,
+    rx_waker: LocalWaker,
+    tx_waker: LocalWaker,
+}
+
+impl Channel {
+    pub(crate) fn new(capacity: usize) -> Rc<Self> {
+        Rc::new(Channel {
+            queue: RefCell::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            eof: Cell::new(false),
+            dropped: Cell::new(false),
+            rx_waker: LocalWaker::new(),
+            tx_waker: LocalWaker::new(),
+        })
+    }
+
+    /// Send message to receiver, wait for free space
+    pub(crate) async fn send(&self, msg: Message) {
+        poll_fn(|cx| {
+            if self.dropped.get() || self.queue.borrow().len() < self.capacity {
+                Poll::Ready(())
+            } else {
+                self.tx_waker.register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await;
+        self.push(msg)
+    }
+
+    /// Send message to receiver regardless of free space
+    pub(crate) fn push(&self, msg: Message) {
+        if self.dropped.get() {
+            return;
+        }
+
+        if matches!(
+            msg.kind(),
+            MessageKind::Headers { eof: true, .. }
+                | MessageKind::Eof(_)
+                | MessageKind::Disconnect(_)
+        ) {
+            self.eof.set(true);
+        }
+        self.queue.borrow_mut().push_back(msg);
+        self.rx_waker.wake();
+    }
+}
+
+impl DetachedStream {
+    pub(crate) fn new(stream: StreamRef, inner: Rc<Channel>) -> Self {
+        DetachedStream { stream, inner }
+    }
+
+    #[inline]
+    /// Get stream id
+    pub fn id(&self) -> StreamId {
+        self.stream.id()
+    }
+
+    #[inline]
+    pub fn stream(&self) -> &StreamRef {
+        &self.stream
+    }
+
+    /// Receive next message of the stream
+    pub async fn recv(&self) -> Option<Message> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Poll for next message of the stream.
+    ///
+    /// Returns `None` after last message of the stream is received.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        if let Some(msg) = self.inner.queue.borrow_mut().pop_front() {
+            self.inner.tx_waker.wake();
+            Poll::Ready(Some(msg))
+        } else if self.inner.eof.get() {
+            Poll::Ready(None)
+        } else {
+            self.inner.rx_waker.register(cx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for DetachedStream {
+    fn drop(&mut self) {
+        self.inner.dropped.set(true);
+        self.inner.queue.borrow_mut().clear();
+        self.inner.tx_waker.wake();
+        if !self.inner.eof.get() {
+            self.stream.reset(Reason::CANCEL);
+        }
+    }
+}
+
+impl FutStream for DetachedStream {
+    type Item = Message;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_recv(cx)
+    }
+}
+
+impl fmt::Debug for DetachedStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetachedStream")
+            .field("stream", &self.stream)
+            .field("queued", &self.inner.queue.borrow().len())
+            .finish()
+    }
+}
@@ -1,15 +1,19 @@
-use std::{fmt, future::poll_fn, future::Future, rc::Rc, task::Context, task::Poll};
+use std::time::Duration;
+use std::{cell::Cell, fmt, future::poll_fn, future::Future, rc::Rc, task::Context, task::Poll};
 
 use ntex_io::DispatchItem;
 use ntex_service::{Pipeline, Service, ServiceCtx};
-use ntex_util::future::{join, Either};
-use ntex_util::{spawn, HashMap};
+use ntex_util::future::{join, select, Either};
+use ntex_util::time::Millis;
+use ntex_util::{spawn, task::LocalWaker, HashMap};
 
+use crate::clock::timeout_checked;
 use crate::connection::{Connection, RecvHalfConnection};
-use crate::control::{Control, ControlAck};
+use crate::control::{Control, ControlAck, ErrorResponse, KeepaliveAction};
 use crate::error::{ConnectionError, OperationError, StreamErrorInner};
 use crate::frame::{Frame, GoAway, Ping, Reason, Reset, StreamId};
-use crate::{codec::Codec, message::Message, stream::StreamRef};
+use crate::message::{Message, MessageKind};
+use crate::{codec::Codec, stream::StreamRef};
 
 /// Amqp server dispatcher service.
 pub(crate) struct Dispatcher<Ctl, Pub>
@@ -30,6 +34,68 @@ where
     publish: Pub,
     connection: Connection,
     last_stream_id: StreamId,
+    inflight: Cell<usize>,
+    inflight_waker: LocalWaker,
+    processed: Cell<usize>,
+}
+
+impl<Ctl, Pub> Inner<Ctl, Pub>
+where
+    Ctl: Service<Control<Pub::Error>>,
+    Pub: Service<Message>,
+{
+    /// Check if new publish call could be started
+    fn poll_inflight(&self, cx: &Context<'_>) -> Poll<()> {
+        let max = self.connection.config().max_concurrent_publish.get();
+        if max == 0 || self.inflight.get() < max {
+            Poll::Ready(())
+        } else {
+            log::trace!(
+                "{}: Max number of in-flight messages is reached {}",
+                self.connection.tag(),
+                max
+            );
+            self.inflight_waker.register(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /// Check if frames budget is exhausted
+    fn budget_exhausted(&self) -> bool {
+        let budget = self.connection.config().frames_budget.get();
+        if budget == 0 {
+            false
+        } else {
+            let processed = self.processed.get() + 1;
+            if processed > budget {
+                self.processed.set(0);
+                true
+            } else {
+                self.processed.set(processed);
+                false
+            }
+        }
+    }
+}
+
+/// Tracks in-flight publish calls
+struct InflightGuard<'a> {
+    count: &'a Cell<usize>,
+    waker: &'a LocalWaker,
+}
+
+impl<'a> InflightGuard<'a> {
+    fn new(count: &'a Cell<usize>, waker: &'a LocalWaker) -> Self {
+        count.set(count.get() + 1);
+        Self { count, waker }
+    }
+}
+
+impl Drop for InflightGuard<'_> {
+    fn drop(&mut self) {
+        self.count.set(self.count.get() - 1);
+        self.waker.wake();
+    }
 }
 
 impl<Ctl, Pub> Dispatcher<Ctl, Pub>
@@ -40,14 +106,52 @@ where
     Pub::Error: fmt::Debug,
 {
     pub(crate) fn new(connection: Connection, control: Ctl, publish: Pub) -> Self {
-        Dispatcher {
+        let dispatcher = Dispatcher {
             connection: connection.recv_half(),
             inner: Rc::new(Inner {
                 publish,
                 connection,
                 control: Pipeline::new(control),
                 last_stream_id: 0.into(),
+                inflight: Cell::new(0),
+                inflight_waker: LocalWaker::new(),
+                processed: Cell::new(0),
             }),
+        };
+
+        // streams monitor checks stalled streams and stream deadlines,
+        // it runs only while there is something to watch
+        let inner = Rc::downgrade(&dispatcher.inner);
+        dispatcher.inner.connection.set_monitor(move || {
+            if let Some(inner) = inner.upgrade() {
+                let _ = spawn(stream_monitor(inner));
+            }
+        });
+        if dispatcher
+            .inner
+            .connection
+            .config()
+            .send_stall_timeout
+            .get()
+            .non_zero()
+        {
+            dispatcher.inner.connection.start_monitor();
+        }
+
+        // keep-alive policy must be applied before io get notified,
+        // io stops dispatcher on keep-alive timeout
+        let inner = Rc::downgrade(&dispatcher.inner);
+        dispatcher.inner.connection.set_keepalive_handler(move || {
+            if let Some(inner) = inner.upgrade() {
+                let _ = spawn(keepalive_timeout(inner));
+            }
+        });
+        dispatcher
+    }
+
+    async fn stream_events<'f>(&'f self, ctx: ServiceCtx<'f, Self>) {
+        for ev in self.connection.take_stream_events() {
+            let _ = control(Control::stream_event(ev), &self.inner, ctx).await;
         }
     }
 
@@ -56,8 +160,19 @@ where
         result: Result<Option<(StreamRef, Message)>, Either<ConnectionError, StreamErrorInner>>,
         ctx: ServiceCtx<'f, Self>,
     ) -> Result<Option<Frame>, ()> {
+        // deliver stream events before message get published
+        self.stream_events(ctx).await;
+
         match result {
-            Ok(Some((stream, msg))) => publish(msg, stream, &self.inner, ctx).await,
+            Ok(Some((stream, msg))) => {
+                // stream could be refused by control service
+                if stream.is_failed() && matches!(msg.kind(), MessageKind::Headers { .. }) {
+                    log::trace!("{}: {:?} is refused", stream.tag(), stream.id());
+                    Ok(None)
+                } else {
+                    publish(msg, stream, &self.inner, ctx).await
+                }
+            }
             Ok(None) => Ok(None),
             Err(Either::Left(err)) => {
                 log::error!(
@@ -65,12 +180,16 @@ where
                     self.connection.tag(),
                     err
                 );
+                if let Some(id) = self.connection.recoverable_error(&err) {
+                    return self.stream_proto_error(err, id, ctx).await;
+                }
                 let streams = self.connection.proto_error(&err);
                 self.handle_connection_error(streams, err.into());
                 control(Control::proto_error(err), &self.inner, ctx).await
             }
             Err(Either::Right(err)) => {
                 let (stream, kind) = err.into_inner();
+                self.connection.stream_error(&kind);
                 stream.set_failed_stream(kind.into());
                 log::error!("{}: Failed to handle message: {:?}", stream.tag(), kind);
 
@@ -81,13 +200,47 @@ where
         }
     }
 
+    /// Let control service handle protocol error as stream error
+    async fn stream_proto_error<'f>(
+        &'f self,
+        err: ConnectionError,
+        id: StreamId,
+        ctx: ServiceCtx<'f, Self>,
+    ) -> Result<Option<Frame>, ()> {
+        let msg = Control::stream_proto_error(err, id)
+            .with_goaway(&self.inner.connection)
+            .with_context(&self.inner.connection)
+            .with_handle(self.inner.connection.control_handle());
+        let result = ctx.call(self.inner.control.get_ref(), msg).await;
+
+        if matches!(result, Ok(ref ack) if !ack.disconnect) {
+            log::debug!(
+                "{}: Protocol error {:?} is handled as stream error for {:?}",
+                self.connection.tag(),
+                err,
+                id
+            );
+            self.connection.downgrade_error(&err, id);
+        } else {
+            let streams = self.connection.proto_error(&err);
+            self.handle_connection_error(streams, err.into());
+        }
+        control_result(result, &self.inner);
+        Ok(None)
+    }
+
+    /// Ask control service how to handle keep-alive timeout
     fn handle_connection_error(&self, streams: HashMap<StreamId, StreamRef>, err: OperationError) {
         if !streams.is_empty() {
             let inner = self.inner.clone();
             let _ = spawn(Box::pin(async move {
                 let p = Pipeline::new(&inner.publish);
                 for stream in streams.into_values() {
-                    let _ = p.call(Message::disconnect(err.clone(), stream)).await;
+                    if let Some(ch) = stream.detached() {
+                        ch.push(Message::disconnect(err.clone(), stream));
+                    } else {
+                        let _ = p.call(Message::disconnect(err.clone(), stream)).await;
+                    }
                 }
             }));
         }
@@ -106,6 +259,29 @@ where
 
     #[inline]
     async fn ready(&self, ctx: ServiceCtx<'_, Self>) -> Result<(), Self::Error> {
+        // yield to other tasks, give a chance to other connections
+        if self.inner.budget_exhausted() {
+            log::trace!("{}: Frames budget is exhausted", self.connection.tag());
+
+            let mut yielded = false;
+            poll_fn(|cx| {
+                if yielded {
+                    Poll::Ready(())
+                } else {
+                    yielded = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await;
+        }
+
+        // stop reading new frames if too many messages are in processing
+        poll_fn(|cx| self.inner.poll_inflight(cx)).await;
+
+        // stop reading new frames if too much payload is buffered
+        poll_fn(|cx| self.inner.connection.poll_recv_buffer(cx)).await;
+
         let (res1, res2) = join(
             ctx.ready(&self.inner.publish),
             ctx.ready(self.inner.control.get_ref()),
@@ -117,7 +293,10 @@ where
                 Err(())
             } else {
                 match ctx
-                    .call_nowait(self.inner.control.get_ref(), Control::error(e))
+                    .call_nowait(
+                        self.inner.control.get_ref(),
+                        Control::error(e).with_handle(self.inner.connection.control_handle()),
+                    )
                     .await
                 {
                     Ok(_) => {
@@ -137,7 +316,8 @@ where
             let inner = self.inner.clone();
             let con = self.connection.connection();
             ntex_util::spawn(async move {
-                if inner.control.call_nowait(Control::error(e)).await.is_ok() {
+                let msg = Control::error(e).with_handle(con.control_handle());
+                if inner.control.call_nowait(msg).await.is_ok() {
                     con.close();
                 }
             });
@@ -146,7 +326,42 @@ where
     }
 
     async fn shutdown(&self) {
-        let _ = self.inner.control.call(Control::terminated()).await;
+        let con = &self.inner.connection;
+        let streams = con.stream_refs();
+        let timeout = con.config().dispatcher_config.disconnect_timeout();
+        let deadline = if timeout.non_zero() {
+            Some(con.clock().now() + Duration::from(Millis::from(timeout)))
+        } else {
+            None
+        };
+
+        let msg = Control::terminated(deadline, streams.iter().map(|s| s.id()).collect())
+            .with_handle(con.control_handle());
+        if let Ok(ack) = self.inner.control.call(msg).await {
+            if ack.drain && !streams.is_empty() {
+                // draining is bounded by disconnect timeout
+                if let Some(deadline) = deadline {
+                    log::debug!(
+                        "{}: Waiting for {} active streams",
+                        con.tag(),
+                        streams.len()
+                    );
+                    let fut = async {
+                        for stream in &streams {
+                            stream.on_close().await;
+                        }
+                    };
+                    let dur = deadline.saturating_duration_since(con.clock().now());
+                    let _ = timeout_checked(con.clock(), dur, fut).await;
+                } else {
+                    log::debug!(
+                        "{}: Disconnect timeout is disabled, do not wait for {} active streams",
+                        con.tag(),
+                        streams.len()
+                    );
+                }
+            }
+        }
 
         join(self.inner.publish.shutdown(), self.inner.control.shutdown()).await;
 
@@ -164,7 +379,16 @@ where
             request
         );
 
-        match request {
+        if let DispatchItem::Item(ref frame) = request {
+            self.connection.frame_received(frame);
+            #[cfg(feature = "tracing")]
+            tracing::trace!(parent: self.connection.span(), frame = ?frame, "recv frame");
+        }
+
+        // all buffered frames are processed, update connection window
+        self.connection.flush_recv_window();
+
+        let result = match request {
             DispatchItem::Item(frame) => match frame {
                 Frame::Headers(hdrs) => {
                     self.handle_message(self.connection.recv_headers(hdrs), ctx)
@@ -184,6 +408,7 @@ where
                         // handle stream errors
                         for err in errs {
                             let (stream, kind) = err.into_inner();
+                            self.connection.stream_error(&kind);
                             stream.set_failed_stream(kind.into());
 
                             self.connection
@@ -198,15 +423,18 @@ where
                         }
                         Ok(None)
                     }
+                    Ok(_) if self.inner.connection.config().frame_notifications() => {
+                        control(Control::settings(settings), &self.inner, ctx).await
+                    }
                     Ok(_) => Ok(None),
                 },
-                Frame::WindowUpdate(update) => {
-                    self.handle_message(
-                        self.connection.recv_window_update(update).map(|_| None),
-                        ctx,
-                    )
-                    .await
-                }
+                Frame::WindowUpdate(update) => match self.connection.recv_window_update(update) {
+                    Ok(()) if self.inner.connection.config().frame_notifications() => {
+                        control(Control::window_update(update), &self.inner, ctx).await
+                    }
+                    Ok(()) => Ok(None),
+                    Err(err) => self.handle_message(Err(err), ctx).await,
+                },
                 Frame::Reset(reset) => {
                     self.handle_message(self.connection.recv_rst_stream(reset).map(|_| None), ctx)
                         .await
@@ -216,6 +444,8 @@ where
                     if ping.is_ack() {
                         self.connection.recv_pong(ping);
                         Ok(None)
+                    } else if self.inner.connection.config().frame_notifications() {
+                        control(Control::ping(ping), &self.inner, ctx).await
                     } else {
                         Ok(Some(Ping::pong(ping.into_payload()).into()))
                     }
@@ -223,9 +453,13 @@ where
                 Frame::GoAway(frm) => {
                     log::trace!("{}: Processing GoAway: {:#?}", self.connection.tag(), frm);
                     let reason = frm.reason();
-                    let streams = self.connection.recv_go_away(reason, frm.data());
+                    let (unprocessed, streams) = self.connection.recv_go_away(&frm);
+                    let mut ids: Vec<_> = unprocessed.keys().copied().collect();
+                    ids.sort();
+                    let drain = self.inner.connection.is_closing();
+                    self.handle_connection_error(unprocessed, OperationError::Unprocessed(reason));
                     self.handle_connection_error(streams, ConnectionError::GoAway(reason).into());
-                    control(Control::go_away(frm), &self.inner, ctx).await
+                    control(Control::go_away(frm, ids, drain), &self.inner, ctx).await
                 }
                 Frame::Priority(prio) => {
                     log::debug!(
@@ -253,7 +487,11 @@ where
                     "{}: did not receive pong response in time, closing connection",
                     self.connection.tag(),
                 );
+                let frm = GoAway::new(Reason::NO_ERROR);
+                let _ = control(Control::local_go_away(frm), &self.inner, ctx).await;
+
                 let streams = self.connection.ping_timeout();
+
                 self.handle_connection_error(streams, ConnectionError::KeepaliveTimeout.into());
                 control(
                     Control::proto_error(ConnectionError::KeepaliveTimeout),
@@ -267,7 +505,11 @@ where
                     "{}: did not receive complete frame in time, closing connection",
                     self.connection.tag(),
                 );
+                let frm = GoAway::new(Reason::NO_ERROR);
+                let _ = control(Control::local_go_away(frm), &self.inner, ctx).await;
+
                 let streams = self.connection.read_timeout();
+
                 self.handle_connection_error(streams, ConnectionError::ReadTimeout.into());
                 control(
                     Control::proto_error(ConnectionError::ReadTimeout),
@@ -282,7 +524,10 @@ where
                 control(Control::peer_gone(err), &self.inner, ctx).await
             }
             DispatchItem::WBackPressureEnabled | DispatchItem::WBackPressureDisabled => Ok(None),
-        }
+        };
+
+        self.stream_events(ctx).await;
+        result
     }
 }
 
@@ -298,6 +543,14 @@ where
     C: Service<Control<P::Error>, Response = ControlAck>,
     C::Error: fmt::Debug,
 {
+    // detached stream, deliver message to stream's receiver
+    if let Some(ch) = stream.detached() {
+        ch.send(msg).await;
+        return Ok(None);
+    }
+
+    let _guard = InflightGuard::new(&inner.inflight, &inner.inflight_waker);
+
     let result = if stream.is_remote() {
         let fut = ctx.call(&inner.publish, msg);
         let mut pinned = std::pin::pin!(fut);
@@ -333,16 +586,34 @@ where
     Pub: Service<Message>,
     Pub::Error: fmt::Debug,
 {
-    match ctx.call(inner.control.get_ref(), pkt).await {
+    let pkt = pkt
+        .with_goaway(&inner.connection)
+        .with_context(&inner.connection)
+        .with_handle(inner.connection.control_handle());
+    control_result(ctx.call(inner.control.get_ref(), pkt).await, inner);
+    Ok(None)
+}
+
+fn control_result<Ctl, Pub>(result: Result<ControlAck, Ctl::Error>, inner: &Inner<Ctl, Pub>)
+where
+    Ctl: Service<Control<Pub::Error>, Response = ControlAck>,
+    Ctl::Error: fmt::Debug,
+    Pub: Service<Message>,
+    Pub::Error: fmt::Debug,
+{
+    match result {
         Ok(res) => {
-            if let Some(Frame::Reset(ref rst)) = res.frame {
-                if !rst.stream_id().is_zero() {
-                    inner.connection.rst_stream(rst.stream_id(), rst.reason());
+            for frm in res.frames {
+                if let Frame::Reset(ref rst) = frm {
+                    if !rst.stream_id().is_zero() {
+                        inner.connection.rst_stream(rst.stream_id(), rst.reason());
+                    }
                 }
-            }
-            if let Some(frm) = res.frame {
                 inner.connection.encode(frm);
             }
+            if let Some(resp) = res.response {
+                send_error_response(resp);
+            }
             if res.disconnect {
                 inner.connection.close();
             }
@@ -360,5 +631,153 @@ where
             inner.connection.close();
         }
     }
-    Ok(None)
+}
+
+/// Send control service response to the failed stream
+fn send_error_response(resp: ErrorResponse) {
+    let ErrorResponse {
+        stream,
+        status,
+        headers,
+        body,
+        reason,
+    } = resp;
+
+    let eof = body.is_empty();
+    if let Err(e) = stream.send_response(status, headers, eof) {
+        log::trace!(
+            "{}: Cannot send error response for {:?}: {:?}",
+            stream.tag(),
+            stream.id(),
+            e
+        );
+        stream.reset(reason);
+    } else if !eof {
+        let _ = spawn(async move {
+            if let Err(e) = stream.send_payload(body, true).await {
+                log::trace!(
+                    "{}: Cannot send error response payload for {:?}: {:?}",
+                    stream.tag(),
+                    stream.id(),
+                    e
+                );
+            }
+        });
+    }
+}
+
+/// Apply keep-alive timeout policy
+async fn keepalive_timeout<Ctl, Pub>(inner: Rc<Inner<Ctl, Pub>>)
+where
+    Ctl: Service<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Pub: Service<Message> + 'static,
+    Pub::Error: fmt::Debug,
+{
+    let con = &inner.connection;
+    let action = if con.is_keepalive_extended() {
+        KeepaliveAction::Close
+    } else {
+        let msg = Control::keepalive_timeout()
+            .with_context(con)
+            .with_handle(con.control_handle());
+        let result = inner.control.call(msg).await;
+        let action = result
+            .as_ref()
+            .ok()
+            .and_then(|ack| ack.keepalive)
+            .unwrap_or(KeepaliveAction::Close);
+        control_result(result, &inner);
+        action
+    };
+    if con.is_closed() {
+        return;
+    }
+
+    match action {
+        KeepaliveAction::Extend => {
+            log::debug!(
+                "{}: did not receive pong response in time, extending deadline",
+                con.tag(),
+            );
+            con.extend_keepalive();
+        }
+        KeepaliveAction::Drain => {
+            log::debug!(
+                "{}: did not receive pong response in time, draining connection",
+                con.tag(),
+            );
+            let frm = GoAway::new(Reason::NO_ERROR).set_last_stream_id(inner.last_stream_id);
+            let msg = Control::local_go_away(frm).with_handle(con.control_handle());
+            let result = inner.control.call(msg).await;
+            control_result(result, &inner);
+
+            con.disconnect_when_ready();
+            con.extend_keepalive();
+        }
+        KeepaliveAction::Close => con.notify_timeout(),
+    }
+}
+
+/// Periodically check streams blocked by flow control and streams with elapsed deadline
+async fn stream_monitor<Ctl, Pub>(inner: Rc<Inner<Ctl, Pub>>)
+where
+    Ctl: Service<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Pub: Service<Message> + 'static,
+    Pub::Error: fmt::Debug,
+{
+    let stall_timeout = inner.connection.config().send_stall_timeout.get();
+    let timeout = Duration::from(Millis::from(stall_timeout));
+    let reset = inner.connection.config().reset_stalled_streams();
+
+    loop {
+        // wake up once per second for stalled streams, or at earliest deadline
+        let con = &inner.connection;
+        let next = con
+            .next_deadline()
+            .map(|deadline| deadline.saturating_duration_since(con.clock().now()));
+        let dur = match (stall_timeout.non_zero(), next) {
+            (true, Some(dur)) => dur.min(Millis::ONE_SEC.into()),
+            (true, None) => Millis::ONE_SEC.into(),
+            (false, Some(dur)) => dur,
+            (false, None) => break,
+        };
+        if !dur.is_zero() {
+            let _ = select(
+                con.clock().sleep(dur),
+                poll_fn(|cx| con.poll_deadline_updated(cx)),
+            )
+            .await;
+        }
+        if inner.connection.is_closed() {
+            break;
+        }
+
+        for stream in inner.connection.expired_streams() {
+            log::debug!("{}: {:?} deadline is elapsed", stream.tag(), stream.id());
+            stream.reset(Reason::CANCEL);
+            let msg =
+                Control::stream_timeout(stream).with_handle(inner.connection.control_handle());
+            let result = inner.control.call(msg).await;
+            control_result(result, &inner);
+        }
+
+        if !stall_timeout.non_zero() {
+            continue;
+        }
+        for (stream, duration) in inner.connection.stalled_streams(timeout) {
+            log::debug!(
+                "{}: {:?} send is blocked by flow control for {:?}",
+                stream.tag(),
+                stream.id(),
+                duration
+            );
+            let msg = Control::stream_stalled(stream, duration, reset)
+                .with_handle(inner.connection.control_handle());
+            let result = inner.control.call(msg).await;
+            control_result(result, &inner);
+        }
+    }
+    inner.connection.monitor_stopped();
 }
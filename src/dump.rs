@@ -0,0 +1,366 @@
+use std::fmt;
+
+use ntex_bytes::ByteString;
+
+use crate::error::OperationError;
+use crate::frame::{Reason, Settings, StreamId, WindowSize};
+
+/// Connection state report
+#[derive(Clone, Debug)]
+pub struct ConnectionDump {
+    pub(crate) tag: &'static str,
+    pub(crate) send_window: WindowSize,
+    pub(crate) recv_window: WindowSize,
+    pub(crate) recv_buffered_bytes: u32,
+    pub(crate) recv_buffered_frames: usize,
+    pub(crate) active_local_streams: u32,
+    pub(crate) active_remote_streams: u32,
+    pub(crate) local_reset_streams: usize,
+    pub(crate) error: Option<OperationError>,
+    pub(crate) streams: Vec<StreamDump>,
+}
+
+/// Negotiated connection settings
+///
+/// Contains settings sent to the peer, settings received from the peer
+/// and effective values in use. Send values apply to frames sent to
+/// the peer, receive values apply to frames received from the peer.
+#[derive(Clone, Debug)]
+pub struct NegotiatedSettings {
+    pub(crate) tag: &'static str,
+    pub(crate) local: Settings,
+    pub(crate) remote: Settings,
+    pub(crate) local_acked: bool,
+    pub(crate) remote_received: bool,
+    pub(crate) send_frame_size: u32,
+    pub(crate) recv_frame_size: u32,
+    pub(crate) send_window_size: WindowSize,
+    pub(crate) recv_window_size: WindowSize,
+    pub(crate) send_max_streams: Option<u32>,
+    pub(crate) recv_max_streams: Option<u32>,
+    pub(crate) send_header_table_size: u32,
+    pub(crate) recv_header_table_size: u32,
+    pub(crate) send_header_list_size: Option<u32>,
+    pub(crate) recv_header_list_size: Option<u32>,
+}
+
+/// Stream state report
+#[derive(Clone, Debug)]
+pub struct StreamDump {
+    pub(crate) id: StreamId,
+    pub(crate) label: Option<ByteString>,
+    pub(crate) remote: bool,
+    pub(crate) send_state: &'static str,
+    pub(crate) recv_state: &'static str,
+    pub(crate) reset_reason: Option<Reason>,
+    pub(crate) send_window: WindowSize,
+    pub(crate) recv_window: WindowSize,
+    pub(crate) recv_buffered: u32,
+    pub(crate) failed: bool,
+}
+
+impl ConnectionDump {
+    /// Connection io tag
+    pub fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    /// Connection level send window
+    pub fn send_window(&self) -> WindowSize {
+        self.send_window
+    }
+
+    /// Connection level receive window
+    pub fn recv_window(&self) -> WindowSize {
+        self.recv_window
+    }
+
+    /// Received but not yet consumed payload size
+    pub fn recv_buffered_bytes(&self) -> u32 {
+        self.recv_buffered_bytes
+    }
+
+    /// Number of received but not yet consumed DATA frames
+    pub fn recv_buffered_frames(&self) -> usize {
+        self.recv_buffered_frames
+    }
+
+    /// Number of active locally initiated streams
+    pub fn active_local_streams(&self) -> u32 {
+        self.active_local_streams
+    }
+
+    /// Number of active remotely initiated streams
+    pub fn active_remote_streams(&self) -> u32 {
+        self.active_remote_streams
+    }
+
+    /// Number of recently reset streams, frames for these streams are ignored
+    pub fn local_reset_streams(&self) -> usize {
+        self.local_reset_streams
+    }
+
+    /// Connection error
+    pub fn error(&self) -> Option<&OperationError> {
+        self.error.as_ref()
+    }
+
+    /// Active streams, ordered by stream id
+    pub fn streams(&self) -> &[StreamDump] {
+        &self.streams
+    }
+}
+
+impl NegotiatedSettings {
+    /// Connection io tag
+    pub fn tag(&self) -> &'static str {
+        self.tag
+    }
+
+    /// Settings sent to the peer
+    pub fn local(&self) -> &Settings {
+        &self.local
+    }
+
+    /// Settings received from the peer, values that peer did not send are `None`
+    pub fn remote(&self) -> &Settings {
+        &self.remote
+    }
+
+    /// Check if local settings are acknowledged by the peer
+    pub fn is_local_acked(&self) -> bool {
+        self.local_acked
+    }
+
+    /// Check if peer's SETTINGS frame is received
+    pub fn is_remote_received(&self) -> bool {
+        self.remote_received
+    }
+
+    /// Max size of sent frames
+    pub fn send_frame_size(&self) -> u32 {
+        self.send_frame_size
+    }
+
+    /// Max size of received frames
+    pub fn recv_frame_size(&self) -> u32 {
+        self.recv_frame_size
+    }
+
+    /// Initial send window of new streams
+    pub fn send_window_size(&self) -> WindowSize {
+        self.send_window_size
+    }
+
+    /// Initial receive window of new streams
+    pub fn recv_window_size(&self) -> WindowSize {
+        self.recv_window_size
+    }
+
+    /// Max number of concurrent locally initiated streams, `None` if unlimited
+    pub fn send_max_streams(&self) -> Option<u32> {
+        self.send_max_streams
+    }
+
+    /// Max number of concurrent remotely initiated streams, `None` if unlimited
+    pub fn recv_max_streams(&self) -> Option<u32> {
+        self.recv_max_streams
+    }
+
+    /// Hpack dynamic table size of the encoder
+    pub fn send_header_table_size(&self) -> u32 {
+        self.send_header_table_size
+    }
+
+    /// Hpack dynamic table size of the decoder
+    pub fn recv_header_table_size(&self) -> u32 {
+        self.recv_header_table_size
+    }
+
+    /// Max size of sent header list, `None` if unlimited
+    pub fn send_header_list_size(&self) -> Option<u32> {
+        self.send_header_list_size
+    }
+
+    /// Max size of received header list, `None` if unlimited
+    pub fn recv_header_list_size(&self) -> Option<u32> {
+        self.recv_header_list_size
+    }
+}
+
+impl StreamDump {
+    /// Stream id
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    /// Stream label
+    pub fn label(&self) -> Option<&ByteString> {
+        self.label.as_ref()
+    }
+
+    /// Check if stream has been opened from remote side
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Send side state, `idle`, `open` or `closed`
+    pub fn send_state(&self) -> &'static str {
+        self.send_state
+    }
+
+    /// Receive side state, `idle`, `open` or `closed`
+    pub fn recv_state(&self) -> &'static str {
+        self.recv_state
+    }
+
+    /// Stream reset reason, local or remote
+    pub fn reset_reason(&self) -> Option<Reason> {
+        self.reset_reason
+    }
+
+    /// Stream send window
+    pub fn send_window(&self) -> WindowSize {
+        self.send_window
+    }
+
+    /// Stream receive window
+    pub fn recv_window(&self) -> WindowSize {
+        self.recv_window
+    }
+
+    /// Received but not yet consumed payload size
+    pub fn recv_buffered(&self) -> u32 {
+        self.recv_buffered
+    }
+
+    /// Check if stream has failed
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+}
+
+impl fmt::Display for ConnectionDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: send-window: {}, recv-window: {}, buffered: {} bytes/{} frames, \
+             streams: {} local/{} remote, reset: {}",
+            self.tag,
+            self.send_window,
+            self.recv_window,
+            self.recv_buffered_bytes,
+            self.recv_buffered_frames,
+            self.active_local_streams,
+            self.active_remote_streams,
+            self.local_reset_streams,
+        )?;
+        if let Some(ref err) = self.error {
+            writeln!(f, "  error: {}", err)?;
+        }
+        for stream in &self.streams {
+            writeln!(f, "  {}", stream)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for NegotiatedSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let acked = if self.local_acked { "" } else { "not " };
+        let received = if self.remote_received { "" } else { "not " };
+        writeln!(
+            f,
+            "{}: local settings {}acked, remote settings {}received",
+            self.tag, acked, received,
+        )?;
+        let rows = [
+            (
+                "header-table-size",
+                self.local.header_table_size(),
+                self.remote.header_table_size(),
+                Some(self.send_header_table_size),
+                Some(self.recv_header_table_size),
+            ),
+            (
+                "max-concurrent-streams",
+                self.local.max_concurrent_streams(),
+                self.remote.max_concurrent_streams(),
+                self.send_max_streams,
+                self.recv_max_streams,
+            ),
+            (
+                "initial-window-size",
+                self.local.initial_window_size(),
+                self.remote.initial_window_size(),
+                Some(self.send_window_size),
+                Some(self.recv_window_size),
+            ),
+            (
+                "max-frame-size",
+                self.local.max_frame_size(),
+                self.remote.max_frame_size(),
+                Some(self.send_frame_size),
+                Some(self.recv_frame_size),
+            ),
+            (
+                "max-header-list-size",
+                self.local.max_header_list_size(),
+                self.remote.max_header_list_size(),
+                self.send_header_list_size,
+                self.recv_header_list_size,
+            ),
+        ];
+        for (name, local, remote, send, recv) in rows {
+            writeln!(
+                f,
+                "  {}: local: {}, remote: {}, send: {}, recv: {}",
+                name,
+                Value(local, "-"),
+                Value(remote, "-"),
+                Value(send, "unlimited"),
+                Value(recv, "unlimited"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Optional setting value
+struct Value(Option<u32>, &'static str);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(val) => write!(f, "{}", val),
+            None => write!(f, "{}", self.1),
+        }
+    }
+}
+
+impl fmt::Display for StreamDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}{}{}: send: {}, recv: {}, send-window: {}, recv-window: {}, buffered: {}",
+            self.id,
+            self.label
+                .as_ref()
+                .map(|label| format!("[{}]", label))
+                .unwrap_or_default(),
+            if self.remote { " (remote)" } else { "" },
+            self.send_state,
+            self.recv_state,
+            self.send_window,
+            self.recv_window,
+            self.recv_buffered,
+        )?;
+        if let Some(reason) = self.reset_reason {
+            write!(f, ", reset: {}", reason)?;
+        }
+        if self.failed {
+            write!(f, ", failed")?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
+use std::{fmt, time::Duration};
+
 pub use crate::codec::EncoderError;
 
 use crate::frame::{self, GoAway, Reason, StreamId};
-use crate::stream::StreamRef;
+use crate::{stats::Stats, stream::StreamRef};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum ConnectionError {
@@ -26,6 +28,12 @@ pub enum ConnectionError {
     /// Missing pseudo header
     #[error("Unexpected pseudo header {0:?}")]
     UnexpectedPseudo(&'static str),
+    /// Pseudo headers ordering violation
+    #[error("Malformed pseudo headers: {0}")]
+    MalformedPseudo(&'static str),
+    /// Extended CONNECT protocol violation
+    #[error("Invalid extended CONNECT: {0}")]
+    ExtendedConnect(&'static str),
     /// Window update value is zero
     #[error("Window update value is zero")]
     ZeroWindowUpdateValue,
@@ -33,6 +41,9 @@ pub enum ConnectionError {
     WindowValueOverflow,
     #[error("Max concurrent streams count achieved")]
     ConcurrencyOverflow,
+    /// Too many empty DATA frames
+    #[error("Too many empty DATA frames")]
+    EmptyDataFlood,
     /// Keep-alive timeout
     #[error("Keep-alive timeout")]
     KeepaliveTimeout,
@@ -48,6 +59,21 @@ impl ConnectionError {
             ConnectionError::Encoder(_) => {
                 GoAway::new(Reason::PROTOCOL_ERROR).set_data("Error during frame encoding")
             }
+            ConnectionError::Decoder(frame::FrameError::InvalidSetting { id, value }) => {
+                // initial window size above maximum is a flow control error
+                let reason = if *id == frame::SETTINGS_INITIAL_WINDOW_SIZE {
+                    Reason::FLOW_CONTROL_ERROR
+                } else {
+                    Reason::PROTOCOL_ERROR
+                };
+                GoAway::new(reason)
+                    .set_data(format!("Invalid value {} for setting {:#x}", value, id))
+            }
+            ConnectionError::Decoder(
+                frame::FrameError::BadFrameSize
+                | frame::FrameError::MaxFrameSize
+                | frame::FrameError::InvalidPayloadLength,
+            ) => GoAway::new(Reason::FRAME_SIZE_ERROR).set_data("Invalid frame size"),
             ConnectionError::Decoder(_) => {
                 GoAway::new(Reason::PROTOCOL_ERROR).set_data("Error during frame decoding")
             }
@@ -55,6 +81,10 @@ impl ConnectionError {
                 .set_data(format!("Missing pseudo header {:?}", s)),
             ConnectionError::UnexpectedPseudo(s) => GoAway::new(Reason::PROTOCOL_ERROR)
                 .set_data(format!("Unexpected pseudo header {:?}", s)),
+            ConnectionError::MalformedPseudo(s) => GoAway::new(Reason::PROTOCOL_ERROR)
+                .set_data(format!("Malformed pseudo headers: {}", s)),
+            ConnectionError::ExtendedConnect(s) => GoAway::new(Reason::PROTOCOL_ERROR)
+                .set_data(format!("Invalid extended CONNECT: {}", s)),
             ConnectionError::UnknownStream(_) => {
                 GoAway::new(Reason::PROTOCOL_ERROR).set_data("Unknown stream")
             }
@@ -72,6 +102,9 @@ impl ConnectionError {
                 .set_data("Updated value for window is overflowed"),
             ConnectionError::ConcurrencyOverflow => GoAway::new(Reason::FLOW_CONTROL_ERROR)
                 .set_data("Max concurrent streams count achieved"),
+            ConnectionError::EmptyDataFlood => {
+                GoAway::new(Reason::ENHANCE_YOUR_CALM).set_data("Too many empty DATA frames")
+            }
             ConnectionError::KeepaliveTimeout => {
                 GoAway::new(Reason::NO_ERROR).set_data("Keep-alive timeout")
             }
@@ -82,6 +115,81 @@ impl ConnectionError {
     }
 }
 
+/// Context of connection failure
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionContext {
+    pub(crate) error: Option<ConnectionError>,
+    pub(crate) frame: Option<(frame::Kind, StreamId)>,
+    pub(crate) go_away: Option<GoAway>,
+    pub(crate) stats: Stats,
+    pub(crate) bytes_sent: u64,
+    pub(crate) bytes_received: u64,
+    pub(crate) duration: Duration,
+}
+
+impl ConnectionContext {
+    /// Local protocol error
+    pub fn error(&self) -> Option<&ConnectionError> {
+        self.error.as_ref()
+    }
+
+    /// Kind of the frame that triggered protocol error
+    ///
+    /// Frame is not available if error happened during frame decoding.
+    pub fn frame_kind(&self) -> Option<frame::Kind> {
+        self.frame.map(|(kind, _)| kind)
+    }
+
+    /// Stream id of the frame that triggered protocol error
+    pub fn stream_id(&self) -> Option<StreamId> {
+        self.frame.map(|(_, id)| id)
+    }
+
+    /// GOAWAY frame received from the peer, includes peer's debug data
+    pub fn go_away(&self) -> Option<&GoAway> {
+        self.go_away.as_ref()
+    }
+
+    /// Connection statistics
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Size of frames sent to the peer, including frame headers
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Size of frames received from the peer, including frame headers
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Connection lifetime
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl fmt::Display for ConnectionContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error {
+            Some(ref err) => write!(f, "{}", err)?,
+            None => write!(f, "Connection is closed")?,
+        }
+        if let Some((kind, id)) = self.frame {
+            write!(f, ", triggered by {:?} frame on {:?}", kind, id)?;
+        }
+        if let Some(ref frm) = self.go_away {
+            write!(f, ", peer sent GOAWAY with {}", frm.reason())?;
+            if !frm.data().is_empty() {
+                write!(f, " ({})", frm.data_lossy())?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, thiserror::Error)]
 #[error("Stream error: {kind:?}")]
 pub(crate) struct StreamErrorInner {
@@ -117,6 +225,10 @@ pub enum StreamError {
     WrongPayloadLength,
     #[error("Non-empty payload for HEAD response")]
     NonEmptyPayload,
+    #[error("Malformed pseudo headers: {0}")]
+    MalformedPseudo(&'static str),
+    #[error("Connection specific header field")]
+    ConnectionHeader,
     #[error("Stream has been reset with {0}")]
     Reset(Reason),
 }
@@ -133,6 +245,8 @@ impl StreamError {
             StreamError::InvalidContentLength => Reason::PROTOCOL_ERROR,
             StreamError::WrongPayloadLength => Reason::PROTOCOL_ERROR,
             StreamError::NonEmptyPayload => Reason::PROTOCOL_ERROR,
+            StreamError::MalformedPseudo(_) => Reason::PROTOCOL_ERROR,
+            StreamError::ConnectionHeader => Reason::PROTOCOL_ERROR,
             StreamError::Reset(r) => *r,
         }
     }
@@ -179,4 +293,80 @@ pub enum OperationError {
     /// Disconnected
     #[error("Connection is closed")]
     Disconnected,
+
+    /// Payload is larger than configured limit
+    #[error("Payload size exceeds limit of {0} bytes")]
+    PayloadOverflow(usize),
+
+    /// Stream has not been processed by the peer
+    ///
+    /// Peer sent GOAWAY frame with last stream id lower than stream's id.
+    #[error("Stream has not been processed by the peer, go away with {0}")]
+    Unprocessed(Reason),
+
+    /// Peer does not support extended CONNECT protocol
+    ///
+    /// Peer did not advertise `SETTINGS_ENABLE_CONNECT_PROTOCOL` setting.
+    #[error("Peer does not support extended CONNECT protocol")]
+    ExtendedConnectNotSupported,
+
+    /// Headers received while reading payload
+    ///
+    /// Message headers must be received before payload is read.
+    #[error("Unexpected headers received while reading payload")]
+    UnexpectedHeaders,
+}
+
+impl OperationError {
+    /// Check if peer did not process the stream.
+    ///
+    /// Such requests could be safely retried regardless of method,
+    /// i.e. stream is refused with `REFUSED_STREAM` reason, stream is not
+    /// processed because of GOAWAY frame or stream has not been opened.
+    pub fn is_before_processing(&self) -> bool {
+        matches!(
+            self,
+            OperationError::Unprocessed(_)
+                | OperationError::OverflowedStreamId
+                | OperationError::RemoteReset(Reason::REFUSED_STREAM)
+        )
+    }
+
+    /// Check if request could be retried on new stream or connection.
+    ///
+    /// Includes errors from `is_before_processing()` and connection failures.
+    /// Connection failures could happen after the peer processed the stream,
+    /// so only idempotent requests should be retried in that case.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            OperationError::Connection(err) => matches!(
+                err,
+                ConnectionError::GoAway(_)
+                    | ConnectionError::KeepaliveTimeout
+                    | ConnectionError::ReadTimeout
+            ),
+            OperationError::Disconnected => true,
+            _ => self.is_before_processing(),
+        }
+    }
+}
+
+/// Configuration errors
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ConfigError {
+    /// Max frame size is not within 16,384 and 16,777,215
+    #[error("Max frame size {0} is out of range")]
+    MaxFrameSize(u32),
+
+    /// Stream window size is larger than 2^31-1
+    #[error("Initial window size {0} is too large")]
+    WindowSize(u32),
+
+    /// Connection window size is larger than 2^31-1
+    #[error("Connection window size {0} is too large")]
+    ConnectionWindowSize(u32),
+
+    /// Environment variable value cannot be parsed
+    #[error("Invalid value of environment variable {0}")]
+    Env(&'static str),
 }
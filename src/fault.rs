@@ -0,0 +1,212 @@
+//! Fault injection for in-memory connections.
+//!
+//! Bytes written to one side of the duplex are relayed to the other side
+//! through fault layer. Layer splits relayed bytes to frames, so faults
+//! could be applied per frame. Connection preface is relayed as is.
+use std::{cmp, rc::Rc};
+
+use ntex_bytes::{Bytes, BytesMut};
+use ntex_io::{testing::IoTest, Io};
+use ntex_util::{task::yield_to, time::Millis};
+
+use crate::clock::{Clock, SystemClock};
+use crate::{consts, frame};
+
+const BUFFER_CAP: usize = 1_000_000;
+
+/// Faults applied to relayed frames
+///
+/// Frames are counted from the first frame after connection preface.
+/// Dropping or corrupting handshake frames fails the handshake.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Faults {
+    drop_every: usize,
+    corrupt_every: usize,
+    delay: Millis,
+    split: usize,
+}
+
+impl Faults {
+    /// Create faults configuration without any faults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop every nth frame.
+    ///
+    /// By default frames are not dropped.
+    pub fn drop_every(mut self, n: usize) -> Self {
+        self.drop_every = n;
+        self
+    }
+
+    /// Corrupt payload of every nth frame.
+    ///
+    /// Last byte of the frame payload is inverted, frames without
+    /// payload are not corrupted.
+    ///
+    /// By default frames are not corrupted.
+    pub fn corrupt_every(mut self, n: usize) -> Self {
+        self.corrupt_every = n;
+        self
+    }
+
+    /// Delay each write.
+    ///
+    /// By default writes are not delayed.
+    pub fn delay(mut self, delay: Millis) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Split frames to chunks of specified size.
+    ///
+    /// Each chunk is delivered with separate read.
+    ///
+    /// By default frames are not split.
+    pub fn split(mut self, size: usize) -> Self {
+        self.split = size;
+        self
+    }
+}
+
+/// Create pair of connected in-memory io objects with fault injection.
+///
+/// `left` faults are applied to bytes written to first io object,
+/// `right` faults are applied to bytes written to second io object.
+pub fn duplex(left: Faults, right: Faults) -> (Io, Io) {
+    duplex_with_clock(left, right, SystemClock)
+}
+
+/// Create pair of connected in-memory io objects with fault injection,
+/// write delays are driven by provided clock.
+pub fn duplex_with_clock<C: Clock>(left: Faults, right: Faults, clock: C) -> (Io, Io) {
+    let clock: Rc<dyn Clock> = Rc::new(clock);
+    let (l_io, l_relay) = IoTest::create();
+    let (r_relay, r_io) = IoTest::create();
+    for io in [&l_io, &l_relay, &r_relay, &r_io] {
+        io.remote_buffer_cap(BUFFER_CAP);
+    }
+
+    let _ = ntex_util::spawn(relay(l_relay.clone(), r_relay.clone(), left, clock.clone()));
+    let _ = ntex_util::spawn(relay(r_relay, l_relay, right, clock));
+
+    (Io::new(l_io), Io::new(r_io))
+}
+
+async fn relay(from: IoTest, to: IoTest, faults: Faults, clock: Rc<dyn Clock>) {
+    let mut layer = Layer::new(faults);
+
+    loop {
+        let data = match from.read().await {
+            Ok(data) if !data.is_empty() => data,
+            _ => break,
+        };
+
+        for frm in layer.process(&data) {
+            if faults.delay.non_zero() {
+                clock.sleep(faults.delay.into()).await;
+            }
+
+            if faults.split == 0 {
+                to.write(frm);
+            } else {
+                for chunk in frm.chunks(faults.split) {
+                    to.write(chunk);
+                    yield_to().await;
+                }
+            }
+        }
+    }
+    log::debug!("Fault relay is stopped");
+    to.close().await;
+}
+
+struct Layer {
+    faults: Faults,
+    buf: BytesMut,
+    preface: bool,
+    count: usize,
+}
+
+impl Layer {
+    fn new(faults: Faults) -> Self {
+        Self {
+            faults,
+            buf: BytesMut::new(),
+            preface: true,
+            count: 0,
+        }
+    }
+
+    /// Split data to frames and apply faults
+    fn process(&mut self, data: &[u8]) -> Vec<Bytes> {
+        let mut frames = Vec::new();
+        self.buf.extend_from_slice(data);
+
+        if self.preface {
+            let len = cmp::min(self.buf.len(), consts::PREFACE.len());
+            if self.buf[..len] == consts::PREFACE[..len] {
+                if len < consts::PREFACE.len() {
+                    return frames;
+                }
+                frames.push(self.buf.split_to(len).freeze());
+            }
+            self.preface = false;
+        }
+
+        while self.buf.len() >= frame::HEADER_LEN {
+            let size = frame::HEADER_LEN
+                + ((self.buf[0] as usize) << 16
+                    | (self.buf[1] as usize) << 8
+                    | self.buf[2] as usize);
+            if self.buf.len() < size {
+                break;
+            }
+            let mut frm = self.buf.split_to(size);
+            self.count += 1;
+
+            if self.faults.drop_every != 0 && self.count % self.faults.drop_every == 0 {
+                log::trace!("Drop frame {} of type {:#x}", self.count, frm[3]);
+                continue;
+            }
+            if self.faults.corrupt_every != 0
+                && self.count % self.faults.corrupt_every == 0
+                && size > frame::HEADER_LEN
+            {
+                log::trace!("Corrupt frame {} of type {:#x}", self.count, frm[3]);
+                frm[size - 1] ^= 0xff;
+            }
+            frames.push(frm.freeze());
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer() {
+        let mut frm = [0; 13];
+        frm[2] = 4;
+        frm[12] = 1;
+
+        let mut layer = Layer::new(Faults::new().drop_every(2).corrupt_every(3));
+        let mut data = consts::PREFACE.to_vec();
+        for _ in 0..3 {
+            data.extend_from_slice(&frm);
+        }
+        let frames = layer.process(&data[..10]);
+        assert!(frames.is_empty());
+        let frames = layer.process(&data[10..30]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], consts::PREFACE[..]);
+
+        let frames = layer.process(&data[30..]);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], frm[..]);
+        assert_eq!(frames[1][12], 0xfe);
+    }
+}
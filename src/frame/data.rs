@@ -1,6 +1,6 @@
 use ntex_bytes::{Bytes, BytesMut};
 
-use crate::frame::{util, Frame, FrameError, Head, Kind, StreamId};
+use crate::frame::{util, Frame, FrameError, Head, Kind, StreamId, HEADER_LEN};
 
 /// Data frame
 ///
@@ -115,6 +115,8 @@ impl Data {
 
     /// Encode the data frame into the `dst` buffer.
     pub(crate) fn encode(&self, dst: &mut BytesMut) {
+        dst.reserve(HEADER_LEN + self.data.len());
+
         // Encode the frame head to the buffer
         self.head().encode(self.data.len(), dst);
         // Encode payload
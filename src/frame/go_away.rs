@@ -4,6 +4,9 @@ use ntex_bytes::{BufMut, Bytes, BytesMut};
 
 use crate::frame::{self, FrameError, Head, Kind, Reason, StreamId};
 
+/// Max size of GOAWAY debug data, received and sent data is truncated
+pub const MAX_GOAWAY_DATA_SIZE: usize = 256;
+
 #[derive(Clone, Eq, PartialEq)]
 pub struct GoAway {
     last_stream_id: StreamId,
@@ -30,6 +33,7 @@ impl GoAway {
         Bytes: From<T>,
     {
         self.data = data.into();
+        self.data.truncate(MAX_GOAWAY_DATA_SIZE);
         self
     }
 
@@ -50,6 +54,15 @@ impl GoAway {
         &self.data
     }
 
+    /// Debug data sanitized for logging.
+    ///
+    /// Invalid utf-8 sequences are replaced, control characters are escaped.
+    pub fn data_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.data)
+            .escape_debug()
+            .to_string()
+    }
+
     pub fn load(payload: &[u8]) -> Result<GoAway, FrameError> {
         if payload.len() < 8 {
             return Err(FrameError::BadFrameSize);
@@ -57,7 +70,9 @@ impl GoAway {
 
         let (last_stream_id, _) = StreamId::parse(&payload[..4]);
         let error_code = unpack_octets_4!(payload, 4, u32);
-        let data = Bytes::copy_from_slice(&payload[8..]);
+        // peer's debug data is not trusted, keep limited prefix only
+        let end = payload.len().min(8 + MAX_GOAWAY_DATA_SIZE);
+        let data = Bytes::copy_from_slice(&payload[8..end]);
 
         Ok(GoAway {
             last_stream_id,
@@ -69,6 +84,7 @@ impl GoAway {
     pub fn encode(&self, dst: &mut BytesMut) {
         log::trace!("encoding GO_AWAY; code={:?}", self.error_code);
         let head = Head::new(Kind::GoAway, 0, StreamId::zero());
+        dst.reserve(frame::HEADER_LEN + 8 + self.data.len());
         head.encode(8 + self.data.len(), dst);
         dst.put_u32(self.last_stream_id.into());
         dst.put_u32(self.error_code.into());
@@ -89,7 +105,7 @@ impl fmt::Debug for GoAway {
         builder.field("last_stream_id", &self.last_stream_id);
 
         if !self.data.is_empty() {
-            builder.field("data", &self.data);
+            builder.field("data", &self.data_lossy());
         }
 
         builder.finish()
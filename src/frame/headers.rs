@@ -6,7 +6,7 @@ use ntex_http::{header, uri, HeaderMap, HeaderName, Method, StatusCode, Uri};
 use crate::hpack;
 
 use super::priority::StreamDependency;
-use super::{util, Frame, FrameError, Head, Kind, Protocol, StreamId};
+use super::{util, Frame, FrameError, Head, Kind, Protocol, StreamId, HEADER_LEN};
 
 /// Header frame
 ///
@@ -55,6 +55,12 @@ struct HeaderBlock {
     /// Pseudo headers, these are broken out as they must be sent as part of the
     /// headers frame.
     pseudo: PseudoHeaders,
+
+    /// Pseudo headers ordering violation
+    malformed: Option<&'static str>,
+
+    /// Connection specific header fields were dropped during decoding
+    conn_headers: bool,
 }
 
 const END_STREAM: u8 = 0x1;
@@ -75,7 +81,12 @@ impl Headers {
         Headers {
             flags,
             stream_id,
-            header_block: HeaderBlock { fields, pseudo },
+            header_block: HeaderBlock {
+                fields,
+                pseudo,
+                malformed: None,
+                conn_headers: false,
+            },
         }
     }
 
@@ -89,6 +100,8 @@ impl Headers {
             header_block: HeaderBlock {
                 fields,
                 pseudo: PseudoHeaders::default(),
+                malformed: None,
+                conn_headers: false,
             },
         }
     }
@@ -145,6 +158,8 @@ impl Headers {
             header_block: HeaderBlock {
                 fields: HeaderMap::new(),
                 pseudo: PseudoHeaders::default(),
+                malformed: None,
+                conn_headers: false,
             },
         })
     }
@@ -189,6 +204,22 @@ impl Headers {
         &self.header_block.pseudo
     }
 
+    /// Pseudo headers ordering violation, if any.
+    ///
+    /// Pseudo headers after regular fields and repeated pseudo headers
+    /// do not fail decoding, first value of pseudo header is kept.
+    pub fn malformed_pseudo(&self) -> Option<&'static str> {
+        self.header_block.malformed
+    }
+
+    /// Check if connection specific header fields were received.
+    ///
+    /// Such fields, for example `transfer-encoding`, are not allowed in http/2
+    /// messages and are not added to decoded header map.
+    pub fn has_connection_headers(&self) -> bool {
+        self.header_block.conn_headers
+    }
+
     pub fn into_fields(self) -> HeaderMap {
         self.header_block.fields
     }
@@ -419,17 +450,18 @@ thread_local! {
 impl HeaderBlock {
     fn load(&mut self, src: &mut BytesMut, decoder: &mut hpack::Decoder) -> Result<(), FrameError> {
         let mut reg = !self.fields.is_empty();
-        let mut malformed = false;
 
         macro_rules! set_pseudo {
             ($field:ident, $val:expr) => {{
-                if reg {
-                    log::trace!("load_hpack; header malformed -- pseudo not at head of block");
-                    malformed = true;
-                } else if self.pseudo.$field.is_some() {
+                if self.pseudo.$field.is_some() {
                     log::trace!("load_hpack; header malformed -- repeated pseudo");
-                    malformed = true;
+                    self.malformed.get_or_insert("repeated pseudo header");
                 } else {
+                    if reg {
+                        log::trace!("load_hpack; header malformed -- pseudo not at head of block");
+                        self.malformed
+                            .get_or_insert("pseudo header after regular field");
+                    }
                     self.pseudo.$field = Some($val.into());
                 }
             }};
@@ -441,13 +473,14 @@ impl HeaderBlock {
         // the headers. A malformed header frame is a stream level error, but
         // the hpack state is connection level. In order to maintain correct
         // state for other streams, the hpack decoding process must complete.
+        // Malformed fields are dropped, stream level errors are handled by stream.
         let res = decoder.decode(&mut cursor, |header| {
             use crate::hpack::Header::*;
 
             match header {
                 Field { name, value } => {
                     // Connection level header fields are not supported and must
-                    // result in a protocol error.
+                    // result in a stream protocol error.
 
                     if name == header::CONNECTION
                         || name == header::TRANSFER_ENCODING
@@ -456,10 +489,10 @@ impl HeaderBlock {
                         || name == "proxy-connection"
                     {
                         log::trace!("load_hpack; connection level header");
-                        malformed = true;
+                        self.conn_headers = true;
                     } else if name == header::TE && value != "trailers" {
                         log::trace!("load_hpack; TE header not set to trailers; val={:?}", value);
-                        malformed = true;
+                        self.conn_headers = true;
                     } else {
                         reg = true;
                         self.fields.append(name, value);
@@ -491,11 +524,6 @@ impl HeaderBlock {
             return Err(e.into());
         }
 
-        if malformed {
-            log::trace!("malformed message");
-            return Err(FrameError::MalformedMessage);
-        }
-
         Ok(())
     }
 
@@ -518,6 +546,10 @@ impl HeaderBlock {
             };
             encoder.encode(headers, hpack);
 
+            // reserve space for all HEADERS/CONTINUATION frames
+            let frames = cmp::max(1, hpack.len().div_ceil(max_size));
+            dst.reserve(hpack.len() + frames * HEADER_LEN);
+
             let mut head = *head;
             let mut start = 0;
             loop {
@@ -24,11 +24,51 @@ macro_rules! unpack_octets_4 {
 
 #[cfg(test)]
 mod tests {
+    use ntex_bytes::BytesMut;
+    use ntex_http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+
+    use super::*;
+
     #[test]
     fn test_unpack_octets_4() {
         let buf: [u8; 4] = [0, 0, 0, 1];
         assert_eq!(1u32, unpack_octets_4!(buf, 0, u32));
     }
+
+    #[test]
+    fn test_parse_write() {
+        let mut hdrs = HeaderMap::new();
+        hdrs.insert(
+            HeaderName::from_static("x-test"),
+            HeaderValue::from_static("value"),
+        );
+        let frm = Headers::new(
+            1.into(),
+            PseudoHeaders::response(StatusCode::OK),
+            hdrs,
+            true,
+        );
+
+        let mut buf = BytesMut::new();
+        Frame::from(frm).write_to(&mut buf).unwrap();
+        Frame::from(Ping::new([1; 8])).write_to(&mut buf).unwrap();
+        let len = buf.len();
+
+        let (frm, size) = Frame::parse(&buf).unwrap().unwrap();
+        let Frame::Headers(hdrs) = frm else {
+            panic!("unexpected frame {:?}", frm);
+        };
+        assert_eq!(hdrs.pseudo().status, Some(StatusCode::OK));
+        assert_eq!(hdrs.fields().get("x-test").unwrap(), "value");
+        assert!(hdrs.is_end_stream());
+
+        let (frm, size2) = Frame::parse(&buf[size..]).unwrap().unwrap();
+        assert!(matches!(frm, Frame::Ping(_)));
+        assert_eq!(size + size2, len);
+
+        // incomplete frame
+        assert!(Frame::parse(&buf[..size - 1]).unwrap().is_none());
+    }
 }
 
 mod data;
@@ -46,7 +86,7 @@ mod util;
 mod window_update;
 
 pub use self::data::Data;
-pub use self::go_away::GoAway;
+pub use self::go_away::{GoAway, MAX_GOAWAY_DATA_SIZE};
 pub use self::head::{Head, Kind};
 pub use self::headers::{Headers, PseudoHeaders};
 pub use self::ping::Ping;
@@ -61,10 +101,13 @@ pub use self::window_update::WindowUpdate;
 // Re-export some constants
 pub use self::settings::{
     DEFAULT_INITIAL_WINDOW_SIZE, DEFAULT_MAX_FRAME_SIZE, DEFAULT_SETTINGS_HEADER_TABLE_SIZE,
-    MAX_INITIAL_WINDOW_SIZE, MAX_MAX_FRAME_SIZE,
+    MAX_INITIAL_WINDOW_SIZE, MAX_MAX_FRAME_SIZE, SETTINGS_INITIAL_WINDOW_SIZE,
 };
 
-use crate::hpack;
+use ntex_bytes::BytesMut;
+use ntex_codec::{Decoder, Encoder};
+
+use crate::{codec::Codec, codec::EncoderError, hpack};
 
 pub type FrameSize = u32;
 pub type WindowSize = u32;
@@ -83,6 +126,28 @@ pub enum Frame {
     Reset(Reset),
 }
 
+impl Frame {
+    /// Parse frame from the buffer.
+    ///
+    /// Returns parsed frame and number of consumed bytes, or `None` if buffer
+    /// does not contain complete frame. Header blocks are decoded with new
+    /// hpack context, use [`Codec`] for decoding sequence of frames.
+    pub fn parse(src: &[u8]) -> Result<Option<(Frame, usize)>, FrameError> {
+        let mut buf = BytesMut::copy_from_slice(src);
+        Ok(Codec::default()
+            .decode(&mut buf)?
+            .map(|frame| (frame, src.len() - buf.len())))
+    }
+
+    /// Serialize frame to the buffer.
+    ///
+    /// Header blocks are encoded with new hpack context, use [`Codec`]
+    /// for encoding sequence of frames.
+    pub fn write_to(self, dst: &mut BytesMut) -> Result<(), EncoderError> {
+        Codec::default().encode(self, dst)
+    }
+}
+
 impl fmt::Debug for Frame {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         use self::Frame::*;
@@ -117,8 +182,8 @@ pub enum FrameError {
     TooMuchPadding,
 
     /// An invalid setting value was provided
-    #[error("An invalid setting value was provided")]
-    InvalidSettingValue,
+    #[error("An invalid value {value} for setting {id:#x} was provided")]
+    InvalidSetting { id: u16, value: u32 },
 
     /// The payload length specified by the frame header was not the
     /// value necessary for the specific frame type.
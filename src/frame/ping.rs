@@ -1,6 +1,6 @@
 use ntex_bytes::BufMut;
 
-use crate::frame::{Frame, FrameError, Head, Kind, StreamId};
+use crate::frame::{Frame, FrameError, Head, Kind, StreamId, HEADER_LEN};
 
 const ACK_FLAG: u8 = 0x1;
 
@@ -13,6 +13,9 @@ pub struct Ping {
 }
 
 impl Ping {
+    /// Size of encoded frame
+    pub(crate) const ENCODED_LEN: usize = HEADER_LEN + 8;
+
     pub fn new(payload: Payload) -> Ping {
         Ping {
             ack: false,
@@ -33,6 +33,10 @@ impl Priority {
             dependency,
         })
     }
+
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
 }
 
 impl From<Priority> for Frame {
@@ -1,6 +1,6 @@
 use ntex_bytes::BufMut;
 
-use crate::frame::{Frame, FrameError, Head, Kind, Reason, StreamId};
+use crate::frame::{Frame, FrameError, Head, Kind, Reason, StreamId, HEADER_LEN};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Reset {
@@ -9,6 +9,9 @@ pub struct Reset {
 }
 
 impl Reset {
+    /// Size of encoded frame
+    pub(crate) const ENCODED_LEN: usize = HEADER_LEN + 4;
+
     pub fn new(stream_id: StreamId, error: Reason) -> Reset {
         Reset {
             stream_id,
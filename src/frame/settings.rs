@@ -1,10 +1,13 @@
 use std::fmt;
 
-use crate::frame::{util, Frame, FrameError, FrameSize, Head, Kind, StreamId};
+use crate::frame::{util, Frame, FrameError, FrameSize, Head, Kind, StreamId, HEADER_LEN};
 use ntex_bytes::{BufMut, BytesMut};
 
 #[derive(Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
 pub struct Settings {
+    #[cfg_attr(feature = "serde", serde(skip))]
     flags: SettingsFlags,
     // Fields
     header_table_size: Option<u32>,
@@ -52,6 +55,9 @@ pub const MAX_INITIAL_WINDOW_SIZE: usize = (1 << 31) - 1;
 /// MAX_FRAME_SIZE upper bound
 pub const MAX_MAX_FRAME_SIZE: FrameSize = (1 << 24) - 1;
 
+/// Identifier of SETTINGS_INITIAL_WINDOW_SIZE setting
+pub const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 4;
+
 // ===== impl Settings =====
 
 impl Settings {
@@ -119,6 +125,19 @@ impl Settings {
         self.header_table_size
     }
 
+    /// Update settings with values set in `other`
+    pub(crate) fn merge(&mut self, other: &Settings) {
+        self.header_table_size = other.header_table_size.or(self.header_table_size);
+        self.enable_push = other.enable_push.or(self.enable_push);
+        self.max_concurrent_streams = other.max_concurrent_streams.or(self.max_concurrent_streams);
+        self.initial_window_size = other.initial_window_size.or(self.initial_window_size);
+        self.max_frame_size = other.max_frame_size.or(self.max_frame_size);
+        self.max_header_list_size = other.max_header_list_size.or(self.max_header_list_size);
+        self.enable_connect_protocol = other
+            .enable_connect_protocol
+            .or(self.enable_connect_protocol);
+    }
+
     /*
     pub fn set_header_table_size(&mut self, size: Option<u32>) {
         self.header_table_size = size;
@@ -166,7 +185,7 @@ impl Settings {
                         settings.enable_push = Some(val);
                     }
                     _ => {
-                        return Err(FrameError::InvalidSettingValue);
+                        return Err(EnablePush(val).invalid());
                     }
                 },
                 Some(MaxConcurrentStreams(val)) => {
@@ -174,7 +193,7 @@ impl Settings {
                 }
                 Some(InitialWindowSize(val)) => {
                     if val as usize > MAX_INITIAL_WINDOW_SIZE {
-                        return Err(FrameError::InvalidSettingValue);
+                        return Err(InitialWindowSize(val).invalid());
                     } else {
                         settings.initial_window_size = Some(val);
                     }
@@ -183,7 +202,7 @@ impl Settings {
                     if (DEFAULT_MAX_FRAME_SIZE..=MAX_MAX_FRAME_SIZE).contains(&val) {
                         settings.max_frame_size = Some(val);
                     } else {
-                        return Err(FrameError::InvalidSettingValue);
+                        return Err(MaxFrameSize(val).invalid());
                     }
                 }
                 Some(MaxHeaderListSize(val)) => {
@@ -194,7 +213,7 @@ impl Settings {
                         settings.enable_connect_protocol = Some(val);
                     }
                     _ => {
-                        return Err(FrameError::InvalidSettingValue);
+                        return Err(EnableConnectProtocol(val).invalid());
                     }
                 },
                 None => {}
@@ -216,6 +235,7 @@ impl Settings {
         // Create & encode an appropriate frame head
         let head = Head::new(Kind::Settings, self.flags.into(), StreamId::zero());
         let payload_len = self.payload_len();
+        dst.reserve(HEADER_LEN + payload_len);
         head.encode(payload_len, dst);
 
         // Encode the settings
@@ -310,7 +330,7 @@ impl Setting {
             1 => Some(HeaderTableSize(val)),
             2 => Some(EnablePush(val)),
             3 => Some(MaxConcurrentStreams(val)),
-            4 => Some(InitialWindowSize(val)),
+            SETTINGS_INITIAL_WINDOW_SIZE => Some(InitialWindowSize(val)),
             5 => Some(MaxFrameSize(val)),
             6 => Some(MaxHeaderListSize(val)),
             8 => Some(EnableConnectProtocol(val)),
@@ -335,18 +355,29 @@ impl Setting {
         Setting::from_id(id, val)
     }
 
-    fn encode(&self, dst: &mut BytesMut) {
+    /// Setting id and value
+    fn parts(&self) -> (u16, u32) {
         use self::Setting::*;
 
-        let (kind, val) = match *self {
+        match *self {
             HeaderTableSize(v) => (1, v),
             EnablePush(v) => (2, v),
             MaxConcurrentStreams(v) => (3, v),
-            InitialWindowSize(v) => (4, v),
+            InitialWindowSize(v) => (SETTINGS_INITIAL_WINDOW_SIZE, v),
             MaxFrameSize(v) => (5, v),
             MaxHeaderListSize(v) => (6, v),
             EnableConnectProtocol(v) => (8, v),
-        };
+        }
+    }
+
+    /// Error for invalid setting value
+    fn invalid(&self) -> FrameError {
+        let (id, value) = self.parts();
+        FrameError::InvalidSetting { id, value }
+    }
+
+    fn encode(&self, dst: &mut BytesMut) {
+        let (kind, val) = self.parts();
 
         dst.put_u16(kind);
         dst.put_u32(val);
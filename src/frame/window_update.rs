@@ -11,6 +11,9 @@ pub struct WindowUpdate {
 }
 
 impl WindowUpdate {
+    /// Size of encoded frame
+    pub(crate) const ENCODED_LEN: usize = frame::HEADER_LEN + 4;
+
     pub fn new(stream_id: StreamId, size_increment: u32) -> WindowUpdate {
         WindowUpdate {
             stream_id,
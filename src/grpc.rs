@@ -0,0 +1,541 @@
+//! gRPC helpers.
+//!
+//! Implements common pieces of [gRPC over HTTP/2] on top of http/2 streams:
+//! length-prefixed message framing over DATA frames, `grpc-status` and
+//! `grpc-message` trailers, trailers-only responses and `grpc-timeout`
+//! propagation to the stream deadline.
+//!
+//! Each message is prefixed with compression flag and message length:
+//!
+//! ```text
+//! +-----------------+---------------------+---------+
+//! | Compressed (8)  | Message length (32) | Message |
+//! +-----------------+---------------------+---------+
+//! ```
+//!
+//! [gRPC over HTTP/2]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+use std::{fmt, time::Duration};
+
+use ntex_bytes::{Buf, BufMut, ByteString, Bytes, BytesMut};
+use ntex_http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use ntex_util::time::Millis;
+
+use crate::{error::OperationError, stream::StreamRef};
+
+/// `grpc-status` header name
+pub const GRPC_STATUS: HeaderName = HeaderName::from_static("grpc-status");
+/// `grpc-message` header name
+pub const GRPC_MESSAGE: HeaderName = HeaderName::from_static("grpc-message");
+/// `grpc-timeout` header name
+pub const GRPC_TIMEOUT: HeaderName = HeaderName::from_static("grpc-timeout");
+
+/// gRPC content type
+pub const CONTENT_TYPE: &str = "application/grpc";
+
+/// Size of message prefix
+pub const PREFIX_LEN: usize = 5;
+
+/// Default max size of received message
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Max value of `grpc-timeout`
+const MAX_TIMEOUT_VALUE: u64 = 99_999_999;
+
+/// gRPC framing errors
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GrpcError {
+    /// Compression flag is not 0 or 1
+    #[error("Invalid compression flag {0}")]
+    InvalidFlag(u8),
+    /// Message is larger than allowed
+    #[error("Message size {0} exceeds limit")]
+    MessageTooLarge(usize),
+    /// Payload ended in the middle of message
+    #[error("Incomplete message")]
+    Incomplete,
+}
+
+/// gRPC status codes.
+///
+/// See [status codes] for more information.
+///
+/// [status codes]: https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Code(u32);
+
+impl Code {
+    pub const OK: Code = Code(0);
+    pub const CANCELLED: Code = Code(1);
+    pub const UNKNOWN: Code = Code(2);
+    pub const INVALID_ARGUMENT: Code = Code(3);
+    pub const DEADLINE_EXCEEDED: Code = Code(4);
+    pub const NOT_FOUND: Code = Code(5);
+    pub const ALREADY_EXISTS: Code = Code(6);
+    pub const PERMISSION_DENIED: Code = Code(7);
+    pub const RESOURCE_EXHAUSTED: Code = Code(8);
+    pub const FAILED_PRECONDITION: Code = Code(9);
+    pub const ABORTED: Code = Code(10);
+    pub const OUT_OF_RANGE: Code = Code(11);
+    pub const UNIMPLEMENTED: Code = Code(12);
+    pub const INTERNAL: Code = Code(13);
+    pub const UNAVAILABLE: Code = Code(14);
+    pub const DATA_LOSS: Code = Code(15);
+    pub const UNAUTHENTICATED: Code = Code(16);
+
+    /// Status code name
+    pub fn name(&self) -> Option<&'static str> {
+        Some(match self.0 {
+            0 => "OK",
+            1 => "CANCELLED",
+            2 => "UNKNOWN",
+            3 => "INVALID_ARGUMENT",
+            4 => "DEADLINE_EXCEEDED",
+            5 => "NOT_FOUND",
+            6 => "ALREADY_EXISTS",
+            7 => "PERMISSION_DENIED",
+            8 => "RESOURCE_EXHAUSTED",
+            9 => "FAILED_PRECONDITION",
+            10 => "ABORTED",
+            11 => "OUT_OF_RANGE",
+            12 => "UNIMPLEMENTED",
+            13 => "INTERNAL",
+            14 => "UNAVAILABLE",
+            15 => "DATA_LOSS",
+            16 => "UNAUTHENTICATED",
+            _ => return None,
+        })
+    }
+}
+
+impl From<u32> for Code {
+    fn from(src: u32) -> Code {
+        Code(src)
+    }
+}
+
+impl From<Code> for u32 {
+    fn from(src: Code) -> u32 {
+        src.0
+    }
+}
+
+impl fmt::Debug for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => f.debug_tuple("Code").field(&self.0).finish(),
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// gRPC call status
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    code: Code,
+    message: ByteString,
+}
+
+impl Status {
+    /// Create new status
+    pub fn new<T: Into<ByteString>>(code: Code, message: T) -> Self {
+        Status {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Successful status
+    pub fn ok() -> Self {
+        Status::new(Code::OK, ByteString::new())
+    }
+
+    /// Status code
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    /// Status message
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Check if status is `OK`
+    pub fn is_ok(&self) -> bool {
+        self.code == Code::OK
+    }
+
+    /// Read status from trailers or from headers of trailers-only response.
+    ///
+    /// Returns `None` if `grpc-status` header is missing. Malformed
+    /// `grpc-status` is reported as `UNKNOWN` status.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Status> {
+        let code = headers.get(&GRPC_STATUS)?;
+        let code = code
+            .to_str()
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .map(Code)
+            .unwrap_or(Code::UNKNOWN);
+        let message = headers
+            .get(&GRPC_MESSAGE)
+            .map(|v| percent_decode(v.as_bytes()))
+            .unwrap_or_default();
+        Some(Status::new(code, message))
+    }
+
+    /// Add `grpc-status` and `grpc-message` headers
+    pub fn to_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(GRPC_STATUS, HeaderValue::from(self.code.0));
+        if !self.message.is_empty() {
+            if let Ok(val) = HeaderValue::from_str(&percent_encode(&self.message)) {
+                headers.insert(GRPC_MESSAGE, val);
+            }
+        }
+    }
+}
+
+/// Length-prefixed gRPC message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrpcMessage {
+    compressed: bool,
+    payload: Bytes,
+}
+
+impl GrpcMessage {
+    /// Create new message
+    pub fn new(payload: Bytes, compressed: bool) -> Self {
+        GrpcMessage {
+            compressed,
+            payload,
+        }
+    }
+
+    /// Check if message payload is compressed
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Message payload
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Consume message and get payload
+    pub fn into_payload(self) -> Bytes {
+        self.payload
+    }
+
+    /// Encode message with length prefix
+    pub fn encode(&self, dst: &mut BytesMut) {
+        dst.reserve(PREFIX_LEN + self.payload.len());
+        dst.put_u8(self.compressed as u8);
+        dst.put_u32(self.payload.len() as u32);
+        dst.extend_from_slice(&self.payload);
+    }
+}
+
+/// Decoder of length-prefixed messages.
+///
+/// Payload chunks of DATA frames are pushed to the decoder, one chunk could
+/// contain several messages or only part of the message.
+#[derive(Debug)]
+pub struct Decoder {
+    buf: BytesMut,
+    max_size: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+    }
+}
+
+impl Decoder {
+    /// Create new decoder
+    pub fn new() -> Self {
+        Decoder {
+            buf: BytesMut::new(),
+            max_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Set max size of received message.
+    ///
+    /// By default max size is 4Mb.
+    pub fn set_max_size(&mut self, size: usize) {
+        self.max_size = size;
+    }
+
+    /// Add payload chunk
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Decode next message from buffered payload
+    pub fn decode(&mut self) -> Result<Option<GrpcMessage>, GrpcError> {
+        if self.buf.len() < PREFIX_LEN {
+            return Ok(None);
+        }
+
+        let compressed = match self.buf[0] {
+            0 => false,
+            1 => true,
+            flag => return Err(GrpcError::InvalidFlag(flag)),
+        };
+        let size = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]);
+        let size = size as usize;
+        if size > self.max_size {
+            return Err(GrpcError::MessageTooLarge(size));
+        }
+
+        if self.buf.len() < PREFIX_LEN + size {
+            self.buf.reserve(PREFIX_LEN + size - self.buf.len());
+            Ok(None)
+        } else {
+            self.buf.advance(PREFIX_LEN);
+            let payload = self.buf.split_to(size).freeze();
+            Ok(Some(GrpcMessage::new(payload, compressed)))
+        }
+    }
+
+    /// Check end of payload.
+    ///
+    /// Returns error if payload ended in the middle of message.
+    pub fn finish(&self) -> Result<(), GrpcError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(GrpcError::Incomplete)
+        }
+    }
+}
+
+/// Parse `grpc-timeout` header value
+pub fn parse_timeout(val: &HeaderValue) -> Option<Duration> {
+    let val = val.as_bytes();
+    if val.len() < 2 || val.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = val.split_at(val.len() - 1);
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let num = std::str::from_utf8(digits).ok()?.parse::<u64>().ok()?;
+
+    Some(match unit[0] {
+        b'H' => Duration::from_secs(num * 3600),
+        b'M' => Duration::from_secs(num * 60),
+        b'S' => Duration::from_secs(num),
+        b'm' => Duration::from_millis(num),
+        b'u' => Duration::from_micros(num),
+        b'n' => Duration::from_nanos(num),
+        _ => return None,
+    })
+}
+
+/// Encode timeout as `grpc-timeout` header value.
+///
+/// Uses the most precise unit that fits into 8 digits.
+pub fn timeout_value(timeout: Duration) -> HeaderValue {
+    let units: [(u128, u8); 6] = [
+        (timeout.as_nanos(), b'n'),
+        (timeout.as_micros(), b'u'),
+        (timeout.as_millis(), b'm'),
+        (timeout.as_secs() as u128, b'S'),
+        (timeout.as_secs().div_ceil(60) as u128, b'M'),
+        (timeout.as_secs().div_ceil(3600) as u128, b'H'),
+    ];
+    let (num, unit) = units
+        .into_iter()
+        .find(|(num, _)| *num <= MAX_TIMEOUT_VALUE as u128)
+        .unwrap_or((MAX_TIMEOUT_VALUE as u128, b'H'));
+
+    let mut buf = num.to_string().into_bytes();
+    buf.push(unit);
+    HeaderValue::from_bytes(&buf).unwrap()
+}
+
+/// Apply `grpc-timeout` of request headers as stream deadline.
+///
+/// Deadline is rounded up to milliseconds, `Control::StreamTimeout` message is
+/// sent to control service once deadline is elapsed.
+pub fn propagate_timeout(stream: &StreamRef, headers: &HeaderMap) -> Option<Duration> {
+    let timeout = headers.get(&GRPC_TIMEOUT).and_then(parse_timeout)?;
+    let millis = timeout.as_millis() + u128::from(timeout.subsec_nanos() % 1_000_000 > 0);
+    stream.set_deadline(Millis(millis.clamp(1, u32::MAX as u128) as u32));
+    Some(timeout)
+}
+
+/// Build headers of gRPC request
+pub fn request_headers(timeout: Option<Duration>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    headers.insert(header::TE, HeaderValue::from_static("trailers"));
+    if let Some(timeout) = timeout {
+        headers.insert(GRPC_TIMEOUT, timeout_value(timeout));
+    }
+    headers
+}
+
+/// Send response headers with gRPC content type
+pub fn send_response(stream: &StreamRef, mut headers: HeaderMap) -> Result<(), OperationError> {
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    stream.send_response(StatusCode::OK, headers, false)
+}
+
+/// Send length-prefixed message
+pub async fn send_message(stream: &StreamRef, msg: &GrpcMessage) -> Result<(), OperationError> {
+    let mut buf = BytesMut::new();
+    msg.encode(&mut buf);
+    stream.send_payload(buf.freeze(), false).await
+}
+
+/// Finish response with status trailers
+pub fn send_status(stream: &StreamRef, status: &Status) {
+    let mut trailers = HeaderMap::new();
+    status.to_headers(&mut trailers);
+    stream.send_trailers(trailers);
+}
+
+/// Send trailers-only response.
+///
+/// Status is sent in response headers, stream is closed immediately.
+pub fn send_trailers_only(
+    stream: &StreamRef,
+    status: &Status,
+    mut headers: HeaderMap,
+) -> Result<(), OperationError> {
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    status.to_headers(&mut headers);
+    stream.send_response(StatusCode::OK, headers, true)
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if (0x20..0x7f).contains(&b) && b != b'%' {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
+    }
+    res
+}
+
+fn percent_decode(s: &[u8]) -> ByteString {
+    let hex = |b: u8| (b as char).to_digit(16);
+
+    let mut res = Vec::with_capacity(s.len());
+    let mut idx = 0;
+    while idx < s.len() {
+        if s[idx] == b'%' && idx + 2 < s.len() {
+            if let (Some(h), Some(l)) = (hex(s[idx + 1]), hex(s[idx + 2])) {
+                res.push((h * 16 + l) as u8);
+                idx += 3;
+                continue;
+            }
+        }
+        res.push(s[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&res).into_owned().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framing() {
+        let mut buf = BytesMut::new();
+        GrpcMessage::new(Bytes::from_static(b"hello"), false).encode(&mut buf);
+        GrpcMessage::new(Bytes::from_static(b"world"), true).encode(&mut buf);
+        assert_eq!(&buf[..PREFIX_LEN], &[0, 0, 0, 0, 5]);
+
+        let mut dec = Decoder::new();
+        dec.push(&buf[..7]);
+        assert_eq!(dec.decode().unwrap(), None);
+        assert_eq!(dec.finish(), Err(GrpcError::Incomplete));
+
+        dec.push(&buf[7..]);
+        let msg = dec.decode().unwrap().unwrap();
+        assert!(!msg.is_compressed());
+        assert_eq!(msg.payload(), &Bytes::from_static(b"hello"));
+        let msg = dec.decode().unwrap().unwrap();
+        assert!(msg.is_compressed());
+        assert_eq!(msg.into_payload(), Bytes::from_static(b"world"));
+        assert_eq!(dec.decode().unwrap(), None);
+        assert!(dec.finish().is_ok());
+
+        let mut dec = Decoder::new();
+        dec.set_max_size(4);
+        dec.push(&buf);
+        assert_eq!(dec.decode(), Err(GrpcError::MessageTooLarge(5)));
+
+        let mut dec = Decoder::new();
+        dec.push(&[2, 0, 0, 0, 0]);
+        assert_eq!(dec.decode(), Err(GrpcError::InvalidFlag(2)));
+    }
+
+    #[test]
+    fn test_status() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Status::from_headers(&headers), None);
+
+        Status::new(Code::NOT_FOUND, "not found: 100%\n").to_headers(&mut headers);
+        assert_eq!(headers.get(&GRPC_STATUS).unwrap(), "5");
+        assert_eq!(headers.get(&GRPC_MESSAGE).unwrap(), "not found: 100%25%0A");
+        let status = Status::from_headers(&headers).unwrap();
+        assert_eq!(status.code(), Code::NOT_FOUND);
+        assert_eq!(status.message(), "not found: 100%\n");
+        assert!(!status.is_ok());
+
+        headers.insert(GRPC_STATUS, HeaderValue::from_static("x"));
+        let status = Status::from_headers(&headers).unwrap();
+        assert_eq!(status.code(), Code::UNKNOWN);
+
+        assert_eq!(format!("{:?}", Code::UNAVAILABLE), "UNAVAILABLE");
+        assert_eq!(format!("{:?}", Code::from(100)), "Code(100)");
+        assert!(Status::ok().is_ok());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let val = |s| HeaderValue::from_static(s);
+        assert_eq!(parse_timeout(&val("1H")), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_timeout(&val("2M")), Some(Duration::from_secs(120)));
+        assert_eq!(parse_timeout(&val("3S")), Some(Duration::from_secs(3)));
+        assert_eq!(
+            parse_timeout(&val("100m")),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(parse_timeout(&val("5u")), Some(Duration::from_micros(5)));
+        assert_eq!(parse_timeout(&val("7n")), Some(Duration::from_nanos(7)));
+        assert_eq!(parse_timeout(&val("S")), None);
+        assert_eq!(parse_timeout(&val("123456789S")), None);
+        assert_eq!(parse_timeout(&val("1x")), None);
+        assert_eq!(parse_timeout(&val("-1S")), None);
+
+        assert_eq!(timeout_value(Duration::from_millis(5)), "5000000n");
+        assert_eq!(timeout_value(Duration::from_millis(500)), "500000u");
+        assert_eq!(timeout_value(Duration::from_secs(30)), "30000000u");
+        assert_eq!(timeout_value(Duration::from_secs(300)), "300000m");
+        assert_eq!(timeout_value(Duration::from_secs(1_000_000)), "1000000S");
+
+        let headers = request_headers(Some(Duration::from_secs(1)));
+        assert_eq!(headers.get(header::TE).unwrap(), "trailers");
+        assert_eq!(
+            parse_timeout(headers.get(&GRPC_TIMEOUT).unwrap()),
+            Some(Duration::from_secs(1))
+        );
+    }
+}
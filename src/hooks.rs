@@ -0,0 +1,75 @@
+use std::{fmt, rc::Rc};
+
+use crate::error::ConnectionError;
+use crate::frame::{Frame, GoAway, Ping, Reason, Settings, StreamId};
+
+type FrameHook<T> = Option<Rc<dyn Fn(&T)>>;
+type MapGoAway = dyn Fn(&ConnectionError, GoAway) -> GoAway;
+
+/// Connection event callbacks
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) on_goaway: FrameHook<GoAway>,
+    pub(crate) on_ping: FrameHook<Ping>,
+    pub(crate) on_stream_reset: Option<Rc<dyn Fn(StreamId, Reason)>>,
+    pub(crate) on_settings: FrameHook<Settings>,
+    pub(crate) map_goaway: Option<Rc<MapGoAway>>,
+}
+
+impl Hooks {
+    /// Check if frame callbacks are registered
+    pub(crate) fn is_empty(&self) -> bool {
+        self.on_goaway.is_none()
+            && self.on_ping.is_none()
+            && self.on_stream_reset.is_none()
+            && self.on_settings.is_none()
+    }
+
+    /// Call registered callback for received frame
+    pub(crate) fn frame_received(&self, frame: &Frame) {
+        match frame {
+            Frame::GoAway(frm) => {
+                if let Some(ref f) = self.on_goaway {
+                    f(frm)
+                }
+            }
+            Frame::Ping(frm) => {
+                if let Some(ref f) = self.on_ping {
+                    f(frm)
+                }
+            }
+            Frame::Reset(frm) => {
+                if let Some(ref f) = self.on_stream_reset {
+                    f(frm.stream_id(), frm.reason())
+                }
+            }
+            Frame::Settings(frm) => {
+                if let Some(ref f) = self.on_settings {
+                    f(frm)
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Convert protocol error to GOAWAY frame
+    pub(crate) fn map_goaway(&self, err: &ConnectionError, frm: GoAway) -> GoAway {
+        if let Some(ref f) = self.map_goaway {
+            f(err, frm)
+        } else {
+            frm
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_goaway", &self.on_goaway.is_some())
+            .field("on_ping", &self.on_ping.is_some())
+            .field("on_stream_reset", &self.on_stream_reset.is_some())
+            .field("on_settings", &self.on_settings.is_some())
+            .field("map_goaway", &self.map_goaway.is_some())
+            .finish()
+    }
+}
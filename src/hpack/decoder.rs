@@ -3,7 +3,7 @@ use std::{cmp, collections::VecDeque, io::Cursor, str::Utf8Error};
 use ntex_bytes::{Buf, ByteString, Bytes, BytesMut};
 use ntex_http::{error, header, Method, StatusCode};
 
-use super::{huffman, Header};
+use super::{huffman, table::TableState, Header};
 
 /// Decodes headers using HPACK
 #[derive(Debug)]
@@ -168,6 +168,15 @@ impl Decoder {
         }
     }
 
+    /// Snapshot of dynamic table
+    pub fn table_state(&self) -> TableState {
+        TableState::new(
+            self.table.size,
+            self.table.max_size,
+            self.table.entries.iter().cloned().collect(),
+        )
+    }
+
     /// Queues a potential size update
     #[allow(dead_code)]
     pub fn queue_size_update(&mut self, size: usize) {
@@ -1,7 +1,7 @@
 use ntex_bytes::{BufMut, BytesMut};
 use ntex_http::header::{HeaderName, HeaderValue};
 
-use super::{huffman, table::Index, table::Table, Header};
+use super::{huffman, table::Index, table::Table, table::TableState, Header};
 
 #[derive(Debug)]
 pub struct Encoder {
@@ -23,6 +23,11 @@ impl Encoder {
         }
     }
 
+    /// Snapshot of dynamic table
+    pub fn table_state(&self) -> TableState {
+        self.table.state()
+    }
+
     /// Queues a max size update.
     ///
     /// The next call to `encode` will include a dynamic size update frame.
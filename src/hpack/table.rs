@@ -1,7 +1,7 @@
-use std::{cmp, collections::VecDeque, hash::Hash, hash::Hasher, mem};
+use std::{cmp, collections::VecDeque, fmt, hash::Hash, hash::Hasher, mem};
 
 use fxhash::FxHasher;
-use ntex_http::{header, Method};
+use ntex_http::Method;
 
 use super::Header;
 
@@ -17,6 +17,14 @@ pub struct Table {
     max_size: usize,
 }
 
+/// Snapshot of hpack dynamic table
+#[derive(Clone, Debug)]
+pub struct TableState {
+    size: usize,
+    max_size: usize,
+    entries: Vec<Header>,
+}
+
 #[derive(Debug)]
 pub enum Index {
     // The header is already fully indexed
@@ -102,6 +110,15 @@ impl Table {
         self.max_size
     }
 
+    /// Snapshot of table entries
+    pub fn state(&self) -> TableState {
+        TableState::new(
+            self.size,
+            self.max_size,
+            self.slots.iter().map(|slot| slot.header.clone()).collect(),
+        )
+    }
+
     /// Gets the header stored in the table
     pub fn resolve<'a>(&'a self, index: &'a Index) -> &'a Header {
         use self::Index::*;
@@ -618,6 +635,56 @@ impl Table {
     }
 }
 
+impl TableState {
+    pub(crate) fn new(size: usize, max_size: usize, entries: Vec<Header>) -> Self {
+        TableState {
+            size,
+            max_size,
+            entries,
+        }
+    }
+
+    /// Current table size in bytes, as defined by hpack spec
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Maximum table size in bytes
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Table entries, newest first.
+    ///
+    /// First entry has hpack index 62.
+    pub fn entries(&self) -> &[Header] {
+        &self.entries
+    }
+}
+
+impl fmt::Display for TableState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "size: {}/{}, entries: {}",
+            self.size,
+            self.max_size,
+            self.entries.len()
+        )?;
+        for (idx, entry) in self.entries.iter().enumerate() {
+            writeln!(
+                f,
+                "[{}] {}: {} ({})",
+                idx + 62,
+                String::from_utf8_lossy(entry.name().as_slice()),
+                String::from_utf8_lossy(entry.value_slice()),
+                entry.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 impl Table {
     /// Returns the number of headers in the table
@@ -676,61 +743,10 @@ fn index_static(header: &Header) -> Option<(usize, bool)> {
         Header::Field {
             ref name,
             ref value,
-        } => match *name {
-            header::ACCEPT_CHARSET => Some((15, false)),
-            header::ACCEPT_ENCODING => {
-                if value == "gzip, deflate" {
-                    Some((16, true))
-                } else {
-                    Some((16, false))
-                }
-            }
-            header::ACCEPT_LANGUAGE => Some((17, false)),
-            header::ACCEPT_RANGES => Some((18, false)),
-            header::ACCEPT => Some((19, false)),
-            header::ACCESS_CONTROL_ALLOW_ORIGIN => Some((20, false)),
-            header::AGE => Some((21, false)),
-            header::ALLOW => Some((22, false)),
-            header::AUTHORIZATION => Some((23, false)),
-            header::CACHE_CONTROL => Some((24, false)),
-            header::CONTENT_DISPOSITION => Some((25, false)),
-            header::CONTENT_ENCODING => Some((26, false)),
-            header::CONTENT_LANGUAGE => Some((27, false)),
-            header::CONTENT_LENGTH => Some((28, false)),
-            header::CONTENT_LOCATION => Some((29, false)),
-            header::CONTENT_RANGE => Some((30, false)),
-            header::CONTENT_TYPE => Some((31, false)),
-            header::COOKIE => Some((32, false)),
-            header::DATE => Some((33, false)),
-            header::ETAG => Some((34, false)),
-            header::EXPECT => Some((35, false)),
-            header::EXPIRES => Some((36, false)),
-            header::FROM => Some((37, false)),
-            header::HOST => Some((38, false)),
-            header::IF_MATCH => Some((39, false)),
-            header::IF_MODIFIED_SINCE => Some((40, false)),
-            header::IF_NONE_MATCH => Some((41, false)),
-            header::IF_RANGE => Some((42, false)),
-            header::IF_UNMODIFIED_SINCE => Some((43, false)),
-            header::LAST_MODIFIED => Some((44, false)),
-            header::LINK => Some((45, false)),
-            header::LOCATION => Some((46, false)),
-            header::MAX_FORWARDS => Some((47, false)),
-            header::PROXY_AUTHENTICATE => Some((48, false)),
-            header::PROXY_AUTHORIZATION => Some((49, false)),
-            header::RANGE => Some((50, false)),
-            header::REFERER => Some((51, false)),
-            header::REFRESH => Some((52, false)),
-            header::RETRY_AFTER => Some((53, false)),
-            header::SERVER => Some((54, false)),
-            header::SET_COOKIE => Some((55, false)),
-            header::STRICT_TRANSPORT_SECURITY => Some((56, false)),
-            header::TRANSFER_ENCODING => Some((57, false)),
-            header::USER_AGENT => Some((58, false)),
-            header::VARY => Some((59, false)),
-            header::VIA => Some((60, false)),
-            header::WWW_AUTHENTICATE => Some((61, false)),
-            _ => None,
+        } => match index_static_name(name.as_str()) {
+            Some(16) if value == "gzip, deflate" => Some((16, true)),
+            Some(idx) => Some((idx, false)),
+            None => None,
         },
         Header::Authority(_) => Some((1, false)),
         Header::Method(ref v) => match *v {
@@ -761,3 +777,120 @@ fn index_static(header: &Header) -> Option<(usize, bool)> {
         },
     }
 }
+
+/// Lookup header name in the static table.
+///
+/// Names are grouped by length, so each lookup compares against
+/// a few candidates at most.
+fn index_static_name(name: &str) -> Option<usize> {
+    let idx = match name.len() {
+        3 => match name {
+            "age" => 21,
+            "via" => 60,
+            _ => return None,
+        },
+        4 => match name {
+            "date" => 33,
+            "etag" => 34,
+            "from" => 37,
+            "host" => 38,
+            "link" => 45,
+            "vary" => 59,
+            _ => return None,
+        },
+        5 => match name {
+            "allow" => 22,
+            "range" => 50,
+            _ => return None,
+        },
+        6 => match name {
+            "accept" => 19,
+            "cookie" => 32,
+            "expect" => 35,
+            "server" => 54,
+            _ => return None,
+        },
+        7 => match name {
+            "expires" => 36,
+            "referer" => 51,
+            "refresh" => 52,
+            _ => return None,
+        },
+        8 => match name {
+            "if-match" => 39,
+            "if-range" => 42,
+            "location" => 46,
+            _ => return None,
+        },
+        10 => match name {
+            "set-cookie" => 55,
+            "user-agent" => 58,
+            _ => return None,
+        },
+        11 if name == "retry-after" => 53,
+        12 => match name {
+            "content-type" => 31,
+            "max-forwards" => 47,
+            _ => return None,
+        },
+        13 => match name {
+            "accept-ranges" => 18,
+            "authorization" => 23,
+            "cache-control" => 24,
+            "content-range" => 30,
+            "if-none-match" => 41,
+            "last-modified" => 44,
+            _ => return None,
+        },
+        14 => match name {
+            "accept-charset" => 15,
+            "content-length" => 28,
+            _ => return None,
+        },
+        15 => match name {
+            "accept-encoding" => 16,
+            "accept-language" => 17,
+            _ => return None,
+        },
+        16 => match name {
+            "content-encoding" => 26,
+            "content-language" => 27,
+            "content-location" => 29,
+            "www-authenticate" => 61,
+            _ => return None,
+        },
+        17 => match name {
+            "if-modified-since" => 40,
+            "transfer-encoding" => 57,
+            _ => return None,
+        },
+        18 if name == "proxy-authenticate" => 48,
+        19 => match name {
+            "content-disposition" => 25,
+            "if-unmodified-since" => 43,
+            "proxy-authorization" => 49,
+            _ => return None,
+        },
+        25 if name == "strict-transport-security" => 56,
+        27 if name == "access-control-allow-origin" => 20,
+        _ => return None,
+    };
+    Some(idx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hpack::decoder::get_static;
+
+    #[test]
+    fn test_index_static_name() {
+        for idx in 15..=61 {
+            let header = get_static(idx);
+            let expected = if idx == 16 { (16, true) } else { (idx, false) };
+            assert_eq!(index_static(&header), Some(expected));
+        }
+        assert_eq!(index_static_name("x-custom"), None);
+        assert_eq!(index_static_name("vias"), None);
+    }
+}
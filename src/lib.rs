@@ -83,29 +83,60 @@ macro_rules! proto_err {
     };
 }
 
+mod body;
+mod clock;
 mod codec;
 mod config;
 mod connection;
 mod consts;
 mod control;
 mod default;
+mod detached;
 mod dispatcher;
+mod dump;
 mod error;
+mod hooks;
 mod message;
+mod qlog;
+mod stats;
 mod stream;
 mod window;
 
+pub mod capture;
 pub mod client;
+#[cfg(feature = "http")]
+pub mod compat;
+pub mod datagram;
+#[cfg(feature = "fault")]
+pub mod fault;
 pub mod frame;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod hpack;
+pub mod proxy;
 pub mod server;
+#[cfg(feature = "testing")]
+pub mod spec;
+#[cfg(feature = "testing")]
+pub mod test;
+#[cfg(feature = "tokio")]
+pub mod transport;
 
-pub use self::codec::Codec;
-pub use self::config::Config;
-pub use self::control::{Control, ControlAck};
+pub use self::body::StreamBody;
+pub use self::clock::{Clock, ManualClock, SystemClock};
+pub use self::codec::{Codec, Direction};
+pub use self::config::{Config, ContentLengthPolicy, HeadPayloadPolicy};
+pub use self::config::{ResetStreamViolation, ViolationPolicy};
+pub use self::control::{Control, ControlAck, ControlHandle, RefuseCause};
+pub use self::default::{ControlPolicy, DefaultControlService, PolicyControlService};
+pub use self::detached::DetachedStream;
+pub use self::dump::{ConnectionDump, NegotiatedSettings, StreamDump};
 pub use self::message::{Message, MessageKind, StreamEof};
-pub use self::stream::{Capacity, Stream, StreamRef};
-pub use crate::error::{ConnectionError, EncoderError, OperationError, StreamError};
+pub use self::stats::{FrameStats, MetricKind, MetricsSnapshot, RttStats, Stats, Violations};
+pub use self::stream::{Capacity, Stream, StreamClose, StreamRef, StreamStatus};
+pub use crate::error::{
+    ConfigError, ConnectionContext, ConnectionError, EncoderError, OperationError, StreamError,
+};
 
 #[doc(hidden)]
 pub use self::control::{ControlMessage, ControlResult};
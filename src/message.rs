@@ -30,6 +30,15 @@ pub enum StreamEof {
     Error(StreamError),
 }
 
+/// Payload item, used by body readers
+pub(crate) enum PayloadItem {
+    Chunk(Bytes),
+    Trailers(HeaderMap),
+    Skip,
+    Eof,
+    Error(OperationError),
+}
+
 impl Message {
     pub(crate) fn new(
         pseudo: PseudoHeaders,
@@ -97,3 +106,29 @@ impl Message {
         &self.stream
     }
 }
+
+impl MessageKind {
+    /// Convert message to payload item
+    ///
+    /// Informational headers are skipped, any other non-final headers
+    /// are not expected while payload is read.
+    pub(crate) fn into_payload(self) -> PayloadItem {
+        match self {
+            MessageKind::Data(data, _cap) => PayloadItem::Chunk(data),
+            MessageKind::Eof(StreamEof::Data(data)) if !data.is_empty() => PayloadItem::Chunk(data),
+            MessageKind::Eof(StreamEof::Data(_)) => PayloadItem::Eof,
+            MessageKind::Eof(StreamEof::Trailers(hdrs)) => PayloadItem::Trailers(hdrs),
+            MessageKind::Eof(StreamEof::Error(err)) => PayloadItem::Error(err.into()),
+            MessageKind::Disconnect(err) => PayloadItem::Error(err),
+            MessageKind::Headers {
+                ref pseudo,
+                eof: false,
+                ..
+            } if pseudo.status.is_some_and(|s| s.is_informational()) => PayloadItem::Skip,
+            MessageKind::Headers { eof: false, .. } => {
+                PayloadItem::Error(OperationError::UnexpectedHeaders)
+            }
+            MessageKind::Headers { eof: true, .. } => PayloadItem::Eof,
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Proxy passthrough helpers.
+//!
+//! Helpers for bridging streams of two http/2 connections. Downstream
+//! stream is the stream received from the client, upstream stream is
+//! the stream opened to the origin. Hop-by-hop state, like flow control
+//! and connection errors, is not forwarded; error codes that describe
+//! failure of a single hop are translated before propagating.
+use ntex_http::{header, HeaderMap, HeaderName};
+
+use crate::error::{ConnectionError, OperationError, StreamError};
+use crate::message::{Message, MessageKind, StreamEof};
+use crate::{frame::Reason, stream::StreamRef};
+
+/// Remove hop-by-hop headers.
+///
+/// Removes connection specific headers and headers listed
+/// in `connection` header. `te` is preserved only with `trailers` value.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(','))
+        .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+
+    headers.remove(header::CONNECTION);
+    headers.remove(header::TRANSFER_ENCODING);
+    headers.remove(header::UPGRADE);
+    headers.remove("keep-alive");
+    headers.remove("proxy-connection");
+    if headers
+        .get(header::TE)
+        .is_some_and(|val| val.as_bytes() != b"trailers")
+    {
+        headers.remove(header::TE);
+    }
+}
+
+/// Translate reset reason received from one hop for the other hop.
+///
+/// Errors related to framing or flow control of a single connection
+/// are not caused by the peer of the other hop, such reasons are
+/// translated to `INTERNAL_ERROR`.
+pub fn translate_reason(reason: Reason) -> Reason {
+    match reason {
+        Reason::NO_ERROR
+        | Reason::REFUSED_STREAM
+        | Reason::CANCEL
+        | Reason::CONNECT_ERROR
+        | Reason::ENHANCE_YOUR_CALM
+        | Reason::INADEQUATE_SECURITY
+        | Reason::HTTP_1_1_REQUIRED => reason,
+        _ => Reason::INTERNAL_ERROR,
+    }
+}
+
+/// Reset reason for the other hop of failed stream.
+///
+/// Streams that were not processed because of GOAWAY are reset
+/// with `REFUSED_STREAM`, so the client could safely retry them.
+pub fn reset_reason(err: &OperationError) -> Reason {
+    match err {
+        OperationError::Unprocessed(_) | OperationError::OverflowedStreamId => {
+            Reason::REFUSED_STREAM
+        }
+        OperationError::RemoteReset(reason)
+        | OperationError::LocalReset(reason)
+        | OperationError::Stream(StreamError::Reset(reason)) => translate_reason(*reason),
+        OperationError::Connection(ConnectionError::GoAway(reason)) => {
+            if *reason == Reason::NO_ERROR {
+                Reason::CANCEL
+            } else {
+                translate_reason(*reason)
+            }
+        }
+        OperationError::Disconnected => Reason::CANCEL,
+        _ => Reason::INTERNAL_ERROR,
+    }
+}
+
+/// Forward message received on one stream to the other stream.
+///
+/// Response headers, including informational responses, are forwarded
+/// only to remote streams, request headers must be sent with client.
+/// Flow control capacity of DATA frame is released after payload is sent
+/// to the other hop, so slow peer applies backpressure to the sender.
+/// Failed stream resets the other stream, see [`reset_reason`].
+///
+/// Returns `true` if message completes the stream.
+pub async fn forward(msg: Message, to: &StreamRef) -> Result<bool, OperationError> {
+    match msg.kind {
+        MessageKind::Headers {
+            pseudo,
+            mut headers,
+            eof,
+        } => {
+            strip_hop_by_hop(&mut headers);
+            match pseudo.status {
+                Some(status) if status.is_informational() => {
+                    to.send_informational(status, headers)?;
+                    Ok(false)
+                }
+                Some(status) if to.is_remote() => {
+                    to.send_response(status, headers, eof)?;
+                    Ok(eof)
+                }
+                _ => {
+                    log::debug!("{}: Cannot forward headers to {:?}", to.tag(), to.id());
+                    Err(OperationError::Payload)
+                }
+            }
+        }
+        MessageKind::Data(data, cap) => {
+            to.send_payload(data, false).await?;
+            drop(cap);
+            Ok(false)
+        }
+        MessageKind::Eof(StreamEof::Data(data)) => {
+            to.send_payload(data, true).await?;
+            Ok(true)
+        }
+        MessageKind::Eof(StreamEof::Trailers(mut trailers)) => {
+            strip_hop_by_hop(&mut trailers);
+            to.send_trailers(trailers);
+            Ok(true)
+        }
+        MessageKind::Eof(StreamEof::Error(err)) => {
+            to.reset(reset_reason(&err.into()));
+            Ok(true)
+        }
+        MessageKind::Disconnect(err) => {
+            to.reset(reset_reason(&err));
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ntex_http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("x-hop, close"));
+        headers.insert(
+            HeaderName::from_static("x-hop"),
+            HeaderValue::from_static("1"),
+        );
+        headers.insert(
+            HeaderName::from_static("x-end"),
+            HeaderValue::from_static("1"),
+        );
+        headers.insert(
+            HeaderName::from_static("keep-alive"),
+            HeaderValue::from_static("5"),
+        );
+        headers.insert(header::TE, HeaderValue::from_static("gzip"));
+        strip_hop_by_hop(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key("x-end"));
+
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+        strip_hop_by_hop(&mut headers);
+        assert!(headers.contains_key(header::TE));
+    }
+
+    #[test]
+    fn test_reset_reason() {
+        assert_eq!(translate_reason(Reason::CANCEL), Reason::CANCEL);
+        assert_eq!(
+            translate_reason(Reason::FLOW_CONTROL_ERROR),
+            Reason::INTERNAL_ERROR
+        );
+        assert_eq!(
+            reset_reason(&OperationError::Unprocessed(Reason::NO_ERROR)),
+            Reason::REFUSED_STREAM
+        );
+        assert_eq!(
+            reset_reason(&OperationError::RemoteReset(Reason::PROTOCOL_ERROR)),
+            Reason::INTERNAL_ERROR
+        );
+        assert_eq!(
+            reset_reason(&ConnectionError::GoAway(Reason::NO_ERROR).into()),
+            Reason::CANCEL
+        );
+        assert_eq!(
+            reset_reason(&ConnectionError::GoAway(Reason::ENHANCE_YOUR_CALM).into()),
+            Reason::ENHANCE_YOUR_CALM
+        );
+        assert_eq!(reset_reason(&OperationError::Disconnected), Reason::CANCEL);
+    }
+}
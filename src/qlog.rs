@@ -0,0 +1,199 @@
+//! qlog event emitter
+use std::{cell::RefCell, fmt::Write as _, io, rc::Rc, time::Instant};
+
+use crate::clock::Clock;
+use crate::frame::{Frame, StreamId};
+
+/// Factory for per-connection qlog writers
+pub(crate) type QLogFactory = Rc<dyn Fn(&'static str) -> Option<Box<dyn io::Write>>>;
+
+/// Record separator, json-seq records start with it
+const RS: u8 = 0x1e;
+
+/// Emits qlog events in JSON-SEQ format
+pub(crate) struct QLog {
+    clock: Rc<dyn Clock>,
+    start: Instant,
+    writer: RefCell<Box<dyn io::Write>>,
+}
+
+impl QLog {
+    pub(crate) fn new(
+        writer: Box<dyn io::Write>,
+        tag: &str,
+        server: bool,
+        clock: Rc<dyn Clock>,
+    ) -> Self {
+        let qlog = QLog {
+            writer: RefCell::new(writer),
+            start: clock.now(),
+            clock,
+        };
+        qlog.record(format_args!(
+            "{{\"qlog_version\":\"0.3\",\"qlog_format\":\"JSON-SEQ\",\"title\":\"{}\",\
+             \"trace\":{{\"vantage_point\":{{\"type\":\"{}\"}}}}}}",
+            escape(tag),
+            if server { "server" } else { "client" }
+        ));
+        qlog
+    }
+
+    pub(crate) fn frame_sent(&self, frame: &Frame) {
+        self.event("http2:frame_created", &frame_data(frame));
+    }
+
+    pub(crate) fn frame_received(&self, frame: &Frame) {
+        self.event("http2:frame_parsed", &frame_data(frame));
+    }
+
+    pub(crate) fn window_updated(&self, id: StreamId, owner: &str, window: i32) {
+        self.event(
+            "http2:flow_control_updated",
+            &format!(
+                "{{\"stream_id\":{},\"owner\":\"{}\",\"window\":{}}}",
+                u32::from(id),
+                owner,
+                window
+            ),
+        );
+    }
+
+    pub(crate) fn stream_state(&self, id: StreamId, side: &str, state: &str) {
+        self.event(
+            "http2:stream_state_updated",
+            &format!(
+                "{{\"stream_id\":{},\"side\":\"{}\",\"new\":\"{}\"}}",
+                u32::from(id),
+                side,
+                state
+            ),
+        );
+    }
+
+    fn event(&self, name: &str, data: &str) {
+        let time = self.clock.now().saturating_duration_since(self.start);
+        let time = time.as_secs_f64() * 1000.0;
+        self.record(format_args!(
+            "{{\"time\":{:.3},\"name\":\"{}\",\"data\":{}}}",
+            time, name, data
+        ));
+    }
+
+    fn record(&self, args: std::fmt::Arguments<'_>) {
+        let mut writer = self.writer.borrow_mut();
+        if let Err(e) = writer
+            .write_all(&[RS])
+            .and_then(|_| writer.write_fmt(args))
+            .and_then(|_| writer.write_all(b"\n"))
+        {
+            log::warn!("Cannot write qlog record: {:?}", e);
+        }
+    }
+}
+
+impl Drop for QLog {
+    fn drop(&mut self) {
+        let _ = self.writer.borrow_mut().flush();
+    }
+}
+
+fn frame_data(frame: &Frame) -> String {
+    let mut s = String::new();
+    let _ = match frame {
+        Frame::Data(frm) => write!(
+            s,
+            "{{\"frame_type\":\"data\",\"stream_id\":{},\"length\":{},\"end_stream\":{}}}",
+            u32::from(frm.stream_id()),
+            frm.payload().len(),
+            frm.is_end_stream()
+        ),
+        Frame::Headers(frm) => write!(
+            s,
+            "{{\"frame_type\":\"headers\",\"stream_id\":{},\"end_stream\":{}}}",
+            u32::from(frm.stream_id()),
+            frm.is_end_stream()
+        ),
+        Frame::Priority(_) => write!(s, "{{\"frame_type\":\"priority\"}}"),
+        Frame::Reset(frm) => write!(
+            s,
+            "{{\"frame_type\":\"rst_stream\",\"stream_id\":{},\"error_code\":{}}}",
+            u32::from(frm.stream_id()),
+            u32::from(frm.reason())
+        ),
+        Frame::Settings(frm) => write!(
+            s,
+            "{{\"frame_type\":\"settings\",\"ack\":{}}}",
+            frm.is_ack()
+        ),
+        Frame::Ping(frm) => write!(s, "{{\"frame_type\":\"ping\",\"ack\":{}}}", frm.is_ack()),
+        Frame::GoAway(frm) => write!(
+            s,
+            "{{\"frame_type\":\"goaway\",\"last_stream_id\":{},\"error_code\":{}}}",
+            u32::from(frm.last_stream_id()),
+            u32::from(frm.reason())
+        ),
+        Frame::WindowUpdate(frm) => write!(
+            s,
+            "{{\"frame_type\":\"window_update\",\"stream_id\":{},\"increment\":{}}}",
+            u32::from(frm.stream_id()),
+            frm.size_increment()
+        ),
+    };
+    s
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::frame::{Reason, Reset};
+
+    #[derive(Clone, Default)]
+    struct Buf(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for Buf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        let buf = Buf::default();
+        let qlog = QLog::new(Box::new(buf.clone()), "test\"", true, Rc::new(SystemClock));
+        qlog.frame_sent(&Reset::new(1.into(), Reason::CANCEL).into());
+        qlog.stream_state(1.into(), "send", "closed");
+        drop(qlog);
+
+        let data = String::from_utf8(buf.0.borrow().clone()).unwrap();
+        let records: Vec<_> = data.split('\u{1e}').skip(1).collect();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].contains("\"title\":\"test\\\"\""));
+        assert!(records[0].contains("\"type\":\"server\""));
+        assert!(
+            records[1].contains("\"frame_type\":\"rst_stream\",\"stream_id\":1,\"error_code\":8")
+        );
+        assert!(records[2].contains("\"name\":\"http2:stream_state_updated\""));
+        assert!(records.iter().all(|r| r.ends_with('\n')));
+    }
+}
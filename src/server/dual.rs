@@ -0,0 +1,206 @@
+use std::{cmp, fmt};
+
+use ntex_io::{types::HttpProtocol, Filter, Io, IoBoxed};
+use ntex_service::{IntoServiceFactory, Pipeline, Service, ServiceCtx, ServiceFactory};
+
+use crate::clock::timeout_checked;
+use crate::control::{Control, ControlAck};
+use crate::{consts, message::Message};
+
+use super::{Server, ServerError, ServerHandler};
+
+#[derive(Debug)]
+/// Http/1.1 and http/2 server factory
+///
+/// Routes connections by negotiated ALPN protocol. If protocol is not
+/// negotiated, connection preface is inspected, connections without http/2
+/// preface are passed to http/1.1 service. Sniffed bytes stay in read buffer.
+pub struct DualServer<Ctl, Pub, H1> {
+    h2: Server<Ctl, Pub>,
+    h1: H1,
+}
+
+impl<Ctl, Pub, H1> DualServer<Ctl, Pub, H1>
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+    H1: ServiceFactory<IoBoxed, Response = ()> + 'static,
+    H1::InitError: fmt::Debug,
+{
+    /// Create new instance of dual protocol server factory
+    pub fn new<F>(h2: Server<Ctl, Pub>, h1: F) -> Self
+    where
+        F: IntoServiceFactory<H1, IoBoxed>,
+    {
+        Self {
+            h2,
+            h1: h1.into_factory(),
+        }
+    }
+
+    async fn create_handler(&self) -> Result<DualHandler<Ctl, Pub, H1::Service>, ()> {
+        let h2 = ServiceFactory::<IoBoxed>::create(&self.h2, ()).await?;
+        let h1 = self.h1.create(()).await.map_err(|e| {
+            log::error!("Http/1.1 service init error: {:?}", e);
+        })?;
+        Ok(DualHandler {
+            h2,
+            h1: Pipeline::new(h1),
+        })
+    }
+}
+
+impl<Ctl, Pub, H1> ServiceFactory<IoBoxed> for DualServer<Ctl, Pub, H1>
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+    H1: ServiceFactory<IoBoxed, Response = ()> + 'static,
+    H1::InitError: fmt::Debug,
+{
+    type Response = ();
+    type Error = ServerError<H1::Error>;
+    type Service = DualHandler<Ctl, Pub, H1::Service>;
+    type InitError = ();
+
+    async fn create(&self, _: ()) -> Result<Self::Service, Self::InitError> {
+        self.create_handler().await
+    }
+}
+
+impl<F, Ctl, Pub, H1> ServiceFactory<Io<F>> for DualServer<Ctl, Pub, H1>
+where
+    F: Filter,
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+    H1: ServiceFactory<IoBoxed, Response = ()> + 'static,
+    H1::InitError: fmt::Debug,
+{
+    type Response = ();
+    type Error = ServerError<H1::Error>;
+    type Service = DualHandler<Ctl, Pub, H1::Service>;
+    type InitError = ();
+
+    async fn create(&self, _: ()) -> Result<Self::Service, Self::InitError> {
+        self.create_handler().await
+    }
+}
+
+#[derive(Debug)]
+/// Http/1.1 and http/2 connections handler
+pub struct DualHandler<Ctl, Pub, H1> {
+    h2: ServerHandler<Ctl, Pub>,
+    h1: Pipeline<H1>,
+}
+
+impl<Ctl, Pub, H1> DualHandler<Ctl, Pub, H1>
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+    H1: Service<IoBoxed, Response = ()> + 'static,
+{
+    /// Handle connection with selected protocol
+    pub async fn run(&self, io: IoBoxed) -> Result<(), ServerError<H1::Error>> {
+        let proto = io.query::<HttpProtocol>().get();
+        let proto = match proto.unwrap_or(HttpProtocol::Unknown) {
+            HttpProtocol::Unknown => {
+                let config = self.h2.config();
+                let timeout = config.0.handshake_timeout.get().into();
+                timeout_checked(&*config.0.clock(), timeout, sniff(&io))
+                    .await
+                    .map_err(|_| ServerError::HandshakeTimeout)??
+            }
+            proto => proto,
+        };
+        log::trace!("{}: Selected protocol {:?}", io.tag(), proto);
+
+        match proto {
+            HttpProtocol::Http2 => self.h2.run(io).await.map_err(ServerError::cast),
+            _ => self.h1.call(io).await.map_err(ServerError::Service),
+        }
+    }
+}
+
+impl<Ctl, Pub, H1> Service<IoBoxed> for DualHandler<Ctl, Pub, H1>
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+    H1: Service<IoBoxed, Response = ()> + 'static,
+{
+    type Response = ();
+    type Error = ServerError<H1::Error>;
+
+    async fn call(
+        &self,
+        io: IoBoxed,
+        _: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        self.run(io).await
+    }
+}
+
+impl<F, Ctl, Pub, H1> Service<Io<F>> for DualHandler<Ctl, Pub, H1>
+where
+    F: Filter,
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+    H1: Service<IoBoxed, Response = ()> + 'static,
+{
+    type Response = ();
+    type Error = ServerError<H1::Error>;
+
+    async fn call(
+        &self,
+        req: Io<F>,
+        _: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        self.run(req.into()).await
+    }
+}
+
+/// Detect protocol by connection preface, read buffer is not consumed
+async fn sniff<E>(io: &IoBoxed) -> Result<HttpProtocol, ServerError<E>> {
+    loop {
+        let proto = io.with_read_buf(|buf| {
+            let len = cmp::min(buf.len(), consts::PREFACE.len());
+            if buf[..len] != consts::PREFACE[..len] {
+                Some(HttpProtocol::Http1)
+            } else if len == consts::PREFACE.len() {
+                Some(HttpProtocol::Http2)
+            } else {
+                None
+            }
+        });
+
+        if let Some(proto) = proto {
+            return Ok(proto);
+        } else {
+            io.read_ready()
+                .await?
+                .ok_or(ServerError::Disconnected(None))?;
+        }
+    }
+}
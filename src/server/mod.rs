@@ -1,7 +1,9 @@
 mod builder;
+mod dual;
 mod service;
 
 pub use self::builder::ServerBuilder;
+pub use self::dual::{DualHandler, DualServer};
 pub use self::service::{handle_one, Server, ServerHandler};
 
 use crate::frame;
@@ -32,6 +34,19 @@ pub enum ServerError<E> {
     Disconnected(Option<std::io::Error>),
 }
 
+impl ServerError<()> {
+    pub(crate) fn cast<E>(self) -> ServerError<E> {
+        match self {
+            ServerError::Service(_) | ServerError::Dispatcher => ServerError::Dispatcher,
+            ServerError::Frame(err) => ServerError::Frame(err),
+            ServerError::ControlServiceError => ServerError::ControlServiceError,
+            ServerError::PublishServiceError => ServerError::PublishServiceError,
+            ServerError::HandshakeTimeout => ServerError::HandshakeTimeout,
+            ServerError::Disconnected(err) => ServerError::Disconnected(err),
+        }
+    }
+}
+
 impl<E> From<std::io::Error> for ServerError<E> {
     fn from(err: std::io::Error) -> Self {
         ServerError::Disconnected(Some(err))
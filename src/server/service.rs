@@ -2,8 +2,8 @@ use std::{fmt, future::poll_fn, future::Future, pin::Pin, rc::Rc};
 
 use ntex_io::{Dispatcher as IoDispatcher, Filter, Io, IoBoxed};
 use ntex_service::{Service, ServiceCtx, ServiceFactory};
-use ntex_util::time::timeout_checked;
 
+use crate::clock::timeout_checked;
 use crate::control::{Control, ControlAck};
 use crate::{codec::Codec, connection::Connection};
 use crate::{config::Config, consts, dispatcher::Dispatcher, frame, message::Message};
@@ -21,6 +21,14 @@ struct ServerInner<Ctl, Pub> {
     config: Config,
 }
 
+impl<Ctl, Pub> ServerInner<Ctl, Pub> {
+    fn validate(&self) -> Result<(), ()> {
+        self.config.validate().map_err(|err| {
+            log::error!("Invalid http/2 configuration: {}", err);
+        })
+    }
+}
+
 impl Server<(), ()> {
     /// Returns a new server builder instance initialized with default
     /// configuration values.
@@ -68,6 +76,7 @@ where
     type InitError = ();
 
     async fn create(&self, _: ()) -> Result<Self::Service, Self::InitError> {
+        self.0.validate()?;
         Ok(ServerHandler(self.0.clone()))
     }
 }
@@ -88,6 +97,7 @@ where
     type InitError = ();
 
     async fn create(&self, _: ()) -> Result<Self::Service, Self::InitError> {
+        self.0.validate()?;
         Ok(ServerHandler(self.0.clone()))
     }
 }
@@ -111,10 +121,16 @@ where
     Pub::Error: fmt::Debug,
     Pub::InitError: fmt::Debug,
 {
+    pub(super) fn config(&self) -> &Config {
+        &self.0.config
+    }
+
     pub async fn run(&self, io: IoBoxed) -> Result<(), ServerError<()>> {
         let inner = &self.0;
 
-        let (ctl_srv, pub_srv) = timeout_checked(inner.config.0.handshake_timeout.get(), async {
+        let clock = inner.config.0.clock();
+        let timeout = inner.config.0.handshake_timeout.get().into();
+        let (ctl_srv, pub_srv) = timeout_checked(&*clock, timeout, async {
             read_preface(&io).await?;
 
             // create publish service
@@ -241,7 +257,8 @@ where
     Pub::Error: fmt::Debug,
 {
     // read preface
-    timeout_checked(config.0.handshake_timeout.get(), async {
+    let timeout = config.0.handshake_timeout.get().into();
+    timeout_checked(&*config.0.clock(), timeout, async {
         read_preface(&io).await
     })
     .await
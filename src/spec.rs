@@ -0,0 +1,280 @@
+//! Conformance scenarios.
+//!
+//! Scenarios replay raw frames against server connection over in-memory io,
+//! in the style of h2spec, and check that connection replies with expected
+//! GOAWAY or RST_STREAM frame. Scenarios run without network, so protocol
+//! regressions could be caught by regular tests.
+use std::fmt;
+
+use ntex_bytes::{Bytes, BytesMut};
+use ntex_codec::Encoder;
+use ntex_http::{header, HeaderMap, HeaderValue, Method, Uri};
+use ntex_service::fn_service;
+use ntex_util::time::{timeout, Seconds};
+
+use crate::frame::{self, Frame, PseudoHeaders, Reason, StreamId};
+use crate::{codec::Codec, config::Config, consts, default::DefaultControlService};
+use crate::{message::Message, server, test};
+
+/// Expected reply of the connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Expect {
+    /// Connection error, GOAWAY frame with reason
+    GoAway(Reason),
+    /// Stream error, RST_STREAM frame for stream with reason
+    Reset(StreamId, Reason),
+}
+
+/// Scenario run error
+#[derive(Debug, thiserror::Error)]
+pub enum SpecError {
+    #[error("Expected {expected:?}, received {received:?}")]
+    Unexpected {
+        expected: Expect,
+        received: Box<Frame>,
+    },
+    #[error("Connection is closed, expected {0:?}")]
+    Disconnected(Expect),
+    #[error("Timeout, expected {0:?}")]
+    Timeout(Expect),
+}
+
+/// Conformance scenario
+///
+/// Scenario sends connection preface and empty SETTINGS frame,
+/// then scenario frames are sent in one batch.
+pub struct Scenario {
+    section: &'static str,
+    description: &'static str,
+    expect: Expect,
+    codec: Codec,
+    buf: BytesMut,
+}
+
+impl Scenario {
+    /// Create new scenario
+    pub fn new(section: &'static str, description: &'static str, expect: Expect) -> Self {
+        let mut scenario = Scenario {
+            section,
+            description,
+            expect,
+            codec: Codec::default(),
+            buf: BytesMut::new(),
+        };
+        scenario.buf.extend_from_slice(&consts::PREFACE);
+        scenario.frame(frame::Settings::default())
+    }
+
+    #[inline]
+    /// Rfc section of the scenario
+    pub fn section(&self) -> &'static str {
+        self.section
+    }
+
+    #[inline]
+    /// Scenario description
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    #[inline]
+    /// Expected reply
+    pub fn expect(&self) -> Expect {
+        self.expect
+    }
+
+    /// Add frame to the scenario.
+    ///
+    /// Header blocks are encoded with scenario's hpack context.
+    ///
+    /// Panics if frame cannot be encoded.
+    pub fn frame<T: Into<Frame>>(mut self, frm: T) -> Self {
+        self.codec
+            .encode(frm.into(), &mut self.buf)
+            .expect("Cannot encode scenario frame");
+        self
+    }
+
+    /// Add raw bytes to the scenario
+    pub fn raw(mut self, data: &[u8]) -> Self {
+        self.buf.extend_from_slice(data);
+        self
+    }
+
+    /// Run scenario against server connection with provided config.
+    ///
+    /// Server does not respond to requests, control messages are handled
+    /// by `DefaultControlService`. Frames other than GOAWAY and RST_STREAM
+    /// are skipped.
+    pub async fn run(&self, config: Config) -> Result<(), SpecError> {
+        let (io, srv) = test::duplex();
+
+        let _ = ntex_util::spawn(async move {
+            let _ = server::handle_one(
+                srv.into(),
+                config,
+                DefaultControlService,
+                fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+            )
+            .await;
+        });
+
+        io.with_write_buf(|buf| buf.extend_from_slice(&self.buf))
+            .map_err(|_| SpecError::Disconnected(self.expect))?;
+
+        let codec = Codec::default();
+        loop {
+            let frm = match timeout(Seconds(5), io.recv(&codec)).await {
+                Ok(Ok(Some(frm))) => frm,
+                Ok(_) => return Err(SpecError::Disconnected(self.expect)),
+                Err(_) => return Err(SpecError::Timeout(self.expect)),
+            };
+
+            match (self.expect, &frm) {
+                (Expect::GoAway(reason), Frame::GoAway(rcv)) if rcv.reason() == reason => {
+                    return Ok(())
+                }
+                (Expect::Reset(id, reason), Frame::Reset(rcv))
+                    if rcv.stream_id() == id && rcv.reason() == reason =>
+                {
+                    return Ok(())
+                }
+                (_, Frame::GoAway(_) | Frame::Reset(_)) => {
+                    return Err(SpecError::Unexpected {
+                        expected: self.expect,
+                        received: Box::new(frm),
+                    })
+                }
+                _ => log::trace!("Skip {:?} frame", frm),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scenario")
+            .field("section", &self.section)
+            .field("description", &self.description)
+            .field("expect", &self.expect)
+            .finish()
+    }
+}
+
+/// Built-in conformance scenarios
+pub fn scenarios() -> Vec<Scenario> {
+    let id1 = StreamId::from(1);
+
+    vec![
+        Scenario::new(
+            "4.2",
+            "DATA frame exceeds max frame size",
+            Expect::GoAway(Reason::FRAME_SIZE_ERROR),
+        )
+        .frame(request(id1, false))
+        .raw(&head(16_385, 0x0, 0x0, 1))
+        .raw(&[0; 16_385]),
+        Scenario::new(
+            "5.1",
+            "DATA frame on half-closed (remote) stream",
+            Expect::Reset(id1, Reason::STREAM_CLOSED),
+        )
+        .frame(request(id1, true))
+        .frame(frame::Data::new(id1, Bytes::from_static(b"test"))),
+        Scenario::new(
+            "5.1.1",
+            "Client opens stream with even identifier",
+            Expect::GoAway(Reason::PROTOCOL_ERROR),
+        )
+        .frame(request(StreamId::from(2), true)),
+        Scenario::new(
+            "6.1",
+            "DATA frame with zero stream identifier",
+            Expect::GoAway(Reason::PROTOCOL_ERROR),
+        )
+        .raw(&head(4, 0x0, 0x0, 0))
+        .raw(b"test"),
+        Scenario::new(
+            "6.5.3",
+            "SETTINGS_INITIAL_WINDOW_SIZE above maximum",
+            Expect::GoAway(Reason::FLOW_CONTROL_ERROR),
+        )
+        .raw(&head(6, 0x4, 0x0, 0))
+        .raw(&[0x0, 0x4, 0x80, 0x0, 0x0, 0x0]),
+        Scenario::new(
+            "6.7",
+            "PING frame with length other than 8",
+            Expect::GoAway(Reason::FRAME_SIZE_ERROR),
+        )
+        .raw(&head(6, 0x6, 0x0, 0))
+        .raw(&[0; 6]),
+        Scenario::new(
+            "6.9",
+            "WINDOW_UPDATE with zero increment for connection",
+            Expect::GoAway(Reason::PROTOCOL_ERROR),
+        )
+        .raw(&head(4, 0x8, 0x0, 0))
+        .raw(&[0; 4]),
+        Scenario::new(
+            "6.9",
+            "WINDOW_UPDATE with zero increment for stream",
+            Expect::Reset(id1, Reason::PROTOCOL_ERROR),
+        )
+        .frame(request(id1, false))
+        .raw(&head(4, 0x8, 0x0, 1))
+        .raw(&[0; 4]),
+        Scenario::new(
+            "6.9.1",
+            "WINDOW_UPDATE overflows connection window",
+            Expect::GoAway(Reason::FLOW_CONTROL_ERROR),
+        )
+        .raw(&head(4, 0x8, 0x0, 0))
+        .raw(&[0x7f, 0xff, 0xff, 0xff]),
+        Scenario::new(
+            "8.1.1",
+            "Payload length does not match content-length",
+            Expect::Reset(id1, Reason::PROTOCOL_ERROR),
+        )
+        .frame({
+            let mut hdrs = HeaderMap::new();
+            hdrs.insert(header::CONTENT_LENGTH, HeaderValue::from_static("8"));
+            frame::Headers::new(id1, post(), hdrs, false)
+        })
+        .frame({
+            let mut data = frame::Data::new(id1, Bytes::from_static(b"test"));
+            data.set_end_stream();
+            data
+        }),
+        Scenario::new(
+            "8.3.1",
+            "Request without :method pseudo header",
+            Expect::GoAway(Reason::PROTOCOL_ERROR),
+        )
+        .frame(frame::Headers::new(
+            id1,
+            PseudoHeaders {
+                method: None,
+                ..post()
+            },
+            HeaderMap::new(),
+            true,
+        )),
+    ]
+}
+
+fn post() -> PseudoHeaders {
+    PseudoHeaders::request(Method::POST, Uri::from_static("http://localhost/"), None)
+}
+
+fn request(id: StreamId, eof: bool) -> frame::Headers {
+    frame::Headers::new(id, post(), HeaderMap::new(), eof)
+}
+
+/// Raw frame header
+fn head(len: u32, kind: u8, flags: u8, id: u32) -> [u8; frame::HEADER_LEN] {
+    let len = len.to_be_bytes();
+    let id = id.to_be_bytes();
+    [
+        len[1], len[2], len[3], kind, flags, id[0], id[1], id[2], id[3],
+    ]
+}
@@ -0,0 +1,527 @@
+use std::{collections::VecDeque, ops, time::Duration};
+
+use crate::error::{ConnectionError, StreamError};
+use crate::frame::{Frame, FrameContinuationError, FrameError};
+
+/// Connection statistics
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Frames sent to the peer
+    pub frames_sent: FrameStats,
+    /// Frames received from the peer
+    pub frames_received: FrameStats,
+    /// Size of encoded header blocks sent to the peer, including frame headers
+    pub header_bytes_sent: u64,
+    /// Size of encoded header blocks received from the peer, including frame headers
+    pub header_bytes_received: u64,
+    /// Size of DATA frames payload sent to the peer
+    pub data_bytes_sent: u64,
+    /// Size of DATA frames payload received from the peer
+    pub data_bytes_received: u64,
+    /// Number of opened streams, local and remote
+    pub streams_opened: u64,
+    /// Number of streams reset locally
+    pub streams_reset_local: u64,
+    /// Number of streams reset by the peer
+    pub streams_reset_remote: u64,
+    /// Number of times sending got blocked by flow control
+    pub flow_control_stalls: u64,
+    /// Number of sent pings
+    pub pings_sent: u64,
+    /// Number of received ping acknowledgements
+    pub pongs_received: u64,
+    /// Protocol violations committed by the peer
+    pub violations: Violations,
+    /// Round-trip time, measured with keep-alive and user pings
+    pub rtt: RttStats,
+}
+
+/// Round-trip time statistics
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RttStats {
+    /// Number of measured samples
+    pub samples: u64,
+    /// Last measured value
+    pub last: Option<Duration>,
+    /// Minimum measured value
+    pub min: Option<Duration>,
+    /// Average of all measured values
+    pub avg: Option<Duration>,
+    /// Median of recent values
+    pub p50: Option<Duration>,
+    /// 90th percentile of recent values
+    pub p90: Option<Duration>,
+    /// 99th percentile of recent values
+    pub p99: Option<Duration>,
+}
+
+/// Number of protocol violations by category
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Violations {
+    /// Malformed or unexpected frames
+    pub bad_frames: u64,
+    /// Header blocks exceeding configured limits
+    pub oversized_headers: u64,
+    /// Flow-control violations
+    pub window_violations: u64,
+    /// Excessive stream creation or resets
+    pub floods: u64,
+    /// Malformed messages on individual streams
+    pub stream_errors: u64,
+}
+
+/// Number of frames by kind
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameStats {
+    pub data: u64,
+    pub headers: u64,
+    pub priority: u64,
+    pub reset: u64,
+    pub settings: u64,
+    pub ping: u64,
+    pub go_away: u64,
+    pub window_update: u64,
+}
+
+impl Stats {
+    pub(crate) fn frame_sent(&mut self, frame: &Frame) {
+        self.frames_sent.add(frame);
+
+        match frame {
+            Frame::Data(frm) => self.data_bytes_sent += frm.payload().len() as u64,
+            Frame::Reset(_) => self.streams_reset_local += 1,
+            Frame::Ping(frm) if !frm.is_ack() => self.pings_sent += 1,
+            _ => (),
+        }
+    }
+
+    pub(crate) fn frame_received(&mut self, frame: &Frame) {
+        self.frames_received.add(frame);
+
+        match frame {
+            Frame::Data(frm) => self.data_bytes_received += frm.payload().len() as u64,
+            Frame::Reset(_) => self.streams_reset_remote += 1,
+            Frame::Ping(frm) if frm.is_ack() => self.pongs_received += 1,
+            _ => (),
+        }
+    }
+}
+
+impl FrameStats {
+    /// Total number of frames
+    pub fn total(&self) -> u64 {
+        self.data
+            + self.headers
+            + self.priority
+            + self.reset
+            + self.settings
+            + self.ping
+            + self.go_away
+            + self.window_update
+    }
+
+    fn add(&mut self, frame: &Frame) {
+        match frame {
+            Frame::Data(_) => self.data += 1,
+            Frame::Headers(_) => self.headers += 1,
+            Frame::Priority(_) => self.priority += 1,
+            Frame::Reset(_) => self.reset += 1,
+            Frame::Settings(_) => self.settings += 1,
+            Frame::Ping(_) => self.ping += 1,
+            Frame::GoAway(_) => self.go_away += 1,
+            Frame::WindowUpdate(_) => self.window_update += 1,
+        }
+    }
+}
+
+impl ops::AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.frames_sent += other.frames_sent;
+        self.frames_received += other.frames_received;
+        self.header_bytes_sent += other.header_bytes_sent;
+        self.header_bytes_received += other.header_bytes_received;
+        self.data_bytes_sent += other.data_bytes_sent;
+        self.data_bytes_received += other.data_bytes_received;
+        self.streams_opened += other.streams_opened;
+        self.streams_reset_local += other.streams_reset_local;
+        self.streams_reset_remote += other.streams_reset_remote;
+        self.flow_control_stalls += other.flow_control_stalls;
+        self.pings_sent += other.pings_sent;
+        self.pongs_received += other.pongs_received;
+        self.violations += other.violations;
+        self.rtt += other.rtt;
+    }
+}
+
+impl Violations {
+    /// Total number of protocol violations
+    pub fn total(&self) -> u64 {
+        self.bad_frames
+            + self.oversized_headers
+            + self.window_violations
+            + self.floods
+            + self.stream_errors
+    }
+
+    pub(crate) fn connection_error(&mut self, err: &ConnectionError) {
+        match err {
+            ConnectionError::Decoder(FrameError::Continuation(
+                FrameContinuationError::MaxLeftoverSize | FrameContinuationError::MaxContinuations,
+            )) => self.oversized_headers += 1,
+            ConnectionError::WindowValueOverflow | ConnectionError::ZeroWindowUpdateValue => {
+                self.window_violations += 1
+            }
+            ConnectionError::ConcurrencyOverflow | ConnectionError::EmptyDataFlood => {
+                self.floods += 1
+            }
+            ConnectionError::Decoder(_)
+            | ConnectionError::UnknownStream(_)
+            | ConnectionError::StreamClosed(..)
+            | ConnectionError::InvalidStreamId(_)
+            | ConnectionError::UnexpectedSettingsAck
+            | ConnectionError::MissingPseudo(_)
+            | ConnectionError::UnexpectedPseudo(_)
+            | ConnectionError::MalformedPseudo(_)
+            | ConnectionError::ExtendedConnect(_) => self.bad_frames += 1,
+            // not caused by the peer
+            ConnectionError::GoAway(_)
+            | ConnectionError::Encoder(_)
+            | ConnectionError::KeepaliveTimeout
+            | ConnectionError::ReadTimeout => (),
+        }
+    }
+
+    pub(crate) fn stream_error(&mut self, err: &StreamError) {
+        match err {
+            StreamError::WindowOverflowed | StreamError::WindowZeroUpdateValue => {
+                self.window_violations += 1
+            }
+            StreamError::Reset(_) => (),
+            _ => self.stream_errors += 1,
+        }
+    }
+}
+
+impl ops::AddAssign for Violations {
+    fn add_assign(&mut self, other: Self) {
+        self.bad_frames += other.bad_frames;
+        self.oversized_headers += other.oversized_headers;
+        self.window_violations += other.window_violations;
+        self.floods += other.floods;
+        self.stream_errors += other.stream_errors;
+    }
+}
+
+/// Aggregated statistics keep minimum and weighted average,
+/// percentiles are replaced by the worst value.
+impl ops::AddAssign for RttStats {
+    fn add_assign(&mut self, other: Self) {
+        if other.samples == 0 {
+            return;
+        }
+        if self.samples == 0 {
+            *self = other;
+            return;
+        }
+
+        let samples = self.samples + other.samples;
+        self.avg = match (self.avg, other.avg) {
+            (Some(a), Some(b)) => Some(Duration::from_nanos(
+                ((a.as_nanos() * self.samples as u128 + b.as_nanos() * other.samples as u128)
+                    / samples as u128) as u64,
+            )),
+            (a, b) => a.or(b),
+        };
+        self.samples = samples;
+        self.last = other.last.or(self.last);
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.p50 = self.p50.max(other.p50);
+        self.p90 = self.p90.max(other.p90);
+        self.p99 = self.p99.max(other.p99);
+    }
+}
+
+/// Number of recent samples used for percentiles
+const RTT_WINDOW: usize = 64;
+
+/// Round-trip time samples
+///
+/// Statistics are updated for each sample, so reading them is cheap.
+#[derive(Debug, Default)]
+pub(crate) struct Rtt {
+    total: Duration,
+    recent: VecDeque<Duration>,
+    stats: RttStats,
+}
+
+impl Rtt {
+    pub(crate) fn add(&mut self, sample: Duration) {
+        self.total += sample;
+        if self.recent.len() == RTT_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(sample);
+
+        let mut sorted = [Duration::ZERO; RTT_WINDOW];
+        let sorted = &mut sorted[..self.recent.len()];
+        for (item, val) in sorted.iter_mut().zip(self.recent.iter()) {
+            *item = *val;
+        }
+        sorted.sort_unstable();
+        let percentile = |p: usize| Some(sorted[(sorted.len() * p / 100).min(sorted.len() - 1)]);
+
+        let samples = self.stats.samples + 1;
+        self.stats = RttStats {
+            samples,
+            last: Some(sample),
+            min: Some(self.stats.min.map_or(sample, |min| min.min(sample))),
+            avg: Some(Duration::from_nanos(
+                (self.total.as_nanos() / samples as u128) as u64,
+            )),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+        };
+    }
+
+    pub(crate) fn stats(&self) -> RttStats {
+        self.stats
+    }
+}
+
+impl ops::AddAssign for FrameStats {
+    fn add_assign(&mut self, other: Self) {
+        self.data += other.data;
+        self.headers += other.headers;
+        self.priority += other.priority;
+        self.reset += other.reset;
+        self.settings += other.settings;
+        self.ping += other.ping;
+        self.go_away += other.go_away;
+        self.window_update += other.window_update;
+    }
+}
+
+/// Kind of metric
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    /// Monotonically increasing value
+    Counter,
+    /// Value that can go up and down
+    Gauge,
+}
+
+/// Connection metrics snapshot with stable metric names
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    stats: Stats,
+    active_local_streams: u64,
+    active_remote_streams: u64,
+    send_window: u64,
+    recv_window: u64,
+}
+
+impl MetricsSnapshot {
+    pub(crate) fn new(
+        stats: Stats,
+        active_local_streams: u32,
+        active_remote_streams: u32,
+        send_window: u32,
+        recv_window: u32,
+    ) -> Self {
+        Self {
+            stats,
+            active_local_streams: active_local_streams as u64,
+            active_remote_streams: active_remote_streams as u64,
+            send_window: send_window as u64,
+            recv_window: recv_window as u64,
+        }
+    }
+
+    /// Connection statistics
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Visit all metrics.
+    ///
+    /// Metric names are stable and follow prometheus naming conventions,
+    /// counters are suffixed with `_total`.
+    pub fn visit<F>(&self, mut f: F)
+    where
+        F: FnMut(&'static str, MetricKind, u64),
+    {
+        use MetricKind::{Counter, Gauge};
+
+        let s = &self.stats;
+        let frames = [
+            ("h2_frames_sent_data_total", s.frames_sent.data),
+            ("h2_frames_sent_headers_total", s.frames_sent.headers),
+            ("h2_frames_sent_priority_total", s.frames_sent.priority),
+            ("h2_frames_sent_reset_total", s.frames_sent.reset),
+            ("h2_frames_sent_settings_total", s.frames_sent.settings),
+            ("h2_frames_sent_ping_total", s.frames_sent.ping),
+            ("h2_frames_sent_goaway_total", s.frames_sent.go_away),
+            (
+                "h2_frames_sent_window_update_total",
+                s.frames_sent.window_update,
+            ),
+            ("h2_frames_received_data_total", s.frames_received.data),
+            (
+                "h2_frames_received_headers_total",
+                s.frames_received.headers,
+            ),
+            (
+                "h2_frames_received_priority_total",
+                s.frames_received.priority,
+            ),
+            ("h2_frames_received_reset_total", s.frames_received.reset),
+            (
+                "h2_frames_received_settings_total",
+                s.frames_received.settings,
+            ),
+            ("h2_frames_received_ping_total", s.frames_received.ping),
+            ("h2_frames_received_goaway_total", s.frames_received.go_away),
+            (
+                "h2_frames_received_window_update_total",
+                s.frames_received.window_update,
+            ),
+            ("h2_header_bytes_sent_total", s.header_bytes_sent),
+            ("h2_header_bytes_received_total", s.header_bytes_received),
+            ("h2_data_bytes_sent_total", s.data_bytes_sent),
+            ("h2_data_bytes_received_total", s.data_bytes_received),
+            ("h2_streams_opened_total", s.streams_opened),
+            ("h2_streams_reset_local_total", s.streams_reset_local),
+            ("h2_streams_reset_remote_total", s.streams_reset_remote),
+            ("h2_flow_control_stalls_total", s.flow_control_stalls),
+            ("h2_pings_sent_total", s.pings_sent),
+            ("h2_pongs_received_total", s.pongs_received),
+            ("h2_violations_bad_frames_total", s.violations.bad_frames),
+            (
+                "h2_violations_oversized_headers_total",
+                s.violations.oversized_headers,
+            ),
+            ("h2_violations_window_total", s.violations.window_violations),
+            ("h2_violations_floods_total", s.violations.floods),
+            (
+                "h2_violations_stream_errors_total",
+                s.violations.stream_errors,
+            ),
+        ];
+        for (name, val) in frames {
+            f(name, Counter, val);
+        }
+
+        f("h2_rtt_samples_total", Counter, s.rtt.samples);
+        let rtt = [
+            ("h2_rtt_last_microseconds", s.rtt.last),
+            ("h2_rtt_min_microseconds", s.rtt.min),
+            ("h2_rtt_avg_microseconds", s.rtt.avg),
+            ("h2_rtt_p50_microseconds", s.rtt.p50),
+            ("h2_rtt_p90_microseconds", s.rtt.p90),
+            ("h2_rtt_p99_microseconds", s.rtt.p99),
+        ];
+        for (name, val) in rtt {
+            f(name, Gauge, val.map_or(0, |v| v.as_micros() as u64));
+        }
+
+        f("h2_active_local_streams", Gauge, self.active_local_streams);
+        f(
+            "h2_active_remote_streams",
+            Gauge,
+            self.active_remote_streams,
+        );
+        f("h2_send_window_bytes", Gauge, self.send_window);
+        f("h2_recv_window_bytes", Gauge, self.recv_window);
+    }
+}
+
+impl ops::AddAssign for MetricsSnapshot {
+    fn add_assign(&mut self, other: Self) {
+        self.stats += other.stats;
+        self.active_local_streams += other.active_local_streams;
+        self.active_remote_streams += other.active_remote_streams;
+        self.send_window += other.send_window;
+        self.recv_window += other.recv_window;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metrics_visit() {
+        let mut stats = Stats::default();
+        stats.frames_sent.data = 3;
+        stats.pings_sent = 1;
+        let mut metrics = MetricsSnapshot::new(stats, 1, 2, 100, 200);
+        metrics += MetricsSnapshot::new(stats, 0, 1, 0, 0);
+
+        let mut items = Vec::new();
+        metrics.visit(|name, kind, val| items.push((name, kind, val)));
+
+        assert!(items.contains(&("h2_frames_sent_data_total", MetricKind::Counter, 6)));
+        assert!(items.contains(&("h2_pings_sent_total", MetricKind::Counter, 2)));
+        assert!(items.contains(&("h2_active_remote_streams", MetricKind::Gauge, 3)));
+        assert!(items.contains(&("h2_recv_window_bytes", MetricKind::Gauge, 200)));
+
+        let mut names: Vec<_> = items.iter().map(|(name, _, _)| *name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), items.len());
+    }
+
+    #[test]
+    fn test_violations() {
+        let mut v = Violations::default();
+        v.connection_error(&ConnectionError::Decoder(FrameError::Continuation(
+            FrameContinuationError::MaxContinuations,
+        )));
+        v.connection_error(&ConnectionError::Decoder(FrameError::BadFrameSize));
+        v.connection_error(&ConnectionError::WindowValueOverflow);
+        v.connection_error(&ConnectionError::ConcurrencyOverflow);
+        v.connection_error(&ConnectionError::KeepaliveTimeout);
+        v.stream_error(&StreamError::WindowOverflowed);
+        v.stream_error(&StreamError::WrongPayloadLength);
+
+        assert_eq!(v.oversized_headers, 1);
+        assert_eq!(v.bad_frames, 1);
+        assert_eq!(v.window_violations, 2);
+        assert_eq!(v.floods, 1);
+        assert_eq!(v.stream_errors, 1);
+        assert_eq!(v.total(), 6);
+    }
+
+    #[test]
+    fn test_rtt() {
+        let mut rtt = Rtt::default();
+        assert_eq!(rtt.stats(), RttStats::default());
+
+        for ms in (1..=100).rev() {
+            rtt.add(Duration::from_millis(ms));
+        }
+        let stats = rtt.stats();
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.last, Some(Duration::from_millis(1)));
+        assert_eq!(stats.min, Some(Duration::from_millis(1)));
+        assert_eq!(stats.avg, Some(Duration::from_micros(50500)));
+        // only recent samples, 1..=64
+        assert_eq!(stats.p50, Some(Duration::from_millis(33)));
+        assert_eq!(stats.p99, Some(Duration::from_millis(64)));
+
+        let mut total = RttStats::default();
+        total += stats;
+        assert_eq!(total, stats);
+        total += stats;
+        assert_eq!(total.samples, 200);
+        assert_eq!(total.avg, stats.avg);
+    }
+}
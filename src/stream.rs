@@ -1,14 +1,20 @@
-use std::{cell::Cell, cmp, fmt, future::poll_fn, mem, ops, rc::Rc, task::Context, task::Poll};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::time::{Duration, Instant};
+use std::{cmp, fmt, future::poll_fn, mem, ops, rc::Rc, task::Context, task::Poll};
 
-use ntex_bytes::Bytes;
-use ntex_http::{header::CONTENT_LENGTH, HeaderMap, StatusCode};
-use ntex_util::task::LocalWaker;
+use ntex_bytes::{ByteString, Bytes};
+use ntex_http::{header::CONTENT_LENGTH, HeaderMap, Method, StatusCode};
+use ntex_util::time::Millis;
+use ntex_util::{services::Extensions, task::LocalWaker};
 
+use crate::config::{ContentLengthPolicy, HeadPayloadPolicy};
+use crate::detached::{Channel, DetachedStream};
 use crate::error::{OperationError, StreamError};
 use crate::frame::{
     Data, Headers, PseudoHeaders, Reason, Reset, StreamId, WindowSize, WindowUpdate,
 };
-use crate::{connection::Connection, frame, message::Message, window::Window};
+use crate::{connection::Connection, dump::StreamDump, message::Message};
+use crate::{stats::Stats, window::Window};
 
 /// HTTP/2 Stream
 pub struct Stream(StreamRef);
@@ -23,6 +29,9 @@ pub struct Capacity {
 impl Capacity {
     fn new(size: u32, stream: &Rc<StreamState>) -> Self {
         stream.add_capacity(size);
+        if size > 0 {
+            stream.con.buffer_frame();
+        }
 
         Self {
             size: Cell::new(size),
@@ -45,12 +54,15 @@ impl Capacity {
             log::trace!(
                 "{}: {:?} capacity consumed from {} to {}",
                 self.stream.tag(),
-                self.stream.id,
+                self.stream.log_id(),
                 size,
                 sz
             );
             self.size.set(sz);
             self.stream.consume_capacity(size - sz);
+            if size > 0 && sz == 0 {
+                self.stream.con.release_frame();
+            }
         } else {
             panic!("Capacity overflow");
         }
@@ -63,6 +75,9 @@ impl ops::Add for Capacity {
 
     fn add(self, other: Self) -> Self {
         if Rc::ptr_eq(&self.stream, &other.stream) {
+            if self.size.get() > 0 && other.size.get() > 0 {
+                self.stream.con.release_frame();
+            }
             let size = Cell::new(self.size.get() + other.size.get());
             self.size.set(0);
             other.size.set(0);
@@ -80,6 +95,9 @@ impl ops::Add for Capacity {
 impl ops::AddAssign for Capacity {
     fn add_assign(&mut self, other: Self) {
         if Rc::ptr_eq(&self.stream, &other.stream) {
+            if self.size.get() > 0 && other.size.get() > 0 {
+                self.stream.con.release_frame();
+            }
             let size = self.size.get() + other.size.get();
             self.size.set(size);
             other.size.set(0);
@@ -94,6 +112,7 @@ impl Drop for Capacity {
         let size = self.size.get();
         if size > 0 {
             self.stream.consume_capacity(size);
+            self.stream.con.release_frame();
         }
     }
 }
@@ -106,6 +125,34 @@ pub enum ContentLength {
     Remaining(u64),
 }
 
+/// Stream close outcome
+#[derive(Clone, Debug)]
+pub enum StreamClose {
+    /// Both sides of the stream are closed without errors
+    Clean,
+    /// Stream has been reset locally
+    LocalReset(Reason),
+    /// Stream has been reset by the peer
+    RemoteReset(Reason),
+    /// Stream failed, usually because of connection failure
+    Failed(OperationError),
+}
+
+/// Stream state, as defined in RFC 7540 section 5.1
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamStatus {
+    /// Headers have not been sent or received yet
+    Idle,
+    /// Both sides of the stream are open
+    Open,
+    /// Local side finished sending, peer could still send frames
+    HalfClosedLocal,
+    /// Peer finished sending, local side could still send frames
+    HalfClosedRemote,
+    /// Both sides are closed, contains reset reason if stream has been reset
+    Closed(Option<Reason>),
+}
+
 #[derive(Clone, Debug)]
 pub struct StreamRef(pub(crate) Rc<StreamState>);
 
@@ -114,6 +161,8 @@ bitflags::bitflags! {
     struct StreamFlags: u8 {
         const REMOTE = 0b0000_0001;
         const FAILED = 0b0000_0010;
+        const STALLED = 0b0000_0100;
+        const TRAILERS = 0b0000_1000;
     }
 }
 
@@ -122,19 +171,38 @@ pub(crate) struct StreamState {
     id: StreamId,
     flags: Cell<StreamFlags>,
     content_length: Cell<ContentLength>,
+    declared_length: Cell<Option<u64>>,
     /// Receive part
     recv: Cell<HalfState>,
     recv_window: Cell<Window>,
+    recv_window_sz: Cell<WindowSize>,
+    recv_window_threshold: Cell<WindowSize>,
     recv_size: Cell<u32>,
+    recv_bytes: Cell<u64>,
     /// Send part
     send: Cell<HalfState>,
     send_window: Cell<Window>,
     send_cap: LocalWaker,
     send_reset: LocalWaker,
+    // time when send got blocked by flow control
+    send_blocked: Cell<Option<Instant>>,
+    // stream must be reset after deadline
+    deadline: Cell<Option<Instant>>,
+    close: LocalWaker,
     /// Connection config
     con: Connection,
     /// error state
     error: Cell<Option<OperationError>>,
+    /// user data
+    extensions: RefCell<Extensions>,
+    /// request pseudo headers
+    request: RefCell<Option<PseudoHeaders>>,
+    /// messages receiver for detached stream
+    detached: RefCell<Option<Rc<Channel>>>,
+    /// user label for log correlation
+    label: RefCell<Option<ByteString>>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -148,6 +216,14 @@ impl HalfState {
     pub(crate) fn is_closed(&self) -> bool {
         matches!(self, HalfState::Closed(_))
     }
+
+    fn name(&self) -> &'static str {
+        match self {
+            HalfState::Idle => "idle",
+            HalfState::Payload => "open",
+            HalfState::Closed(_) => "closed",
+        }
+    }
 }
 
 impl StreamState {
@@ -155,7 +231,35 @@ impl StreamState {
         self.con.tag()
     }
 
+    #[cfg(feature = "tracing")]
+    fn record_pseudo(&self, pseudo: &PseudoHeaders) {
+        if let Some(ref method) = pseudo.method {
+            self.span.record("method", method.as_str());
+        }
+        if let Some(ref path) = pseudo.path {
+            self.span.record("path", &**path);
+        }
+    }
+
+    /// Stream id with user label, for log records
+    fn log_id(&self) -> LogId<'_> {
+        LogId(self)
+    }
+
+    fn store_request(&self, pseudo: &PseudoHeaders) {
+        if pseudo.method.is_some() {
+            *self.request.borrow_mut() = Some(pseudo.clone());
+        }
+    }
+
+    fn qlog_state(&self, side: &str, state: &str) {
+        if let Some(qlog) = self.con.qlog() {
+            qlog.stream_state(self.id, side, state);
+        }
+    }
+
     fn state_send_payload(&self) {
+        self.qlog_state("send", "open");
         self.send.set(HalfState::Payload);
     }
 
@@ -163,24 +267,40 @@ impl StreamState {
         log::trace!(
             "{}: {:?} send side is closed with reason {:?}",
             self.tag(),
-            self.id,
+            self.log_id(),
             reason
         );
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &self.span, ?reason, "send side closed");
+
+        self.qlog_state("send", "closed");
         self.send.set(HalfState::Closed(reason));
         self.send_cap.wake();
         self.review_state();
     }
 
     fn state_recv_payload(&self) {
+        self.qlog_state("recv", "open");
         self.recv.set(HalfState::Payload);
     }
 
     fn state_recv_close(&self, reason: Option<Reason>) {
-        log::trace!("{}: {:?} receive side is closed", self.tag(), self.id);
+        log::trace!("{}: {:?} receive side is closed", self.tag(), self.log_id());
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &self.span, ?reason, "receive side closed");
+
+        self.qlog_state("recv", "closed");
         self.recv.set(HalfState::Closed(reason));
         self.review_state();
     }
 
+    fn send_unblocked(&self) {
+        self.send_blocked.set(None);
+        let mut flags = self.flags.get();
+        flags.remove(StreamFlags::STALLED);
+        self.flags.set(flags);
+    }
+
     fn set_failed(&self) {
         let mut flags = self.flags.get();
         flags.insert(StreamFlags::FAILED);
@@ -190,6 +310,10 @@ impl StreamState {
     }
 
     fn reset_stream(&self, reason: Option<Reason>) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &self.span, ?reason, "stream reset");
+
+        self.qlog_state("both", "reset");
         self.set_failed();
         self.recv.set(HalfState::Closed(None));
         self.send.set(HalfState::Closed(reason));
@@ -200,6 +324,10 @@ impl StreamState {
     }
 
     fn remote_reset_stream(&self, reason: Reason) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &self.span, ?reason, "stream reset by peer");
+
+        self.qlog_state("both", "reset_by_peer");
         self.set_failed();
         self.recv.set(HalfState::Closed(Some(reason)));
         self.send.set(HalfState::Closed(None));
@@ -208,6 +336,10 @@ impl StreamState {
     }
 
     fn failed(&self, err: OperationError) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &self.span, error = ?err, "stream failed");
+
+        self.qlog_state("both", "failed");
         self.set_failed();
         self.recv.set(HalfState::Closed(None));
         self.send.set(HalfState::Closed(None));
@@ -230,17 +362,18 @@ impl StreamState {
 
             if let HalfState::Closed(reason) = self.send.get() {
                 // stream is closed
+                self.close.wake();
                 if reason.is_some() {
                     log::trace!(
                         "{}: {:?} is closed with local reset, dropping stream",
                         self.tag(),
-                        self.id
+                        self.log_id()
                     );
                 } else {
                     log::trace!(
                         "{}: {:?} both sides are closed, dropping stream",
                         self.tag(),
-                        self.id
+                        self.log_id()
                     );
                 }
                 self.con.drop_stream(self.id);
@@ -256,7 +389,7 @@ impl StreamState {
         log::trace!(
             "{}: {:?} capacity incresed from {} to {}",
             self.tag(),
-            self.id,
+            self.log_id(),
             cap,
             cap + size
         );
@@ -272,55 +405,81 @@ impl StreamState {
         log::trace!(
             "{}: {:?} capacity decresed from {} to {}",
             self.tag(),
-            self.id,
+            self.log_id(),
             cap,
             size
         );
 
         self.recv_size.set(size);
+        self.con.release_bytes(cap - size);
+        self.update_recv_window(self.recv_window_threshold.get());
+    }
+
+    /// send WINDOW_UPDATE frame if reclaimed capacity reaches threshold
+    fn update_recv_window(&self, threshold: WindowSize) {
         let mut window = self.recv_window.get();
-        if let Some(val) = window.update(
-            size,
-            self.con.config().window_sz.get(),
-            self.con.config().window_sz_threshold.get(),
-        ) {
+        if let Some(val) = window.update(self.recv_size.get(), self.recv_window_sz.get(), threshold)
+        {
             log::trace!(
                 "{}: {:?} capacity decresed below threshold {} increase by {} ({})",
                 self.tag(),
-                self.id,
-                self.con.config().window_sz_threshold.get(),
+                self.log_id(),
+                threshold,
                 val,
-                self.con.config().window_sz.get(),
+                self.recv_window_sz.get(),
             );
             self.recv_window.set(window);
             self.con.encode(WindowUpdate::new(self.id, val));
+            if let Some(qlog) = self.con.qlog() {
+                qlog.window_updated(self.id, "local", window.window_size);
+            }
         }
     }
 }
 
 impl StreamRef {
     pub(crate) fn new(id: StreamId, remote: bool, con: Connection) -> Self {
-        // if peer has accepted settings, we can use local config window size
-        // otherwise use default window size
-        let recv_window = if con.settings_processed() {
-            Window::new(con.config().window_sz.get() as i32)
-        } else {
-            Window::new(frame::DEFAULT_INITIAL_WINDOW_SIZE as i32)
-        };
+        // window size acknowledged by peer, default window size
+        // is used until peer accepts local settings
+        let recv_window = Window::new(con.local_window_size() as i32);
         let send_window = Window::new(con.remote_window_size() as i32);
+        con.update_stats(|stats| stats.streams_opened += 1);
+        let recv_window_sz = con.config().window_sz.get();
+        let recv_window_threshold = con.config().window_sz_threshold.get();
 
         StreamRef(Rc::new(StreamState {
             id,
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                parent: con.span(),
+                "h2::stream",
+                id = u32::from(id),
+                remote,
+                method = tracing::field::Empty,
+                path = tracing::field::Empty,
+                label = tracing::field::Empty,
+            ),
             con,
             recv: Cell::new(HalfState::Idle),
             recv_window: Cell::new(recv_window),
+            recv_window_sz: Cell::new(recv_window_sz),
+            recv_window_threshold: Cell::new(recv_window_threshold),
             recv_size: Cell::new(0),
+            recv_bytes: Cell::new(0),
             send: Cell::new(HalfState::Idle),
             send_window: Cell::new(send_window),
             send_cap: LocalWaker::new(),
             send_reset: LocalWaker::new(),
+            send_blocked: Cell::new(None),
+            deadline: Cell::new(None),
+            close: LocalWaker::new(),
             error: Cell::new(None),
+            extensions: RefCell::new(Extensions::new()),
+            request: RefCell::new(None),
+            detached: RefCell::new(None),
+            label: RefCell::new(None),
             content_length: Cell::new(ContentLength::Omitted),
+            declared_length: Cell::new(None),
             flags: Cell::new(if remote {
                 StreamFlags::REMOTE
             } else {
@@ -339,12 +498,95 @@ impl StreamRef {
         self.0.con.tag()
     }
 
+    /// Statistics of the stream's connection
+    #[inline]
+    pub fn stats(&self) -> Stats {
+        self.0.con.stats()
+    }
+
     /// Check if stream has been opened from remote side
     #[inline]
     pub fn is_remote(&self) -> bool {
         self.0.flags.get().contains(StreamFlags::REMOTE)
     }
 
+    /// Request method
+    pub fn method(&self) -> Option<Method> {
+        self.0.request.borrow().as_ref()?.method.clone()
+    }
+
+    /// Request path
+    pub fn path(&self) -> Option<ByteString> {
+        self.0.request.borrow().as_ref()?.path.clone()
+    }
+
+    /// Request authority
+    pub fn authority(&self) -> Option<ByteString> {
+        self.0.request.borrow().as_ref()?.authority.clone()
+    }
+
+    /// Content length declared by the peer
+    pub fn content_length(&self) -> Option<u64> {
+        self.0.declared_length.get()
+    }
+
+    /// Number of payload bytes received so far
+    pub fn received_bytes(&self) -> u64 {
+        self.0.recv_bytes.get()
+    }
+
+    /// Number of payload bytes remaining, if content length is declared
+    pub fn remaining(&self) -> Option<u64> {
+        if let ContentLength::Remaining(rem) = self.0.content_length.get() {
+            Some(rem)
+        } else {
+            None
+        }
+    }
+
+    /// Set stream label.
+    ///
+    /// Label, for example application request id, is included
+    /// in log records and tracing span of the stream.
+    pub fn set_label<T: Into<ByteString>>(&self, label: T) {
+        let label = label.into();
+        #[cfg(feature = "tracing")]
+        self.0.span.record("label", &*label);
+        *self.0.label.borrow_mut() = Some(label);
+    }
+
+    /// Stream label
+    pub fn label(&self) -> Option<ByteString> {
+        self.0.label.borrow().clone()
+    }
+
+    /// Detach stream from publish service.
+    ///
+    /// All following messages of the stream are delivered to returned
+    /// receiver, which could be consumed by separate task. Receiver holds
+    /// up to `capacity` messages.
+    pub fn detach(&self, capacity: usize) -> DetachedStream {
+        let ch = Channel::new(capacity);
+        *self.0.detached.borrow_mut() = Some(ch.clone());
+        DetachedStream::new(self.clone(), ch)
+    }
+
+    pub(crate) fn detached(&self) -> Option<Rc<Channel>> {
+        self.0.detached.borrow().clone()
+    }
+
+    /// Per-stream user data.
+    ///
+    /// Data is shared between all messages of the stream.
+    pub fn extensions(&self) -> Ref<'_, Extensions> {
+        self.0.extensions.borrow()
+    }
+
+    /// Mutable reference to per-stream user data
+    pub fn extensions_mut(&self) -> RefMut<'_, Extensions> {
+        self.0.extensions.borrow_mut()
+    }
+
     /// Check if stream has failed
     #[inline]
     pub fn is_failed(&self) -> bool {
@@ -359,7 +601,110 @@ impl StreamRef {
         self.0.recv.get()
     }
 
-    /// Reset stream
+    pub(crate) fn dump(&self) -> StreamDump {
+        StreamDump {
+            id: self.0.id,
+            label: self.label(),
+            remote: self.is_remote(),
+            send_state: self.0.send.get().name(),
+            recv_state: self.0.recv.get().name(),
+            reset_reason: self.reset_reason(),
+            send_window: self.0.send_window.get().window_size(),
+            recv_window: self.0.recv_window.get().window_size(),
+            recv_buffered: self.0.recv_size.get(),
+            failed: self.is_failed(),
+        }
+    }
+
+    /// Set stream deadline.
+    ///
+    /// Stream is reset with `CANCEL` reason if it is not closed within
+    /// `timeout`, and `Control::StreamTimeout` message is sent to control service.
+    /// Zero timeout removes deadline.
+    pub fn set_deadline<T: Into<Millis>>(&self, timeout: T) {
+        let timeout = timeout.into();
+        if timeout.non_zero() {
+            self.0
+                .deadline
+                .set(Some(self.0.con.clock().now() + Duration::from(timeout)));
+            self.0.con.deadline_updated();
+        } else {
+            self.0.deadline.set(None);
+        }
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.0.deadline.get()
+    }
+
+    /// Check if stream deadline is elapsed.
+    ///
+    /// Deadline is reported once.
+    pub(crate) fn check_deadline(&self, now: Instant) -> bool {
+        match self.0.deadline.get() {
+            Some(deadline) if deadline <= now => {
+                self.0.deadline.set(None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Check if send is blocked by flow control for longer than timeout.
+    ///
+    /// Stall is reported once until peer grants send window.
+    pub(crate) fn check_stalled(&self, timeout: Duration) -> Option<Duration> {
+        let flags = self.0.flags.get();
+        if flags.intersects(StreamFlags::STALLED | StreamFlags::FAILED) {
+            return None;
+        }
+
+        let blocked = self
+            .0
+            .con
+            .clock()
+            .now()
+            .saturating_duration_since(self.0.send_blocked.get()?);
+        if blocked >= timeout {
+            self.0.flags.set(flags | StreamFlags::STALLED);
+            Some(blocked)
+        } else {
+            None
+        }
+    }
+
+    /// Current stream state
+    pub fn state(&self) -> StreamStatus {
+        match (self.0.send.get(), self.0.recv.get()) {
+            (HalfState::Idle, HalfState::Idle) => StreamStatus::Idle,
+            (HalfState::Closed(_), HalfState::Closed(_)) => {
+                StreamStatus::Closed(self.reset_reason())
+            }
+            (HalfState::Closed(_), _) => StreamStatus::HalfClosedLocal,
+            (_, HalfState::Closed(_)) => StreamStatus::HalfClosedRemote,
+            _ => StreamStatus::Open,
+        }
+    }
+
+    /// Check if trailers have been received from the peer
+    pub fn is_trailers_received(&self) -> bool {
+        self.0.flags.get().contains(StreamFlags::TRAILERS)
+    }
+
+    /// Stream reset reason, local or remote
+    pub(crate) fn reset_reason(&self) -> Option<Reason> {
+        match (self.0.send.get(), self.0.recv.get()) {
+            (HalfState::Closed(Some(reason)), _) | (_, HalfState::Closed(Some(reason))) => {
+                Some(reason)
+            }
+            _ => None,
+        }
+    }
+
+    /// Reset stream.
+    ///
+    /// Sends RST_STREAM frame with provided reason and closes both
+    /// sides of the stream. Does nothing if stream is already closed.
     #[inline]
     pub fn reset(&self, reason: Reason) {
         if !self.0.recv.get().is_closed() || !self.0.send.get().is_closed() {
@@ -368,6 +713,29 @@ impl StreamRef {
         }
     }
 
+    /// Reset stream with `CANCEL` reason, if configured for dropped streams
+    pub(crate) fn drop_reset(&self) {
+        if self.0.con.config().reset_streams_on_drop() {
+            self.reset(Reason::CANCEL);
+        }
+    }
+
+    /// Set stream receive window size.
+    ///
+    /// Overrides connection's initial window size for this stream,
+    /// for example from `Control::StreamOpened` handler. Increase is announced
+    /// to the peer immediately, decrease takes effect as received data
+    /// get consumed.
+    pub fn set_recv_window(&self, size: WindowSize) {
+        self.0.recv_window_sz.set(size);
+        self.0
+            .recv_window_threshold
+            .set(((size as f32) / 3.0) as WindowSize);
+        if !self.0.recv.get().is_closed() {
+            self.0.update_recv_window(1);
+        }
+    }
+
     /// Get capacity instance for current stream
     #[inline]
     pub fn empty_capacity(&self) -> Capacity {
@@ -396,10 +764,15 @@ impl StreamRef {
             hdrs.is_end_stream()
         );
 
-        if hdrs
-            .pseudo()
-            .status
-            .is_some_and(|status| status.is_informational())
+        #[cfg(feature = "tracing")]
+        self.0.record_pseudo(hdrs.pseudo());
+        self.0.store_request(hdrs.pseudo());
+
+        if hdrs.pseudo().method == Some(Method::HEAD)
+            || hdrs
+                .pseudo()
+                .status
+                .is_some_and(|status| status.is_informational())
         {
             self.0.content_length.set(ContentLength::Head)
         }
@@ -422,13 +795,43 @@ impl StreamRef {
         log::trace!(
             "{}: processing HEADERS for {:?}:\n{:#?}\nrecv_state:{:?}, send_state: {:?}",
             self.tag(),
-            self.0.id,
+            self.0.log_id(),
             hdrs,
             self.0.recv.get(),
             self.0.send.get(),
         );
 
+        if hdrs.has_connection_headers() {
+            if self.0.con.config().strip_connection_headers() {
+                log::debug!(
+                    "{}: connection specific headers are stripped; stream={:?}",
+                    self.tag(),
+                    self.0.id
+                );
+            } else {
+                proto_err!(stream: "connection specific headers; stream={:?}", self.0.id);
+                return Err(StreamError::ConnectionHeader);
+            }
+        }
+
         match self.0.recv.get() {
+            HalfState::Idle
+                if hdrs
+                    .pseudo()
+                    .status
+                    .is_some_and(|status| status.is_informational()) =>
+            {
+                // interim response, final response is expected
+                if hdrs.is_end_stream() {
+                    proto_err!(stream: "informational response with END_STREAM; stream={:?}", self.0.id);
+                    Err(StreamError::MalformedPseudo(
+                        "informational response with END_STREAM",
+                    ))
+                } else {
+                    let (pseudo, headers) = hdrs.into_parts();
+                    Ok(Some(Message::new(pseudo, headers, false, self)))
+                }
+            }
             HalfState::Idle => {
                 let eof = hdrs.is_end_stream();
                 if eof {
@@ -436,15 +839,35 @@ impl StreamRef {
                 } else {
                     self.0.state_recv_payload();
                 }
-                let (pseudo, headers) = hdrs.into_parts();
+                let (pseudo, mut headers) = hdrs.into_parts();
+                #[cfg(feature = "tracing")]
+                self.0.record_pseudo(&pseudo);
+                self.0.store_request(&pseudo);
 
                 if self.0.content_length.get() != ContentLength::Head {
-                    if let Some(content_length) = headers.get(CONTENT_LENGTH) {
-                        if let Some(v) = parse_u64(content_length.as_bytes()) {
-                            self.0.content_length.set(ContentLength::Remaining(v));
+                    if let Some(content_length) = content_length(&mut headers) {
+                        let policy = self.0.con.config().content_length_policy.get();
+                        if let Some(v) = content_length {
+                            if policy != ContentLengthPolicy::Ignore {
+                                self.0.content_length.set(ContentLength::Remaining(v));
+                            }
+                            self.0.declared_length.set(Some(v));
                         } else {
-                            proto_err!(stream: "could not parse content-length; stream={:?}", self.0.id);
-                            return Err(StreamError::InvalidContentLength);
+                            match policy {
+                                ContentLengthPolicy::Strict => {
+                                    proto_err!(stream: "invalid or conflicting content-length; stream={:?}", self.0.id);
+                                    return Err(StreamError::InvalidContentLength);
+                                }
+                                ContentLengthPolicy::Strip => {
+                                    log::warn!(
+                                        "{}: invalid or conflicting content-length, stripping header; stream={:?}",
+                                        self.tag(),
+                                        self.0.id
+                                    );
+                                    headers.remove(CONTENT_LENGTH);
+                                }
+                                ContentLengthPolicy::Ignore => (),
+                            }
                         }
                     }
                 }
@@ -455,6 +878,7 @@ impl StreamRef {
                 if !hdrs.is_end_stream() {
                     Err(StreamError::TrailersWithoutEos)
                 } else {
+                    self.0.flags.set(self.0.flags.get() | StreamFlags::TRAILERS);
                     self.0.state_recv_close(None);
                     Ok(Some(Message::trailers(hdrs.into_fields(), self)))
                 }
@@ -468,30 +892,56 @@ impl StreamRef {
         log::trace!(
             "{}: processing DATA frame for {:?}, len: {:?}",
             self.tag(),
-            self.0.id,
+            self.0.log_id(),
             data.payload().len()
         );
 
         match self.0.recv.get() {
             HalfState::Payload => {
                 let eof = data.is_end_stream();
+                self.0
+                    .recv_bytes
+                    .set(self.0.recv_bytes.get() + data.payload().len() as u64);
 
                 // Returns `Err` when the decrement cannot be completed due to overflow
                 match self.0.content_length.get() {
                     ContentLength::Remaining(rem) => {
                         match rem.checked_sub(data.payload().len() as u64) {
-                            Some(val) => {
+                            Some(val) if !eof || val == 0 => {
                                 self.0.content_length.set(ContentLength::Remaining(val));
-                                if eof && val != 0 {
+                            }
+                            _ => {
+                                if self.0.con.config().content_length_policy.get()
+                                    == ContentLengthPolicy::Strict
+                                {
                                     return Err(StreamError::WrongPayloadLength);
                                 }
+                                log::warn!(
+                                    "{}: payload size does not match content-length; stream={:?}",
+                                    self.tag(),
+                                    self.0.id
+                                );
+                                self.0.content_length.set(ContentLength::Omitted);
                             }
-                            None => return Err(StreamError::WrongPayloadLength),
                         }
                     }
-                    ContentLength::Head => {
-                        if !data.payload().is_empty() {
-                            return Err(StreamError::NonEmptyPayload);
+                    ContentLength::Head if !data.payload().is_empty() => {
+                        match self.0.con.config().head_payload_policy.get() {
+                            HeadPayloadPolicy::Reset => return Err(StreamError::NonEmptyPayload),
+                            HeadPayloadPolicy::Truncate => {
+                                log::debug!(
+                                    "{}: discarding non-empty payload of HEAD response; stream={:?}",
+                                    self.tag(),
+                                    self.0.id
+                                );
+                                return if eof {
+                                    self.0.state_recv_close(None);
+                                    Ok(Some(Message::eof_data(Bytes::new(), self)))
+                                } else {
+                                    Ok(None)
+                                };
+                            }
+                            HeadPayloadPolicy::PassThrough => (),
                         }
                     }
                     _ => (),
@@ -524,8 +974,12 @@ impl StreamRef {
                 .inc(frm.size_increment())
                 .map_err(|_| StreamError::WindowOverflowed)?;
             self.0.send_window.set(window);
+            if let Some(qlog) = self.0.con.qlog() {
+                qlog.window_updated(self.0.id, "remote", window.window_size);
+            }
 
             if window.window_size() > 0 {
+                self.0.send_unblocked();
                 self.0.send_cap.wake();
             }
             Ok(())
@@ -548,6 +1002,9 @@ impl StreamRef {
             window.window_size
         );
         self.0.send_window.set(window);
+        if upd > 0 {
+            self.0.send_cap.wake();
+        }
         Ok(())
     }
 
@@ -564,8 +1021,8 @@ impl StreamRef {
         };
         if let Some(val) = window.update(
             self.0.recv_size.get(),
-            self.0.con.config().window_sz.get(),
-            self.0.con.config().window_sz_threshold.get(),
+            self.0.recv_window_sz.get(),
+            self.0.recv_window_threshold.get(),
         ) {
             self.0.recv_window.set(window);
             Ok(Some(val))
@@ -601,6 +1058,29 @@ impl StreamRef {
         }
     }
 
+    /// Send informational (1xx) response.
+    ///
+    /// Could be sent multiple times before final response.
+    pub fn send_informational(
+        &self,
+        status: StatusCode,
+        headers: HeaderMap,
+    ) -> Result<(), OperationError> {
+        debug_assert!(status.is_informational());
+
+        match self.0.send.get() {
+            HalfState::Idle => {
+                let pseudo = PseudoHeaders::response(status);
+                self.0
+                    .con
+                    .encode(Headers::new(self.0.id, pseudo, headers, false));
+                Ok(())
+            }
+            HalfState::Payload => Err(OperationError::Payload),
+            HalfState::Closed(r) => Err(OperationError::Closed(r)),
+        }
+    }
+
     /// Send payload
     pub async fn send_payload(&self, mut res: Bytes, eof: bool) -> Result<(), OperationError> {
         match self.0.send.get() {
@@ -611,7 +1091,7 @@ impl StreamRef {
                 log::trace!(
                     "{}: {:?} sending {} bytes, eof: {}, send: {:?}",
                     self.0.tag(),
-                    self.0.id,
+                    self.0.log_id(),
                     res.len(),
                     eof,
                     self.0.send.get()
@@ -619,44 +1099,14 @@ impl StreamRef {
 
                 // eof and empty data
                 if eof && res.is_empty() {
-                    let mut data = Data::new(self.0.id, Bytes::new());
-                    data.set_end_stream();
-                    self.0.state_send_close(None);
-
-                    // write to io buffer
-                    self.0.con.encode(data);
-                    return Ok(());
+                    return self.end_stream();
                 }
 
                 loop {
                     // calaculate available send window size
                     let win = self.available_send_capacity() as usize;
                     if win > 0 {
-                        let size =
-                            cmp::min(win, cmp::min(res.len(), self.0.con.remote_frame_size()));
-                        let mut data = if size >= res.len() {
-                            Data::new(self.0.id, mem::replace(&mut res, Bytes::new()))
-                        } else {
-                            log::trace!(
-                                "{}: {:?} sending {} out of {} bytes",
-                                self.0.tag(),
-                                self.0.id,
-                                size,
-                                res.len()
-                            );
-                            Data::new(self.0.id, res.split_to(size))
-                        };
-                        if eof && res.is_empty() {
-                            data.set_end_stream();
-                            self.0.state_send_close(None);
-                        }
-
-                        // update send window
-                        self.0
-                            .send_window
-                            .set(self.0.send_window.get().dec(size as u32));
-                        // write to io buffer
-                        self.0.con.encode(data);
+                        self.send_data_frame(&mut res, win, eof);
                         if res.is_empty() {
                             return Ok(());
                         }
@@ -664,7 +1114,7 @@ impl StreamRef {
                         log::trace!(
                             "{}: Not enough sending capacity for {:?} remaining {:?}",
                             self.0.tag(),
-                            self.0.id,
+                            self.0.log_id(),
                             res.len()
                         );
                         // wait for available send window
@@ -677,6 +1127,83 @@ impl StreamRef {
         }
     }
 
+    /// Send payload without closing send side, as much as send window allows.
+    ///
+    /// Sent data is removed from `res`.
+    #[cfg(feature = "futures")]
+    pub(crate) fn poll_send_payload(
+        &self,
+        cx: &Context<'_>,
+        res: &mut Bytes,
+    ) -> Poll<Result<(), OperationError>> {
+        match self.0.send.get() {
+            HalfState::Payload => {
+                while !res.is_empty() {
+                    let win = match self.poll_send_capacity(cx) {
+                        Poll::Ready(Ok(win)) => win as usize,
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    self.send_data_frame(res, win, false);
+                }
+                Poll::Ready(Ok(()))
+            }
+            HalfState::Idle => Poll::Ready(Err(OperationError::Idle)),
+            HalfState::Closed(reason) => Poll::Ready(Err(OperationError::Closed(reason))),
+        }
+    }
+
+    /// Finish sending payload.
+    ///
+    /// Sends empty DATA frame with END_STREAM flag and closes send side
+    /// of the stream. Same as `send_payload(Bytes::new(), true)`, but does not
+    /// need to be awaited.
+    pub fn end_stream(&self) -> Result<(), OperationError> {
+        match self.0.send.get() {
+            HalfState::Payload => {
+                self.0.check_error()?;
+
+                let mut data = Data::new(self.0.id, Bytes::new());
+                data.set_end_stream();
+                self.0.state_send_close(None);
+
+                // write to io buffer
+                self.0.con.encode(data);
+                Ok(())
+            }
+            HalfState::Idle => Err(OperationError::Idle),
+            HalfState::Closed(reason) => Err(OperationError::Closed(reason)),
+        }
+    }
+
+    /// Encode DATA frame, frame size is limited by send window and peer's max frame size
+    fn send_data_frame(&self, res: &mut Bytes, win: usize, eof: bool) {
+        let size = cmp::min(win, cmp::min(res.len(), self.0.con.remote_frame_size()));
+        let mut data = if size >= res.len() {
+            Data::new(self.0.id, mem::replace(res, Bytes::new()))
+        } else {
+            log::trace!(
+                "{}: {:?} sending {} out of {} bytes",
+                self.0.tag(),
+                self.0.log_id(),
+                size,
+                res.len()
+            );
+            Data::new(self.0.id, res.split_to(size))
+        };
+        if eof && res.is_empty() {
+            data.set_end_stream();
+            self.0.state_send_close(None);
+        }
+
+        // update send window
+        self.0
+            .send_window
+            .set(self.0.send_window.get().dec(size as u32));
+        // write to io buffer
+        self.0.con.encode(data);
+    }
+
     /// Send client trailers and close stream
     pub fn send_trailers(&self, map: HeaderMap) {
         if self.0.send.get() == HalfState::Payload {
@@ -703,13 +1230,78 @@ impl StreamRef {
 
         let win = self.0.send_window.get().window_size();
         if win > 0 {
+            if self.0.send_blocked.get().is_some() {
+                self.0.send_unblocked();
+            }
             Poll::Ready(Ok(win))
         } else {
+            if self.0.send_blocked.get().is_none() {
+                self.0.send_blocked.set(Some(self.0.con.clock().now()));
+            }
+            self.0
+                .con
+                .update_stats(|stats| stats.flow_control_stalls += 1);
             self.0.send_cap.register(cx.waker());
             Poll::Pending
         }
     }
 
+    /// Wait until at least `min` bytes of send capacity is available.
+    ///
+    /// Returns available send capacity.
+    pub async fn on_capacity(&self, min: WindowSize) -> Result<WindowSize, OperationError> {
+        poll_fn(|cx| self.poll_capacity(cx, min)).await
+    }
+
+    /// Check for at least `min` bytes of available send capacity
+    pub fn poll_capacity(
+        &self,
+        cx: &Context<'_>,
+        min: WindowSize,
+    ) -> Poll<Result<WindowSize, OperationError>> {
+        match self.poll_send_capacity(cx) {
+            Poll::Ready(Ok(win)) if win < min => {
+                self.0.send_cap.register(cx.waker());
+                Poll::Pending
+            }
+            res => res,
+        }
+    }
+
+    /// Wait until both sides of the stream are closed
+    pub async fn on_close(&self) -> StreamClose {
+        poll_fn(|cx| self.poll_close(cx)).await
+    }
+
+    /// Check if both sides of the stream are closed
+    pub fn poll_close(&self, cx: &Context<'_>) -> Poll<StreamClose> {
+        if let Some(reason) = self.close_reason() {
+            Poll::Ready(reason)
+        } else {
+            self.0.close.register(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /// Stream close outcome.
+    ///
+    /// Returns `None` if stream is not closed yet.
+    pub fn close_reason(&self) -> Option<StreamClose> {
+        if !self.0.recv.get().is_closed() || !self.0.send.get().is_closed() {
+            return None;
+        }
+
+        Some(match self.0.check_error() {
+            Err(OperationError::LocalReset(reason)) => StreamClose::LocalReset(reason),
+            Err(OperationError::RemoteReset(reason)) => StreamClose::RemoteReset(reason),
+            Err(err) => StreamClose::Failed(err),
+            Ok(()) => match self.0.send.get() {
+                HalfState::Closed(Some(reason)) => StreamClose::LocalReset(reason),
+                _ => StreamClose::Clean,
+            },
+        })
+    }
+
     /// Check if send part of stream get reset
     pub fn poll_send_reset(&self, cx: &Context<'_>) -> Poll<Result<(), OperationError>> {
         if self.0.send.get().is_closed() {
@@ -721,6 +1313,20 @@ impl StreamRef {
             Poll::Pending
         }
     }
+
+    /// Check if stream has been reset.
+    ///
+    /// Resolves with reset reason once stream get reset by the peer or locally,
+    /// failed streams resolve with `CANCEL`. Could be polled concurrently
+    /// with payload generation, to abort it early.
+    pub fn poll_reset(&self, cx: &Context<'_>) -> Poll<Reason> {
+        if self.is_failed() {
+            Poll::Ready(self.reset_reason().unwrap_or(Reason::CANCEL))
+        } else {
+            self.0.send_reset.register(cx.waker());
+            Poll::Pending
+        }
+    }
 }
 
 impl PartialEq for StreamRef {
@@ -740,7 +1346,7 @@ impl ops::Deref for Stream {
 
 impl Drop for Stream {
     fn drop(&mut self) {
-        self.0.reset(Reason::CANCEL);
+        self.0.drop_reset();
     }
 }
 
@@ -760,8 +1366,10 @@ impl fmt::Debug for StreamState {
         let mut builder = f.debug_struct("StreamState");
         builder
             .field("id", &self.id)
+            .field("label", &self.label.borrow())
             .field("recv", &self.recv.get())
             .field("recv_window", &self.recv_window.get())
+            .field("recv_window_sz", &self.recv_window_sz.get())
             .field("recv_size", &self.recv_size.get())
             .field("send", &self.send.get())
             .field("send_window", &self.send_window.get())
@@ -770,6 +1378,39 @@ impl fmt::Debug for StreamState {
     }
 }
 
+/// Stream id with user label
+struct LogId<'a>(&'a StreamState);
+
+impl fmt::Debug for LogId<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref label) = *self.0.label.borrow() {
+            write!(f, "{:?}[{}]", self.0.id, label)
+        } else {
+            write!(f, "{:?}", self.0.id)
+        }
+    }
+}
+
+/// Parse content-length header.
+///
+/// Duplicated values must be identical, identical values are collapsed.
+/// Returns `Some(None)` for invalid or conflicting values.
+fn content_length(headers: &mut HeaderMap) -> Option<Option<u64>> {
+    let mut values = headers.get_all(CONTENT_LENGTH);
+    let first = values.next()?.clone();
+    let mut duplicated = false;
+    for val in values {
+        if *val != first {
+            return Some(None);
+        }
+        duplicated = true;
+    }
+    if duplicated {
+        headers.insert(CONTENT_LENGTH, first.clone());
+    }
+    Some(parse_u64(first.as_bytes()))
+}
+
 pub fn parse_u64(src: &[u8]) -> Option<u64> {
     if src.len() > 19 {
         // At danger for overflow...
@@ -0,0 +1,212 @@
+//! In-memory client/server harness.
+//!
+//! Connects http/2 client to server handler in one process with in-memory
+//! duplex transport, so publish and control services could be tested
+//! without sockets or tls. [`Peer`] sends scripted frames, including
+//! malformed ones, and records frames sent back by the connection.
+use std::{cell::Cell, cell::RefCell, fmt};
+
+use ntex_http::uri::Scheme;
+use ntex_io::{testing::IoTest, Io};
+use ntex_service::ServiceFactory;
+use ntex_util::time::{timeout, Seconds};
+
+use crate::client::SimpleClient;
+use crate::control::{Control, ControlAck};
+use crate::{codec::Codec, config::Config, consts, frame::Frame};
+use crate::{message::Message, server::Server};
+
+const BUFFER_CAP: usize = 1_000_000;
+
+/// Create pair of connected in-memory io objects.
+///
+/// Bytes written to one io object are readable from the other one.
+pub fn duplex() -> (Io, Io) {
+    let (left, right) = IoTest::create();
+    left.remote_buffer_cap(BUFFER_CAP);
+    right.remote_buffer_cap(BUFFER_CAP);
+    (Io::new(left), Io::new(right))
+}
+
+/// Start server connection and return client connected to it.
+///
+/// Client uses default client configuration.
+pub fn connect<Ctl, Pub>(server: &Server<Ctl, Pub>) -> SimpleClient
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+{
+    connect_with(server, Config::client())
+}
+
+/// Start server connection and return client with provided configuration.
+///
+/// Server connection runs on spawned task until client or server
+/// closes connection.
+pub fn connect_with<Ctl, Pub>(server: &Server<Ctl, Pub>, config: Config) -> SimpleClient
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+{
+    SimpleClient::new(start(server), config, Scheme::HTTP, "localhost".into())
+}
+
+/// Start server connection on spawned task, returns client side io
+fn start<Ctl, Pub>(server: &Server<Ctl, Pub>) -> Io
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+{
+    let (client, srv) = duplex();
+
+    let handler = server.handler();
+    let _ = ntex_util::spawn(async move {
+        if let Err(err) = handler.run(srv.into()).await {
+            log::debug!("Test server connection is failed: {:?}", err);
+        }
+    });
+    client
+}
+
+/// Scriptable raw-frame peer
+///
+/// Frames are sent as is, without any validation. All received
+/// frames are recorded in order of arrival.
+pub struct Peer {
+    io: Io,
+    codec: Codec,
+    timeout: Cell<Seconds>,
+    received: RefCell<Vec<Frame>>,
+}
+
+impl Peer {
+    /// Create peer for io object
+    pub fn new(io: Io) -> Self {
+        Peer {
+            io,
+            codec: Codec::default(),
+            timeout: Cell::new(Seconds(5)),
+            received: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Start server connection and return peer connected to it.
+    ///
+    /// Connection preface is sent, SETTINGS frame must be sent by the script.
+    pub fn connect<Ctl, Pub>(server: &Server<Ctl, Pub>) -> Self
+    where
+        Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+        Ctl::Error: fmt::Debug,
+        Ctl::InitError: fmt::Debug,
+        Pub: ServiceFactory<Message, Response = ()> + 'static,
+        Pub::Error: fmt::Debug,
+        Pub::InitError: fmt::Debug,
+    {
+        let peer = Peer::new(start(server));
+        peer.send_raw(&consts::PREFACE);
+        peer
+    }
+
+    /// Set timeout for receiving frames.
+    ///
+    /// By default timeout is 5 seconds.
+    pub fn set_timeout(&self, timeout: Seconds) -> &Self {
+        self.timeout.set(timeout);
+        self
+    }
+
+    /// Get peer's codec
+    pub fn codec(&self) -> &Codec {
+        &self.codec
+    }
+
+    /// Send frame.
+    ///
+    /// Header blocks are encoded with peer's hpack context.
+    ///
+    /// Panics if frame cannot be encoded.
+    pub fn send<T: Into<Frame>>(&self, frm: T) -> &Self {
+        self.io
+            .encode(frm.into(), &self.codec)
+            .expect("Cannot encode frame");
+        self
+    }
+
+    /// Send raw bytes
+    pub fn send_raw(&self, data: &[u8]) -> &Self {
+        let _ = self.io.with_write_buf(|buf| buf.extend_from_slice(data));
+        self
+    }
+
+    /// Receive next frame.
+    ///
+    /// Returns `None` if connection is closed, frame cannot be decoded
+    /// or timeout is elapsed.
+    pub async fn recv(&self) -> Option<Frame> {
+        match timeout(self.timeout.get(), self.io.recv(&self.codec)).await {
+            Ok(Ok(Some(frm))) => {
+                self.received.borrow_mut().push(frm.clone());
+                Some(frm)
+            }
+            Ok(Ok(None)) => None,
+            Ok(Err(err)) => {
+                log::debug!("{}: Peer cannot receive frame: {:?}", self.io.tag(), err);
+                None
+            }
+            Err(_) => {
+                log::debug!("{}: Peer receive timeout", self.io.tag());
+                None
+            }
+        }
+    }
+
+    /// Receive frames until frame matches predicate
+    pub async fn recv_until<F>(&self, f: F) -> Option<Frame>
+    where
+        F: Fn(&Frame) -> bool,
+    {
+        loop {
+            let frm = self.recv().await?;
+            if f(&frm) {
+                return Some(frm);
+            }
+        }
+    }
+
+    /// Receive frames until connection is closed, returns all received frames
+    pub async fn closed(&self) -> Vec<Frame> {
+        while self.recv().await.is_some() {}
+        self.received()
+    }
+
+    /// Frames received so far
+    pub fn received(&self) -> Vec<Frame> {
+        self.received.borrow().clone()
+    }
+
+    /// Close connection
+    pub fn close(&self) {
+        self.io.close();
+    }
+}
+
+impl fmt::Debug for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Peer")
+            .field("timeout", &self.timeout.get())
+            .field("received", &self.received.borrow())
+            .finish()
+    }
+}
@@ -0,0 +1,100 @@
+//! Adapter for generic async transports.
+//!
+//! Wraps any tokio `AsyncRead + AsyncWrite` transport, like tcp stream
+//! or in-memory duplex, to `IoBoxed`, so it could be used with client
+//! connector or server handler. Read and write tasks are spawned with
+//! `tokio::task::spawn_local`, transport must be started within tokio `LocalSet`.
+use std::{fmt, io};
+
+use ntex_bytes::{BufMut, BytesVec};
+use ntex_io::{Handle, Io, IoBoxed, IoStream, ReadContext, WriteContext, WriteContextBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+
+// min read buffer capacity
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Generic async transport
+pub struct Transport<T>(T);
+
+impl<T> Transport<T>
+where
+    T: AsyncRead + AsyncWrite + 'static,
+{
+    /// Create new transport
+    pub fn new(io: T) -> Self {
+        Transport(io)
+    }
+
+    /// Convert transport to boxed io object
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of tokio `LocalSet`.
+    pub fn into_boxed(self) -> IoBoxed {
+        Io::new(self).into()
+    }
+}
+
+impl<T> IoStream for Transport<T>
+where
+    T: AsyncRead + AsyncWrite + 'static,
+{
+    fn start(self, read: ReadContext, write: WriteContext) -> Option<Box<dyn Handle>> {
+        let (rd, wr) = tokio::io::split(self.0);
+
+        tokio::task::spawn_local(async move {
+            read.handle(&mut Reader(rd)).await;
+            log::debug!("{}: Transport read task is stopped", read.tag());
+        });
+        tokio::task::spawn_local(async move {
+            write.handle(&mut Writer(wr)).await;
+            log::debug!("{}: Transport write task is stopped", write.tag());
+        });
+        None
+    }
+}
+
+impl<T> fmt::Debug for Transport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transport").finish()
+    }
+}
+
+struct Reader<T>(ReadHalf<T>);
+
+impl<T: AsyncRead> ntex_io::AsyncRead for Reader<T> {
+    async fn read(&mut self, mut buf: BytesVec) -> (BytesVec, io::Result<usize>) {
+        if buf.remaining_mut() < READ_CHUNK {
+            buf.reserve(READ_CHUNK);
+        }
+
+        // read directly to spare capacity of the buffer
+        let result = self.0.read_buf(&mut buf).await;
+        (buf, result)
+    }
+}
+
+struct Writer<T>(WriteHalf<T>);
+
+impl<T: AsyncWrite> ntex_io::AsyncWrite for Writer<T> {
+    async fn write(&mut self, buf: &mut WriteContextBuf) -> io::Result<()> {
+        if let Some(mut b) = buf.take() {
+            let result = self.0.write_all(&b).await;
+            if result.is_ok() {
+                b.clear();
+            }
+            buf.set(b);
+            result
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.0.flush().await
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.0.shutdown().await
+    }
+}
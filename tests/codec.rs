@@ -1,11 +1,23 @@
 mod support;
 
-use ntex_bytes::BytesMut;
-use ntex_codec::Decoder;
-use ntex_h2::{frame, frame::FrameError, Codec};
-use ntex_http::{HeaderMap, HeaderName, Method, StatusCode};
-use ntex_io::testing::IoTest;
+use std::{cell::Cell, cell::RefCell, future::poll_fn, rc::Rc, time::Duration};
+
+use ntex_bytes::{Bytes, BytesMut};
+use ntex_codec::{Decoder, Encoder};
+use ntex_h2::{client, server, Config, ContentLengthPolicy, Control, ControlPolicy};
+use ntex_h2::{datagram, datagram::DatagramStream, proxy, StreamBody, ViolationPolicy};
+use ntex_h2::{frame, frame::FrameError, Codec, Direction};
+use ntex_h2::{
+    ConfigError, ConnectionError, HeadPayloadPolicy, Message, MessageKind, OperationError,
+};
+use ntex_h2::{DefaultControlService, StreamStatus};
+use ntex_h2::{ManualClock, ResetStreamViolation, StreamEof, StreamError};
+use ntex_http::body::{BodySize, MessageBody};
+use ntex_http::{header::CONTENT_LENGTH, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use ntex_io::{testing::IoTest, Io, IoBoxed};
+use ntex_service::{fn_service, Pipeline, ServiceFactory};
 use ntex_util::future::join;
+use ntex_util::time::{sleep, Millis, Seconds};
 
 use support::{build_large_headers, frames};
 
@@ -103,6 +115,37 @@ fn read_data_stream_id_zero() {
     decode_err!(buf, FrameError::InvalidStreamId);
 }
 
+#[test]
+fn read_settings_invalid_value() {
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(&[0, 0, 6, 0x4, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(&[0, 0x2, 0, 0, 0, 0x2]); // ENABLE_PUSH=2
+    decode_err!(buf, FrameError::InvalidSetting { id: 2, value: 2 });
+
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(&[0, 0, 6, 0x4, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(&[0, 0x5, 0, 0, 0, 0x1]); // MAX_FRAME_SIZE=1
+    decode_err!(buf, FrameError::InvalidSetting { id: 5, value: 1 });
+}
+
+#[test]
+fn goaway_debug_data() {
+    let mut buf = BytesMut::new();
+    let len = 8 + 1000;
+    buf.extend_from_slice(&[0, (len >> 8) as u8, len as u8, 0x7, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(&[0, 0, 0, 0x1, 0, 0, 0, 0x2]);
+    buf.extend_from_slice(b"line\n");
+    buf.extend_from_slice(&[0xff; 995]);
+
+    let frm = decode_frame!(GoAway, buf);
+    assert_eq!(frm.reason(), frame::Reason::INTERNAL_ERROR);
+    assert_eq!(frm.data().len(), frame::MAX_GOAWAY_DATA_SIZE);
+    assert!(frm.data_lossy().starts_with("line\\n\u{fffd}"));
+
+    let frm = frame::GoAway::new(frame::Reason::NO_ERROR).set_data(vec![b'a'; 1000]);
+    assert_eq!(frm.data().len(), frame::MAX_GOAWAY_DATA_SIZE);
+}
+
 // ===== HEADERS =====
 
 #[ntex::test]
@@ -156,6 +199,2995 @@ async fn read_continuation_frames() {
     join(srv_fut, client_fut).await;
 }
 
+#[ntex::test]
+async fn app_error_response() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(|msg: Control<&'static str>| async move {
+            Ok::<_, ()>(match msg {
+                Control::AppError(err) => err.ack_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    HeaderMap::new(),
+                    Bytes::from_static(b"service error"),
+                ),
+                msg => msg.ack(),
+            })
+        }),
+        fn_service(|_: Message| async { Err("failed") }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _hdrs, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::INTERNAL_SERVER_ERROR));
+    assert!(!eof);
+
+    let mut body = BytesMut::new();
+    loop {
+        match rcv.recv().await.unwrap().kind {
+            MessageKind::Data(data, cap) => {
+                cap.consume(data.len() as u32);
+                body.extend_from_slice(&data);
+            }
+            MessageKind::Eof(StreamEof::Data(data)) => {
+                body.extend_from_slice(&data);
+                break;
+            }
+            kind => panic!("unexpected message kind; actual={:?}", kind),
+        }
+    }
+    assert_eq!(&body[..], b"service error");
+}
+
+#[ntex::test]
+async fn refuse_stream() {
+    let (cli, srv) = IoTest::create();
+    let published = Rc::new(Cell::new(false));
+    let published2 = published.clone();
+    let config = Config::server();
+    config.stream_notifications(true);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move {
+            Ok::<_, ()>(match msg {
+                Control::StreamOpened(item) => item.ack_refuse(frame::Reason::REFUSED_STREAM),
+                msg => msg.ack(),
+            })
+        }),
+        fn_service(move |_: Message| {
+            published2.set(true);
+            async { Ok(()) }
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+
+    let msg = rcv.recv().await.unwrap();
+    assert!(matches!(
+        msg.kind,
+        MessageKind::Eof(StreamEof::Error(StreamError::Reset(
+            frame::Reason::REFUSED_STREAM
+        )))
+    ));
+    assert!(!published.get());
+}
+
+#[ntex::test]
+async fn default_control_app_error_reason() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        DefaultControlService::with_policy(
+            ControlPolicy::new().app_error_reason(frame::Reason::INTERNAL_ERROR),
+        ),
+        fn_service(|_: Message| async { Err("failed") }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+
+    let msg = rcv.recv().await.unwrap();
+    assert!(matches!(
+        msg.kind,
+        MessageKind::Eof(StreamEof::Error(StreamError::Reset(
+            frame::Reason::INTERNAL_ERROR
+        )))
+    ));
+}
+
+#[ntex::test]
+async fn proto_error_as_stream_error() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(|msg: Control<()>| async move {
+            Ok::<_, ()>(match msg {
+                Control::ConnectionError(item) => {
+                    assert!(item.stream_id().is_some());
+                    item.ack_reset()
+                }
+                msg => msg.ack(),
+            })
+        }),
+        fn_service(|msg: Message| async move {
+            msg.stream()
+                .send_response(StatusCode::OK, HeaderMap::new(), true)
+                .unwrap();
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    // empty path
+    let (_snd, rcv) = client
+        .send(Method::GET, "".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    assert!(matches!(
+        msg.kind,
+        MessageKind::Eof(StreamEof::Error(StreamError::Reset(
+            frame::Reason::PROTOCOL_ERROR
+        )))
+    ));
+
+    // connection is still usable
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _hdrs, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(eof);
+}
+
+#[ntex::test]
+async fn stream_notifications_disabled() {
+    let (cli, srv) = IoTest::create();
+    let events = Rc::new(Cell::new(0));
+    let events2 = events.clone();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(move |msg: Control<()>| {
+            if matches!(
+                msg,
+                Control::StreamOpened(_) | Control::StreamClosed(_) | Control::StreamResetByPeer(_)
+            ) {
+                events2.set(events2.get() + 1);
+            }
+            async move { Ok::<_, ()>(msg.ack()) }
+        }),
+        fn_service(|msg: Message| async move {
+            msg.stream()
+                .send_response(StatusCode::OK, HeaderMap::new(), true)
+                .unwrap();
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _hdrs, _eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    sleep(Millis(50)).await;
+    assert_eq!(events.get(), 0);
+}
+
+#[ntex::test]
+async fn stream_recv_window() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.stream_notifications(true);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move {
+            if let Control::StreamOpened(ref item) = msg {
+                item.stream().set_recv_window(200_000);
+            }
+            Ok::<_, ()>(msg.ack())
+        }),
+        fn_service(|_: Message| async { Ok(()) }),
+    );
+    let client = support::start_client(cli);
+
+    let (snd, _rcv) = client
+        .send(Method::POST, "/index.html".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    assert_eq!(snd.available_send_capacity(), 65_535);
+
+    sleep(Millis(50)).await;
+    assert_eq!(snd.available_send_capacity(), 200_000);
+}
+
+#[ntex::test]
+async fn stream_on_capacity() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.stream_notifications(true);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move {
+            if let Control::StreamOpened(ref item) = msg {
+                item.stream().set_recv_window(200_000);
+            }
+            Ok::<_, ()>(msg.ack())
+        }),
+        fn_service(|_: Message| async { Ok(()) }),
+    );
+    let client = support::start_client(cli);
+
+    let (snd, _rcv) = client
+        .send(Method::POST, "/index.html".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    assert_eq!(snd.stream().on_capacity(1024).await.unwrap(), 65_535);
+    assert_eq!(snd.stream().on_capacity(100_000).await.unwrap(), 200_000);
+}
+
+#[ntex::test]
+async fn stream_extensions() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.stream_notifications(true);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move {
+            if let Control::StreamOpened(ref item) = msg {
+                item.stream().extensions_mut().insert(42u32);
+            }
+            Ok::<_, ()>(msg.ack())
+        }),
+        fn_service(|msg: Message| async move {
+            let status = if msg.stream().extensions().get::<u32>() == Some(&42) {
+                StatusCode::OK
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            msg.stream()
+                .send_response(status, HeaderMap::new(), true)
+                .unwrap();
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _hdrs, _eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+}
+
+#[ntex::test]
+async fn stream_state() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(|msg: Control<()>| async move { Ok::<_, ()>(msg.ack()) }),
+        fn_service(|msg: Message| async move {
+            let stream = msg.stream();
+            match msg.kind() {
+                MessageKind::Headers { .. } => {
+                    assert_eq!(stream.state(), StreamStatus::Open);
+                    assert!(!stream.is_trailers_received());
+                }
+                MessageKind::Eof(StreamEof::Trailers(_)) => {
+                    assert_eq!(stream.state(), StreamStatus::HalfClosedRemote);
+                    assert!(stream.is_trailers_received());
+                    stream
+                        .send_response(StatusCode::OK, HeaderMap::new(), true)
+                        .unwrap();
+                    assert_eq!(stream.state(), StreamStatus::Closed(None));
+                }
+                _ => (),
+            }
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (snd, rcv) = client
+        .send(Method::POST, "/index.html".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    assert_eq!(snd.stream().state(), StreamStatus::Open);
+    snd.send_trailers(HeaderMap::new());
+    assert_eq!(snd.stream().state(), StreamStatus::HalfClosedLocal);
+
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _hdrs, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(eof);
+}
+
+#[ntex::test]
+async fn recv_stream_next_chunk() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let client = support::start_client(cli);
+
+    let srv_fut = async move {
+        let msg = srv_rx.recv().await.unwrap();
+        let stream = msg.stream();
+        stream
+            .send_response(StatusCode::OK, HeaderMap::new(), false)
+            .unwrap();
+        stream
+            .send_payload(Bytes::from_static(b"chunk1"), false)
+            .await
+            .unwrap();
+        stream
+            .send_payload(Bytes::from_static(b"chunk2"), true)
+            .await
+            .unwrap();
+    };
+
+    let client_fut = async move {
+        let (_snd, rcv) = client
+            .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+
+        let msg = rcv.recv().await.unwrap();
+        let (pseudo, _hdrs, eof) = get_headers!(msg);
+        assert_eq!(pseudo.status, Some(StatusCode::OK));
+        assert!(!eof);
+
+        let mut body = Vec::new();
+        while let Some(chunk) = rcv.next_chunk().await {
+            body.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(body, b"chunk1chunk2");
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn recv_stream_next_chunk_headers() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let client = support::start_client(cli);
+
+    let srv_fut = async move {
+        let msg = srv_rx.recv().await.unwrap();
+        let stream = msg.stream();
+        stream
+            .send_informational(StatusCode::CONTINUE, HeaderMap::new())
+            .unwrap();
+        stream
+            .send_response(StatusCode::OK, HeaderMap::new(), false)
+            .unwrap();
+        let _ = stream
+            .send_payload(Bytes::from_static(b"chunk1"), true)
+            .await;
+    };
+
+    let client_fut = async move {
+        let (_snd, rcv) = client
+            .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+
+        // informational headers are skipped, final headers are not expected
+        let err = rcv.next_chunk().await.unwrap().unwrap_err();
+        assert!(matches!(err, OperationError::UnexpectedHeaders));
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn recv_stream_collect_body() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let client = support::start_client(cli);
+
+    let srv_fut = async move {
+        for _ in 0..2 {
+            let msg = srv_rx.recv().await.unwrap();
+            let stream = msg.stream();
+            stream
+                .send_response(StatusCode::OK, HeaderMap::new(), false)
+                .unwrap();
+            let _ = stream
+                .send_payload(Bytes::from_static(b"chunk1"), false)
+                .await;
+            let _ = stream
+                .send_payload(Bytes::from_static(b"chunk2"), true)
+                .await;
+        }
+    };
+
+    let client_fut = async move {
+        let (_snd, rcv) = client
+            .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let _ = rcv.recv().await.unwrap();
+        let body = rcv.collect_body(1024).await.unwrap();
+        assert_eq!(body, Bytes::from_static(b"chunk1chunk2"));
+
+        let (_snd, rcv) = client
+            .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let _ = rcv.recv().await.unwrap();
+        let err = rcv.collect_body(8).await.unwrap_err();
+        assert!(matches!(err, OperationError::PayloadOverflow(8)));
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn poll_reset() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let client = support::start_client(cli);
+
+    let srv_fut = async move {
+        let msg = srv_rx.recv().await.unwrap();
+        let stream = msg.stream();
+        stream
+            .send_response(StatusCode::OK, HeaderMap::new(), false)
+            .unwrap();
+        let reason = poll_fn(|cx| stream.poll_reset(cx)).await;
+        assert_eq!(reason, frame::Reason::CANCEL);
+    };
+
+    let client_fut = async move {
+        let (_snd, rcv) = client
+            .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+
+        let msg = rcv.recv().await.unwrap();
+        let (pseudo, _hdrs, _eof) = get_headers!(msg);
+        assert_eq!(pseudo.status, Some(StatusCode::OK));
+        rcv.reset(frame::Reason::CANCEL);
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn stream_request_info() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let client = support::start_client(cli);
+
+    let srv_fut = async move {
+        let _ = srv_rx.recv().await.unwrap();
+        let msg = srv_rx.recv().await.unwrap();
+        assert!(matches!(msg.kind(), MessageKind::Eof(StreamEof::Data(_))));
+
+        let stream = msg.stream();
+        assert_eq!(stream.method(), Some(Method::POST));
+        assert_eq!(stream.path(), Some("/upload".into()));
+        assert_eq!(stream.authority(), Some("localhost".into()));
+    };
+
+    let client_fut = async move {
+        let (snd, _rcv) = client
+            .send(Method::POST, "/upload".into(), HeaderMap::new(), false)
+            .await
+            .unwrap();
+        snd.send_payload(Bytes::from_static(b"data"), true)
+            .await
+            .unwrap();
+        assert_eq!(snd.stream().method(), Some(Method::POST));
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn stream_content_length() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let client = support::start_client(cli);
+
+    let srv_fut = async move {
+        let msg = srv_rx.recv().await.unwrap();
+        assert_eq!(msg.stream().content_length(), Some(10));
+        assert_eq!(msg.stream().received_bytes(), 0);
+        assert_eq!(msg.stream().remaining(), Some(10));
+
+        let msg = srv_rx.recv().await.unwrap();
+        assert_eq!(msg.stream().received_bytes(), 4);
+        assert_eq!(msg.stream().remaining(), Some(6));
+
+        let msg = srv_rx.recv().await.unwrap();
+        assert_eq!(msg.stream().received_bytes(), 10);
+        assert_eq!(msg.stream().remaining(), Some(0));
+    };
+
+    let client_fut = async move {
+        let mut hdrs = HeaderMap::new();
+        hdrs.insert(CONTENT_LENGTH, "10".parse().unwrap());
+        let (snd, _rcv) = client
+            .send(Method::POST, "/upload".into(), hdrs, false)
+            .await
+            .unwrap();
+        assert_eq!(snd.stream().content_length(), None);
+        snd.send_payload(Bytes::from_static(b"data"), false)
+            .await
+            .unwrap();
+        snd.send_payload(Bytes::from_static(b"-data!"), true)
+            .await
+            .unwrap();
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn content_length_policy() {
+    let (cli, srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let config = Config::client();
+    config.content_length_policy(ContentLengthPolicy::Strip);
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config,
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let srv_fut = async move {
+        let msg = srv_rx.recv().await.unwrap();
+        let mut hdrs = HeaderMap::new();
+        hdrs.insert(CONTENT_LENGTH, "abc".parse().unwrap());
+        msg.stream()
+            .send_response(StatusCode::OK, hdrs, true)
+            .unwrap();
+
+        let msg = srv_rx.recv().await.unwrap();
+        let mut hdrs = HeaderMap::new();
+        hdrs.insert(CONTENT_LENGTH, "10".parse().unwrap());
+        msg.stream()
+            .send_response(StatusCode::OK, hdrs, false)
+            .unwrap();
+        msg.stream()
+            .send_payload(Bytes::from_static(b"hello"), true)
+            .await
+            .unwrap();
+    };
+
+    let client_fut = async move {
+        // invalid content-length is stripped
+        let (_snd, rcv) = client
+            .send(Method::GET, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        let (_pseudo, hdrs, eof) = get_headers!(msg);
+        assert!(eof);
+        assert!(!hdrs.contains_key(CONTENT_LENGTH));
+
+        // mismatched payload size is not an error
+        let (_snd, rcv) = client
+            .send(Method::GET, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        assert_eq!(msg.stream().content_length(), Some(10));
+        assert_eq!(
+            rcv.collect_body(1024).await.unwrap(),
+            Bytes::from_static(b"hello")
+        );
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn request_smuggling() {
+    let (cli, srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let config = Config::client();
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config.clone(),
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let srv_fut = async move {
+        let te = HeaderName::from_static("transfer-encoding");
+        let responses = [
+            vec![(te.clone(), "chunked")],
+            vec![(CONTENT_LENGTH, "1"), (CONTENT_LENGTH, "2")],
+            vec![(CONTENT_LENGTH, "0"), (CONTENT_LENGTH, "0")],
+            vec![(te, "chunked")],
+        ];
+        for fields in responses {
+            let msg = srv_rx.recv().await.unwrap();
+            let mut hdrs = HeaderMap::new();
+            for (name, val) in fields {
+                hdrs.append(name, val.parse().unwrap());
+            }
+            msg.stream()
+                .send_response(StatusCode::OK, hdrs, true)
+                .unwrap();
+        }
+    };
+
+    let client_fut = async move {
+        // transfer-encoding is not allowed
+        let (_snd, rcv) = client
+            .send(Method::GET, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        assert!(matches!(
+            msg.kind,
+            MessageKind::Eof(StreamEof::Error(StreamError::ConnectionHeader))
+        ));
+
+        // conflicting content-length
+        let (_snd, rcv) = client
+            .send(Method::GET, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        assert!(matches!(
+            msg.kind,
+            MessageKind::Eof(StreamEof::Error(StreamError::InvalidContentLength))
+        ));
+
+        // identical content-length values are collapsed
+        let (_snd, rcv) = client
+            .send(Method::GET, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        let (_pseudo, hdrs, _eof) = get_headers!(msg);
+        assert_eq!(hdrs.get_all(CONTENT_LENGTH).count(), 1);
+
+        // transfer-encoding is stripped
+        config.strip_connection_headers(true);
+        let (_snd, rcv) = client
+            .send(Method::GET, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        let (pseudo, hdrs, eof) = get_headers!(msg);
+        assert_eq!(pseudo.status, Some(StatusCode::OK));
+        assert!(!hdrs.contains_key("transfer-encoding"));
+        assert!(eof);
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn head_payload_policy() {
+    let (cli, srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+
+    let srv_rx = support::start_server(srv);
+    let config = Config::client();
+    config.head_payload_policy(HeadPayloadPolicy::Truncate);
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config.clone(),
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let srv_fut = async move {
+        for _ in 0..2 {
+            let msg = srv_rx.recv().await.unwrap();
+            msg.stream()
+                .send_response(StatusCode::OK, HeaderMap::new(), false)
+                .unwrap();
+            msg.stream()
+                .send_payload(Bytes::from_static(b"data"), true)
+                .await
+                .unwrap();
+        }
+    };
+
+    let client_fut = async move {
+        let (_snd, rcv) = client
+            .send(Method::HEAD, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let _ = rcv.recv().await.unwrap();
+        assert_eq!(rcv.collect_body(1024).await.unwrap(), Bytes::new());
+
+        config.head_payload_policy(HeadPayloadPolicy::Reset);
+        let (_snd, rcv) = client
+            .send(Method::HEAD, "/".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let _ = rcv.recv().await.unwrap();
+        let _ = rcv.recv().await;
+        assert_eq!(
+            rcv.stream().state(),
+            StreamStatus::Closed(Some(frame::Reason::PROTOCOL_ERROR))
+        );
+    };
+
+    join(srv_fut, client_fut).await;
+}
+
+#[ntex::test]
+async fn stream_deadline() {
+    let (cli, srv) = IoTest::create();
+    let timeouts = Rc::new(Cell::new(0));
+    let timeouts2 = timeouts.clone();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(move |msg: Control<()>| {
+            if let Control::StreamTimeout(ref item) = msg {
+                assert_eq!(
+                    item.stream().state(),
+                    StreamStatus::Closed(Some(frame::Reason::CANCEL))
+                );
+                timeouts2.set(timeouts2.get() + 1);
+            }
+            async move { Ok::<_, ()>(msg.ack()) }
+        }),
+        fn_service(|msg: Message| async move {
+            msg.stream().set_deadline(Seconds(1));
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    assert!(matches!(
+        msg.kind(),
+        MessageKind::Eof(StreamEof::Error(StreamError::Reset(frame::Reason::CANCEL)))
+    ));
+    assert_eq!(timeouts.get(), 1);
+}
+
+#[ntex::test]
+async fn stream_deadline_monitor_restart() {
+    let (cli, srv) = IoTest::create();
+    let timeouts = Rc::new(Cell::new(0));
+    let timeouts2 = timeouts.clone();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(move |msg: Control<()>| {
+            if let Control::StreamTimeout(_) = msg {
+                timeouts2.set(timeouts2.get() + 1);
+            }
+            async move { Ok::<_, ()>(msg.ack()) }
+        }),
+        fn_service(|msg: Message| async move {
+            msg.stream().set_deadline(Seconds(1));
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    // monitor stops after first deadline, and starts again for second stream
+    for idx in 1..3 {
+        let (_snd, rcv) = client
+            .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        assert!(matches!(
+            msg.kind(),
+            MessageKind::Eof(StreamEof::Error(StreamError::Reset(frame::Reason::CANCEL)))
+        ));
+        assert_eq!(timeouts.get(), idx);
+        sleep(Millis(1500)).await;
+    }
+}
+
+#[ntex::test]
+async fn stream_deadline_millis() {
+    let (cli, srv) = IoTest::create();
+    let clock = ManualClock::new();
+    let config = Config::server();
+    config.clock(clock.clone());
+
+    let timeouts = Rc::new(Cell::new(0));
+    let timeouts2 = timeouts.clone();
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(move |msg: Control<()>| {
+            if let Control::StreamTimeout(_) = msg {
+                timeouts2.set(timeouts2.get() + 1);
+            }
+            async move { Ok::<_, ()>(msg.ack()) }
+        }),
+        fn_service(|msg: Message| async move {
+            msg.stream().set_deadline(Millis(250));
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, _rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    sleep(Millis(50)).await;
+
+    // deadline is not rounded to seconds
+    clock.advance(Duration::from_millis(200));
+    sleep(Millis(50)).await;
+    assert_eq!(timeouts.get(), 0);
+
+    clock.advance(Duration::from_millis(50));
+    sleep(Millis(50)).await;
+    assert_eq!(timeouts.get(), 1);
+}
+
+#[ntex::test]
+async fn client_tunnel() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(|msg: Control<()>| async move { Ok::<_, ()>(msg.ack()) }),
+        fn_service(|msg: Message| async move {
+            let stream = msg.stream().clone();
+            match msg.kind {
+                MessageKind::Headers { .. } => {
+                    stream
+                        .send_response(StatusCode::OK, HeaderMap::new(), false)
+                        .unwrap();
+                }
+                MessageKind::Data(data, _cap) => {
+                    stream.send_payload(data, false).await.unwrap();
+                }
+                MessageKind::Eof(StreamEof::Data(data)) => {
+                    stream.send_payload(data, true).await.unwrap();
+                }
+                _ => (),
+            }
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (snd, rcv) = client
+        .send(Method::POST, "/tunnel".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _hdrs, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(!eof);
+
+    let tunnel = client::Tunnel::new(snd, rcv);
+    assert!(tunnel.write_ready().await.unwrap() > 0);
+    tunnel.write(Bytes::from_static(b"ping")).await.unwrap();
+    assert_eq!(
+        tunnel.read().await.unwrap().unwrap(),
+        Bytes::from_static(b"ping")
+    );
+
+    tunnel.shutdown().await.unwrap();
+    assert!(tunnel.read().await.is_none());
+}
+
+#[cfg(feature = "futures")]
+#[ntex::test]
+async fn client_body_adapters() {
+    use futures_sink::Sink;
+    use ntex_util::Stream;
+    use std::pin::Pin;
+
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(|msg: Control<()>| async move { Ok::<_, ()>(msg.ack()) }),
+        fn_service(|msg: Message| async move {
+            let stream = msg.stream().clone();
+            match msg.kind {
+                MessageKind::Headers { .. } => {
+                    stream
+                        .send_response(StatusCode::OK, HeaderMap::new(), false)
+                        .unwrap();
+                }
+                MessageKind::Data(data, _cap) => {
+                    stream.send_payload(data, false).await.unwrap();
+                }
+                MessageKind::Eof(StreamEof::Data(data)) => {
+                    stream.send_payload(data, true).await.unwrap();
+                }
+                _ => (),
+            }
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (snd, rcv) = client
+        .send(Method::POST, "/echo".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    let _ = rcv.recv().await.unwrap();
+
+    let mut sink = snd.into_sink();
+    let mut body = rcv.into_body();
+    for chunk in [&b"chunk1"[..], &b"chunk2"[..]] {
+        poll_fn(|cx| Pin::new(&mut sink).poll_ready(cx))
+            .await
+            .unwrap();
+        Pin::new(&mut sink)
+            .start_send(Bytes::copy_from_slice(chunk))
+            .unwrap();
+        poll_fn(|cx| Pin::new(&mut sink).poll_flush(cx))
+            .await
+            .unwrap();
+
+        let data = poll_fn(|cx| Pin::new(&mut body).poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data, Bytes::copy_from_slice(chunk));
+    }
+    poll_fn(|cx| Pin::new(&mut sink).poll_close(cx))
+        .await
+        .unwrap();
+    assert!(poll_fn(|cx| Pin::new(&mut body).poll_next(cx))
+        .await
+        .is_none());
+}
+
+#[ntex::test]
+async fn detached_stream() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        fn_service(|msg: Control<()>| async move { Ok::<_, ()>(msg.ack()) }),
+        fn_service(|msg: Message| async move {
+            if let MessageKind::Headers { .. } = msg.kind() {
+                let detached = msg.stream().detach(1);
+                ntex_util::spawn(async move {
+                    let mut body = BytesMut::new();
+                    while let Some(msg) = detached.recv().await {
+                        match msg.kind {
+                            MessageKind::Data(data, _) => body.extend_from_slice(&data),
+                            MessageKind::Eof(StreamEof::Data(data)) => {
+                                body.extend_from_slice(&data)
+                            }
+                            _ => panic!("unexpected message {:?}", msg),
+                        }
+                    }
+                    let stream = detached.stream();
+                    stream
+                        .send_response(StatusCode::OK, HeaderMap::new(), false)
+                        .unwrap();
+                    stream.send_payload(body.freeze(), true).await.unwrap();
+                });
+            } else {
+                panic!("unexpected message {:?}", msg);
+            }
+            Ok(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (snd, rcv) = client
+        .send(Method::POST, "/upload".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    for chunk in [&b"chunk1"[..], &b"chunk2"[..], &b"chunk3"[..]] {
+        snd.send_payload(Bytes::copy_from_slice(chunk), false)
+            .await
+            .unwrap();
+    }
+    snd.end_stream().unwrap();
+    assert!(snd.end_stream().is_err());
+
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _hdrs, _eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    let body = rcv.collect_body(1024).await.unwrap();
+    assert_eq!(body, Bytes::from_static(b"chunk1chunk2chunk3"));
+}
+
+#[ntex::test]
+async fn keep_streams_on_drop() {
+    let (cli, srv) = IoTest::create();
+    let _srv_rx = support::start_server(srv);
+
+    cli.remote_buffer_cap(1000000);
+    let config = Config::client();
+    config.reset_streams_on_drop(false);
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config,
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let (snd, rcv) = client
+        .send(Method::POST, "/index.html".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    let id = snd.id();
+    drop(snd);
+    drop(rcv);
+
+    let streams = client.connection().streams();
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].id(), id);
+    assert!(streams[0].reset_reason().is_none());
+}
+
+#[test]
+fn config_try_setters() {
+    let config = Config::client();
+    assert_eq!(
+        config.try_max_frame_size(1024).unwrap_err(),
+        ConfigError::MaxFrameSize(1024)
+    );
+    assert_eq!(
+        config.try_initial_window_size(u32::MAX).unwrap_err(),
+        ConfigError::WindowSize(u32::MAX)
+    );
+    assert_eq!(
+        config
+            .try_initial_connection_window_size(u32::MAX)
+            .unwrap_err(),
+        ConfigError::ConnectionWindowSize(u32::MAX)
+    );
+    assert!(config.try_max_frame_size(32_768).is_ok());
+    assert!(config.validate().is_ok());
+
+    config.initial_window_size(u32::MAX);
+    assert_eq!(
+        config.validate().unwrap_err(),
+        ConfigError::WindowSize(u32::MAX)
+    );
+}
+
+#[ntex::test]
+async fn ping_idle_only() {
+    let (cli, _srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+    let config = Config::client();
+    config.ping_timeout(Seconds(1)).ping_idle_only(true);
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config,
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    // peer never responds to pings
+    let (_snd, _rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    sleep(Millis(2500)).await;
+    assert!(!client.is_closed());
+}
+
+#[ntex::test]
+async fn client_closing() {
+    let (cli, _srv) = IoTest::create();
+    let client = support::start_client(cli);
+    let (_snd, _rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    assert!(!client.is_closing());
+
+    client.close();
+    assert!(client.is_closing());
+    assert!(!client.is_closed());
+    assert!(!client.is_ready());
+    assert!(matches!(
+        client
+            .send(Method::GET, "/".into(), HeaderMap::new(), false)
+            .await,
+        Err(OperationError::Disconnected)
+    ));
+}
+
+#[ntex::test]
+async fn empty_data_flood() {
+    let (cli, srv) = IoTest::create();
+    let stats = support::start_server_with_stats(srv);
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let id = frame::StreamId::CLIENT;
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::POST),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+    let hdrs = frame::Headers::new(id, pseudo, HeaderMap::new(), false);
+    io.encode(hdrs.into(), &codec).unwrap();
+    for _ in 0..200 {
+        io.encode(frame::Data::new(id, Bytes::new()).into(), &codec)
+            .unwrap();
+    }
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::ENHANCE_YOUR_CALM);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+
+    let stats = stats.get().unwrap();
+    assert_eq!(stats.violations.floods, 1);
+    assert!(stats.frames_received.data > 0);
+}
+
+#[ntex::test]
+async fn reset_stream_max_frames() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config
+        .reset_stream_max_frames(2)
+        .reset_stream_violation(ResetStreamViolation::Connection);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move { Ok::<_, ()>(msg.ack()) }),
+        fn_service(|msg: Message| async move {
+            msg.stream().reset(frame::Reason::CANCEL);
+            Ok::<_, ()>(())
+        }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let id = frame::StreamId::CLIENT;
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::POST),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+    let hdrs = frame::Headers::new(id, pseudo, HeaderMap::new(), false);
+    io.encode(hdrs.into(), &codec).unwrap();
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Reset(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::CANCEL);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+    sleep(Millis(50)).await;
+
+    // frames within limit are ignored
+    for _ in 0..2 {
+        let data = frame::Data::new(id, Bytes::from_static(b"data"));
+        io.encode(data.into(), &codec).unwrap();
+    }
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Reset(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::STREAM_CLOSED);
+                break;
+            }
+            Some(frame::Frame::GoAway(_)) => panic!("unexpected GOAWAY"),
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+
+    io.encode(frame::Data::new(id, Bytes::new()).into(), &codec)
+        .unwrap();
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::STREAM_CLOSED);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+}
+
+#[ntex::test]
+async fn connection_window_overflow() {
+    let (cli, srv) = IoTest::create();
+    let stats = support::start_server_with_stats(srv);
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let upd = frame::WindowUpdate::new(frame::StreamId::CON, (1 << 31) - 1);
+    io.encode(upd.into(), &codec).unwrap();
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::FLOW_CONTROL_ERROR);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+
+    let stats = stats.get().unwrap();
+    assert_eq!(stats.violations.window_violations, 1);
+    assert_eq!(stats.frames_received.window_update, 1);
+}
+
+#[ntex::test]
+async fn malformed_pseudo_headers() {
+    // response headers with ":status" placed after regular field
+    fn headers(id: u32) -> Bytes {
+        let mut block = vec![0x00, 0x03];
+        block.extend_from_slice(b"x-a");
+        block.push(0x01);
+        block.extend_from_slice(b"b");
+        block.push(0x88);
+
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+        buf.extend_from_slice(&[0x1, 0x5]);
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&block);
+        buf.freeze()
+    }
+
+    let (cli, srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+    let config = Config::client();
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config.clone(),
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let codec = Codec::default();
+    let mut buf = BytesMut::new();
+    codec
+        .encode(frame::Settings::default().into(), &mut buf)
+        .unwrap();
+    srv.write(buf);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    srv.write(headers(1));
+
+    let msg = rcv.recv().await.unwrap();
+    assert!(matches!(
+        msg.kind,
+        MessageKind::Eof(StreamEof::Error(StreamError::MalformedPseudo(_)))
+    ));
+    assert_eq!(
+        rcv.stream().state(),
+        StreamStatus::Closed(Some(frame::Reason::PROTOCOL_ERROR))
+    );
+    assert!(!client.is_closed());
+
+    config.lenient_pseudo_headers(true);
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    srv.write(headers(3));
+
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, hdrs, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert_eq!(hdrs.get("x-a").unwrap(), "b");
+    assert!(eof);
+}
+
+#[ntex::test]
+async fn settings_window_overflow() {
+    let (cli, srv) = IoTest::create();
+    let _srv_rx = support::start_server(srv);
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let mut settings = frame::Settings::default();
+    settings.set_initial_window_size(Some(1 << 31));
+    io.encode(settings.into(), &codec).unwrap();
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::FLOW_CONTROL_ERROR);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+}
+
+#[ntex::test]
+async fn map_goaway() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.map_goaway(|err, frm| match err {
+        ConnectionError::WindowValueOverflow => frm
+            .set_reason(frame::Reason::ENHANCE_YOUR_CALM)
+            .set_data(Bytes::new()),
+        _ => frm,
+    });
+    support::spawn_server(
+        srv,
+        config,
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let upd = frame::WindowUpdate::new(frame::StreamId::CON, (1 << 31) - 1);
+    io.encode(upd.into(), &codec).unwrap();
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::ENHANCE_YOUR_CALM);
+                assert!(frm.data().is_empty());
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+}
+
+#[ntex::test]
+async fn no_frames_after_error_goaway() {
+    let (cli, srv) = IoTest::create();
+    let failed = Rc::new(Cell::new(false));
+    let failed2 = failed.clone();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        DefaultControlService,
+        fn_service(move |msg: Message| {
+            let failed = failed2.clone();
+            async move {
+                if let MessageKind::Headers { eof: true, .. } = msg.kind {
+                    let stream = msg.stream().clone();
+                    ntex_util::spawn(async move {
+                        sleep(Millis(50)).await;
+                        let res = stream.send_response(StatusCode::OK, HeaderMap::new(), true);
+                        failed.set(res.is_err());
+                    });
+                }
+                Ok::<_, ()>(())
+            }
+        }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let id = frame::StreamId::CLIENT;
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::GET),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+    let hdrs = frame::Headers::new(id, pseudo, HeaderMap::new(), true);
+    io.encode(hdrs.into(), &codec).unwrap();
+    let upd = frame::WindowUpdate::new(frame::StreamId::CON, 0);
+    io.encode(upd.into(), &codec).unwrap();
+
+    // GOAWAY reports processed stream
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::PROTOCOL_ERROR);
+                assert_eq!(frm.last_stream_id(), id);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+
+    // no frames after GOAWAY, in-flight stream is failed
+    assert!(io.recv(&codec).await.unwrap().is_none());
+    sleep(Millis(100)).await;
+    assert!(failed.get());
+}
+
+#[ntex::test]
+async fn violation_policy() {
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::GET),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+
+    // DATA for half-closed stream closes connection
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.violation_policy(ViolationPolicy::Connection);
+    support::spawn_server(
+        srv,
+        config,
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let id = frame::StreamId::CLIENT;
+    let hdrs = frame::Headers::new(id, pseudo.clone(), HeaderMap::new(), true);
+    io.encode(hdrs.into(), &codec).unwrap();
+    io.encode(
+        frame::Data::new(id, Bytes::from_static(b"data")).into(),
+        &codec,
+    )
+    .unwrap();
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::STREAM_CLOSED);
+                break;
+            }
+            Some(frame::Frame::Reset(_)) => panic!("unexpected RST_STREAM"),
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+
+    // WINDOW_UPDATE for idle stream is ignored
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.violation_policy(ViolationPolicy::Stream);
+    support::spawn_server(
+        srv,
+        config,
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let io = support::start_raw_client(cli, &codec);
+
+    let upd = frame::WindowUpdate::new(101.into(), 1024);
+    io.encode(upd.into(), &codec).unwrap();
+    io.encode(frame::Ping::new([1; 8]).into(), &codec).unwrap();
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Ping(ping)) => {
+                assert!(ping.is_ack());
+                break;
+            }
+            Some(frame::Frame::GoAway(frm)) => panic!("unexpected GOAWAY {:?}", frm),
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+}
+
+#[ntex::test]
+async fn extended_connect() {
+    async fn expect_goaway(io: &Io, codec: &Codec) -> frame::GoAway {
+        loop {
+            match io.recv(codec).await.unwrap() {
+                Some(frame::Frame::GoAway(frm)) => return frm,
+                Some(_) => (),
+                None => panic!("connection is closed without GOAWAY"),
+            }
+        }
+    }
+
+    fn start(enabled: bool) -> (Io, Codec) {
+        let (cli, srv) = IoTest::create();
+        let config = Config::server();
+        if enabled {
+            config.enable_connect_protocol();
+        }
+        support::spawn_server(
+            srv,
+            config,
+            DefaultControlService,
+            fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+        );
+        let codec = Codec::default();
+        let io = support::start_raw_client(cli, &codec);
+        (io, codec)
+    }
+
+    let req = |method| frame::PseudoHeaders {
+        method: Some(method),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/chat".into()),
+        protocol: Some(frame::Protocol::from("websocket")),
+        ..Default::default()
+    };
+    let id = frame::StreamId::CLIENT;
+
+    // extended CONNECT is not advertised
+    let (io, codec) = start(false);
+    let hdrs = frame::Headers::new(id, req(Method::CONNECT), HeaderMap::new(), false);
+    io.encode(hdrs.into(), &codec).unwrap();
+    let frm = expect_goaway(&io, &codec).await;
+    assert_eq!(frm.reason(), frame::Reason::PROTOCOL_ERROR);
+
+    // :protocol in non-CONNECT request
+    let (io, codec) = start(true);
+    let hdrs = frame::Headers::new(id, req(Method::GET), HeaderMap::new(), false);
+    io.encode(hdrs.into(), &codec).unwrap();
+    let frm = expect_goaway(&io, &codec).await;
+    assert_eq!(frm.reason(), frame::Reason::PROTOCOL_ERROR);
+
+    // valid extended CONNECT
+    let (io, codec) = start(true);
+    let hdrs = frame::Headers::new(id, req(Method::CONNECT), HeaderMap::new(), false);
+    io.encode(hdrs.into(), &codec).unwrap();
+    io.encode(frame::Ping::new([1; 8]).into(), &codec).unwrap();
+    let mut advertised = false;
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Settings(settings)) if !settings.is_ack() => {
+                advertised = settings.is_extended_connect_protocol_enabled() == Some(true);
+            }
+            Some(frame::Frame::Ping(ping)) => {
+                assert!(ping.is_ack());
+                break;
+            }
+            Some(frame::Frame::GoAway(frm)) => panic!("unexpected GOAWAY {:?}", frm),
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+    assert!(advertised);
+
+    // peer cannot disable extended CONNECT after enabling it
+    let (io, codec) = start(false);
+    let mut settings = frame::Settings::default();
+    settings.set_enable_connect_protocol(Some(1));
+    io.encode(settings.into(), &codec).unwrap();
+    settings.set_enable_connect_protocol(Some(0));
+    io.encode(settings.into(), &codec).unwrap();
+    let frm = expect_goaway(&io, &codec).await;
+    assert_eq!(frm.reason(), frame::Reason::PROTOCOL_ERROR);
+}
+
+#[ntex::test]
+async fn message_body() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        DefaultControlService,
+        fn_service(|msg: Message| async move {
+            if let MessageKind::Headers { .. } = msg.kind {
+                let mut trailers = HeaderMap::new();
+                trailers.insert(
+                    HeaderName::from_static("x-checksum"),
+                    HeaderValue::from_static("42"),
+                );
+                msg.stream()
+                    .send_response_body(
+                        StatusCode::OK,
+                        HeaderMap::new(),
+                        Bytes::from_static(b"hello world"),
+                        Some(trailers),
+                    )
+                    .await
+                    .unwrap();
+            }
+            Ok::<_, ()>(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, hdrs, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert_eq!(hdrs.get(CONTENT_LENGTH).unwrap(), "11");
+    assert!(!eof);
+
+    let mut body = StreamBody::from(rcv);
+    assert_eq!(body.size(), BodySize::Sized(11));
+    let mut payload = BytesMut::new();
+    while let Some(chunk) = poll_fn(|cx| body.poll_next_chunk(cx)).await {
+        payload.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(&payload[..], b"hello world");
+    assert_eq!(body.trailers().unwrap().get("x-checksum").unwrap(), "42");
+}
+
+#[ntex::test]
+async fn proxy_passthrough() {
+    // origin
+    let (up_cli, up_srv) = IoTest::create();
+    support::spawn_server(
+        up_srv,
+        Config::server(),
+        DefaultControlService,
+        fn_service(|msg: Message| async move {
+            if let MessageKind::Headers { .. } = msg.kind {
+                let stream = msg.stream();
+                stream
+                    .send_informational(StatusCode::from_u16(103).unwrap(), HeaderMap::new())
+                    .unwrap();
+                let mut hdrs = HeaderMap::new();
+                hdrs.insert(
+                    HeaderName::from_static("keep-alive"),
+                    HeaderValue::from_static("5"),
+                );
+                stream.send_response(StatusCode::OK, hdrs, false).unwrap();
+                stream
+                    .send_payload(Bytes::from_static(b"data"), false)
+                    .await
+                    .unwrap();
+                let mut trailers = HeaderMap::new();
+                trailers.insert(
+                    HeaderName::from_static("x-checksum"),
+                    HeaderValue::from_static("42"),
+                );
+                stream.send_trailers(trailers);
+            }
+            Ok::<_, ()>(())
+        }),
+    );
+    let upstream = support::start_client(up_cli);
+
+    // proxy
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        DefaultControlService,
+        fn_service(move |msg: Message| {
+            let upstream = upstream.clone();
+            async move {
+                if let MessageKind::Headers {
+                    pseudo, headers, ..
+                } = msg.kind
+                {
+                    let (_snd, rcv) = upstream
+                        .send(pseudo.method.unwrap(), pseudo.path.unwrap(), headers, true)
+                        .await
+                        .unwrap();
+                    while let Some(item) = rcv.recv().await {
+                        if proxy::forward(item, &msg.stream).await.unwrap() {
+                            break;
+                        }
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::from_u16(103).unwrap()));
+    assert!(!eof);
+
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, hdrs, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(!hdrs.contains_key("keep-alive"));
+    assert!(!eof);
+
+    let msg = rcv.recv().await.unwrap();
+    let MessageKind::Data(data, _cap) = msg.kind else {
+        panic!("unexpected message {:?}", msg);
+    };
+    assert_eq!(data, Bytes::from_static(b"data"));
+
+    let msg = rcv.recv().await.unwrap();
+    let MessageKind::Eof(StreamEof::Trailers(trailers)) = msg.kind else {
+        panic!("unexpected message {:?}", msg);
+    };
+    assert_eq!(trailers.get("x-checksum").unwrap(), "42");
+}
+
+#[ntex::test]
+async fn dual_protocol() {
+    let h1 = Rc::new(Cell::new(0));
+    let h1_2 = h1.clone();
+    let factory = server::DualServer::new(
+        server::Server::new(
+            Config::server(),
+            DefaultControlService,
+            fn_service(|msg: Message| async move {
+                if let MessageKind::Headers { .. } = msg.kind {
+                    msg.stream()
+                        .send_response(StatusCode::OK, HeaderMap::new(), true)
+                        .unwrap();
+                }
+                Ok::<_, ()>(())
+            }),
+        ),
+        fn_service(move |io: IoBoxed| {
+            let h1 = h1_2.clone();
+            async move {
+                // sniffed bytes are not consumed
+                io.with_read_buf(|buf| assert!(buf.starts_with(b"GET / HTTP/1.1\r\n")));
+                h1.set(h1.get() + 1);
+                Ok::<_, ()>(())
+            }
+        }),
+    );
+    let handler = Pipeline::new(
+        ServiceFactory::<IoBoxed>::create(&factory, ())
+            .await
+            .unwrap(),
+    );
+
+    // http/1.1
+    let (cli, srv) = IoTest::create();
+    cli.write("GET / HTTP/1.1\r\nhost: localhost\r\n\r\n");
+    handler.call(Io::new(srv)).await.unwrap();
+    assert_eq!(h1.get(), 1);
+
+    // http/2
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+    let handler2 = handler.clone();
+    ntex_util::spawn(async move {
+        let _ = handler2.call(Io::new(srv)).await;
+    });
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(eof);
+    assert_eq!(h1.get(), 1);
+}
+
+#[cfg(feature = "tokio")]
+#[ntex::test]
+async fn tokio_transport() {
+    use ntex_h2::transport::Transport;
+
+    let (cli, srv) = tokio::io::duplex(64 * 1024);
+    ntex_util::spawn(async move {
+        let _ = server::Server::new(
+            Config::server(),
+            DefaultControlService,
+            fn_service(|msg: Message| async move {
+                if let MessageKind::Headers { .. } = msg.kind {
+                    msg.stream()
+                        .send_response(StatusCode::OK, HeaderMap::new(), false)
+                        .unwrap();
+                    msg.stream()
+                        .send_payload(Bytes::from_static(b"hello"), true)
+                        .await
+                        .unwrap();
+                }
+                Ok::<_, ()>(())
+            }),
+        )
+        .handler()
+        .run(Transport::new(srv).into_boxed())
+        .await;
+    });
+    let client = client::SimpleClient::new(
+        Transport::new(cli).into_boxed(),
+        Config::client(),
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(!eof);
+
+    let msg = rcv.recv().await.unwrap();
+    let MessageKind::Eof(StreamEof::Data(data)) = msg.kind else {
+        panic!("unexpected message {:?}", msg);
+    };
+    assert_eq!(data, Bytes::from_static(b"hello"));
+}
+
+#[ntex::test]
+async fn client_handshake() {
+    let (cli, srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+    support::spawn_server(
+        srv,
+        Config::server(),
+        DefaultControlService,
+        fn_service(|msg: Message| async move {
+            if let MessageKind::Headers { .. } = msg.kind {
+                msg.stream()
+                    .send_response(StatusCode::OK, HeaderMap::new(), true)
+                    .unwrap();
+            }
+            Ok::<_, ()>(())
+        }),
+    );
+
+    let client = client::handshake(
+        Io::new(cli),
+        Config::client(),
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    )
+    .await
+    .unwrap();
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(eof);
+
+    // peer does not send settings
+    let (cli, _srv) = IoTest::create();
+    let config = Config::client();
+    config.handshake_timeout(Seconds(1));
+    let res = client::handshake(
+        Io::new(cli),
+        config,
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    )
+    .await;
+    assert!(matches!(res, Err(client::ClientError::HandshakeTimeout)));
+}
+
+#[ntex::test]
+async fn connect_udp() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.enable_connect_protocol();
+
+    support::spawn_server(
+        srv,
+        config,
+        DefaultControlService,
+        fn_service(|msg: Message| async move {
+            if let MessageKind::Headers { ref pseudo, .. } = msg.kind {
+                assert_eq!(pseudo.protocol, Some(datagram::CONNECT_UDP));
+                let pipe = DatagramStream::from(msg.stream().detach(4));
+                pipe.stream()
+                    .send_response(StatusCode::OK, HeaderMap::new(), false)
+                    .unwrap();
+                ntex_util::spawn(async move {
+                    // echo udp payloads
+                    while let Some(capsule) = pipe.recv().await {
+                        let capsule = capsule.unwrap();
+                        if let Some(payload) = capsule.udp_payload() {
+                            pipe.send(&datagram::Capsule::udp(&payload)).await.unwrap();
+                        }
+                    }
+                    pipe.close().await.unwrap();
+                });
+            }
+            Ok::<_, ()>(())
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let (snd, rcv) = client
+        .send_connect(
+            datagram::CONNECT_UDP,
+            "/.well-known/masque/udp/localhost/53/".into(),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(!eof);
+
+    let pipe = DatagramStream::new(snd, rcv);
+    pipe.send(&datagram::Capsule::udp(b"ping")).await.unwrap();
+    pipe.send(&datagram::Capsule::new(0x2a, Bytes::from_static(b"ext")))
+        .await
+        .unwrap();
+    pipe.send(&datagram::Capsule::udp(b"pong")).await.unwrap();
+    pipe.close().await.unwrap();
+
+    let payload = pipe.recv_datagram().await.unwrap().unwrap();
+    assert_eq!(&payload[..], b"\x00ping");
+    let capsule = pipe.recv().await.unwrap().unwrap();
+    assert_eq!(capsule.udp_payload().unwrap(), Bytes::from_static(b"pong"));
+    assert!(pipe.recv().await.is_none());
+}
+
+#[ntex::test]
+async fn connect_udp_not_supported() {
+    let (cli, srv) = IoTest::create();
+    support::spawn_server(
+        srv,
+        Config::server(),
+        DefaultControlService,
+        fn_service(|_: Message| async move { Ok::<_, ()>(()) }),
+    );
+    let client = support::start_client(cli);
+
+    let res = client
+        .send_connect(datagram::CONNECT_UDP, "/".into(), HeaderMap::new())
+        .await;
+    assert!(matches!(
+        res,
+        Err(OperationError::ExtendedConnectNotSupported)
+    ));
+}
+
+#[cfg(feature = "testing")]
+#[ntex::test]
+async fn spec_scenarios() {
+    for scenario in ntex_h2::spec::scenarios() {
+        if let Err(err) = scenario.run(Config::server()).await {
+            panic!("{} {}: {}", scenario.section(), scenario.description(), err);
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+#[ntex::test]
+async fn test_harness() {
+    let srv = server::Server::new(
+        Config::server(),
+        DefaultControlService,
+        fn_service(|msg: Message| async move {
+            if let MessageKind::Headers { pseudo, .. } = msg.kind {
+                assert_eq!(pseudo.path.unwrap(), "/test");
+                msg.stream()
+                    .send_response(StatusCode::OK, HeaderMap::new(), false)
+                    .unwrap();
+                msg.stream()
+                    .send_payload(Bytes::from_static(b"hello"), true)
+                    .await
+                    .unwrap();
+            }
+            Ok::<_, ()>(())
+        }),
+    );
+    let client = ntex_h2::test::connect(&srv);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/test".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(!eof);
+
+    let msg = rcv.recv().await.unwrap();
+    match msg.kind {
+        MessageKind::Eof(StreamEof::Data(data)) => assert_eq!(data, b"hello"[..]),
+        kind => panic!("unexpected message kind; actual={:?}", kind),
+    }
+}
+
+#[cfg(feature = "testing")]
+#[ntex::test]
+async fn test_peer_interrupted_continuation() {
+    let srv = server::Server::new(
+        Config::server(),
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let peer = ntex_h2::test::Peer::connect(&srv);
+
+    // HEADERS without END_HEADERS flag, followed by DATA frame
+    peer.send(frame::Settings::default())
+        .send_raw(&[0, 0, 3, 0x1, 0x1, 0, 0, 0, 1, 0x82, 0x86, 0x84])
+        .send(frame::Data::new(1.into(), Bytes::from_static(b"data")));
+
+    let frames = peer.closed().await;
+    assert!(matches!(frames[0], frame::Frame::Settings(_)));
+    match frames.last() {
+        Some(frame::Frame::GoAway(frm)) => assert_eq!(frm.reason(), frame::Reason::PROTOCOL_ERROR),
+        frm => panic!("Expected GOAWAY, received {:?}", frm),
+    }
+}
+
+#[cfg(feature = "fault")]
+#[ntex::test]
+async fn fault_split_frames() {
+    use ntex_h2::fault::{duplex, Faults};
+
+    let (cli, srv) = duplex(
+        Faults::new().split(3).delay(Millis(1)),
+        Faults::new().split(5),
+    );
+    let server = server::Server::new(
+        Config::server(),
+        DefaultControlService,
+        fn_service(|msg: Message| async move {
+            if let MessageKind::Headers { .. } = msg.kind {
+                msg.stream()
+                    .send_response(StatusCode::OK, HeaderMap::new(), true)
+                    .unwrap();
+            }
+            Ok::<_, ()>(())
+        }),
+    );
+    let handler = server.handler();
+    ntex_util::spawn(async move {
+        let _ = handler.run(srv.into()).await;
+    });
+    let client = client::SimpleClient::new(
+        cli,
+        Config::client(),
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = rcv.recv().await.unwrap();
+    let (pseudo, _, eof) = get_headers!(msg);
+    assert_eq!(pseudo.status, Some(StatusCode::OK));
+    assert!(eof);
+}
+
+#[cfg(all(feature = "fault", feature = "testing"))]
+#[ntex::test]
+async fn fault_drop_frames() {
+    use ntex_h2::fault::{duplex, Faults};
+
+    let (cli, srv) = duplex(Faults::new().drop_every(2), Faults::new());
+    let server = server::Server::new(
+        Config::server(),
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let handler = server.handler();
+    ntex_util::spawn(async move {
+        let _ = handler.run(srv.into()).await;
+    });
+
+    // second frame is dropped
+    let peer = ntex_h2::test::Peer::new(cli);
+    peer.send_raw(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+        .send(frame::Settings::default())
+        .send(frame::Ping::new([1; 8]))
+        .send(frame::Ping::new([2; 8]));
+
+    let frm = peer
+        .recv_until(|frm| matches!(frm, frame::Frame::Ping(_)))
+        .await
+        .unwrap();
+    match frm {
+        frame::Frame::Ping(ping) => {
+            assert!(ping.is_ack());
+            assert_eq!(ping.payload(), &[2; 8]);
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[ntex::test]
+async fn manual_clock_handshake_timeout() {
+    let clock = ManualClock::new();
+    let config = Config::server();
+    config.handshake_timeout(Seconds(5)).clock(clock.clone());
+
+    let (_cli, srv) = IoTest::create();
+    let handler = server::Server::new(
+        config,
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    )
+    .handler();
+    let result = Rc::new(Cell::new(None));
+    let result2 = result.clone();
+    ntex_util::spawn(async move {
+        let res = handler.run(Io::new(srv).into()).await;
+        result2.set(Some(matches!(
+            res,
+            Err(server::ServerError::HandshakeTimeout)
+        )));
+    });
+
+    // real time does not affect virtual clock
+    sleep(Millis(100)).await;
+    assert_eq!(result.get(), None);
+
+    clock.advance(Duration::from_secs(4));
+    sleep(Millis(50)).await;
+    assert_eq!(result.get(), None);
+
+    clock.advance(Duration::from_secs(1));
+    sleep(Millis(50)).await;
+    assert_eq!(result.get(), Some(true));
+}
+
+#[cfg(feature = "testing")]
+#[ntex::test]
+async fn config_presets() {
+    for cfg in [Config::server(), Config::client()] {
+        cfg.grpc().validate().unwrap();
+        cfg.low_memory().validate().unwrap();
+        cfg.high_throughput().validate().unwrap();
+    }
+
+    let config = Config::server();
+    config.grpc();
+    assert!(config.is_server());
+
+    let srv = server::Server::new(
+        config,
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let peer = ntex_h2::test::Peer::connect(&srv);
+    peer.send(frame::Settings::default());
+    match peer.recv().await.unwrap() {
+        frame::Frame::Settings(settings) => {
+            assert_eq!(settings.initial_window_size(), Some(1024 * 1024));
+            assert_eq!(settings.max_concurrent_streams(), Some(1024));
+        }
+        frm => panic!("Expected SETTINGS, received {:?}", frm),
+    }
+}
+
+#[cfg(feature = "testing")]
+#[ntex::test]
+async fn negotiated_settings() {
+    let (cli, srv) = ntex_h2::test::duplex();
+    let config = Config::server();
+    config
+        .max_frame_size(32_768)
+        .initial_window_size(131_072)
+        .max_concurrent_streams(10);
+    ntex_util::spawn(async move {
+        let _ = server::handle_one(
+            srv.into(),
+            config,
+            DefaultControlService,
+            fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+        )
+        .await;
+    });
+
+    let config = Config::client();
+    config.max_frame_size(65_536);
+    let client = client::handshake(
+        cli,
+        config,
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    )
+    .await
+    .unwrap();
+    sleep(Millis(50)).await;
+
+    let settings = client.negotiated_settings();
+    assert!(settings.is_local_acked());
+    assert!(settings.is_remote_received());
+    assert_eq!(settings.local().max_frame_size(), Some(65_536));
+    assert_eq!(settings.remote().max_frame_size(), Some(32_768));
+    assert_eq!(settings.send_frame_size(), 32_768);
+    assert_eq!(settings.recv_frame_size(), 65_536);
+    assert_eq!(settings.send_window_size(), 131_072);
+    assert_eq!(settings.send_max_streams(), Some(10));
+    assert_eq!(settings.send_header_table_size(), 4_096);
+
+    let report = settings.to_string();
+    assert!(report.contains("local settings acked, remote settings received"));
+    assert!(
+        report.contains("max-frame-size: local: 65536, remote: 32768, send: 32768, recv: 65536")
+    );
+    assert!(report.contains("max-concurrent-streams: local: 256, remote: 10, send: 10, recv: 256"));
+}
+
+#[cfg(feature = "testing")]
+#[ntex::test]
+async fn multi_client_coalescing() {
+    use ntex_bytes::ByteString;
+
+    let authorities = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let authorities2 = authorities.clone();
+    let server = server::Server::new(
+        Config::server(),
+        DefaultControlService,
+        fn_service(move |msg: Message| {
+            let authorities = authorities2.clone();
+            async move {
+                if let MessageKind::Headers { ref pseudo, .. } = msg.kind {
+                    authorities
+                        .borrow_mut()
+                        .push(pseudo.authority.clone().unwrap());
+                    msg.stream()
+                        .send_response(StatusCode::OK, HeaderMap::new(), true)
+                        .unwrap();
+                }
+                Ok::<_, ()>(())
+            }
+        }),
+    );
+
+    let client = client::MultiClient::build(fn_service(
+        move |_: ntex_net::connect::Connect<ByteString>| {
+            let (cli, srv) = ntex_h2::test::duplex();
+            let handler = server.handler();
+            ntex_util::spawn(async move {
+                let _ = handler.run(srv.into()).await;
+            });
+            async move { Ok(cli) }
+        },
+    ))
+    .origins(|client| {
+        if client.authority() == "example.com" {
+            vec![ByteString::from_static("www.example.com")]
+        } else {
+            Vec::new()
+        }
+    })
+    .finish();
+
+    for authority in ["example.com", "www.example.com", "other.com"] {
+        let (_snd, rcv) = client
+            .send(
+                authority.into(),
+                Method::GET,
+                "/".into(),
+                HeaderMap::new(),
+                true,
+            )
+            .await
+            .unwrap();
+        let msg = rcv.recv().await.unwrap();
+        let (pseudo, _, _) = get_headers!(msg);
+        assert_eq!(pseudo.status, Some(StatusCode::OK));
+    }
+
+    assert_eq!(client.stat_total_connections(), 2);
+    assert_eq!(client.stat_coalesced_requests(), 1);
+    assert_eq!(client.authorities(), vec!["example.com", "other.com"]);
+    assert_eq!(
+        *authorities.borrow(),
+        vec!["example.com", "www.example.com", "other.com"]
+    );
+}
+
+#[ntex::test]
+async fn decoder_error_context() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+    let client = support::start_client(cli);
+
+    let mut buf = BytesMut::new();
+    Codec::default()
+        .encode(frame::Settings::default().into(), &mut buf)
+        .unwrap();
+    // PING frame with invalid payload size
+    buf.extend_from_slice(&[0, 0, 4, 0x6, 0, 0, 0, 0, 0, 1, 2, 3, 4]);
+    srv.write(buf);
+    sleep(Millis(50)).await;
+
+    let ctx = client.connection().context();
+    assert!(matches!(ctx.error(), Some(ConnectionError::Decoder(_))));
+    assert!(ctx.frame_kind().is_none());
+}
+
+#[ntex::test]
+async fn handshake_settings_updated() {
+    async fn settings(config: Config) -> frame::Settings {
+        let (cli, srv) = IoTest::create();
+        support::spawn_server(
+            srv,
+            config,
+            DefaultControlService,
+            fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+        );
+        let codec = Codec::default();
+        let io = support::start_raw_client(cli, &codec);
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Settings(settings)) => settings,
+            frm => panic!("Expected SETTINGS, received {:?}", frm),
+        }
+    }
+
+    let config = Config::server();
+    let s = settings(config.clone()).await;
+    assert_eq!(s.is_extended_connect_protocol_enabled(), None);
+
+    // settings changed after first connection
+    config.enable_connect_protocol();
+    let s = settings(config).await;
+    assert_eq!(s.is_extended_connect_protocol_enabled(), Some(true));
+}
+
+#[ntex::test]
+async fn handshake_frames_observed() {
+    let (cli, _srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+    let frames = Rc::new(RefCell::new(Vec::new()));
+    let frames2 = frames.clone();
+    let config = Config::client();
+    config.frame_observer(move |frm, dir| {
+        if dir == Direction::Outbound {
+            frames2.borrow_mut().push(match frm {
+                frame::Frame::Settings(_) => "settings",
+                frame::Frame::WindowUpdate(_) => "window-update",
+                _ => "other",
+            });
+        }
+    });
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config,
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    assert_eq!(*frames.borrow(), vec!["settings", "window-update"]);
+    let stats = client.connection().stats();
+    assert_eq!(stats.frames_sent.settings, 1);
+    assert_eq!(stats.frames_sent.window_update, 1);
+    assert!(client.connection().context().bytes_sent() > 0);
+}
+
+#[ntex::test]
+async fn local_settings_ack() {
+    fn write(srv: &IoTest, frm: frame::Settings) {
+        let mut buf = BytesMut::new();
+        Codec::default().encode(frm.into(), &mut buf).unwrap();
+        srv.write(buf);
+    }
+
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+    let client = support::start_client(cli);
+    let con = client.connection();
+
+    // handshake
+    write(&srv, frame::Settings::default());
+    write(&srv, frame::Settings::ack());
+    sleep(Millis(50)).await;
+    assert!(con.negotiated_settings().is_local_acked());
+
+    let mut settings = frame::Settings::default();
+    settings.set_initial_window_size(Some(1_000_000));
+    settings.set_max_frame_size(32_768);
+    con.control_handle().settings(settings);
+    let mut settings = frame::Settings::default();
+    settings.set_max_concurrent_streams(Some(5));
+    con.control_handle().settings(settings);
+
+    // settings are applied on ack, one ack per frame
+    write(&srv, frame::Settings::ack());
+    sleep(Millis(50)).await;
+    let negotiated = con.negotiated_settings();
+    assert_eq!(negotiated.recv_window_size(), 1_000_000);
+    assert_eq!(negotiated.recv_frame_size(), 32_768);
+    assert_ne!(negotiated.recv_max_streams(), Some(5));
+
+    write(&srv, frame::Settings::ack());
+    sleep(Millis(50)).await;
+    assert!(con.context().error().is_none());
+    assert_eq!(con.negotiated_settings().recv_max_streams(), Some(5));
+
+    // no pending settings
+    write(&srv, frame::Settings::ack());
+    sleep(Millis(50)).await;
+    assert!(matches!(
+        con.context().error(),
+        Some(ConnectionError::UnexpectedSettingsAck)
+    ));
+}
+
+#[ntex::test]
+async fn local_go_away_on_ping_timeout() {
+    let (cli, srv) = IoTest::create();
+    let local = Rc::new(Cell::new(0));
+    let local2 = local.clone();
+    let config = Config::server();
+    config.ping_timeout(Seconds(1));
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(move |msg: Control<()>| {
+            let ack = if let Control::LocalGoAway(item) = msg {
+                assert_eq!(item.frame().reason(), frame::Reason::NO_ERROR);
+                local2.set(local2.get() + 1);
+                let frm = item.frame().clone().set_data("incident");
+                item.ack_replace(frm)
+            } else {
+                msg.ack()
+            };
+            async move { Ok::<_, ()>(ack) }
+        }),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    // peer never responds to pings
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::NO_ERROR);
+                assert_eq!(frm.data(), &Bytes::from_static(b"incident"));
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+    sleep(Millis(50)).await;
+    assert_eq!(local.get(), 1);
+}
+
+#[ntex::test]
+async fn local_go_away_control_error() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.ping_timeout(Seconds(1));
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move {
+            if let Control::LocalGoAway(_) = msg {
+                Err(())
+            } else {
+                Ok::<_, ()>(msg.ack())
+            }
+        }),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    // control service failure replaces GOAWAY frame
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::GoAway(frm)) => {
+                assert_eq!(frm.reason(), frame::Reason::INTERNAL_ERROR);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+}
+
+#[ntex::test]
+async fn terminated_drain_without_timeout() {
+    let (cli, srv) = IoTest::create();
+    srv.remote_buffer_cap(1000000);
+    let terminated = Rc::new(Cell::new(false));
+    let terminated2 = terminated.clone();
+    let done = Rc::new(Cell::new(false));
+    let done2 = done.clone();
+
+    let config = Config::server();
+    config.disconnect_timeout(Seconds::ZERO);
+    let server = server::Server::new(
+        config,
+        fn_service(move |msg: Control<()>| {
+            if let Control::AppError(_) = msg {
+                msg.handle().unwrap().close();
+            }
+            let ack = match msg {
+                Control::Terminated(item) => {
+                    assert!(item.deadline().is_none());
+                    assert_eq!(item.active_streams().len(), 1);
+                    terminated2.set(true);
+                    item.ack_drain()
+                }
+                msg => msg.ack(),
+            };
+            async move { Ok::<_, ()>(ack) }
+        }),
+        fn_service(|msg: Message| async move {
+            let (pseudo, _, _) = get_headers!(msg);
+            if pseudo.path.unwrap() == "/fail" {
+                Err(())
+            } else {
+                Ok(())
+            }
+        }),
+    );
+    ntex_util::spawn(async move {
+        let _ = server.handler().run(Io::new(srv).into()).await;
+        done2.set(true);
+    });
+    let client = support::start_client(cli);
+
+    // first stream stays open
+    let (_snd1, _rcv1) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+    let (_snd2, _rcv2) = client
+        .send(Method::GET, "/fail".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+
+    sleep(Millis(250)).await;
+    assert!(terminated.get());
+    assert!(done.get());
+}
+
+async fn frame_notifications(enabled: bool) -> Vec<&'static str> {
+    let (cli, srv) = IoTest::create();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events2 = events.clone();
+    let config = Config::server();
+    config.frame_notifications(enabled);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(move |msg: Control<()>| {
+            match msg {
+                Control::SettingsReceived(ref item) => {
+                    assert_eq!(item.settings().max_concurrent_streams(), Some(10));
+                    events2.borrow_mut().push("settings");
+                }
+                Control::SettingsAcked(_) => events2.borrow_mut().push("settings-ack"),
+                Control::WindowUpdate(ref item) => {
+                    assert_eq!(item.stream_id(), frame::StreamId::CON);
+                    assert_eq!(item.size(), 1024);
+                    events2.borrow_mut().push("window-update");
+                }
+                _ => (),
+            }
+            async move { Ok::<_, ()>(msg.ack()) }
+        }),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+
+    let codec = Codec::default();
+    cli.remote_buffer_cap(1000000);
+    let io = Io::new(cli);
+    let _ = io.with_write_buf(|buf| buf.extend_from_slice(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"));
+    let mut settings = frame::Settings::default();
+    settings.set_max_concurrent_streams(Some(10));
+    io.encode(settings.into(), &codec).unwrap();
+    io.encode(frame::Settings::ack().into(), &codec).unwrap();
+    let upd = frame::WindowUpdate::new(frame::StreamId::CON, 1024);
+    io.encode(upd.into(), &codec).unwrap();
+    io.encode(frame::Ping::new([7; 8]).into(), &codec).unwrap();
+
+    // pong is sent after all frames are processed
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Ping(ping)) if ping.is_ack() => break,
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+    let result = events.borrow().clone();
+    result
+}
+
+#[ntex::test]
+async fn frame_notifications_enabled() {
+    assert_eq!(
+        frame_notifications(true).await,
+        vec!["settings", "settings-ack", "window-update"]
+    );
+}
+
+#[ntex::test]
+async fn frame_notifications_disabled() {
+    assert!(frame_notifications(false).await.is_empty());
+}
+
+#[ntex::test]
+async fn keepalive_timeout_extend() {
+    let (cli, srv) = IoTest::create();
+    let timeouts = Rc::new(Cell::new(0));
+    let timeouts2 = timeouts.clone();
+    let errors = Rc::new(Cell::new(0));
+    let errors2 = errors.clone();
+    let config = Config::server();
+    config.ping_timeout(Seconds(1));
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(move |msg: Control<()>| {
+            let ack = match msg {
+                Control::KeepaliveTimeout(item) => {
+                    timeouts2.set(timeouts2.get() + 1);
+                    item.ack_extend()
+                }
+                Control::ConnectionError(item) => {
+                    assert!(matches!(item.get_ref(), ConnectionError::KeepaliveTimeout));
+                    errors2.set(errors2.get() + 1);
+                    item.ack()
+                }
+                msg => msg.ack(),
+            };
+            async move { Ok::<_, ()>(ack) }
+        }),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let _io = support::start_raw_client(cli, &codec);
+
+    // peer never responds to pings, without extension connection
+    // is closed after ~2.2 seconds
+    sleep(Millis(3000)).await;
+    assert_eq!(timeouts.get(), 1);
+    assert_eq!(errors.get(), 0);
+
+    // deadline is extended only once
+    sleep(Millis(2000)).await;
+    assert_eq!(timeouts.get(), 1);
+    assert_eq!(errors.get(), 1);
+}
+
+#[ntex::test]
+async fn go_away_after_last_stream_headers() {
+    let (cli, srv) = IoTest::create();
+    cli.remote_buffer_cap(1000000);
+    let config = Config::client();
+    config.max_lifetime_streams(1);
+    let client = client::SimpleClient::new(
+        Io::new(cli),
+        config,
+        ntex_http::uri::Scheme::HTTP,
+        "localhost".into(),
+    );
+
+    let mut buf = BytesMut::new();
+    Codec::default()
+        .encode(frame::Settings::default().into(), &mut buf)
+        .unwrap();
+    srv.write(buf);
+
+    let (_snd, _rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    sleep(Millis(50)).await;
+
+    // skip connection preface
+    let mut buf = BytesMut::from(&srv.read_any()[24..]);
+    let _ = decode_frame!(Settings, buf);
+    loop {
+        match Codec::default().decode(&mut buf) {
+            Ok(Some(frame::Frame::Headers(hdrs))) => {
+                assert_eq!(hdrs.stream_id(), frame::StreamId::CLIENT);
+                break;
+            }
+            Ok(Some(frame::Frame::GoAway(_))) => panic!("GOAWAY is sent before HEADERS"),
+            Ok(Some(_)) => (),
+            frm => panic!("unexpected frame; actual={:?}", frm),
+        }
+    }
+    let _ = decode_frame!(GoAway, buf);
+}
+
+#[ntex::test]
+async fn go_away_unprocessed() {
+    let (cli, srv) = IoTest::create();
+    let client = support::start_client(cli);
+    let (snd, rcv) = client
+        .send(Method::GET, "/".into(), HeaderMap::new(), false)
+        .await
+        .unwrap();
+
+    let codec = Codec::default();
+    let mut buf = BytesMut::new();
+    codec
+        .encode(frame::Settings::default().into(), &mut buf)
+        .unwrap();
+    codec
+        .encode(frame::GoAway::new(frame::Reason::NO_ERROR).into(), &mut buf)
+        .unwrap();
+    srv.write(buf);
+
+    let msg = rcv.recv().await.unwrap();
+    let MessageKind::Disconnect(ref err) = msg.kind else {
+        panic!("unexpected message {:?}", msg);
+    };
+    assert!(matches!(
+        err,
+        OperationError::Unprocessed(frame::Reason::NO_ERROR)
+    ));
+    assert!(err.is_before_processing());
+    assert!(err.is_retryable());
+
+    let err = snd.send_payload(Bytes::new(), true).await.unwrap_err();
+    assert!(err.is_before_processing());
+
+    assert!(OperationError::RemoteReset(frame::Reason::REFUSED_STREAM).is_before_processing());
+    assert!(!OperationError::RemoteReset(frame::Reason::CANCEL).is_retryable());
+    assert!(!OperationError::Disconnected.is_before_processing());
+    assert!(OperationError::Disconnected.is_retryable());
+}
+
+#[ntex::test]
+async fn go_away_graceful() {
+    let (cli, srv) = IoTest::create();
+    let client = support::start_client(cli);
+    let mut streams = Vec::new();
+    for _ in 0..3 {
+        streams.push(
+            client
+                .send(Method::GET, "/".into(), HeaderMap::new(), true)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let codec = Codec::default();
+    let mut buf = BytesMut::new();
+    codec
+        .encode(frame::Settings::default().into(), &mut buf)
+        .unwrap();
+    let frm = frame::GoAway::new(frame::Reason::NO_ERROR).set_last_stream_id(frame::StreamId::MAX);
+    codec.encode(frm.into(), &mut buf).unwrap();
+    srv.write(buf);
+    sleep(Millis(50)).await;
+
+    // all streams are active, new streams are not allowed
+    assert!(client.is_closing());
+    assert!(!client.is_closed());
+
+    // last stream id could only decrease
+    let mut buf = BytesMut::new();
+    let frm = frame::GoAway::new(frame::Reason::NO_ERROR).set_last_stream_id(3.into());
+    codec.encode(frm.into(), &mut buf).unwrap();
+    let frm = frame::GoAway::new(frame::Reason::NO_ERROR).set_last_stream_id(5.into());
+    codec.encode(frm.into(), &mut buf).unwrap();
+    srv.write(buf);
+
+    let msg = streams[2].1.recv().await.unwrap();
+    assert!(matches!(
+        msg.kind,
+        MessageKind::Disconnect(OperationError::Unprocessed(frame::Reason::NO_ERROR))
+    ));
+
+    // streams below last stream id complete
+    let mut buf = BytesMut::new();
+    for id in [1u32, 3] {
+        let pseudo = frame::PseudoHeaders::response(StatusCode::OK);
+        let hdrs = frame::Headers::new(id.into(), pseudo, HeaderMap::new(), true);
+        codec.encode(hdrs.into(), &mut buf).unwrap();
+    }
+    srv.write(buf);
+
+    for (_, rcv) in &streams[..2] {
+        let msg = rcv.recv().await.unwrap();
+        let (pseudo, _, eof) = get_headers!(msg);
+        assert_eq!(pseudo.status, Some(StatusCode::OK));
+        assert!(eof);
+    }
+    sleep(Millis(50)).await;
+    assert!(client.is_closed());
+}
+
+#[ntex::test]
+async fn connector_settings_error() {
+    let (cli, srv) = IoTest::create();
+    let io = Cell::new(Some(cli));
+    let connector = client::Connector::new(fn_service(
+        move |_: ntex_net::connect::Connect<&'static str>| {
+            let io = io.take().unwrap();
+            async move { Ok(Io::new(io)) }
+        },
+    ));
+
+    // peer responds with http/1.1 instead of SETTINGS frame
+    srv.write("HTTP/1.1 400 Bad Request\r\n\r\n");
+    let err = connector.connect("localhost").await.unwrap_err();
+    assert!(matches!(err, client::ClientError::Settings(_)), "{:?}", err);
+}
+
+#[ntex::test]
+async fn connector_builder() {
+    fn is_send<T: Send>(_: &T) {}
+
+    let builder = client::Connector::<&'static str, _>::builder()
+        .max_frame_size(32_768)
+        .max_concurrent_streams(16);
+    is_send(&builder);
+
+    let err = client::Connector::<&'static str, _>::builder()
+        .max_frame_size(100)
+        .finish()
+        .unwrap_err();
+    assert!(matches!(err, ConfigError::MaxFrameSize(100)));
+
+    let connector = builder.finish().unwrap();
+    assert!(!connector.is_server());
+
+    let settings = connector_settings(connector).await;
+    assert_eq!(settings.max_frame_size(), Some(32_768));
+    assert_eq!(settings.max_concurrent_streams(), Some(16));
+}
+
+#[ntex::test]
+async fn connector_from_lookup() {
+    let config = Config::from_lookup(false, |name| match name {
+        "NTEX_H2_MAX_FRAME_SIZE" => Some("32768".to_string()),
+        "NTEX_H2_INITIAL_WINDOW_SIZE" => Some(" 131072 ".to_string()),
+        "NTEX_H2_PING_TIMEOUT" => Some("30".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    assert!(!config.is_server());
+
+    let settings = connector_settings(client::Connector::from_config(config)).await;
+    assert_eq!(settings.max_frame_size(), Some(32_768));
+    assert_eq!(settings.initial_window_size(), Some(131_072));
+
+    let lookup = |val: &'static str| {
+        move |name: &str| (name == "NTEX_H2_MAX_FRAME_SIZE").then(|| val.to_string())
+    };
+    assert_eq!(
+        Config::from_lookup(true, lookup("100")).unwrap_err(),
+        ConfigError::MaxFrameSize(100)
+    );
+    assert_eq!(
+        Config::from_lookup(true, lookup("large")).unwrap_err(),
+        ConfigError::Env("NTEX_H2_MAX_FRAME_SIZE")
+    );
+    assert!(Config::from_lookup(true, |_| None).unwrap().is_server());
+}
+
+#[test]
+#[should_panic]
+fn connector_from_server_config() {
+    let _ = client::Connector::<&'static str, _>::from_config(Config::server());
+}
+
+/// Connect with in-memory io and return SETTINGS frame sent by connector
+async fn connector_settings(
+    connector: client::Connector<&'static str, ntex_net::connect::Connector<&'static str>>,
+) -> frame::Settings {
+    let (cli, srv) = IoTest::create();
+    let io = Cell::new(Some(cli));
+    let connector = connector.connector(fn_service(
+        move |_: ntex_net::connect::Connect<&'static str>| {
+            let io = io.take().unwrap();
+            async move { Ok(Io::new(io)) }
+        },
+    ));
+
+    let mut buf = BytesMut::new();
+    Codec::default()
+        .encode(frame::Settings::default().into(), &mut buf)
+        .unwrap();
+    srv.write(buf);
+    let _client = connector.connect("localhost").await.unwrap();
+
+    // skip connection preface
+    let mut buf = BytesMut::from(&srv.read().await.unwrap()[24..]);
+    decode_frame!(Settings, buf)
+}
+
+async fn control_policy_pong(ack_pings: bool) -> bool {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.frame_notifications(true);
+    support::spawn_server(
+        srv,
+        config,
+        DefaultControlService::with_policy(ControlPolicy::new().auto_ack_pings(ack_pings)),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+    io.encode(frame::Ping::new([3; 8]).into(), &codec).unwrap();
+
+    // zero increment is protocol error, GOAWAY is sent after ping is processed
+    let upd = frame::WindowUpdate::new(frame::StreamId::CON, 0);
+    io.encode(upd.into(), &codec).unwrap();
+
+    let mut pong = false;
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Ping(ping)) if ping.is_ack() => {
+                assert_eq!(ping.payload(), &[3; 8]);
+                pong = true;
+            }
+            Some(frame::Frame::GoAway(_)) => break,
+            Some(_) => (),
+            None => panic!("connection is closed without GOAWAY"),
+        }
+    }
+    pong
+}
+
+#[ntex::test]
+async fn control_policy_ack_pings() {
+    assert!(control_policy_pong(true).await);
+    assert!(!control_policy_pong(false).await);
+}
+
 #[test]
 fn update_max_frame_len_at_rest() {
     let mut buf = BytesMut::new();
@@ -1,13 +1,19 @@
-use std::{cell::Cell, io, net, rc::Rc};
+mod support;
 
-use ::openssl::ssl::{AlpnError, SslAcceptor, SslConnector, SslFiletype, SslMethod, SslVerifyMode};
+use std::{cell::Cell, cell::RefCell, io, rc::Rc};
+
+use ::openssl::ssl::{AlpnError, SslAcceptor, SslFiletype, SslMethod};
 use ntex::http::{
-    test::server as test_server, uri::Scheme, HeaderMap, HttpService, Method, Response,
+    test::server as test_server, uri::Scheme, HeaderMap, HttpService, Method, Response, StatusCode,
 };
 use ntex::service::{fn_service, ServiceFactory};
 use ntex::time::{sleep, Millis};
-use ntex::{channel::oneshot, connect::openssl, io::IoBoxed, util::Bytes};
-use ntex_h2::{client, client::Client, client::SimpleClient, frame, frame::Reason, Codec};
+use ntex::{channel::oneshot, util::Bytes};
+use ntex_h2::{
+    client, client::Client, client::SimpleClient, frame, frame::Reason, Codec, Config, Control,
+    DefaultControlService, Message, MessageKind, RefuseCause, StreamClose,
+};
+use ntex_io::testing::IoTest;
 
 fn ssl_acceptor() -> SslAcceptor {
     // load ssl keys
@@ -52,33 +58,20 @@ fn start_server() -> ntex::http::test::TestServer {
     })
 }
 
-async fn connect(addr: net::SocketAddr) -> IoBoxed {
-    // disable ssl verification
-    let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
-    builder.set_verify(SslVerifyMode::NONE);
-    let _ = builder
-        .set_alpn_protos(b"\x02h2\x08http/1.1")
-        .map_err(|e| log::error!("Cannot set alpn protocol: {:?}", e));
-
-    let addr = ntex::connect::Connect::new("localhost").set_addr(Some(addr));
-    openssl::SslConnector::new(builder.build())
-        .connect(addr)
-        .await
-        .unwrap()
-        .into()
-}
-
 #[ntex::test]
 async fn test_max_concurrent_streams() {
     let srv = start_server();
     let addr = srv.addr();
-    let client =
-        client::Connector::new(fn_service(move |_| async move { Ok(connect(addr).await) }))
-            .scheme(Scheme::HTTP)
-            .connector(fn_service(move |_| async move { Ok(connect(addr).await) }))
-            .connect("localhost")
-            .await
-            .unwrap();
+    let client = client::Connector::new(fn_service(move |_| async move {
+        Ok(support::connect(addr).await)
+    }))
+    .scheme(Scheme::HTTP)
+    .connector(fn_service(move |_| async move {
+        Ok(support::connect(addr).await)
+    }))
+    .connect("localhost")
+    .await
+    .unwrap();
     assert!(format!("{:?}", client).contains("SimpleClient"));
     assert_eq!(client.authority(), "localhost");
 
@@ -117,13 +110,250 @@ async fn test_max_concurrent_streams() {
     assert!(opened.get());
 }
 
+#[ntex::test]
+async fn test_stats() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let client = support::connect_client(addr).await;
+
+    let (stream, recv_stream) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+    stream
+        .send_payload(Bytes::from_static(b"data"), true)
+        .await
+        .unwrap();
+    while let Some(msg) = recv_stream.recv().await {
+        if matches!(msg.kind(), MessageKind::Eof(_) | MessageKind::Disconnect(_)) {
+            break;
+        }
+    }
+
+    let stats = client.stats();
+    assert_eq!(stats.streams_opened, 1);
+    assert_eq!(stats.frames_sent.headers, 1);
+    assert_eq!(stats.frames_sent.data, 1);
+    assert_eq!(stats.data_bytes_sent, 4);
+    assert_eq!(stats.data_bytes_received, 9);
+    assert!(stats.frames_received.headers >= 1);
+    assert!(stats.frames_received.settings >= 1);
+    assert!(stats.header_bytes_sent > 0);
+    assert!(stats.header_bytes_received > 0);
+    assert_eq!(stream.stream().stats(), stats);
+}
+
+#[ntex::test]
+async fn test_connection_context() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let client = support::connect_client(addr).await;
+
+    client.ping().await.unwrap();
+    sleep(Millis(50)).await;
+
+    let ctx = client.connection().context();
+    assert!(ctx.bytes_sent() > 0);
+    assert!(ctx.bytes_received() > 0);
+    assert!(ctx.duration() >= std::time::Duration::from_millis(40));
+    assert!(ctx.go_away().is_none());
+}
+
+#[ntex::test]
+async fn test_ping_rtt() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let client = support::connect_client(addr).await;
+
+    let rtt = client.ping().await.unwrap();
+    let _ = client.ping().await.unwrap();
+
+    let stats = client.stats().rtt;
+    assert_eq!(stats.samples, 2);
+    assert_eq!(stats.min, Some(stats.min.unwrap().min(rtt)));
+    assert!(stats.last.is_some());
+    assert!(stats.avg.is_some());
+    assert!(stats.p99 >= stats.p50);
+}
+
+#[ntex::test]
+async fn test_connection_dump() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let client = support::connect_client(addr).await;
+
+    let (stream, _recv_stream) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+
+    let dump = client.connection().dump();
+    assert_eq!(dump.active_local_streams(), 1);
+    assert!(dump.error().is_none());
+    assert_eq!(dump.streams().len(), 1);
+
+    let st = &dump.streams()[0];
+    assert_eq!(st.id(), stream.id());
+    assert!(!st.is_remote());
+    assert_eq!(st.send_state(), "open");
+    assert!(format!("{}", dump).contains("send: open"));
+}
+
+#[ntex::test]
+async fn test_event_hooks() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let settings = Rc::new(Cell::new(0));
+    let pings = Rc::new(Cell::new(0));
+
+    let mut connector = client::Connector::new(fn_service(move |_| async move {
+        Ok(support::connect(addr).await)
+    }));
+    let (settings2, pings2) = (settings.clone(), pings.clone());
+    connector
+        .scheme(Scheme::HTTP)
+        .on_settings(move |_| settings2.set(settings2.get() + 1))
+        .on_ping(move |frm| {
+            assert!(frm.is_ack());
+            pings2.set(pings2.get() + 1)
+        });
+    let client = connector.connect("localhost").await.unwrap();
+
+    client.ping().await.unwrap();
+    assert_eq!(pings.get(), 1);
+    assert!(settings.get() >= 1);
+}
+
+#[ntex::test]
+async fn test_control_handle() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let client = support::connect_client(addr).await;
+
+    let handle = client.connection().control_handle();
+    let (stream, _recv_stream) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+    handle.ping().await.unwrap();
+    handle.reset(stream.id(), Reason::CANCEL);
+    assert!(matches!(
+        stream.stream().on_close().await,
+        StreamClose::LocalReset(Reason::CANCEL)
+    ));
+
+    handle.go_away(frame::GoAway::new(Reason::NO_ERROR));
+    assert!(client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .is_err());
+    handle.close();
+    sleep(Millis(50)).await;
+    assert!(handle.is_closed());
+    assert!(client.is_closed());
+}
+
+#[ntex::test]
+async fn test_control_handle_state() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let client = support::connect_client(addr).await;
+
+    let handle = client.connection().control_handle();
+    handle.ping().await.unwrap();
+    assert!(handle.remote_settings().max_header_list_size().is_some());
+    assert!(handle.stats().frames_received.settings >= 1);
+    assert!(handle.streams().is_empty());
+
+    let (stream1, _recv_stream1) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+    let (stream2, _recv_stream2) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+    let ids: Vec<_> = handle.streams().iter().map(|s| s.id()).collect();
+    assert_eq!(ids, vec![stream1.id(), stream2.id()]);
+    assert_eq!(handle.stream(stream1.id()).unwrap().id(), stream1.id());
+
+    let con = client.connection();
+    let ids: Vec<_> = con.streams().iter().map(|s| s.id()).collect();
+    assert_eq!(ids, vec![stream1.id(), stream2.id()]);
+    assert_eq!(con.streams()[0].send_state(), "open");
+
+    stream1.stream().set_label("req-1");
+    assert_eq!(stream1.stream().label(), Some("req-1".into()));
+    assert_eq!(con.streams()[0].label(), Some(&"req-1".into()));
+    assert!(con.streams()[0].to_string().contains("[req-1]"));
+
+    con.stream(stream2.id()).unwrap().reset(Reason::CANCEL);
+    let ids: Vec<_> = con.streams().iter().map(|s| s.id()).collect();
+    assert_eq!(ids, vec![stream1.id()]);
+    assert!(con.stream(stream2.id()).is_none());
+}
+
+#[ntex::test]
+async fn test_stream_on_close() {
+    let srv = start_server();
+    let addr = srv.addr();
+    let client = support::connect_client(addr).await;
+
+    let (stream, recv_stream) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+    stream
+        .send_payload(Bytes::from_static(b"data"), true)
+        .await
+        .unwrap();
+    while let Some(msg) = recv_stream.recv().await {
+        if matches!(msg.kind(), MessageKind::Eof(_) | MessageKind::Disconnect(_)) {
+            break;
+        }
+    }
+    assert!(matches!(
+        stream.stream().on_close().await,
+        StreamClose::Clean
+    ));
+    assert!(matches!(
+        stream.stream().close_reason(),
+        Some(StreamClose::Clean)
+    ));
+
+    let (stream, _recv_stream) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+    assert!(stream.stream().close_reason().is_none());
+    stream.reset(Reason::CANCEL);
+    assert!(matches!(
+        stream.stream().on_close().await,
+        StreamClose::LocalReset(Reason::CANCEL)
+    ));
+    assert!(matches!(
+        stream.stream().close_reason(),
+        Some(StreamClose::LocalReset(Reason::CANCEL))
+    ));
+
+    let (stream, recv_stream) = client
+        .send(Method::GET, "/".into(), HeaderMap::default(), false)
+        .await
+        .unwrap();
+    recv_stream.reset(Reason::ENHANCE_YOUR_CALM);
+    assert!(matches!(
+        stream.stream().on_close().await,
+        StreamClose::LocalReset(Reason::ENHANCE_YOUR_CALM)
+    ));
+}
+
 #[ntex::test]
 async fn test_max_concurrent_streams_pool() {
     let srv = start_server();
     let addr = srv.addr();
     let client = Client::build(
         "localhost",
-        fn_service(move |_| async move { Ok(connect(addr).await) }),
+        fn_service(move |_| async move { Ok(support::connect(addr).await) }),
     );
     assert!(format!("{:?}", client).contains("ClientBuilder"));
     let client = client
@@ -131,7 +361,7 @@ async fn test_max_concurrent_streams_pool() {
         .scheme(Scheme::HTTPS)
         .connector(
             "localhost",
-            fn_service(move |_| async move { Ok(connect(addr).await) }),
+            fn_service(move |_| async move { Ok(support::connect(addr).await) }),
         )
         .finish();
     assert!(format!("{:?}", client).contains("Client"));
@@ -173,7 +403,7 @@ async fn test_max_concurrent_streams_pool2() {
         "localhost",
         fn_service(move |_| {
             cnt2.set(cnt2.get() + 1);
-            async move { Ok(connect(addr).await) }
+            async move { Ok(support::connect(addr).await) }
         }),
     )
     .maxconn(2)
@@ -208,7 +438,7 @@ async fn test_max_concurrent_streams_pool2() {
 #[ntex::test]
 async fn test_max_concurrent_streams_reset() {
     let srv = start_server();
-    let io = connect(srv.addr()).await;
+    let io = support::connect(srv.addr()).await;
     let client = SimpleClient::new(
         io,
         ntex_h2::Config::client(),
@@ -273,7 +503,7 @@ async fn test_goaway_on_overflow() {
     let srv = start_server();
     let addr = srv.addr();
 
-    let io = connect(addr).await;
+    let io = support::connect(addr).await;
     let codec = Codec::default();
     let _ = io.with_write_buf(|buf| buf.extend_from_slice(&PREFACE));
 
@@ -318,12 +548,67 @@ async fn test_goaway_on_overflow() {
     assert!(io.recv(&codec).await.unwrap().is_none());
 }
 
+#[ntex::test]
+async fn test_goaway_on_lifetime_streams() {
+    let srv = test_server(move || {
+        HttpService::build()
+            .h2_configure(|cfg| {
+                cfg.max_lifetime_streams(1);
+            })
+            .h2(|_: ntex::http::Request| async move {
+                Ok::<_, io::Error>(Response::Ok().body("test body"))
+            })
+            .openssl(ssl_acceptor())
+            .map_err(|_| ())
+    });
+    let addr = srv.addr();
+
+    let io = support::connect(addr).await;
+    let codec = Codec::default();
+    let _ = io.with_write_buf(|buf| buf.extend_from_slice(&PREFACE));
+
+    let settings = frame::Settings::default();
+    io.encode(settings.into(), &codec).unwrap();
+
+    // settings & window
+    let _ = io.recv(&codec).await;
+    let _ = io.recv(&codec).await;
+    let _ = io.recv(&codec).await;
+
+    let id = frame::StreamId::CLIENT;
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::GET),
+        scheme: Some("HTTPS".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+    let hdrs = frame::Headers::new(id, pseudo, HeaderMap::new(), true);
+    io.send(hdrs.into(), &codec).await.unwrap();
+
+    let res = if let frame::Frame::GoAway(rst) = io.recv(&codec).await.unwrap().unwrap() {
+        rst
+    } else {
+        panic!()
+    };
+    assert_eq!(res.reason(), Reason::NO_ERROR);
+    assert_eq!(res.last_stream_id(), id);
+
+    // stream is processed
+    let res = if let frame::Frame::Headers(hdrs) = io.recv(&codec).await.unwrap().unwrap() {
+        hdrs
+    } else {
+        panic!()
+    };
+    assert_eq!(res.stream_id(), id);
+}
+
 #[ntex::test]
 async fn test_goaway_on_reset() {
     let srv = start_server();
     let addr = srv.addr();
 
-    let io = connect(addr).await;
+    let io = support::connect(addr).await;
     let codec = Codec::default();
     let _ = io.with_write_buf(|buf| buf.extend_from_slice(&PREFACE));
 
@@ -378,7 +663,7 @@ async fn test_goaway_on_reset2() {
     let srv = start_server();
     let addr = srv.addr();
 
-    let io = connect(addr).await;
+    let io = support::connect(addr).await;
     let codec = Codec::default();
     let _ = io.with_write_buf(|buf| buf.extend_from_slice(&PREFACE));
 
@@ -433,3 +718,357 @@ async fn test_goaway_on_reset2() {
     assert_eq!(res.reason(), Reason::FLOW_CONTROL_ERROR);
     assert!(io.recv(&codec).await.unwrap().is_none());
 }
+
+#[ntex::test]
+async fn test_max_concurrent_publish() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.max_concurrent_publish(1);
+
+    let inflight = Rc::new(Cell::new(0));
+    let max_inflight = Rc::new(Cell::new(0));
+    let inflight2 = inflight.clone();
+    let max_inflight2 = max_inflight.clone();
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move { Ok::<_, ()>(msg.ack()) }),
+        fn_service(move |msg: Message| {
+            let inflight = inflight2.clone();
+            let max_inflight = max_inflight2.clone();
+            async move {
+                inflight.set(inflight.get() + 1);
+                max_inflight.set(max_inflight.get().max(inflight.get()));
+                sleep(Millis(50)).await;
+                inflight.set(inflight.get() - 1);
+
+                msg.stream()
+                    .send_response(StatusCode::OK, HeaderMap::new(), true)
+                    .unwrap();
+                Ok::<_, ()>(())
+            }
+        }),
+    );
+    let client = support::start_client(cli);
+
+    let mut streams = Vec::new();
+    for _ in 0..3 {
+        let (_snd, rcv) = client
+            .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+            .await
+            .unwrap();
+        streams.push(rcv);
+    }
+    for rcv in streams {
+        let msg = rcv.recv().await.unwrap();
+        let (pseudo, _hdrs, eof) = get_headers!(msg);
+        assert_eq!(pseudo.status, Some(StatusCode::OK));
+        assert!(eof);
+    }
+    assert_eq!(max_inflight.get(), 1);
+}
+
+#[ntex::test]
+async fn test_connection_window_update_deferred() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.initial_connection_window_size(100_000);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move { Ok::<_, ()>(msg.ack()) }),
+        fn_service(|_: Message| async move { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    // handshake window update
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::WindowUpdate(frm)) if frm.stream_id().is_zero() => {
+                assert_eq!(frm.size_increment(), 100_000 - 65_535);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+
+    // all buffered frames are written at once
+    let id = frame::StreamId::CLIENT;
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::POST),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+    let hdrs = frame::Headers::new(id, pseudo, HeaderMap::new(), false);
+    io.encode(hdrs.into(), &codec).unwrap();
+    for _ in 0..4 {
+        let data = frame::Data::new(id, Bytes::from(vec![0; 16_000]));
+        io.encode(data.into(), &codec).unwrap();
+    }
+    io.encode(frame::Ping::new([1; 8]).into(), &codec).unwrap();
+
+    // received bytes are accounted in connection window
+    let mut increment = 0;
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::WindowUpdate(frm)) if frm.stream_id().is_zero() => {
+                increment += frm.size_increment();
+            }
+            Some(frame::Frame::Ping(ping)) if ping.is_ack() => break,
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+    assert_eq!(increment, 64_000);
+}
+
+#[ntex::test]
+async fn test_frames_budget() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.frames_budget(2);
+    support::spawn_server(
+        srv,
+        config,
+        DefaultControlService,
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    // connection yields after budget is exhausted, but processes all frames
+    for idx in 0..10 {
+        io.encode(frame::Ping::new([idx; 8]).into(), &codec)
+            .unwrap();
+    }
+    let mut pongs = Vec::new();
+    while pongs.len() < 10 {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Ping(ping)) if ping.is_ack() => pongs.push(ping.payload()[0]),
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+    assert_eq!(pongs, (0..10).collect::<Vec<u8>>());
+}
+
+#[ntex::test]
+async fn test_max_buffered_frames() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.max_buffered_frames(1);
+
+    let headers = Rc::new(Cell::new(0));
+    let buffered = Rc::new(RefCell::new(Vec::new()));
+    let headers2 = headers.clone();
+    let buffered2 = buffered.clone();
+    support::spawn_server(
+        srv,
+        config,
+        DefaultControlService,
+        fn_service(move |msg: Message| {
+            match msg.kind() {
+                MessageKind::Headers { .. } => headers2.set(headers2.get() + 1),
+                MessageKind::Data(..) => buffered2.borrow_mut().push(msg),
+                _ => (),
+            }
+            async { Ok::<_, ()>(()) }
+        }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::POST),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+    let id = frame::StreamId::CLIENT;
+    let hdrs = frame::Headers::new(id, pseudo.clone(), HeaderMap::new(), false);
+    io.encode(hdrs.into(), &codec).unwrap();
+    let data = frame::Data::new(id, Bytes::from_static(b"data"));
+    io.encode(data.into(), &codec).unwrap();
+    let hdrs = frame::Headers::new(3.into(), pseudo, HeaderMap::new(), true);
+    io.encode(hdrs.into(), &codec).unwrap();
+
+    // payload frame is not consumed, connection stops reading
+    sleep(Millis(100)).await;
+    assert_eq!(headers.get(), 1);
+    assert_eq!(buffered.borrow().len(), 1);
+
+    buffered.borrow_mut().clear();
+    sleep(Millis(50)).await;
+    assert_eq!(headers.get(), 2);
+}
+
+#[cfg(feature = "tracing")]
+#[ntex::test]
+async fn test_tracing_spans() {
+    use std::{fmt, sync::Arc, sync::Mutex};
+    use tracing::{field, span, Event, Metadata, Subscriber};
+
+    /// Records span names and fields
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<(&'static str, String)>>>);
+
+    struct Fields<'a>(&'a mut String);
+
+    impl field::Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &field::Field, value: &dyn fmt::Debug) {
+            self.0.push_str(&format!("{}={:?} ", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            let mut fields = String::new();
+            span.record(&mut Fields(&mut fields));
+            let mut spans = self.0.lock().unwrap();
+            spans.push((span.metadata().name(), fields));
+            span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut Fields(&mut spans[id.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let spans = Spans::default();
+    let _guard = tracing::subscriber::set_default(spans.clone());
+
+    let (cli, srv) = IoTest::create();
+    let srv_rx = support::start_server(srv);
+    let client = support::start_client(cli);
+
+    let (_snd, rcv) = client
+        .send(Method::GET, "/index.html".into(), HeaderMap::new(), true)
+        .await
+        .unwrap();
+    let msg = srv_rx.recv().await.unwrap();
+    msg.stream()
+        .send_response(StatusCode::OK, HeaderMap::new(), true)
+        .unwrap();
+    let _ = rcv.recv().await.unwrap();
+
+    let spans = spans.0.lock().unwrap();
+    let connections = spans
+        .iter()
+        .filter(|(name, _)| *name == "h2::connection")
+        .count();
+    assert_eq!(connections, 2);
+
+    // client and server streams record request pseudo headers
+    let streams: Vec<_> = spans
+        .iter()
+        .filter(|(name, _)| *name == "h2::stream")
+        .collect();
+    assert_eq!(streams.len(), 2);
+    for (_, fields) in streams {
+        assert!(fields.contains("id=1"));
+        assert!(fields.contains("method=\"GET\""));
+        assert!(fields.contains("path=\"/index.html\""));
+    }
+}
+
+#[ntex::test]
+async fn test_control_ack_frames() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.frame_notifications(true);
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(|msg: Control<()>| async move {
+            let ack = if let Control::SettingsReceived(_) = msg {
+                msg.ack()
+                    .frame(frame::Ping::new([1; 8]))
+                    .frame(frame::Ping::new([2; 8]))
+            } else {
+                msg.ack()
+            };
+            Ok::<_, ()>(ack)
+        }),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    // frames are sent in the order they were added
+    let mut pings = Vec::new();
+    while pings.len() < 2 {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Ping(ping)) if !ping.is_ack() => pings.push(ping.payload()[0]),
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+    assert_eq!(pings, vec![1, 2]);
+}
+
+#[ntex::test]
+async fn test_refused_stream_notification() {
+    let (cli, srv) = IoTest::create();
+    let config = Config::server();
+    config.max_concurrent_streams(1);
+
+    let refused = Rc::new(RefCell::new(Vec::new()));
+    let refused2 = refused.clone();
+    support::spawn_server(
+        srv,
+        config,
+        fn_service(move |msg: Control<()>| {
+            if let Control::StreamRefused(ref item) = msg {
+                refused2.borrow_mut().push((item.id(), item.cause()));
+            }
+            async move { Ok::<_, ()>(msg.ack()) }
+        }),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+    let codec = Codec::default();
+    let io = support::start_raw_client(cli, &codec);
+
+    let pseudo = frame::PseudoHeaders {
+        method: Some(Method::GET),
+        scheme: Some("http".into()),
+        authority: Some("localhost".into()),
+        path: Some("/".into()),
+        ..Default::default()
+    };
+    for id in [1, 3] {
+        let hdrs = frame::Headers::new(id.into(), pseudo.clone(), HeaderMap::new(), true);
+        io.encode(hdrs.into(), &codec).unwrap();
+    }
+
+    loop {
+        match io.recv(&codec).await.unwrap() {
+            Some(frame::Frame::Reset(frm)) => {
+                assert_eq!(frm.stream_id(), 3);
+                assert_eq!(frm.reason(), frame::Reason::REFUSED_STREAM);
+                break;
+            }
+            Some(_) => (),
+            None => panic!("connection is closed"),
+        }
+    }
+    sleep(Millis(50)).await;
+    assert_eq!(
+        *refused.borrow(),
+        vec![(frame::StreamId::from(3), RefuseCause::MaxConcurrentStreams)]
+    );
+}
@@ -1,12 +1,18 @@
-use ntex_h2::{client, server, Config, Control, Message};
+#![allow(dead_code)]
+
+use std::{cell::Cell, fmt, rc::Rc};
+
+use ntex_h2::{client, frame, server, Codec, Config, Control, ControlAck, Message, Stats};
 use ntex_http::uri::Scheme;
 use ntex_io::{testing::IoTest, Io};
-use ntex_service::fn_service;
+use ntex_service::{fn_service, ServiceFactory};
 use ntex_util::channel::mpsc;
 
 pub mod frames;
+mod tls;
 mod utils;
 
+pub use self::tls::*;
 pub use self::utils::*;
 
 pub fn start_client(io: IoTest) -> client::SimpleClient {
@@ -19,30 +25,73 @@ pub fn start_client(io: IoTest) -> client::SimpleClient {
     )
 }
 
-pub fn start_server(io: IoTest) -> mpsc::Receiver<Message> {
+/// Send connection preface and SETTINGS frame, return raw io
+pub fn start_raw_client(io: IoTest, codec: &Codec) -> Io {
     io.remote_buffer_cap(1000000);
 
+    let io = Io::new(io);
+    let _ = io.with_write_buf(|buf| buf.extend_from_slice(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"));
+    io.encode(frame::Settings::default().into(), codec).unwrap();
+    io
+}
+
+pub fn start_server(io: IoTest) -> mpsc::Receiver<Message> {
     let (tx, rx) = mpsc::channel();
-    ntex_util::spawn(async move {
-        let _ = server::Server::new(
-            Config::server(),
-            fn_service(|msg: Control<()>| async move {
-                log::trace!("Control message: {:?}", msg);
-                Ok::<_, ()>(msg.ack())
-            }),
-            fn_service(move |msg: Message| {
-                let _ = tx.send(msg);
-                async { Ok(()) }
-            }),
-        )
-        .handler()
-        .run(Io::new(io).into())
-        .await;
-    });
+    spawn_server(
+        io,
+        Config::server(),
+        fn_service(|msg: Control<()>| async move {
+            log::trace!("Control message: {:?}", msg);
+            Ok::<_, ()>(msg.ack())
+        }),
+        fn_service(move |msg: Message| {
+            let _ = tx.send(msg);
+            async { Ok(()) }
+        }),
+    );
 
     rx
 }
 
+/// Run server, returns statistics reported with connection error
+pub fn start_server_with_stats(io: IoTest) -> Rc<Cell<Option<Stats>>> {
+    let stats = Rc::new(Cell::new(None));
+    let stats2 = stats.clone();
+    spawn_server(
+        io,
+        Config::server(),
+        fn_service(move |msg: Control<()>| {
+            if let Control::ConnectionError(ref item) = msg {
+                stats2.set(Some(*item.stats()));
+            }
+            async move { Ok::<_, ()>(msg.ack()) }
+        }),
+        fn_service(|_: Message| async { Ok::<_, ()>(()) }),
+    );
+
+    stats
+}
+
+/// Run server with custom config and services
+pub fn spawn_server<Ctl, Pub>(io: IoTest, config: Config, control: Ctl, publish: Pub)
+where
+    Ctl: ServiceFactory<Control<Pub::Error>, Response = ControlAck> + 'static,
+    Ctl::Error: fmt::Debug,
+    Ctl::InitError: fmt::Debug,
+    Pub: ServiceFactory<Message, Response = ()> + 'static,
+    Pub::Error: fmt::Debug,
+    Pub::InitError: fmt::Debug,
+{
+    io.remote_buffer_cap(1000000);
+
+    ntex_util::spawn(async move {
+        let _ = server::Server::new(config, control, publish)
+            .handler()
+            .run(Io::new(io).into())
+            .await;
+    });
+}
+
 #[macro_export]
 macro_rules! get_headers {
     ($msg: ident) => {{
@@ -0,0 +1,31 @@
+use std::net;
+
+use ::openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use ntex::{connect::openssl, io::IoBoxed, service::fn_service};
+use ntex_h2::client;
+use ntex_http::uri::Scheme;
+
+/// Connect to test server with disabled ssl verification
+pub async fn connect(addr: net::SocketAddr) -> IoBoxed {
+    let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
+    builder.set_verify(SslVerifyMode::NONE);
+    let _ = builder
+        .set_alpn_protos(b"\x02h2\x08http/1.1")
+        .map_err(|e| log::error!("Cannot set alpn protocol: {:?}", e));
+
+    let addr = ntex::connect::Connect::new("localhost").set_addr(Some(addr));
+    openssl::SslConnector::new(builder.build())
+        .connect(addr)
+        .await
+        .unwrap()
+        .into()
+}
+
+/// Connect h2 client to test server
+pub async fn connect_client(addr: net::SocketAddr) -> client::SimpleClient {
+    client::Connector::new(fn_service(move |_| async move { Ok(connect(addr).await) }))
+        .scheme(Scheme::HTTP)
+        .connect("localhost")
+        .await
+        .unwrap()
+}